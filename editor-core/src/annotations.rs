@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use backend::{end_of, shift_position};
+
+/// How an annotation is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    /// Not drawn, for annotations that only keep track of a range.
+    Hidden,
+    /// Behind the text, like the occurrences of the word under the cursor.
+    Highlight,
+    /// A line under the text, like errors.
+    Underline,
+    /// A mark in the gutter, like the ones of the lines changed since the last commit.
+    Gutter(Mark),
+    /// Behind whole lines, in the color of the mark, like the lines added and removed in a
+    /// diff.
+    Background(Mark),
+}
+
+/// What a gutter mark stands for: how the lines next to it changed since the last commit, or a
+/// bookmark.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mark {
+    Added,
+    Modified,
+    /// Lines were removed right above the mark.
+    Removed,
+    Bookmark,
+}
+
+/// Something attached to a range of text, which moves along with it as the buffer is edited.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    /// Who added it, so that everything a feature added can be replaced at once.
+    pub owner: String,
+    /// The line and byte it starts at.
+    pub start: (usize, usize),
+    /// The line and byte it ends at.
+    pub end: (usize, usize),
+    pub style: Style,
+    /// Anything the owner wants to keep along with the range, such as a message.
+    pub data: String,
+}
+
+impl Annotation {
+    pub fn new<S: Into<String>>(owner: S,
+                                start: (usize, usize),
+                                end: (usize, usize),
+                                style: Style)
+                                -> Annotation {
+        Annotation {
+            owner: owner.into(),
+            start: start,
+            end: end,
+            style: style,
+            data: String::new(),
+        }
+    }
+
+    /// Whether any of it is on the given lines.
+    pub fn touches(&self, lines: &Range<usize>) -> bool {
+        self.start.0 < lines.end && self.end.0 >= lines.start
+    }
+}
+
+/// The annotations of a buffer, kept in place as its text changes.
+#[derive(Clone, Debug, Default)]
+pub struct Annotations {
+    annotations: Vec<Annotation>,
+}
+
+impl Annotations {
+    pub fn new() -> Annotations {
+        Default::default()
+    }
+
+    pub fn add(&mut self, annotation: Annotation) {
+        self.annotations.push(annotation);
+    }
+
+    /// Replaces everything the owner added with the given annotations.
+    pub fn set(&mut self, owner: &str, annotations: Vec<Annotation>) {
+        self.remove(owner);
+        self.annotations.extend(annotations);
+    }
+
+    /// Removes everything the owner added.
+    pub fn remove(&mut self, owner: &str) {
+        self.annotations.retain(|a| a.owner != owner);
+    }
+
+    /// The annotations on the given lines.
+    pub fn on_lines(&self, lines: Range<usize>) -> Vec<&Annotation> {
+        self.annotations.iter().filter(|a| a.touches(&lines)).collect()
+    }
+
+    /// Moves the annotations after a change that replaced `removed` at `position` with
+    /// `inserted`. Annotations overlapping the removed text shrink, and the ones that were
+    /// entirely in it are dropped.
+    pub fn adjust(&mut self, position: (usize, usize), removed: &str, inserted: &str) {
+        let old_end = end_of(position, removed);
+        let new_end = end_of(position, inserted);
+        let moved = |point: (usize, usize)| if point <= position {
+            point
+        } else if point < old_end {
+            position
+        } else {
+            shift_position(point, old_end, new_end)
+        };
+        self.annotations.retain(|a| {
+            a.start == a.end || a.start < position || a.end > old_end
+        });
+        for annotation in self.annotations.iter_mut() {
+            annotation.start = moved(annotation.start);
+            annotation.end = moved(annotation.end);
+        }
+    }
+}
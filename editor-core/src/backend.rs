@@ -0,0 +1,3213 @@
+use std::{char, cmp, fs, io, thread, usize};
+use std::collections::{BTreeMap, HashSet};
+use std::iter;
+use std::ops::Range;
+use std::io::{Write, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::slice::Iter;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant, SystemTime};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use annotations::{Annotation, Annotations, Style};
+use history::{Change, History};
+use snippets;
+use words::{CharClass, WordChars};
+
+pub const BACKSPACE: char = '\u{0008}';
+/// The byte order mark some files start with, kept at the start of their first line.
+const BOM: char = '\u{feff}';
+pub const DEL: char = '\u{007F}';
+pub const ALLOWED_CONTROL: [char; 4] = ['\t', '\n', BACKSPACE, DEL];
+/// The opening and closing brackets that are matched and closed automatically.
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+/// How many lines away from the cursor a matching bracket is looked for.
+const BRACKET_SEARCH_LINES: usize = 1000;
+/// Who the tab stops of the snippet being filled in belong to.
+const SNIPPET_OWNER: &'static str = "snippet";
+/// Who the cursors waiting for `for_each_cursor()` to get to them belong to, and the ones left
+/// by the runs done so far.
+const WAITING_CURSORS_OWNER: &'static str = "waiting cursors";
+const LEFT_CURSORS_OWNER: &'static str = "left cursors";
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cursor {
+    pub start_line: usize,
+    pub start_byte: usize,
+    pub start_character: usize,
+    pub end_line: usize,
+    pub end_byte: usize,
+    pub end_character: usize,
+    /// Whether the selection was extended backwards, so its start is the end that moves.
+    reversed: bool,
+    /// The display column moving up or down aims for, along with the line and byte the last
+    /// vertical move left the cursor at. It only holds while the cursor stays there, so that
+    /// passing through shorter lines does not pull it to the left.
+    goal_column: Option<(usize, (usize, usize))>,
+}
+
+impl Cursor {
+    fn atomize(&mut self) {
+        self.end_line = self.start_line;
+        self.end_byte = self.start_byte;
+        self.end_character = self.start_character;
+        self.reversed = false;
+    }
+
+    pub fn is_atomic(&self) -> bool {
+        self.start_line == self.end_line && self.start_byte == self.end_byte
+    }
+
+    /// The line of the end that moves when the selection is extended.
+    pub fn head_line(&self) -> usize {
+        if self.reversed {
+            self.start_line
+        } else {
+            self.end_line
+        }
+    }
+
+    /// The line and byte of the end that moves when the selection is extended.
+    pub fn head(&self) -> (usize, usize) {
+        if self.reversed {
+            self.start()
+        } else {
+            self.end()
+        }
+    }
+
+    fn start(&self) -> (usize, usize) {
+        (self.start_line, self.start_byte)
+    }
+
+    fn end(&self) -> (usize, usize) {
+        (self.end_line, self.end_byte)
+    }
+
+    fn new(lines: &[String], start: (usize, usize), end: (usize, usize)) -> Cursor {
+        Cursor {
+            start_line: start.0,
+            start_byte: start.1,
+            start_character: grapheme_count(&lines[start.0][..start.1]),
+            end_line: end.0,
+            end_byte: end.1,
+            end_character: grapheme_count(&lines[end.0][..end.1]),
+            reversed: false,
+            goal_column: None,
+        }
+    }
+}
+
+impl Default for Cursor {
+    fn default() -> Cursor {
+        Cursor {
+            start_line: 0,
+            start_byte: 0,
+            start_character: 0,
+            end_line: 0,
+            end_byte: 0,
+            end_character: 0,
+            reversed: false,
+            goal_column: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub line: usize,
+    pub start_byte: usize,
+    pub start_character: usize,
+    pub end_byte: usize,
+    pub end_character: usize,
+}
+
+#[derive(Debug)]
+pub struct Loader {
+    receiver: Receiver<io::Result<String>>,
+    read_bytes: usize,
+    size: usize,
+}
+
+impl Loader {
+    pub fn progress(&self) -> f64 {
+        if self.size == 0 {
+            1.0
+        } else {
+            (self.read_bytes as f64 / self.size as f64).min(1.0)
+        }
+    }
+}
+
+/// A save running in a background thread, writing a copy of the buffer taken when it started.
+#[derive(Debug)]
+pub struct Saver {
+    /// Whether the file was written, once the thread is done.
+    receiver: Receiver<io::Result<bool>>,
+    cancelled: Arc<AtomicBool>,
+    /// The version and line ending of the text being written.
+    version: usize,
+    line_ending: LineEnding,
+    deadline: Instant,
+}
+
+/// The text of a buffer as it is written to its file.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    path: PathBuf,
+    text: TextSnapshot,
+    line_ending: LineEnding,
+    /// Whether a line break is added after the last line if it has none.
+    final_newline: bool,
+    /// Whether the file starts with a byte order mark, or as the text does if not set.
+    bom: Option<bool>,
+    backup: bool,
+}
+
+impl Snapshot {
+    /// Writes the text to a temporary file next to the target and renames it over the original,
+    /// so that the file is never left half written. Returns whether it was written, which it is
+    /// not if `cancelled` tells to stop before it is done.
+    fn write<F, C>(&self, callback: F, cancelled: C) -> io::Result<bool>
+        where F: Fn(usize, usize),
+              C: Fn() -> bool
+    {
+        let file_name = self.path.file_name().unwrap().to_string_lossy().into_owned();
+        let temp_path = self.path.with_file_name(format!(".{}.tmp", file_name));
+
+        match self.write_to(&temp_path, callback, &cancelled) {
+            Ok(true) if !cancelled() => {}
+            Ok(_) => {
+                let _ = fs::remove_file(&temp_path);
+                return Ok(false);
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        }
+        if self.path.exists() {
+            try!(fs::set_permissions(&temp_path, try!(self.path.metadata()).permissions()));
+            if self.backup {
+                let _ = try!(fs::copy(&self.path, self.path.with_file_name(file_name + "~")));
+            }
+        }
+        try!(fs::rename(&temp_path, &self.path));
+
+        Ok(true)
+    }
+
+    fn write_to<F, C>(&self, path: &Path, callback: F, cancelled: &C) -> io::Result<bool>
+        where F: Fn(usize, usize),
+              C: Fn() -> bool
+    {
+        let mut f = try!(fs::File::create(path));
+        let line_ending = self.line_ending.as_str();
+        let last = self.text.lines().last();
+        let add_newline = self.final_newline &&
+                          last.map_or(false, |l| !l.is_empty() && !l.ends_with('\n'));
+        let read_bom = self.text.lines().next().map_or(false, |l| l.starts_with(BOM));
+        let bom = self.bom.unwrap_or(read_bom);
+        let bom_len = BOM.len_utf8();
+
+        let mut wrote_bytes = 0usize;
+        let total_bytes = self.text.lines().fold(0, |acc, x| {
+            acc + x.as_bytes().len() +
+            if x.ends_with('\n') {
+                line_ending.len() - 1
+            } else {
+                0
+            }
+        }) + if add_newline { line_ending.len() } else { 0 } + if bom { bom_len } else { 0 } -
+                          if read_bom { bom_len } else { 0 };
+        if bom {
+            try!(write!(f, "{}", BOM));
+            wrote_bytes += bom_len;
+        }
+        for (i, line) in self.text.lines().enumerate() {
+            if cancelled() {
+                return Ok(false);
+            }
+            let content = &line[if i == 0 && read_bom { bom_len } else { 0 }..content_len(line)];
+            try!(f.write_all(content.as_bytes()));
+            wrote_bytes += content.len();
+            if line.ends_with('\n') {
+                try!(f.write_all(line_ending.as_bytes()));
+                wrote_bytes += line_ending.len();
+            }
+            callback(wrote_bytes, total_bytes);
+        }
+        if add_newline {
+            try!(f.write_all(line_ending.as_bytes()));
+            callback(total_bytes, total_bytes);
+        }
+        try!(f.sync_all());
+
+        Ok(true)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+/// Per-buffer settings, usually filled from the configuration for the buffer's file type.
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub backup: bool,
+    /// Whether new lines start with the indentation of the previous one.
+    pub auto_indent: bool,
+    /// Whether typing an opening bracket adds the closing one, which is typed over afterwards.
+    pub auto_close_brackets: bool,
+    /// Whether the bracket matching the one next to the cursor is looked for.
+    pub match_brackets: bool,
+    /// The text added for each indentation level.
+    pub indent: String,
+    /// Whether Backspace in the spaces indenting a line removes a whole indentation level.
+    pub smart_backspace: bool,
+    /// Whether Tab inserts a tab character instead of spaces.
+    pub hard_tabs: bool,
+    /// Number of columns between tab stops.
+    pub tab_width: usize,
+    pub continue_comments: bool,
+    pub line_comments: Vec<String>,
+    /// Opening, continuation and closing markers of block comments.
+    pub block_comment: Option<(String, String, String)>,
+    /// What happens to the control characters typed or pasted into the buffer.
+    pub control_characters: ControlCharacters,
+    /// How long a save in the background may take before it is given up, in seconds.
+    pub save_timeout: u64,
+    /// Whether lines longer than the editor is wide are broken into several rows.
+    pub wrap: bool,
+    /// Whether spaces and tabs are shown, and whitespace at the end of lines highlighted.
+    pub show_whitespace: bool,
+    /// Whether the whitespace at the end of lines is removed on save.
+    pub trim_trailing_whitespace: bool,
+    /// Whether a line break is added at the end of the file on save if there is none.
+    pub final_newline: bool,
+    /// The line ending the file is converted to, instead of keeping the one it was read with.
+    pub line_ending: Option<LineEnding>,
+    /// Whether the file is saved starting with a byte order mark, or as it was read if not set.
+    pub bom: Option<bool>,
+    /// Number of columns past which lines are shown as too long, if any.
+    pub max_line_length: Option<usize>,
+    /// Which characters words are made of.
+    pub word_chars: WordChars,
+    /// Whether the file is big enough that the features going through every line are turned off.
+    pub large_file: bool,
+    /// Whether edits are refused, for buffers only there to be read, such as the welcome page.
+    pub read_only: bool,
+    /// The bodies of the snippets Tab expands, by their prefix.
+    pub snippets: BTreeMap<String, String>,
+    /// The language the words are spell checked in, or none if empty.
+    pub spell_language: String,
+    /// Whether all the words are spell checked, instead of only the ones in comments.
+    pub prose: bool,
+}
+
+impl Options {
+    /// Turns off wrapping, long line checks, word highlights and bracket matching, which are too
+    /// slow for files with millions of lines.
+    pub fn limit_for_large_file(&mut self) {
+        self.large_file = true;
+        self.wrap = false;
+        self.max_line_length = None;
+        self.match_brackets = false;
+    }
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            backup: false,
+            auto_indent: false,
+            auto_close_brackets: false,
+            match_brackets: false,
+            indent: String::from("    "),
+            smart_backspace: false,
+            hard_tabs: false,
+            tab_width: 4,
+            continue_comments: false,
+            line_comments: Vec::new(),
+            block_comment: None,
+            control_characters: ControlCharacters::Visualize,
+            save_timeout: 10,
+            wrap: false,
+            show_whitespace: false,
+            trim_trailing_whitespace: false,
+            final_newline: false,
+            line_ending: None,
+            bom: None,
+            max_line_length: None,
+            word_chars: WordChars::default(),
+            large_file: false,
+            read_only: false,
+            snippets: BTreeMap::new(),
+            spell_language: String::new(),
+            prose: false,
+        }
+    }
+}
+
+/// How control characters that cannot be part of the text, such as a stray `\r` or `\x0b` in
+/// pasted text, are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlCharacters {
+    /// They are dropped.
+    Strip,
+    /// They are replaced by their symbol from the Control Pictures block, such as `␋`.
+    Visualize,
+}
+
+impl ControlCharacters {
+    pub fn from_name(name: &str) -> Option<ControlCharacters> {
+        match name {
+            "strip" => Some(ControlCharacters::Strip),
+            "visualize" => Some(ControlCharacters::Visualize),
+            _ => None,
+        }
+    }
+
+    /// What the control character is written as, if anything.
+    fn replacement(self, c: char) -> Option<char> {
+        match self {
+            ControlCharacters::Strip => None,
+            ControlCharacters::Visualize => {
+                Some(match c as u32 {
+                    n @ 0...0x1F => char::from_u32(0x2400 + n).unwrap(),
+                    0x7F => '\u{2421}',
+                    _ => '\u{FFFD}',
+                })
+            }
+        }
+    }
+}
+
+/// The text of a buffer at one version, for background work such as searching or comparing it
+/// to be done on other threads without holding up typing.
+///
+/// The buffer is only ever edited from the thread that owns it, and shares its lines with the
+/// snapshots taken of it. The first edit after a snapshot is taken copies the lines instead of
+/// changing them in place, so a snapshot never changes and never shows an edit half made, and
+/// taking one costs nothing until then.
+#[derive(Clone, Debug)]
+pub struct TextSnapshot {
+    lines: Arc<Vec<String>>,
+    version: usize,
+}
+
+impl TextSnapshot {
+    /// The version of the buffer the snapshot was taken at, to tell whether work done on it is
+    /// still up to date.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// The lines, each with its line break but the last.
+    pub fn lines(&self) -> Iter<String> {
+        self.lines.iter()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TextBuffer {
+    path: Option<String>,
+    size: usize,
+    /// Shared with the snapshots taken since it was last edited.
+    lines: Arc<Vec<String>>,
+    saved: bool,
+    loaded: bool,
+    options: Options,
+    line_ending: LineEnding,
+    cursors: Vec<Cursor>,
+    /// Changes whenever the text does.
+    version: usize,
+    history: History,
+    annotations: Annotations,
+    /// The tab stop of the snippet being filled in, counting in the order Tab goes through them.
+    tab_stop: Option<usize>,
+    /// When the file was modified as far as the buffer knows, from when it was last read or
+    /// written, to tell when another program changes it.
+    disk_modified: Option<SystemTime>,
+    /// The rectangle the cursors were last made to select, which they still do if they were not
+    /// changed since.
+    column_rect: Option<ColumnRect>,
+}
+
+impl TextBuffer {
+    pub fn new<P: AsRef<Path>>(path: Option<P>) -> io::Result<TextBuffer> {
+        let metadata = match path.as_ref() {
+            Some(p) => Some(try!(p.as_ref().metadata())),
+            None => None,
+        };
+        let size = metadata.as_ref().map_or(0, |m| m.len() as usize);
+        let owned_path = match path.as_ref() {
+            Some(p) => Some(p.as_ref().to_string_lossy().into_owned()),
+            None => None,
+        };
+
+        let mut history = History::new();
+        if path.is_some() {
+            history.mark_saved();
+        }
+        let text_buffer = TextBuffer {
+            path: owned_path,
+            size: size,
+            lines: Arc::new(if path.is_some() {
+                Vec::new()
+            } else {
+                vec![String::new()]
+            }),
+            saved: path.is_some(),
+            loaded: false,
+            options: Options::default(),
+            line_ending: LineEnding::Lf,
+            cursors: Vec::new(),
+            version: 0,
+            history: history,
+            annotations: Annotations::new(),
+            tab_stop: None,
+            disk_modified: metadata.and_then(|m| m.modified().ok()),
+            column_rect: None,
+        };
+
+        Ok(text_buffer)
+    }
+
+    /// Creates a buffer that is not backed by a file, holding the given lines.
+    pub fn from_lines(lines: Vec<String>) -> TextBuffer {
+        let mut lines: Vec<String> = lines.into_iter().map(|line| line + "\n").collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        let mut history = History::new();
+        history.mark_saved();
+
+        TextBuffer {
+            path: None,
+            size: 0,
+            lines: Arc::new(lines),
+            saved: true,
+            loaded: true,
+            options: Options::default(),
+            line_ending: LineEnding::Lf,
+            cursors: Vec::new(),
+            version: 0,
+            history: history,
+            annotations: Annotations::new(),
+            tab_stop: None,
+            disk_modified: None,
+            column_rect: None,
+        }
+    }
+
+    pub fn load<F>(&mut self, callback: F) -> io::Result<()>
+        where F: Fn(usize, usize)
+    {
+        let f = try!(fs::File::open(self.path.as_ref().unwrap()));
+        let mut read_bytes = 0usize;
+        for line in RawLines(BufReader::new(f)) {
+            let line = try!(line);
+            read_bytes += line.as_bytes().len();
+            self.push_line(line);
+            callback(read_bytes, self.size);
+        }
+        if self.lines.is_empty() {
+            Arc::make_mut(&mut self.lines).push(String::new());
+        }
+        self.loaded = true;
+
+        Ok(())
+    }
+
+    /// Starts reading the file in a background thread. The lines it reads are moved into the
+    /// buffer by `receive_lines()`.
+    pub fn load_async(&mut self) -> io::Result<Loader> {
+        let f = try!(fs::File::open(self.path.as_ref().unwrap()));
+        let (sender, receiver) = mpsc::channel();
+        let _ = thread::spawn(move || for line in RawLines(BufReader::new(f)) {
+            let failed = line.is_err();
+            if sender.send(line).is_err() || failed {
+                break;
+            }
+        });
+
+        Ok(Loader {
+            receiver: receiver,
+            read_bytes: 0,
+            size: self.size,
+        })
+    }
+
+    /// Appends the lines the loader has read so far, returning `true` once the whole file has
+    /// been loaded.
+    pub fn receive_lines(&mut self, loader: &mut Loader) -> io::Result<bool> {
+        loop {
+            match loader.receiver.try_recv() {
+                Ok(line) => {
+                    let line = try!(line);
+                    loader.read_bytes += line.as_bytes().len();
+                    self.push_line(line);
+                }
+                Err(TryRecvError::Empty) => return Ok(false),
+                Err(TryRecvError::Disconnected) => {
+                    if self.lines.is_empty() {
+                        Arc::make_mut(&mut self.lines).push(String::new());
+                    }
+                    self.loaded = true;
+                    if let Some(line_ending) = self.options.line_ending {
+                        self.set_line_ending(line_ending);
+                    }
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Adds lines at the end of the buffer, outside of the history, for buffers filled as results
+    /// come in.
+    pub fn append_lines(&mut self, lines: Vec<String>) {
+        for line in lines {
+            self.push_line(line);
+        }
+    }
+
+    /// Adds a line read from the file, storing it with a plain `\n` and remembering whether the
+    /// file used `\r\n` line endings.
+    fn push_line(&mut self, mut line: String) {
+        if line.ends_with("\r\n") {
+            if self.lines.is_empty() {
+                self.line_ending = LineEnding::CrLf;
+            }
+            let len = line.len();
+            line.truncate(len - 2);
+        } else if line.ends_with('\n') {
+            let len = line.len();
+            line.truncate(len - 1);
+        }
+        Arc::make_mut(&mut self.lines).push(line + "\n");
+        self.version += 1;
+    }
+
+    /// A number that changes whenever the text of the buffer does, so that what was measured from
+    /// it can be thrown away.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn file_size(&self) -> usize {
+        self.size
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn get_path(&self) -> Option<&Path> {
+        match self.path.as_ref() {
+            Some(p) => Some(Path::new(p)),
+            None => None,
+        }
+    }
+
+    pub fn set_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.saved = false;
+        self.history.forget_saved();
+        self.path = Some(path.as_ref().to_string_lossy().into_owned());
+    }
+
+    /// Points the buffer at the new path of its file after the file was moved there, keeping
+    /// whether it is saved.
+    pub fn move_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.path = Some(path.as_ref().to_string_lossy().into_owned());
+        self.disk_modified = self.file_modified();
+    }
+
+    pub fn remove_path(&mut self) {
+        self.saved = false;
+        self.history.forget_saved();
+        self.path = None
+    }
+
+    pub fn get_options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Sets the options of the buffer, converting its line endings if they say so and it is
+    /// loaded.
+    pub fn set_options(&mut self, options: Options) {
+        self.options = options;
+        if let (true, Some(line_ending)) = (self.loaded, self.options.line_ending) {
+            self.set_line_ending(line_ending);
+        }
+    }
+
+    /// Saves the buffer by writing it to a temporary file next to the target and renaming it over
+    /// the original, so that the file is never left half written.
+    pub fn save<F>(&mut self, callback: F) -> io::Result<()>
+        where F: Fn(usize, usize)
+    {
+        if !self.saved {
+            if self.options.trim_trailing_whitespace {
+                self.trim_trailing_whitespace();
+            }
+            let _ = try!(self.snapshot().write(callback, || false));
+            self.saved = true;
+            self.history.mark_saved();
+            self.disk_modified = self.file_modified();
+        }
+
+        Ok(())
+    }
+
+    /// Starts saving a copy of the buffer in a background thread, so that slow file systems do
+    /// not hold up editing. Returns `None` if there is nothing to save. The save is given up if
+    /// it takes longer than the timeout in the buffer options.
+    pub fn save_async(&mut self) -> Option<Saver> {
+        if self.saved {
+            return None;
+        }
+        if self.options.trim_trailing_whitespace {
+            self.trim_trailing_whitespace();
+        }
+        let snapshot = self.snapshot();
+        let version = snapshot.text.version();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let flag = cancelled.clone();
+        let _ = thread::spawn(move || {
+            let result = snapshot.write(|_, _| {}, || flag.load(Ordering::SeqCst));
+            let _ = sender.send(result);
+        });
+
+        Some(Saver {
+            receiver: receiver,
+            cancelled: cancelled,
+            version: version,
+            line_ending: self.line_ending,
+            deadline: Instant::now() + Duration::from_secs(self.options.save_timeout),
+        })
+    }
+
+    /// Checks on a save running in the background, returning `true` once it is over. The buffer
+    /// is only marked as saved if it was not changed while the copy was being written, and a
+    /// save taking too long is cancelled, leaving the buffer modified. Once past its deadline the
+    /// save counts as over even if the thread is stuck in a write that never returns, so that
+    /// another one can be started.
+    pub fn finish_save(&mut self, saver: &Saver) -> io::Result<bool> {
+        match saver.receiver.try_recv() {
+            Ok(result) => {
+                let wrote = try!(result);
+                if wrote {
+                    self.disk_modified = self.file_modified();
+                }
+                if wrote && saver.version == self.version && saver.line_ending == self.line_ending {
+                    self.saved = true;
+                    self.history.mark_saved();
+                }
+                Ok(true)
+            }
+            Err(TryRecvError::Empty) => {
+                if Instant::now() >= saver.deadline {
+                    // The thread stops before renaming the file over the original if it ever
+                    // gets there, so the file is left as it was.
+                    saver.cancelled.store(true, Ordering::SeqCst);
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            Err(TryRecvError::Disconnected) => Ok(true),
+        }
+    }
+
+    /// Whether another program changed the file since the buffer last read or wrote it.
+    pub fn changed_on_disk(&self) -> bool {
+        self.loaded &&
+        match self.file_modified() {
+            Some(modified) => self.disk_modified != Some(modified),
+            None => false,
+        }
+    }
+
+    /// Keeps the text of the buffer over the file changed on disk, which it replaces on the next
+    /// save.
+    pub fn ignore_disk_change(&mut self) {
+        self.disk_modified = self.file_modified();
+        self.saved = false;
+        self.history.forget_saved();
+    }
+
+    fn file_modified(&self) -> Option<SystemTime> {
+        self.path.as_ref().and_then(|p| fs::metadata(p).ok()).and_then(|m| m.modified().ok())
+    }
+
+    /// The text to write, shared with the buffer until it is edited.
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            path: PathBuf::from(self.path.as_ref().unwrap()),
+            text: self.text_snapshot(),
+            line_ending: self.line_ending,
+            final_newline: self.options.final_newline,
+            bom: self.options.bom,
+            backup: self.options.backup,
+        }
+    }
+
+    /// The text as it is now, to be read from other threads while the buffer goes on being
+    /// edited.
+    pub fn text_snapshot(&self) -> TextSnapshot {
+        TextSnapshot {
+            lines: self.lines.clone(),
+            version: self.version,
+        }
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved
+    }
+
+    /// Marks the buffer as saved, for buffers whose contents are stored by other means than
+    /// `save()`.
+    pub fn mark_saved(&mut self) {
+        self.saved = true;
+        self.history.mark_saved();
+    }
+
+    pub fn get_line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Sets the line ending used when saving, dropping the carriage returns that pasted text may
+    /// have left before the line breaks.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        let mut changes = Vec::new();
+        for (i, line) in Arc::make_mut(&mut self.lines).iter_mut().enumerate() {
+            if line.ends_with("\r\n") {
+                let len = line.len();
+                line.truncate(len - 2);
+                line.push('\n');
+                self.annotations.adjust((i, len - 2), "\r", "");
+                changes.push(Change {
+                    position: (i, len - 2),
+                    removed: String::from("\r"),
+                    inserted: String::new(),
+                });
+            }
+        }
+        let stripped = !changes.is_empty();
+        if stripped {
+            let before = self.cursors.clone();
+            let cursors = self.cursors
+                .iter()
+                .map(|c| {
+                    let start = self.cursor_at(c.start_line, c.start_character);
+                    let end = self.cursor_at(c.end_line, c.end_character);
+                    Cursor::new(&self.lines, start.start(), end.start())
+                })
+                .collect();
+            self.cursors = cursors;
+            self.history.record(changes, before, self.cursors.clone());
+            self.version += 1;
+        }
+        if stripped || line_ending != self.line_ending {
+            self.saved = false;
+        }
+        if line_ending != self.line_ending {
+            self.history.forget_saved();
+        }
+        self.line_ending = line_ending;
+    }
+
+    pub fn get_cursors(&self) -> &[Cursor] {
+        &self.cursors
+    }
+
+    pub fn set_cursors(&mut self, cursors: Vec<Cursor>) {
+        // TODO check that cursors are valid with asserts
+        self.cursors = cursors;
+    }
+
+    /// Number of characters selected by all the cursors together.
+    pub fn selected_characters(&self) -> usize {
+        self.cursors.iter().fold(0, |acc, cursor| {
+            acc +
+            if cursor.start_line == cursor.end_line {
+                cursor.end_character - cursor.start_character
+            } else {
+                self.lines[cursor.start_line..cursor.end_line]
+                    .iter()
+                    .fold(cursor.end_character, |acc, l| acc + grapheme_count(l)) -
+                cursor.start_character
+            }
+        })
+    }
+
+    /// The text of every selection, one after the other on separate lines.
+    pub fn selected_text(&self) -> String {
+        self.cursors
+            .iter()
+            .filter(|c| !c.is_atomic())
+            .map(|c| text_between(&self.lines, c.start(), c.end()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn add_cursor(&mut self, cursor: Cursor) {
+        self.cursors.push(cursor);
+        self.merge_cursors();
+    }
+
+    /// Returns an atomic cursor at the given line and character, clamped to the buffer contents.
+    pub fn cursor_at(&self, line: usize, character: usize) -> Cursor {
+        let line = if line < self.lines.len() {
+            line
+        } else {
+            self.lines.len() - 1
+        };
+        let content = &self.lines[line][..content_len(&self.lines[line])];
+        let byte = grapheme_byte(content, character);
+        Cursor::new(&self.lines, (line, byte), (line, byte))
+    }
+
+    /// Returns a cursor selecting from one line and character to another, clamped to the buffer
+    /// contents.
+    pub fn cursor_between(&self, start: (usize, usize), end: (usize, usize)) -> Cursor {
+        let start = self.cursor_at(start.0, start.1).start();
+        let end = self.cursor_at(end.0, end.1).start();
+        Cursor::new(&self.lines, cmp::min(start, end), cmp::max(start, end))
+    }
+
+    /// Returns an atomic cursor at the given byte of a line, which must be in the buffer.
+    pub fn cursor_at_byte(&self, line: usize, byte: usize) -> Cursor {
+        Cursor::new(&self.lines, (line, byte), (line, byte))
+    }
+
+    /// Leaves a single cursor at the given line and display column, clamped to the buffer
+    /// contents, returning it.
+    pub fn goto(&mut self, line: usize, column: usize) -> Cursor {
+        let line = cmp::min(line, self.lines.len() - 1);
+        let byte = column_byte(&self.lines[line], column, self.options.tab_width);
+        let cursor = Cursor::new(&self.lines, (line, byte), (line, byte));
+        self.cursors = vec![cursor];
+        cursor
+    }
+
+    /// Column at which the given byte of a line is displayed.
+    pub fn display_column(&self, line: usize, byte: usize) -> usize {
+        display_column(&self.lines[line], byte, self.options.tab_width)
+    }
+
+    /// Where the whitespace at the end of the given line starts, if it has any.
+    pub fn trailing_whitespace_start(&self, line: usize) -> Option<usize> {
+        let line = &self.lines[line];
+        let content = &line[..content_len(line)];
+        let trimmed = content.trim_right().len();
+        if trimmed < content.len() {
+            Some(trimmed)
+        } else {
+            None
+        }
+    }
+
+    /// The bytes of the spaces and tabs in the given line, along with which one each is.
+    pub fn whitespace(&self, line: usize) -> Vec<(usize, char)> {
+        self.lines[line]
+            .char_indices()
+            .filter(|&(_, c)| c == ' ' || c == '\t')
+            .collect()
+    }
+
+    /// Byte of the line at which it goes past the maximum line length, if it is too long.
+    pub fn overflow_start(&self, line: usize) -> Option<usize> {
+        let max = match self.options.max_line_length {
+            Some(max) => max,
+            None => return None,
+        };
+        let line = &self.lines[line];
+        let mut column = 0;
+        for (byte, grapheme) in line[..content_len(line)].grapheme_indices(true) {
+            if column >= max {
+                return Some(byte);
+            }
+            column = next_column(column, grapheme, self.options.tab_width);
+        }
+        None
+    }
+
+    /// The lines longer than the maximum line length, along with how many columns they take.
+    pub fn long_lines(&self) -> Vec<(usize, usize)> {
+        (0..self.lines.len())
+            .filter(|&i| self.overflow_start(i).is_some())
+            .map(|i| (i, self.display_column(i, content_len(&self.lines[i]))))
+            .collect()
+    }
+
+    pub fn add_cursor_above(&mut self) {
+        if let Some(&first) = self.cursors.first() {
+            if first.start_line != 0 {
+                let mut cursor = first;
+                cursor.atomize();
+                move_up(&mut cursor, &self.lines, self.options.tab_width);
+                self.add_cursor(cursor);
+            }
+        }
+    }
+
+    pub fn add_cursor_below(&mut self) {
+        if let Some(&last) = self.cursors.last() {
+            if last.end_line != self.lines.len() - 1 {
+                let mut cursor = last;
+                cursor.start_line = cursor.end_line;
+                cursor.start_byte = cursor.end_byte;
+                cursor.start_character = cursor.end_character;
+                move_down(&mut cursor, &self.lines, self.options.tab_width);
+                self.add_cursor(cursor);
+            }
+        }
+    }
+
+    /// Adds a cursor on every line of the rectangle, selecting the text between its columns, or
+    /// at the end of the lines too short to reach them, so that typing edits the block.
+    pub fn add_column_cursors(&mut self, rect: ColumnRect) {
+        let cursors = column_cursors(&self.lines, rect, self.options.tab_width);
+        self.cursors.extend(cursors);
+        self.merge_cursors();
+        self.column_rect = Some(rect);
+    }
+
+    /// Moves the corner of the rectangle selected with `add_column_cursors()` that is not the
+    /// one it was started from by a line or a column, leaving a cursor on every line of it.
+    /// Cursors that were changed since start a new rectangle from the head of the last one.
+    pub fn extend_column_cursors(&mut self, movement: Move) {
+        let rect = match self.column_rect {
+            // The cursors still select the rectangle if they are the ones made for it alone.
+            Some(rect) if self.selects_column_rect(rect) => rect,
+            _ => {
+                let head = match self.cursors.last() {
+                    Some(cursor) => cursor.head(),
+                    None => return,
+                };
+                let position = (head.0, self.display_column(head.0, head.1));
+                ColumnRect {
+                    anchor: position,
+                    head: position,
+                }
+            }
+        };
+        let (line, column) = rect.head;
+        let head = match movement {
+            Move::Up => (line.saturating_sub(1), column),
+            Move::Down => (cmp::min(line + 1, self.lines.len() - 1), column),
+            Move::Left => (line, column.saturating_sub(1)),
+            Move::Right => (line, column + 1),
+            _ => return,
+        };
+        self.cursors.clear();
+        self.add_column_cursors(ColumnRect {
+            anchor: rect.anchor,
+            head: head,
+        });
+    }
+
+    fn selects_column_rect(&self, rect: ColumnRect) -> bool {
+        let cursors = column_cursors(&self.lines, rect, self.options.tab_width);
+        cursors.len() == self.cursors.len() &&
+        cursors.iter().zip(&self.cursors).all(|(a, b)| a.start() == b.start() && a.end() == b.end())
+    }
+
+    /// Selects the word under the last cursor if it has no selection, or adds a cursor selecting
+    /// the next occurrence of its selected text otherwise, wrapping around the end of the buffer.
+    pub fn add_cursor_at_next_occurrence(&mut self) {
+        let last = match self.cursors.last() {
+            Some(&c) => c,
+            None => return,
+        };
+        if last.is_atomic() {
+            let (start, end) = self.options
+                .word_chars
+                .range(&self.lines[last.start_line], last.start_byte);
+            if start != end {
+                let cursor = Cursor::new(&self.lines,
+                                         (last.start_line, start),
+                                         (last.start_line, end));
+                let _ = self.cursors.pop();
+                self.add_cursor(cursor);
+            }
+            return;
+        }
+
+        let text = self.lines.concat();
+        let needle = &text[offset_of(&self.lines, last.start())..offset_of(&self.lines,
+                                                                            last.end())];
+        let after = offset_of(&self.lines, last.end());
+        let taken: Vec<usize> = self.cursors
+            .iter()
+            .map(|c| offset_of(&self.lines, c.start()))
+            .collect();
+        let occurrences: Vec<usize> = text.match_indices(needle)
+            .map(|(i, _)| i)
+            .filter(|i| !taken.contains(i))
+            .collect();
+        let next = occurrences.iter()
+            .find(|&&i| i >= after)
+            .or_else(|| occurrences.first());
+
+        if let Some(&start) = next {
+            let cursor = Cursor::new(&self.lines,
+                                     position_of(&self.lines, start),
+                                     position_of(&self.lines, start + needle.len()));
+            self.add_cursor(cursor);
+        }
+    }
+
+    /// Drops every cursor but the first one, or removes its selection if it is the only one.
+    pub fn collapse_cursors(&mut self) {
+        if self.cursors.len() > 1 {
+            self.cursors.truncate(1);
+        } else if let Some(cursor) = self.cursors.first_mut() {
+            cursor.atomize();
+        }
+    }
+
+    /// Runs `run` with each cursor alone, from the first one, and keeps every cursor it leaves.
+    /// The cursors are kept in place through the edits made at the others, and the ones whose
+    /// selected text another run removed are skipped.
+    pub fn for_each_cursor<F: FnMut(&mut TextBuffer)>(&mut self, mut run: F) {
+        self.merge_cursors();
+        let waiting = self.cursors
+            .iter()
+            .enumerate()
+            .map(|(i, cursor)| {
+                let mut waiting = Annotation::new(WAITING_CURSORS_OWNER,
+                                                  cursor.start(),
+                                                  cursor.end(),
+                                                  Style::Hidden);
+                waiting.data = i.to_string();
+                waiting
+            })
+            .collect();
+        self.annotations.set(WAITING_CURSORS_OWNER, waiting);
+
+        for i in 0..self.cursors.len() {
+            let number = i.to_string();
+            let cursor = self.annotations
+                .on_lines(0..self.lines.len())
+                .into_iter()
+                .find(|a| a.owner == WAITING_CURSORS_OWNER && a.data == number)
+                .map(|a| Cursor::new(&self.lines, a.start, a.end));
+            if let Some(cursor) = cursor {
+                self.cursors = vec![cursor];
+                run(self);
+                for cursor in &self.cursors {
+                    self.annotations.add(Annotation::new(LEFT_CURSORS_OWNER,
+                                                         cursor.start(),
+                                                         cursor.end(),
+                                                         Style::Hidden));
+                }
+            }
+        }
+
+        let left: Vec<Cursor> = self.annotations
+            .on_lines(0..self.lines.len())
+            .into_iter()
+            .filter(|a| a.owner == LEFT_CURSORS_OWNER)
+            .map(|a| Cursor::new(&self.lines, a.start, a.end))
+            .collect();
+        self.annotations.remove(WAITING_CURSORS_OWNER);
+        self.annotations.remove(LEFT_CURSORS_OWNER);
+        if !left.is_empty() {
+            self.cursors = left;
+            self.merge_cursors();
+        }
+    }
+
+    /// Removes the whitespace at the end of every line, as a single undo step. The whitespace
+    /// before a cursor is kept, so that saving while typing does not take the space just typed.
+    pub fn trim_trailing_whitespace(&mut self) {
+        let mut changes = Vec::new();
+        for i in 0..self.lines.len() {
+            let len = content_len(&self.lines[i]);
+            let mut keep = self.lines[i][..len].trim_right().len();
+            for cursor in &self.cursors {
+                for &(line, byte) in &[cursor.start(), cursor.end()] {
+                    if line == i {
+                        keep = cmp::max(keep, byte);
+                    }
+                }
+            }
+            if keep >= len {
+                continue;
+            }
+            let removed: String = Arc::make_mut(&mut self.lines)[i].drain(keep..len).collect();
+            self.annotations.adjust((i, keep), &removed, "");
+            changes.push(Change {
+                position: (i, keep),
+                removed: removed,
+                inserted: String::new(),
+            });
+        }
+        if changes.is_empty() {
+            return;
+        }
+        self.saved = false;
+        self.version += 1;
+        let cursors = self.cursors.clone();
+        self.history.record(changes, cursors.clone(), cursors);
+    }
+
+    /// The given cursor with the word it is on selected, or unchanged if it is not on a word.
+    pub fn word_at(&self, cursor: Cursor) -> Cursor {
+        let line = cursor.start_line;
+        let (start, end) = self.options.word_chars.range(&self.lines[line], cursor.start_byte);
+        if start == end {
+            cursor
+        } else {
+            Cursor::new(&self.lines, (line, start), (line, end))
+        }
+    }
+
+    /// Selects the whole buffer with a single cursor.
+    pub fn select_all(&mut self) {
+        let last = self.lines.len() - 1;
+        let end = (last, content_len(&self.lines[last]));
+        self.cursors = vec![Cursor::new(&self.lines, (0, 0), end)];
+    }
+
+    /// Selects the lines with a cursor, along with their line breaks. Selections of whole lines
+    /// take the next line too, so that doing it again extends them down.
+    pub fn select_lines(&mut self) {
+        let cursors = self.cursors
+            .iter()
+            .map(|cursor| {
+                // A selection ending at the start of a line leaves that line out, unless it
+                // already holds whole lines.
+                let end = if cursor.end_byte == 0 && cursor.end_line > cursor.start_line &&
+                             cursor.start_byte != 0 {
+                    (cursor.end_line, 0)
+                } else {
+                    line_after(&self.lines, cursor.end_line)
+                };
+                Cursor::new(&self.lines, (cursor.start_line, 0), end)
+            })
+            .collect();
+        self.cursors = cursors;
+        self.merge_cursors();
+    }
+
+    /// Grows every selection to the next larger part of the text around it: the word, the
+    /// lines, the paragraph and then the whole buffer.
+    pub fn expand_selection(&mut self) {
+        let cursors = self.cursors.iter().map(|cursor| self.expanded(cursor)).collect();
+        self.cursors = cursors;
+        self.merge_cursors();
+    }
+
+    /// The smallest of the word, the lines, the paragraph and the buffer that holds more than
+    /// the cursor selects.
+    fn expanded(&self, cursor: &Cursor) -> Cursor {
+        let lines = &self.lines;
+        let last = lines.len() - 1;
+        let (start, end) = (cursor.start(), cursor.end());
+        let mut candidates = Vec::new();
+        if cursor.start_line == cursor.end_line {
+            let (word_start, word_end) = self.options
+                .word_chars
+                .range(&lines[cursor.start_line], cursor.start_byte);
+            candidates.push(((cursor.start_line, word_start), (cursor.start_line, word_end)));
+        }
+        candidates.push(((cursor.start_line, 0), line_after(lines, cursor.end_line)));
+        let mut first = cursor.start_line;
+        while first != 0 && !is_blank(&lines[first - 1]) {
+            first -= 1;
+        }
+        let mut end_line = cursor.end_line;
+        while end_line != last && !is_blank(&lines[end_line + 1]) {
+            end_line += 1;
+        }
+        candidates.push(((first, 0), line_after(lines, end_line)));
+        candidates.push(((0, 0), (last, content_len(&lines[last]))));
+
+        match candidates.into_iter()
+            .find(|&(from, to)| from <= start && end <= to && (from, to) != (start, end)) {
+            Some((from, to)) => Cursor::new(lines, from, to),
+            None => *cursor,
+        }
+    }
+
+    /// Sorts the cursors and merges the ones that overlap.
+    fn merge_cursors(&mut self) {
+        self.cursors.sort_by(|a, b| a.start().cmp(&b.start()));
+        let mut merged: Vec<Cursor> = Vec::with_capacity(self.cursors.len());
+        for cursor in self.cursors.drain(..) {
+            if let Some(last) = merged.last_mut() {
+                if cursor.start() == last.start() || cursor.start() < last.end() {
+                    if cursor.end() > last.end() {
+                        last.end_line = cursor.end_line;
+                        last.end_byte = cursor.end_byte;
+                        last.end_character = cursor.end_character;
+                    }
+                    continue;
+                }
+            }
+            merged.push(cursor);
+        }
+        self.cursors = merged;
+    }
+
+    pub fn move_cursors(&mut self, movement: Move) {
+        for cursor in self.cursors.iter_mut() {
+            move_cursor(cursor, movement, &self.lines, &self.options);
+        }
+        self.merge_cursors();
+    }
+
+    /// Moves the active end of every cursor, extending or shrinking its selection.
+    pub fn extend_cursors(&mut self, movement: Move) {
+        for cursor in self.cursors.iter_mut() {
+            let head = cursor.head();
+            let mut target = Cursor::new(&self.lines, head, head);
+            target.goal_column = cursor.goal_column;
+            move_cursor(&mut target, movement, &self.lines, &self.options);
+            extend_to(cursor, target.start(), &self.lines);
+            cursor.goal_column = target.goal_column;
+        }
+        self.merge_cursors();
+    }
+
+    /// Moves every cursor, or with `extend` the active end of every selection, to the line and
+    /// character given for it, for motions that depend on how the text is laid out, such as
+    /// moving between wrapped rows.
+    pub fn move_cursors_to(&mut self, targets: &[(usize, usize)], extend: bool) {
+        for (i, &(line, character)) in targets.iter().enumerate() {
+            let target = self.cursor_at(line, character).start();
+            if extend {
+                extend_to(&mut self.cursors[i], target, &self.lines);
+            } else {
+                self.cursors[i] = Cursor::new(&self.lines, target, target);
+            }
+        }
+        self.merge_cursors();
+    }
+
+    pub fn delete_word_backward(&mut self) {
+        self.delete_backward_to(word_left);
+    }
+
+    pub fn delete_word_forward(&mut self) {
+        self.delete_forward_to(word_right);
+    }
+
+    pub fn delete_subword_backward(&mut self) {
+        self.delete_backward_to(subword_left);
+    }
+
+    pub fn delete_subword_forward(&mut self) {
+        self.delete_forward_to(subword_right);
+    }
+
+    /// Deletes the selections, or the text between each cursor and where `movement` takes it.
+    fn delete_backward_to(&mut self, movement: fn(&mut Cursor, &[String], &WordChars)) {
+        let words = self.options.word_chars.clone();
+        self.edit("", |cursor, lines| if cursor.is_atomic() {
+            let mut target = *cursor;
+            movement(&mut target, lines, &words);
+            (target.start(), cursor.start())
+        } else {
+            (cursor.start(), cursor.end())
+        });
+    }
+
+    fn delete_forward_to(&mut self, movement: fn(&mut Cursor, &[String], &WordChars)) {
+        let words = self.options.word_chars.clone();
+        self.edit("", |cursor, lines| if cursor.is_atomic() {
+            let mut target = *cursor;
+            movement(&mut target, lines, &words);
+            (cursor.start(), target.start())
+        } else {
+            (cursor.start(), cursor.end())
+        });
+    }
+
+    /// The index of the cursor whose selection contains the given line and byte, if any.
+    pub fn selection_at(&self, position: (usize, usize)) -> Option<usize> {
+        self.cursors
+            .iter()
+            .position(|c| !c.is_atomic() && c.start() <= position && position <= c.end())
+    }
+
+    /// Moves the text selected by the cursor at `index` to `target`, or copies it there, leaving
+    /// it selected at its new place as the only cursor. Returns `false` without changing anything
+    /// if the target is in the selection itself.
+    pub fn drop_selection(&mut self, index: usize, target: (usize, usize), copy: bool) -> bool {
+        let (start, end) = (self.cursors[index].start(), self.cursors[index].end());
+        if start == end || (start <= target && target <= end) {
+            return false;
+        }
+        let before = self.cursors.clone();
+        let text = text_between(&self.lines, start, end);
+        let mut changes = Vec::new();
+
+        // The text is inserted before the selection is removed, so that the target stays valid.
+        let inserted_end = insert_text(Arc::make_mut(&mut self.lines), target.0, target.1, &text);
+        self.annotations.adjust(target, "", &text);
+        changes.push(Change {
+            position: target,
+            removed: String::new(),
+            inserted: text.clone(),
+        });
+        let mut selection = (target, inserted_end);
+        if !copy {
+            let (start, end) = if target < start {
+                (shift_position(start, target, inserted_end),
+                 shift_position(end, target, inserted_end))
+            } else {
+                selection = (shift_position(target, end, start),
+                             shift_position(inserted_end, end, start));
+                (start, end)
+            };
+            remove_range(Arc::make_mut(&mut self.lines), start.0, start.1, end.0, end.1);
+            self.annotations.adjust(start, &text, "");
+            changes.push(Change {
+                position: start,
+                removed: text,
+                inserted: String::new(),
+            });
+        }
+
+        self.cursors = vec![Cursor::new(&self.lines, selection.0, selection.1)];
+        self.history.record(changes, before, self.cursors.clone());
+        self.saved = false;
+        self.version += 1;
+        true
+    }
+
+    /// Copies the lines with a cursor right above themselves, leaving the cursors on the lower
+    /// copy, as a single undo step.
+    pub fn duplicate_lines(&mut self) {
+        self.edit_line_blocks(|lines, block| {
+            let copy: Vec<String> = lines[block.clone()]
+                .iter()
+                .map(|line| String::from(&line[..content_len(line)]))
+                .collect();
+            let mut new = copy.clone();
+            new.extend(copy);
+            (block.clone(), new, block.len() as isize)
+        });
+    }
+
+    /// Removes the lines with a cursor, as a single undo step.
+    pub fn delete_lines(&mut self) {
+        self.edit_line_blocks(|_, block| (block, Vec::new(), 0));
+    }
+
+    /// Swaps the lines with a cursor with the line above them, as a single undo step. Nothing
+    /// moves if any of them is at the start of the buffer.
+    pub fn move_lines_up(&mut self) {
+        if self.cursors.first().map_or(true, |c| c.start_line == 0) {
+            return;
+        }
+        self.edit_line_blocks(|lines, block| {
+            let mut new: Vec<String> = lines[block.clone()]
+                .iter()
+                .map(|line| String::from(&line[..content_len(line)]))
+                .collect();
+            let above = &lines[block.start - 1];
+            new.push(String::from(&above[..content_len(above)]));
+            (block.start - 1..block.end, new, -1)
+        });
+    }
+
+    /// Swaps the lines with a cursor with the line below them, as a single undo step. Nothing
+    /// moves if any of them is at the end of the buffer.
+    pub fn move_lines_down(&mut self) {
+        let count = self.lines.len();
+        if self.line_blocks().last().map_or(true, |block| block.end >= count) {
+            return;
+        }
+        self.edit_line_blocks(|lines, block| {
+            let below = &lines[block.end];
+            let mut new = vec![String::from(&below[..content_len(below)])];
+            new.extend(lines[block.clone()]
+                .iter()
+                .map(|line| String::from(&line[..content_len(line)])));
+            (block.start..block.end + 1, new, 1)
+        });
+    }
+
+    /// Comments out the lines with a cursor, or uncomments them if they all are, as a single undo
+    /// step. The shortest line comment leader of the file type is used, and block comments
+    /// around each block of lines when it has none.
+    pub fn toggle_comment(&mut self) {
+        self.merge_cursors();
+        let before = self.cursors.clone();
+        let mut positions: Vec<_> = self.cursors.iter().map(|c| (c.start(), c.end())).collect();
+        let mut changes = Vec::new();
+        for block in self.line_blocks().into_iter().rev() {
+            let mut edits = comment_edits(&self.lines[block.clone()], &self.options);
+            if edits.is_empty() {
+                continue;
+            }
+            // Applied from the end of each line, so that the positions of earlier edits hold.
+            edits.sort_by(|a, b| (b.line, b.at).cmp(&(a.line, a.at)));
+            let mut new: Vec<String> = self.lines[block.clone()]
+                .iter()
+                .map(|line| String::from(&line[..content_len(line)]))
+                .collect();
+            for edit in &edits {
+                let line = &mut new[edit.line];
+                *line = format!("{}{}{}",
+                                &line[..edit.at],
+                                edit.inserted,
+                                &line[edit.at + edit.removed..]);
+            }
+            changes.push(self.replace_lines(block.clone(), &new));
+
+            for &mut (ref mut start, ref mut end) in positions.iter_mut() {
+                for &mut (line, ref mut byte) in vec![start, end] {
+                    for edit in edits.iter().filter(|e| block.start + e.line == line) {
+                        *byte = edit.shift(*byte);
+                    }
+                }
+            }
+        }
+        if changes.is_empty() {
+            return;
+        }
+        let lines = &self.lines;
+        self.cursors = before.iter()
+            .zip(positions)
+            .map(|(cursor, (start, end))| {
+                let mut moved = Cursor::new(lines, start, end);
+                moved.reversed = cursor.reversed;
+                moved
+            })
+            .collect();
+        self.saved = false;
+        self.version += 1;
+        self.history.record(changes, before, self.cursors.clone());
+    }
+
+    /// Changes the case of the text selected by every cursor, or of the word a cursor without
+    /// a selection is on, or sorts, reverses, removes the repeated ones of or joins the lines
+    /// with a cursor, as a single undo step.
+    pub fn transform(&mut self, transform: Transform) {
+        self.merge_cursors();
+        let blocks = self.line_blocks();
+        match transform {
+            Transform::Uppercase | Transform::Lowercase | Transform::TitleCase => {
+                let words = self.options.word_chars.clone();
+                self.replace_selections(|cursor, lines| {
+                    let (from, to) = if cursor.is_atomic() {
+                        let line = cursor.start_line;
+                        let (start, end) = words.range(&lines[line], cursor.start_byte);
+                        ((line, start), (line, end))
+                    } else {
+                        (cursor.start(), cursor.end())
+                    };
+                    (from, to, change_case(&text_between(lines, from, to), transform))
+                })
+            }
+            Transform::SortLines => {
+                self.transform_line_blocks(blocks, |mut lines| {
+                    lines.sort();
+                    lines
+                })
+            }
+            Transform::ReverseLines => {
+                self.transform_line_blocks(blocks, |mut lines| {
+                    lines.reverse();
+                    lines
+                })
+            }
+            Transform::UniqueLines => {
+                self.transform_line_blocks(blocks, |lines| {
+                    let mut seen = HashSet::new();
+                    lines.into_iter().filter(|line| seen.insert(line.clone())).collect()
+                })
+            }
+            Transform::JoinLines => {
+                // A single line is joined with the one below it.
+                let count = self.lines.len();
+                let blocks = blocks.into_iter()
+                    .map(|block| if block.len() == 1 && block.end < count {
+                        block.start..block.end + 1
+                    } else {
+                        block
+                    })
+                    .collect();
+                self.transform_line_blocks(blocks, |lines| vec![join_lines(&lines)])
+            }
+        }
+    }
+
+    /// Replaces, for every cursor, the range returned by `replace` with the text returned along
+    /// with it, as a single undo step, leaving the new text selected.
+    fn replace_selections<F>(&mut self, replace: F)
+        where F: Fn(&Cursor, &[String]) -> ((usize, usize), (usize, usize), String)
+    {
+        self.merge_cursors();
+        let before = self.cursors.clone();
+        let mut changes = Vec::new();
+        let mut swallowed = vec![false; self.cursors.len()];
+        for i in 0..self.cursors.len() {
+            if swallowed[i] {
+                continue;
+            }
+            let (from, to, text) = replace(&self.cursors[i], &self.lines);
+            let removed = text_between(&self.lines, from, to);
+            if removed == text {
+                continue;
+            }
+            remove_range(Arc::make_mut(&mut self.lines), from.0, from.1, to.0, to.1);
+            let position = insert_text(Arc::make_mut(&mut self.lines), from.0, from.1, &text);
+            self.annotations.adjust(from, &removed, &text);
+            changes.push(Change {
+                position: from,
+                removed: removed,
+                inserted: text,
+            });
+
+            let reversed = self.cursors[i].reversed;
+            self.cursors[i] = Cursor::new(&self.lines, from, position);
+            self.cursors[i].reversed = reversed;
+            for j in self.move_other_cursors(i, from, to, position) {
+                swallowed[j] = true;
+            }
+        }
+        if changes.is_empty() {
+            return;
+        }
+        self.merge_cursors();
+        self.saved = false;
+        self.version += 1;
+        self.history.record(changes, before, self.cursors.clone());
+    }
+
+    /// Replaces the lines of every block with what `transform` makes of them, given without
+    /// their line breaks, as a single undo step. The blocks, which must not overlap, are left
+    /// selected.
+    fn transform_line_blocks<F>(&mut self, blocks: Vec<Range<usize>>, transform: F)
+        where F: Fn(Vec<String>) -> Vec<String>
+    {
+        let before = self.cursors.clone();
+        let mut changes = Vec::new();
+        let mut replaced = Vec::new();
+        for block in blocks.into_iter().rev() {
+            let old: Vec<String> = self.lines[block.clone()]
+                .iter()
+                .map(|line| String::from(&line[..content_len(line)]))
+                .collect();
+            let new = transform(old.clone());
+            if new != old {
+                changes.push(self.replace_lines(block.clone(), &new));
+            }
+            replaced.push((block, new.len()));
+        }
+        if changes.is_empty() {
+            return;
+        }
+
+        // The blocks were replaced from the last one, so the earlier ones moved the later ones.
+        let mut shift = 0isize;
+        let mut cursors = Vec::new();
+        for (block, count) in replaced.into_iter().rev() {
+            let start = (block.start as isize + shift) as usize;
+            let last = start + cmp::max(count, 1) - 1;
+            cursors.push(Cursor::new(&self.lines,
+                                     (start, 0),
+                                     (last, content_len(&self.lines[last]))));
+            shift += count as isize - block.len() as isize;
+        }
+        self.cursors = cursors;
+        self.merge_cursors();
+        self.saved = false;
+        self.version += 1;
+        self.history.record(changes, before, self.cursors.clone());
+    }
+
+    /// The ranges of lines with a cursor on them, joined where they touch. Selections ending at
+    /// the start of a line leave that line out.
+    fn line_blocks(&self) -> Vec<Range<usize>> {
+        let mut blocks: Vec<Range<usize>> = Vec::new();
+        for cursor in &self.cursors {
+            let end = if cursor.end_line > cursor.start_line && cursor.end_byte == 0 {
+                cursor.end_line
+            } else {
+                cursor.end_line + 1
+            };
+            match blocks.last_mut() {
+                Some(last) if cursor.start_line <= last.end => last.end = cmp::max(last.end, end),
+                _ => blocks.push(cursor.start_line..end),
+            }
+        }
+        blocks
+    }
+
+    /// Replaces the lines around every block of lines with a cursor, from the last block to the
+    /// first, as a single undo step. `edit` returns the lines to replace, the new lines without
+    /// their line breaks and how many lines the cursors in the block move by. Cursors in blocks
+    /// that are removed are left at the start of where they were.
+    fn edit_line_blocks<F>(&mut self, edit: F)
+        where F: Fn(&[String], Range<usize>) -> (Range<usize>, Vec<String>, isize)
+    {
+        self.merge_cursors();
+        let before = self.cursors.clone();
+        let mut changes = Vec::new();
+        for block in self.line_blocks().into_iter().rev() {
+            let (replaced, new, shift) = edit(&self.lines, block.clone());
+            let added = new.len() as isize - replaced.len() as isize;
+            let removed_block = new.is_empty();
+            changes.push(self.replace_lines(replaced, &new));
+
+            let last = self.lines.len() - 1;
+            let lines = &self.lines;
+            for cursor in self.cursors.iter_mut() {
+                let (start, end) = if cursor.start_line >= block.end {
+                    (shift_line(cursor.start(), added), shift_line(cursor.end(), added))
+                } else if cursor.start_line < block.start {
+                    continue;
+                } else if removed_block {
+                    let line = cmp::min(block.start, last);
+                    ((line, 0), (line, 0))
+                } else {
+                    (shift_line(cursor.start(), shift), shift_line(cursor.end(), shift))
+                };
+                // A selection ending at the start of the line after the block ends past the
+                // last line once the block moves to the end.
+                let end = if end.0 > last {
+                    (last, content_len(&lines[last]))
+                } else {
+                    end
+                };
+                *cursor = Cursor::new(lines, start, end);
+            }
+        }
+        if changes.is_empty() {
+            return;
+        }
+        self.merge_cursors();
+        self.saved = false;
+        self.version += 1;
+        self.history.record(changes, before, self.cursors.clone());
+    }
+
+    /// Replaces whole lines with the given ones, which are given without their line breaks.
+    fn replace_lines(&mut self, lines: Range<usize>, new: &[String]) -> Change {
+        let from = (lines.start, 0);
+        let to = if lines.end < self.lines.len() {
+            (lines.end, 0)
+        } else {
+            (lines.end - 1, self.lines[lines.end - 1].len())
+        };
+        let removed = text_between(&self.lines, from, to);
+        let mut text = new.join("\n");
+        if removed.ends_with('\n') && !new.is_empty() {
+            text.push('\n');
+        }
+        remove_range(Arc::make_mut(&mut self.lines), from.0, from.1, to.0, to.1);
+        let _ = insert_text(Arc::make_mut(&mut self.lines), from.0, from.1, &text);
+        self.annotations.adjust(from, &removed, &text);
+        Change {
+            position: from,
+            removed: removed,
+            inserted: text,
+        }
+    }
+
+    /// Replaces the text of a line, keeping its line break. Cursors on the line are moved to its
+    /// start.
+    pub fn replace_line(&mut self, line: usize, text: &str) {
+        let before = self.cursors.clone();
+        let end = content_len(&self.lines[line]);
+        let removed = self.lines[line][..end].to_owned();
+        let rest = self.lines[line][end..].to_owned();
+        Arc::make_mut(&mut self.lines)[line] = String::from(text) + &rest;
+        for cursor in self.cursors.iter_mut() {
+            if cursor.start_line == line || cursor.end_line == line {
+                *cursor = Cursor::new(&self.lines, (line, 0), (line, 0));
+            }
+        }
+        self.annotations.adjust((line, 0), &removed, text);
+        let change = Change {
+            position: (line, 0),
+            removed: removed,
+            inserted: String::from(text),
+        };
+        self.history.record(vec![change], before, self.cursors.clone());
+        self.saved = false;
+        self.version += 1;
+    }
+
+    /// Finds every whole-word occurrence of the word under the first cursor within the given
+    /// lines.
+    pub fn word_highlights(&self, lines: Range<usize>) -> Vec<Span> {
+        let mut highlights = Vec::new();
+        if self.options.large_file {
+            return highlights;
+        }
+        let cursor = match self.cursors.first() {
+            Some(c) if c.is_atomic() => c,
+            _ => return highlights,
+        };
+        let words = &self.options.word_chars;
+        let (start, end) = words.range(&self.lines[cursor.start_line], cursor.start_byte);
+        if start == end {
+            return highlights;
+        }
+        let word = &self.lines[cursor.start_line][start..end];
+
+        let last = cmp::min(lines.end, self.lines.len());
+        for i in cmp::min(lines.start, last)..last {
+            let line = &self.lines[i];
+            for (byte, _) in line.match_indices(word) {
+                let end_byte = byte + word.len();
+                let before = line[..byte].graphemes(true).next_back();
+                let after = line[end_byte..].graphemes(true).next();
+                if before.map_or(false, |g| words.class_of(g) == CharClass::Word) ||
+                   after.map_or(false, |g| words.class_of(g) == CharClass::Word) {
+                    continue;
+                }
+                let start_character = grapheme_count(&line[..byte]);
+                highlights.push(Span {
+                    line: i,
+                    start_byte: byte,
+                    start_character: start_character,
+                    end_byte: end_byte,
+                    end_character: start_character + grapheme_count(word),
+                });
+            }
+        }
+
+        highlights
+    }
+
+    /// The bracket next to the first cursor, the one after it before the one before it, along
+    /// with the bracket matching it, if there is one close enough.
+    pub fn matching_brackets(&self) -> Vec<Span> {
+        if !self.options.match_brackets {
+            return Vec::new();
+        }
+        let cursor = match self.cursors.first() {
+            Some(c) if c.is_atomic() => c,
+            _ => return Vec::new(),
+        };
+        let line = &self.lines[cursor.start_line];
+        let after = line[cursor.start_byte..].chars().next().map(|c| (cursor.start_byte, c));
+        let before = line[..cursor.start_byte].char_indices().next_back();
+        let is_bracket = |&(_, c): &(usize, char)| {
+            BRACKETS.iter().any(|&(open, close)| c == open || c == close)
+        };
+        let (byte, bracket) = match after.into_iter().chain(before).find(is_bracket) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+
+        let span = |line: usize, byte: usize, c: char| {
+            let start_character = grapheme_count(&self.lines[line][..byte]);
+            Span {
+                line: line,
+                start_byte: byte,
+                start_character: start_character,
+                end_byte: byte + c.len_utf8(),
+                end_character: start_character + 1,
+            }
+        };
+        match find_matching_bracket(&self.lines, (cursor.start_line, byte), bracket) {
+            Some((line, other, c)) => {
+                vec![span(cursor.start_line, byte, bracket), span(line, other, c)]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub fn lines(&self) -> Iter<String> {
+        self.lines.iter()
+    }
+
+    /// Types a character at every cursor. Backspace and delete remove text, and the control
+    /// characters that cannot be typed are handled as the buffer options say.
+    pub fn write_character(&mut self, c: char) {
+        let c = if c.is_control() && !ALLOWED_CONTROL.contains(&c) {
+            match self.options.control_characters.replacement(c) {
+                Some(replacement) => replacement,
+                None => return,
+            }
+        } else {
+            c
+        };
+
+        match c {
+            BACKSPACE => {
+                let options = self.options.clone();
+                self.edit("", |cursor, lines| if cursor.is_atomic() {
+                    let (line, byte) = cursor.start();
+                    match indent_level_start(&lines[line][..byte], &options) {
+                        Some(start) if options.smart_backspace => ((line, start), (line, byte)),
+                        _ => (previous_position(lines, cursor.start()), cursor.start()),
+                    }
+                } else {
+                    (cursor.start(), cursor.end())
+                })
+            }
+            DEL => {
+                self.edit("", |cursor, lines| if cursor.is_atomic() {
+                    (cursor.start(), next_position(lines, cursor.start()))
+                } else {
+                    (cursor.start(), cursor.end())
+                })
+            }
+            '\n' => {
+                let options = self.options.clone();
+                self.edit_each(|cursor, lines| {
+                    let mut text = String::from("\n");
+                    let before = &lines[cursor.start_line][..cursor.start_byte];
+                    let leader = if options.continue_comments {
+                        comment_continuation(before, &options)
+                    } else {
+                        None
+                    };
+                    match leader {
+                        Some(leader) => text.push_str(&leader),
+                        None if options.auto_indent => {
+                            text.push_str(&auto_indentation(before, &options))
+                        }
+                        None => {}
+                    }
+                    (cursor.start(), cursor.end(), text)
+                })
+            }
+            c if self.options.auto_close_brackets &&
+                 BRACKETS.iter().any(|&(open, close)| c == open || c == close) => {
+                self.edit_each_leaving(|cursor, lines| {
+                    let next = lines[cursor.start_line][cursor.start_byte..].chars().next();
+                    let closing = BRACKETS.iter().find(|&&(open, _)| open == c);
+                    match closing {
+                        // Only before a space or another closing bracket, so that typing in
+                        // front of text does not add brackets that are not wanted.
+                        Some(&(_, close)) if cursor.is_atomic() &&
+                                       next.map_or(true, |n| {
+                            n.is_whitespace() || BRACKETS.iter().any(|&(_, b)| b == n)
+                        }) => (cursor.start(), cursor.start(), format!("{}{}", c, close), 1),
+                        // Typing the closing bracket in front of the same one types over it.
+                        None if cursor.is_atomic() && next == Some(c) => {
+                            let (line, byte) = cursor.start();
+                            (cursor.start(), (line, byte + 1), c.to_string(), 1)
+                        }
+                        _ => (cursor.start(), cursor.end(), c.to_string(), 1),
+                    }
+                })
+            }
+            _ => self.edit(&c.to_string(), |cursor, _| (cursor.start(), cursor.end())),
+        }
+    }
+
+    /// Replaces, for every cursor, the range returned by `range` with `text`, leaving the cursor
+    /// right after the inserted text and shifting the cursors that come after it.
+    fn edit<F>(&mut self, text: &str, range: F)
+        where F: Fn(&Cursor, &[String]) -> ((usize, usize), (usize, usize))
+    {
+        self.edit_each(|cursor, lines| {
+            let (from, to) = range(cursor, lines);
+            (from, to, String::from(text))
+        });
+    }
+
+    /// Like `edit()`, but the inserted text can be different for every cursor.
+    fn edit_each<F>(&mut self, edit: F)
+        where F: Fn(&Cursor, &[String]) -> ((usize, usize), (usize, usize), String)
+    {
+        self.edit_each_leaving(|cursor, lines| {
+            let (from, to, text) = edit(cursor, lines);
+            let end = text.len();
+            (from, to, text, end)
+        });
+    }
+
+    /// Like `edit_each()`, but the cursor is left at the returned byte of the inserted text.
+    /// Replacing text with the same text only moves the cursor.
+    fn edit_each_leaving<F>(&mut self, edit: F)
+        where F: Fn(&Cursor, &[String]) -> ((usize, usize), (usize, usize), String, usize)
+    {
+        self.merge_cursors();
+        let before = self.cursors.clone();
+        let mut changes = Vec::new();
+        // The cursors whose place was replaced by the edit of another one, which have nothing
+        // left to edit.
+        let mut swallowed = vec![false; self.cursors.len()];
+        for i in 0..self.cursors.len() {
+            if swallowed[i] {
+                continue;
+            }
+            let (from, to, text, caret) = edit(&self.cursors[i], &self.lines);
+            if from == to && text.is_empty() {
+                continue;
+            }
+            let caret = end_of(from, &text[..caret]);
+            if text_between(&self.lines, from, to) == text {
+                self.cursors[i] = Cursor::new(&self.lines, caret, caret);
+                continue;
+            }
+            self.saved = false;
+            self.version += 1;
+            let removed = text_between(&self.lines, from, to);
+            remove_range(Arc::make_mut(&mut self.lines), from.0, from.1, to.0, to.1);
+            let position = insert_text(Arc::make_mut(&mut self.lines), from.0, from.1, &text);
+            self.annotations.adjust(from, &removed, &text);
+            changes.push(Change {
+                position: from,
+                removed: removed,
+                inserted: text,
+            });
+
+            self.cursors[i] = Cursor::new(&self.lines, caret, caret);
+            for j in self.move_other_cursors(i, from, to, position) {
+                swallowed[j] = true;
+            }
+        }
+        self.merge_cursors();
+        let after = self.cursors.clone();
+        self.history.record(changes, before, after);
+    }
+
+    /// Moves the cursors other than the one at `index` along with the edit it made, which
+    /// replaced the text from `from` to `to` with text ending at `end`. The ends of cursors in
+    /// the replaced text go to the end of the new one, where they are merged later. Returns the
+    /// indices of the cursors that were entirely in it.
+    fn move_other_cursors(&mut self,
+                          index: usize,
+                          from: (usize, usize),
+                          to: (usize, usize),
+                          end: (usize, usize))
+                          -> Vec<usize> {
+        let mut swallowed = Vec::new();
+        for (i, cursor) in self.cursors.iter_mut().enumerate() {
+            if i == index {
+                continue;
+            }
+            let (old_start, old_end) = (cursor.start(), cursor.end());
+            if from <= old_start && old_end < to {
+                swallowed.push(i);
+            }
+            let start = replaced_position(old_start, from, to, end);
+            let new_end = replaced_position(old_end, from, to, end);
+            // The ones before keep which way they were extended unless the edit reached them.
+            if i > index || (start, new_end) != (old_start, old_end) {
+                *cursor = Cursor::new(&self.lines, start, new_end);
+            }
+        }
+        swallowed
+    }
+
+    /// What is attached to ranges of the text, kept in place as it is edited.
+    pub fn annotations(&self) -> &Annotations {
+        &self.annotations
+    }
+
+    pub fn annotations_mut(&mut self) -> &mut Annotations {
+        &mut self.annotations
+    }
+
+    pub fn get_history(&self) -> &History {
+        &self.history
+    }
+
+    /// Goes back to the revision before the current one, returning whether there was one.
+    pub fn undo(&mut self) -> bool {
+        match self.history.revisions()[self.history.current()].parent() {
+            Some(parent) => {
+                self.go_to_revision(parent);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Goes to the revision last undone from the current one, returning whether there was one.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo_target() {
+            Some(child) => {
+                self.go_to_revision(child);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Brings the text to how it was at the given revision of its history, undoing and redoing
+    /// changes along the branches of the undo tree.
+    pub fn go_to_revision(&mut self, revision: usize) {
+        let (undo, redo) = self.history.path(revision);
+        if undo.is_empty() && redo.is_empty() {
+            return;
+        }
+        for i in undo {
+            let revision = self.history.revisions()[i].clone();
+            for change in revision.changes().iter().rev() {
+                let end = end_of(change.position, &change.inserted);
+                let lines = Arc::make_mut(&mut self.lines);
+                remove_range(lines, change.position.0, change.position.1, end.0, end.1);
+                let _ = insert_text(lines,
+                                    change.position.0,
+                                    change.position.1,
+                                    &change.removed);
+                self.annotations.adjust(change.position, &change.inserted, &change.removed);
+            }
+            self.cursors = revision.cursors_before().to_vec();
+        }
+        for i in redo {
+            let revision = self.history.revisions()[i].clone();
+            for change in revision.changes() {
+                let end = end_of(change.position, &change.removed);
+                let lines = Arc::make_mut(&mut self.lines);
+                remove_range(lines, change.position.0, change.position.1, end.0, end.1);
+                let _ = insert_text(lines,
+                                    change.position.0,
+                                    change.position.1,
+                                    &change.inserted);
+                self.annotations.adjust(change.position, &change.removed, &change.inserted);
+            }
+            self.cursors = revision.cursors_after().to_vec();
+        }
+        self.history.set_current(revision);
+        self.saved = self.history.is_saved();
+        self.version += 1;
+    }
+
+    /// Inserts pasted text at every cursor. Tabs and line breaks are kept, and the other control
+    /// characters are handled as the buffer options say.
+    pub fn write_str<S: AsRef<str>>(&mut self, string: S) {
+        let text = sanitize(string.as_ref(), self.options.control_characters);
+        if !text.is_empty() {
+            self.edit(&text, |cursor, _| (cursor.start(), cursor.end()));
+        }
+    }
+
+    /// Inserts text at the end of the buffer, leaving a single cursor after it.
+    pub fn append<S: AsRef<str>>(&mut self, string: S) {
+        self.cursors = vec![self.cursor_at(usize::MAX, usize::MAX)];
+        self.write_str(string);
+    }
+
+    /// Moves to the next tab stop of the snippet being filled in, or else expands the snippet
+    /// whose prefix is right before the cursor, or else inserts a tab.
+    pub fn tab(&mut self) {
+        if !self.go_to_tab_stop(true) && !self.expand_snippet() {
+            self.insert_tab();
+        }
+    }
+
+    /// Moves back to the previous tab stop of the snippet being filled in, if any.
+    pub fn back_tab(&mut self) {
+        let _ = self.go_to_tab_stop(false);
+    }
+
+    /// Replaces the prefix of a snippet right before the cursor with its body, selecting its
+    /// first tab stop. Returns whether there was one, which needs a single cursor.
+    fn expand_snippet(&mut self) -> bool {
+        if self.cursors.len() != 1 || !self.cursors[0].is_atomic() {
+            return false;
+        }
+        let (line, byte) = self.cursors[0].start();
+        let (prefix_len, body) = {
+            let before = &self.lines[line][..byte];
+            let words = &self.options.word_chars;
+            // A prefix only counts as a whole word, the longest one first.
+            let found = self.options
+                .snippets
+                .iter()
+                .filter(|&(prefix, _)| {
+                    !prefix.is_empty() && before.ends_with(prefix.as_str()) &&
+                    before[..before.len() - prefix.len()]
+                        .chars()
+                        .next_back()
+                        .map_or(true, |c| words.class(c) != CharClass::Word)
+                })
+                .max_by_key(|&(prefix, _)| prefix.len());
+            match found {
+                Some((prefix, body)) => (prefix.len(), body.clone()),
+                None => return false,
+            }
+        };
+
+        let indentation = {
+            let text = &self.lines[line];
+            String::from(&text[..first_non_whitespace(text)])
+        };
+        let template = snippets::parse(&body, &indentation, &self.options.indent);
+        let start = (line, byte - prefix_len);
+        self.edit_each_leaving(|_, _| (start, (line, byte), template.text.clone(), 0));
+
+        let mut stops = Vec::new();
+        for (i, ranges) in template.stops.iter().enumerate() {
+            for &(from, to) in ranges {
+                let mut stop = Annotation::new(SNIPPET_OWNER,
+                                               end_of(start, &template.text[..from]),
+                                               end_of(start, &template.text[..to]),
+                                               Style::Hidden);
+                stop.data = i.to_string();
+                stops.push(stop);
+            }
+        }
+        // The whole snippet, to tell when the cursor leaves it.
+        stops.push(Annotation::new(SNIPPET_OWNER,
+                                   start,
+                                   end_of(start, &template.text),
+                                   Style::Hidden));
+        self.annotations.set(SNIPPET_OWNER, stops);
+        self.tab_stop = None;
+        let _ = self.go_to_tab_stop(true);
+        true
+    }
+
+    /// Selects every range of the next or the previous tab stop of the snippet being filled in,
+    /// skipping the ones whose text was removed. The snippet is done once its last tab stop is
+    /// reached or the cursor leaves it. Returns whether the cursors moved.
+    fn go_to_tab_stop(&mut self, forward: bool) -> bool {
+        let (inside, last) = {
+            let stops: Vec<&Annotation> = self.annotations
+                .on_lines(0..self.lines.len())
+                .into_iter()
+                .filter(|a| a.owner == SNIPPET_OWNER)
+                .collect();
+            let snippet = stops.iter().find(|a| a.data.is_empty());
+            let inside = snippet.map_or(false, |s| {
+                self.cursors.iter().all(|c| s.start <= c.start() && c.end() <= s.end)
+            });
+            let last = stops.iter().filter_map(|a| a.data.parse::<usize>().ok()).max();
+            (inside, last.unwrap_or(0))
+        };
+        if !inside {
+            self.leave_snippet();
+            return false;
+        }
+
+        let mut next = self.tab_stop;
+        loop {
+            next = match (next, forward) {
+                (None, _) => Some(0),
+                (Some(i), true) if i < last => Some(i + 1),
+                (Some(i), false) if i > 0 => Some(i - 1),
+                (Some(_), true) => {
+                    self.leave_snippet();
+                    return true;
+                }
+                (Some(_), false) => return true,
+            };
+            let number = next.unwrap().to_string();
+            let cursors: Vec<Cursor> = self.annotations
+                .on_lines(0..self.lines.len())
+                .into_iter()
+                .filter(|a| a.owner == SNIPPET_OWNER && a.data == number)
+                .map(|a| Cursor::new(&self.lines, a.start, a.end))
+                .collect();
+            if !cursors.is_empty() {
+                self.cursors = cursors;
+                self.merge_cursors();
+                self.tab_stop = next;
+                if next == Some(last) {
+                    self.leave_snippet();
+                }
+                return true;
+            }
+        }
+    }
+
+    fn leave_snippet(&mut self) {
+        self.annotations.remove(SNIPPET_OWNER);
+        self.tab_stop = None;
+    }
+
+    /// Inserts a tab at every cursor, or with soft tabs, the spaces up to the next tab stop.
+    pub fn insert_tab(&mut self) {
+        let options = self.options.clone();
+        self.edit_each(|cursor, lines| {
+            let text = if options.hard_tabs {
+                String::from("\t")
+            } else {
+                let column = display_column(&lines[cursor.start_line],
+                                            cursor.start_byte,
+                                            options.tab_width);
+                iter::repeat(' ').take(options.tab_width - column % options.tab_width).collect()
+            };
+            (cursor.start(), cursor.end(), text)
+        });
+    }
+
+    /// Makes the edit or cursor change. Every sequence of them is valid on any buffer, so that
+    /// fuzzers and property tests can run random ones and check `debug_validate()` after each.
+    pub fn apply(&mut self, op: &EditOp) {
+        match *op {
+            EditOp::Insert(ref text) => {
+                for c in text.chars() {
+                    self.write_character(c);
+                }
+            }
+            EditOp::Paste(ref text) => self.write_str(text),
+            EditOp::NewLine => self.write_character('\n'),
+            EditOp::Backspace => self.write_character(BACKSPACE),
+            EditOp::Delete => self.write_character(DEL),
+            EditOp::Tab => self.tab(),
+            EditOp::BackTab => self.back_tab(),
+            EditOp::DeleteWordBackward => self.delete_word_backward(),
+            EditOp::DeleteWordForward => self.delete_word_forward(),
+            EditOp::DeleteSubwordBackward => self.delete_subword_backward(),
+            EditOp::DeleteSubwordForward => self.delete_subword_forward(),
+            EditOp::DuplicateLines => self.duplicate_lines(),
+            EditOp::DeleteLines => self.delete_lines(),
+            EditOp::MoveLinesUp => self.move_lines_up(),
+            EditOp::MoveLinesDown => self.move_lines_down(),
+            EditOp::ToggleComment => self.toggle_comment(),
+            EditOp::TrimTrailingWhitespace => self.trim_trailing_whitespace(),
+            EditOp::AddCursorAbove => self.add_cursor_above(),
+            EditOp::AddCursorBelow => self.add_cursor_below(),
+            EditOp::AddCursorAtNextOccurrence => self.add_cursor_at_next_occurrence(),
+            EditOp::ExtendColumnCursors(movement) => self.extend_column_cursors(movement),
+            EditOp::CollapseCursors => self.collapse_cursors(),
+            EditOp::SelectAll => self.select_all(),
+            EditOp::SelectLines => self.select_lines(),
+            EditOp::ExpandSelection => self.expand_selection(),
+            EditOp::Undo => {
+                let _ = self.undo();
+            }
+            EditOp::Redo => {
+                let _ = self.redo();
+            }
+            EditOp::Move(movement) => self.move_cursors(movement),
+            EditOp::Extend(movement) => self.extend_cursors(movement),
+            EditOp::GoTo(line, column) => {
+                let _ = self.goto(line, column);
+            }
+            EditOp::Transform(transform) => self.transform(transform),
+        }
+    }
+
+    /// Checks that the lines and cursors are in a state the edits can work with: every line
+    /// ends with a single line break and has no other, but the last, which may have none as in
+    /// a new buffer, and the cursors are in order, on the lines of the buffer and at character
+    /// boundaries before the line breaks. Returns what is wrong otherwise. It goes through every
+    /// line, so it is meant for tests and debugging rather than to be run after every edit.
+    pub fn debug_validate(&self) -> Result<(), String> {
+        if self.lines.is_empty() {
+            return Err(String::from("the buffer has no lines"));
+        }
+        let last = self.lines.len() - 1;
+        for (i, line) in self.lines.iter().enumerate() {
+            let breaks = line.matches('\n').count();
+            if i < last && (breaks != 1 || !line.ends_with('\n')) {
+                return Err(format!("line {} does not end with a single line break: {:?}",
+                                   i,
+                                   line));
+            }
+            if i == last && (breaks > 1 || breaks == 1 && !line.ends_with('\n')) {
+                return Err(format!("the last line has a line break before its end: {:?}",
+                                   line));
+            }
+        }
+
+        if self.cursors.is_empty() {
+            return Err(String::from("the buffer has no cursors"));
+        }
+        for (i, cursor) in self.cursors.iter().enumerate() {
+            for &(line, byte) in &[cursor.start(), cursor.end()] {
+                if line > last {
+                    return Err(format!("cursor {} is on line {}, past the last one", i, line));
+                }
+                let text = &self.lines[line];
+                if byte > content_len(text) || !text.is_char_boundary(byte) {
+                    return Err(format!("cursor {} is at byte {} of line {:?}, which is not a \
+                                        character boundary before the line break",
+                                       i,
+                                       byte,
+                                       text));
+                }
+            }
+            if cursor.start() > cursor.end() {
+                return Err(format!("cursor {} starts after it ends: {:?}", i, cursor));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An edit or cursor change to make with `TextBuffer::apply()`, which is everything the editor
+/// does to a buffer that does not depend on how it is shown.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditOp {
+    /// Types the text one character at a time, as if typed on the keyboard.
+    Insert(String),
+    /// Inserts the text at once, as pasted.
+    Paste(String),
+    NewLine,
+    Backspace,
+    Delete,
+    Tab,
+    BackTab,
+    DeleteWordBackward,
+    DeleteWordForward,
+    DeleteSubwordBackward,
+    DeleteSubwordForward,
+    DuplicateLines,
+    DeleteLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    ToggleComment,
+    TrimTrailingWhitespace,
+    AddCursorAbove,
+    AddCursorBelow,
+    AddCursorAtNextOccurrence,
+    /// Grows or shrinks the rectangle of text selected with a cursor per line by a line or a
+    /// column, given by `Up`, `Down`, `Left` or `Right`.
+    ExtendColumnCursors(Move),
+    CollapseCursors,
+    SelectAll,
+    SelectLines,
+    ExpandSelection,
+    Undo,
+    Redo,
+    /// Moves every cursor.
+    Move(Move),
+    /// Moves the moving end of every selection, leaving the other one in place.
+    Extend(Move),
+    /// Leaves a single cursor at the line and display column, clamped to the text.
+    GoTo(usize, usize),
+    Transform(Transform),
+}
+
+/// A rectangle of text between two corners, each a line and a display column: the one it was
+/// started from and the one that moves when it is extended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColumnRect {
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+}
+
+/// A change made to the text of every selection or the lines with a cursor, with
+/// `TextBuffer::transform()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    Uppercase,
+    Lowercase,
+    /// Capitalizes the first letter of every word and lowercases the rest.
+    TitleCase,
+    SortLines,
+    ReverseLines,
+    /// Removes the lines that are the same as one above them.
+    UniqueLines,
+    /// Joins the lines into one, separated by single spaces instead of their indentation.
+    JoinLines,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    SubwordLeft,
+    SubwordRight,
+    LineStart,
+    LineEnd,
+    /// To the very start of the line, even if it is indented.
+    FirstColumn,
+    /// To the start of the next word or run of punctuation, as Vim's `w`.
+    NextWordStart,
+    /// To the blank line before the current paragraph, or the previous one.
+    PreviousParagraph,
+    /// To the blank line after the current paragraph, or the next one.
+    NextParagraph,
+    /// To the previous line at the same indentation, within the enclosing block.
+    PreviousBlock,
+    /// To the next line at the same indentation, within the enclosing block.
+    NextBlock,
+    BufferStart,
+    BufferEnd,
+}
+
+/// Where the indentation level ending at the end of `before` starts, if `before` is made of
+/// spaces only: at the previous multiple of the indentation width, so that uneven indentation
+/// is evened out first.
+fn indent_level_start(before: &str, options: &Options) -> Option<usize> {
+    if before.is_empty() || before.chars().any(|c| c != ' ') {
+        return None;
+    }
+    let width = if !options.indent.is_empty() && options.indent.chars().all(|c| c == ' ') {
+        options.indent.len()
+    } else {
+        options.tab_width
+    };
+    Some((before.len() - 1) / width * width)
+}
+
+/// Column at which the byte of the line is displayed, with tabs advancing to the next tab stop.
+fn display_column(line: &str, byte: usize, tab_width: usize) -> usize {
+    line[..byte].graphemes(true).fold(0, |column, g| next_column(column, g, tab_width))
+}
+
+/// A cursor on every line of the rectangle, selecting the text between its columns, in order.
+fn column_cursors(lines: &[String], rect: ColumnRect, tab_width: usize) -> Vec<Cursor> {
+    let last = lines.len() - 1;
+    let first_line = cmp::min(cmp::min(rect.anchor.0, rect.head.0), last);
+    let last_line = cmp::min(cmp::max(rect.anchor.0, rect.head.0), last);
+    let start_column = cmp::min(rect.anchor.1, rect.head.1);
+    let end_column = cmp::max(rect.anchor.1, rect.head.1);
+    (first_line..last_line + 1)
+        .map(|line| {
+            let start = column_byte(&lines[line], start_column, tab_width);
+            let end = column_byte(&lines[line], end_column, tab_width);
+            let mut cursor = Cursor::new(lines, (line, start), (line, end));
+            // The selections end on the side the rectangle is extended from.
+            cursor.reversed = rect.head.1 < rect.anchor.1;
+            cursor
+        })
+        .collect()
+}
+
+/// The byte of the line at which the given display column starts, or the end of the line if it
+/// is not that long.
+fn column_byte(line: &str, column: usize, tab_width: usize) -> usize {
+    let mut current = 0;
+    for (i, grapheme) in line[..content_len(line)].grapheme_indices(true) {
+        if current >= column {
+            return i;
+        }
+        current = next_column(current, grapheme, tab_width);
+    }
+    content_len(line)
+}
+
+/// The column after a character displayed at `column`.
+fn next_column(column: usize, grapheme: &str, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        column + tab_width - column % tab_width
+    } else {
+        column + 1
+    }
+}
+
+/// Number of grapheme clusters in the text, which are what cursors count as characters.
+fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Byte index of the grapheme cluster at the given position, or the length of the text if it has
+/// fewer.
+fn grapheme_byte(text: &str, grapheme: usize) -> usize {
+    match text.grapheme_indices(true).nth(grapheme) {
+        Some((i, _)) => i,
+        None => text.len(),
+    }
+}
+
+/// Length in bytes of the line without its trailing line break.
+fn content_len(line: &str) -> usize {
+    if line.ends_with('\n') {
+        line.len() - 1
+    } else {
+        line.len()
+    }
+}
+
+/// Iterates over the lines of a reader, keeping their line breaks.
+struct RawLines<R: BufRead>(R);
+
+impl<R: BufRead> Iterator for RawLines<R> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        let mut line = String::new();
+        match self.0.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// The start of the line after the given one, or the end of the buffer if it is the last.
+fn line_after(lines: &[String], line: usize) -> (usize, usize) {
+    if line + 1 < lines.len() {
+        (line + 1, 0)
+    } else {
+        (line, content_len(&lines[line]))
+    }
+}
+
+fn is_blank(line: &str) -> bool {
+    line.chars().all(char::is_whitespace)
+}
+
+/// Byte index of the first character of the line that is not whitespace.
+fn first_non_whitespace(line: &str) -> usize {
+    let end = content_len(line);
+    match line[..end].char_indices().skip_while(|&(_, c)| c.is_whitespace()).next() {
+        Some((i, _)) => i,
+        None => end,
+    }
+}
+
+/// Finds the bracket matching the one at the given line and byte, skipping the pairs nested in
+/// between. Gives up past `BRACKET_SEARCH_LINES` lines.
+fn find_matching_bracket(lines: &[String],
+                         position: (usize, usize),
+                         bracket: char)
+                         -> Option<(usize, usize, char)> {
+    let (line, byte) = position;
+    let mut depth = 0;
+    if let Some(&(open, close)) = BRACKETS.iter().find(|&&(open, _)| open == bracket) {
+        let start = byte + open.len_utf8();
+        let last = cmp::min(lines.len(), line + BRACKET_SEARCH_LINES);
+        for i in line..last {
+            let from = if i == line { start } else { 0 };
+            for (b, c) in lines[i][from..].char_indices() {
+                if c == open {
+                    depth += 1;
+                } else if c == close {
+                    if depth == 0 {
+                        return Some((i, from + b, c));
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+    } else if let Some(&(open, close)) = BRACKETS.iter().find(|&&(_, close)| close == bracket) {
+        let first = line.saturating_sub(BRACKET_SEARCH_LINES);
+        for i in (first..line + 1).rev() {
+            let to = if i == line { byte } else { lines[i].len() };
+            for (b, c) in lines[i][..to].char_indices().rev() {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    if depth == 0 {
+                        return Some((i, b, c));
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Text replaced in a line while toggling comments, at a byte of the line.
+struct LineEdit {
+    line: usize,
+    at: usize,
+    removed: usize,
+    inserted: String,
+}
+
+impl LineEdit {
+    /// Where a byte of the line ends up after the edit. Bytes in the removed text go to its
+    /// start.
+    fn shift(&self, byte: usize) -> usize {
+        if byte <= self.at {
+            byte
+        } else if byte >= self.at + self.removed {
+            byte - self.removed + self.inserted.len()
+        } else {
+            self.at
+        }
+    }
+}
+
+/// The edits that comment out the given lines, or uncomment them if every line with text is
+/// already commented. Lines are given by their index in `lines`, and blank ones are left alone.
+fn comment_edits(lines: &[String], options: &Options) -> Vec<LineEdit> {
+    let contents: Vec<(usize, &str)> = lines.iter()
+        .map(|line| line[..content_len(line)].trim_right())
+        .enumerate()
+        .filter(|&(_, content)| !content.trim().is_empty())
+        .collect();
+    let indentation = |content: &str| content.len() - content.trim_left().len();
+    // Removes the marker at the byte, with the space that follows it.
+    let remove = |line: usize, at: usize, marker: &str, content: &str| {
+        let space = content[at + marker.len()..].starts_with(' ');
+        LineEdit {
+            line: line,
+            at: at,
+            removed: marker.len() + if space { 1 } else { 0 },
+            inserted: String::new(),
+        }
+    };
+
+    if let Some(leader) = options.line_comments.iter().min_by_key(|l| l.len()) {
+        let commented = |content: &str| {
+            options.line_comments.iter().find(|l| content.trim_left().starts_with(l.as_str()))
+        };
+        if contents.iter().all(|&(_, content)| commented(content).is_some()) {
+            return contents.iter()
+                .map(|&(i, content)| {
+                    remove(i, indentation(content), commented(content).unwrap(), content)
+                })
+                .collect();
+        }
+        // Leaders line up at the smallest indentation, so that nested code stays nested.
+        let at = contents.iter().map(|&(_, content)| indentation(content)).min().unwrap_or(0);
+        return contents.iter()
+            .map(|&(i, _)| {
+                LineEdit {
+                    line: i,
+                    at: at,
+                    removed: 0,
+                    inserted: format!("{} ", leader),
+                }
+            })
+            .collect();
+    }
+
+    let (open, close) = match options.block_comment {
+        Some((ref open, _, ref close)) if !contents.is_empty() => (open, close),
+        _ => return Vec::new(),
+    };
+    let (first, first_content) = contents[0];
+    let (last, last_content) = contents[contents.len() - 1];
+    let start = indentation(first_content);
+    let commented = first_content[start..].starts_with(open.as_str()) &&
+                    last_content.ends_with(close.as_str()) &&
+                    (first != last || first_content.len() - start >= open.len() + close.len());
+    if !commented {
+        return vec![LineEdit {
+                        line: first,
+                        at: start,
+                        removed: 0,
+                        inserted: format!("{} ", open),
+                    },
+                    LineEdit {
+                        line: last,
+                        at: last_content.len(),
+                        removed: 0,
+                        inserted: format!(" {}", close),
+                    }];
+    }
+
+    let opening = remove(first, start, open, first_content);
+    // The space before the closing marker is kept if it is the one after the opening marker.
+    let text_start = if first == last {
+        start + opening.removed
+    } else {
+        0
+    };
+    let mut end = last_content.len() - close.len();
+    if end > text_start && last_content[..end].ends_with(' ') {
+        end -= 1;
+    }
+    vec![opening,
+         LineEdit {
+             line: last,
+             at: end,
+             removed: last_content.len() - end,
+             inserted: String::new(),
+         }]
+}
+
+/// Returns the indentation and comment leader to start the next line with, if `before` (the text
+/// before the cursor) is inside a comment.
+fn comment_continuation(before: &str, options: &Options) -> Option<String> {
+    let content = before.trim_left();
+    let indentation = &before[..before.len() - content.len()];
+    let followed_by_space = |leader: &str| {
+        content[leader.len()..].chars().next().map_or(true, char::is_whitespace)
+    };
+
+    let mut leaders: Vec<&String> = options.line_comments.iter().collect();
+    leaders.sort_by(|a, b| b.len().cmp(&a.len()));
+    for leader in leaders {
+        if content.starts_with(leader.as_str()) {
+            let space = if followed_by_space(leader) { " " } else { "" };
+            return Some(format!("{}{}{}", indentation, leader, space));
+        }
+    }
+
+    if let Some((ref open, ref middle, ref close)) = options.block_comment {
+        if content.starts_with(open.as_str()) && !content[open.len()..].contains(close.as_str()) {
+            return Some(format!("{} {} ", indentation, middle));
+        }
+        if content.starts_with(middle.as_str()) && !content.contains(close.as_str()) &&
+           followed_by_space(middle) {
+            return Some(format!("{}{} ", indentation, middle));
+        }
+    }
+
+    None
+}
+
+/// Returns the indentation for the line after `before`: the same as its own, one level deeper if
+/// it opens a block.
+fn auto_indentation(before: &str, options: &Options) -> String {
+    let content = before.trim_left();
+    let mut indentation = String::from(&before[..before.len() - content.len()]);
+    if content.trim_right().ends_with(|c| c == '{' || c == '(' || c == '[' || c == ':') {
+        indentation.push_str(&options.indent);
+    }
+    indentation
+}
+
+fn word_left(cursor: &mut Cursor, lines: &[String], words: &WordChars) {
+    move_to_previous_boundary(cursor, lines, |line, byte| words.previous_boundary(line, byte));
+}
+
+fn word_right(cursor: &mut Cursor, lines: &[String], words: &WordChars) {
+    move_to_next_boundary(cursor, lines, |line, byte| words.next_boundary(line, byte));
+}
+
+fn subword_left(cursor: &mut Cursor, lines: &[String], words: &WordChars) {
+    move_to_previous_boundary(cursor,
+                              lines,
+                              |line, byte| words.previous_subword_boundary(line, byte));
+}
+
+fn subword_right(cursor: &mut Cursor, lines: &[String], words: &WordChars) {
+    move_to_next_boundary(cursor,
+                          lines,
+                          |line, byte| words.next_subword_boundary(line, byte));
+}
+
+fn move_to_previous_boundary<F>(cursor: &mut Cursor, lines: &[String], boundary: F)
+    where F: Fn(&str, usize) -> usize
+{
+    if cursor.start_byte == 0 {
+        if cursor.start_line != 0 {
+            cursor.start_line -= 1;
+            let line = &lines[cursor.start_line];
+            cursor.start_byte = content_len(line);
+            cursor.start_character = grapheme_count(&line[..cursor.start_byte]);
+        }
+    } else {
+        let line = &lines[cursor.start_line];
+        cursor.start_byte = boundary(line, cursor.start_byte);
+        cursor.start_character = grapheme_count(&line[..cursor.start_byte]);
+    }
+    cursor.atomize();
+}
+
+fn move_to_next_boundary<F>(cursor: &mut Cursor, lines: &[String], boundary: F)
+    where F: Fn(&str, usize) -> usize
+{
+    cursor.start_line = cursor.end_line;
+    cursor.start_byte = cursor.end_byte;
+    cursor.start_character = cursor.end_character;
+
+    let line = &lines[cursor.start_line];
+    if cursor.start_byte == content_len(line) {
+        if cursor.start_line != lines.len() - 1 {
+            cursor.start_line += 1;
+            cursor.start_byte = 0;
+            cursor.start_character = 0;
+        }
+    } else {
+        cursor.start_byte = boundary(line, cursor.start_byte);
+        cursor.start_character = grapheme_count(&line[..cursor.start_byte]);
+    }
+    cursor.atomize();
+}
+
+fn move_cursor(cursor: &mut Cursor, movement: Move, lines: &[String], options: &Options) {
+    let words = &options.word_chars;
+    match movement {
+        Move::Up => move_up(cursor, lines, options.tab_width),
+        Move::Down => move_down(cursor, lines, options.tab_width),
+        Move::Left => {
+            if cursor.is_atomic() {
+                let position = previous_position(lines, cursor.start());
+                *cursor = Cursor::new(lines, position, position);
+            }
+            cursor.atomize();
+        }
+        Move::Right => {
+            let position = if cursor.is_atomic() {
+                next_position(lines, cursor.start())
+            } else {
+                cursor.end()
+            };
+            *cursor = Cursor::new(lines, position, position);
+        }
+        Move::WordLeft => word_left(cursor, lines, words),
+        Move::WordRight => word_right(cursor, lines, words),
+        Move::NextWordStart => {
+            move_to_next_boundary(cursor, lines, |line, byte| words.next_start(line, byte))
+        }
+        Move::SubwordLeft => subword_left(cursor, lines, words),
+        Move::SubwordRight => subword_right(cursor, lines, words),
+        Move::LineStart => {
+            let line = &lines[cursor.start_line];
+            let indentation = first_non_whitespace(line);
+            cursor.start_byte = if cursor.start_byte == indentation {
+                0
+            } else {
+                indentation
+            };
+            cursor.start_character = grapheme_count(&line[..cursor.start_byte]);
+            cursor.atomize();
+        }
+        Move::FirstColumn => {
+            cursor.start_byte = 0;
+            cursor.start_character = 0;
+            cursor.atomize();
+        }
+        Move::LineEnd => {
+            let line = &lines[cursor.end_line];
+            cursor.start_line = cursor.end_line;
+            cursor.start_byte = content_len(line);
+            cursor.start_character = grapheme_count(&line[..cursor.start_byte]);
+            cursor.atomize();
+        }
+        Move::PreviousParagraph => previous_paragraph(cursor, lines),
+        Move::NextParagraph => next_paragraph(cursor, lines),
+        Move::PreviousBlock => previous_block(cursor, lines),
+        Move::NextBlock => next_block(cursor, lines),
+        Move::BufferStart => {
+            cursor.start_line = 0;
+            cursor.start_byte = 0;
+            cursor.start_character = 0;
+            cursor.atomize();
+        }
+        Move::BufferEnd => {
+            let last_line = lines.len() - 1;
+            let line = &lines[last_line];
+            cursor.start_line = last_line;
+            cursor.start_byte = content_len(line);
+            cursor.start_character = grapheme_count(&line[..cursor.start_byte]);
+            cursor.atomize();
+        }
+    }
+}
+
+fn previous_paragraph(cursor: &mut Cursor, lines: &[String]) {
+    let mut line = cursor.start_line;
+    while line != 0 && is_blank(&lines[line]) {
+        line -= 1;
+    }
+    while line != 0 && !is_blank(&lines[line]) {
+        line -= 1;
+    }
+    *cursor = Cursor::new(lines, (line, 0), (line, 0));
+}
+
+fn next_paragraph(cursor: &mut Cursor, lines: &[String]) {
+    let last_line = lines.len() - 1;
+    let mut line = cursor.end_line;
+    while line != last_line && is_blank(&lines[line]) {
+        line += 1;
+    }
+    while line != last_line && !is_blank(&lines[line]) {
+        line += 1;
+    }
+    let byte = if is_blank(&lines[line]) {
+        0
+    } else {
+        content_len(&lines[line])
+    };
+    *cursor = Cursor::new(lines, (line, byte), (line, byte));
+}
+
+fn previous_block(cursor: &mut Cursor, lines: &[String]) {
+    let indentation = first_non_whitespace(&lines[cursor.start_line]);
+    let target = lines[..cursor.start_line]
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|&(_, line)| !is_blank(line))
+        .map(|(i, line)| (i, first_non_whitespace(line)))
+        .take_while(|&(_, level)| level >= indentation)
+        .find(|&(_, level)| level == indentation);
+    move_to_block_line(cursor, lines, target);
+}
+
+fn next_block(cursor: &mut Cursor, lines: &[String]) {
+    let indentation = first_non_whitespace(&lines[cursor.end_line]);
+    let target = lines.iter()
+        .enumerate()
+        .skip(cursor.end_line + 1)
+        .filter(|&(_, line)| !is_blank(line))
+        .map(|(i, line)| (i, first_non_whitespace(line)))
+        .take_while(|&(_, level)| level >= indentation)
+        .find(|&(_, level)| level == indentation);
+    move_to_block_line(cursor, lines, target);
+}
+
+/// Moves the cursor to the start of the text of the line found by a block motion, or collapses
+/// it in place if there is none.
+fn move_to_block_line(cursor: &mut Cursor, lines: &[String], target: Option<(usize, usize)>) {
+    match target {
+        Some((line, byte)) => *cursor = Cursor::new(lines, (line, byte), (line, byte)),
+        None => cursor.atomize(),
+    }
+}
+
+/// Moves the active end of the cursor's selection to the given position, swapping its ends if
+/// it crosses the other one.
+fn extend_to(cursor: &mut Cursor, head: (usize, usize), lines: &[String]) {
+    let anchor = if cursor.reversed {
+        cursor.end()
+    } else {
+        cursor.start()
+    };
+    *cursor = if head < anchor {
+        let mut cursor = Cursor::new(lines, head, anchor);
+        cursor.reversed = true;
+        cursor
+    } else {
+        Cursor::new(lines, anchor, head)
+    };
+}
+
+/// Moves the cursor to its goal column on the line above its start, or to the start of the
+/// buffer from the first line.
+fn move_up(cursor: &mut Cursor, lines: &[String], tab_width: usize) {
+    if cursor.start_line == 0 {
+        *cursor = Cursor::new(lines, (0, 0), (0, 0));
+        return;
+    }
+    let goal = goal_column(cursor, cursor.start(), lines, tab_width);
+    let line = cursor.start_line - 1;
+    let byte = column_byte(&lines[line], goal, tab_width);
+    *cursor = Cursor::new(lines, (line, byte), (line, byte));
+    cursor.goal_column = Some((goal, (line, byte)));
+}
+
+/// Moves the cursor to its goal column on the line below its end, or to the end of the buffer
+/// from the last line.
+fn move_down(cursor: &mut Cursor, lines: &[String], tab_width: usize) {
+    let last = lines.len() - 1;
+    if cursor.end_line == last {
+        let end = (last, content_len(&lines[last]));
+        *cursor = Cursor::new(lines, end, end);
+        return;
+    }
+    let goal = goal_column(cursor, cursor.end(), lines, tab_width);
+    let line = cursor.end_line + 1;
+    let byte = column_byte(&lines[line], goal, tab_width);
+    *cursor = Cursor::new(lines, (line, byte), (line, byte));
+    cursor.goal_column = Some((goal, (line, byte)));
+}
+
+/// The display column a vertical move from the position aims for: the one the cursor kept from
+/// the moves before, or the one it is at if it moved or was edited since.
+fn goal_column(cursor: &Cursor,
+               (line, byte): (usize, usize),
+               lines: &[String],
+               tab_width: usize)
+               -> usize {
+    match cursor.goal_column {
+        Some((column, at)) if at == (line, byte) => column,
+        _ => display_column(&lines[line], byte, tab_width),
+    }
+}
+
+fn previous_position(lines: &[String], (line, byte): (usize, usize)) -> (usize, usize) {
+    if byte != 0 {
+        (line, lines[line][..byte].grapheme_indices(true).next_back().unwrap().0)
+    } else if line != 0 {
+        (line - 1, content_len(&lines[line - 1]))
+    } else {
+        (line, byte)
+    }
+}
+
+fn next_position(lines: &[String], (line, byte): (usize, usize)) -> (usize, usize) {
+    if byte != content_len(&lines[line]) {
+        (line, byte + lines[line][byte..].graphemes(true).next().unwrap().len())
+    } else if line != lines.len() - 1 {
+        (line + 1, 0)
+    } else {
+        (line, byte)
+    }
+}
+
+/// Byte offset of a position from the start of the buffer.
+fn offset_of(lines: &[String], (line, byte): (usize, usize)) -> usize {
+    lines[..line].iter().fold(byte, |acc, l| acc + l.len())
+}
+
+fn position_of(lines: &[String], mut offset: usize) -> (usize, usize) {
+    for (i, line) in lines.iter().enumerate() {
+        if offset < line.len() || i == lines.len() - 1 {
+            return (i, offset);
+        }
+        offset -= line.len();
+    }
+    (0, 0)
+}
+
+/// Maps a position at or after `old` to where it lands once the text starting at `old` has been
+/// moved to `new`.
+pub fn shift_position(position: (usize, usize),
+                      old: (usize, usize),
+                      new: (usize, usize))
+                      -> (usize, usize) {
+    if position.0 == old.0 {
+        (new.0, new.1 + position.1 - old.1)
+    } else {
+        (position.0 + new.0 - old.0, position.1)
+    }
+}
+
+/// Maps a position to where it lands once the text from `from` to `to` has been replaced by
+/// text ending at `end`. Positions in the replaced text go to the end of the new one.
+fn replaced_position(position: (usize, usize),
+                     from: (usize, usize),
+                     to: (usize, usize),
+                     end: (usize, usize))
+                     -> (usize, usize) {
+    if position < from {
+        position
+    } else if position < to {
+        end
+    } else {
+        shift_position(position, to, end)
+    }
+}
+
+/// The position the given number of lines below, or above if it is negative.
+fn shift_line(position: (usize, usize), lines: isize) -> (usize, usize) {
+    ((position.0 as isize + lines) as usize, position.1)
+}
+
+/// The text in the case the transform asks for, or as it is for transforms that do not change
+/// case.
+fn change_case(text: &str, transform: Transform) -> String {
+    match transform {
+        Transform::Uppercase => text.to_uppercase(),
+        Transform::Lowercase => text.to_lowercase(),
+        Transform::TitleCase => title_case(text),
+        _ => String::from(text),
+    }
+}
+
+/// The text with the first letter of every word in upper case and the rest in lower case.
+fn title_case(text: &str) -> String {
+    let mut title = String::with_capacity(text.len());
+    let mut word_start = true;
+    for c in text.chars() {
+        if word_start {
+            title.extend(c.to_uppercase());
+        } else {
+            title.extend(c.to_lowercase());
+        }
+        word_start = !c.is_alphanumeric() && c != '\'';
+    }
+    title
+}
+
+/// The lines as one, keeping the indentation of the first one and separating the rest by a
+/// single space. Blank lines are left out.
+fn join_lines(lines: &[String]) -> String {
+    let mut joined = String::new();
+    for line in lines {
+        let line = if joined.is_empty() {
+            line.trim_right()
+        } else {
+            line.trim()
+        };
+        if line.is_empty() {
+            continue;
+        }
+        if !joined.is_empty() {
+            joined.push(' ');
+        }
+        joined.push_str(line);
+    }
+    joined
+}
+
+/// The text between two positions.
+fn text_between(lines: &[String], start: (usize, usize), end: (usize, usize)) -> String {
+    if start.0 == end.0 {
+        String::from(&lines[start.0][start.1..end.1])
+    } else {
+        let mut text = String::from(&lines[start.0][start.1..]);
+        for line in &lines[start.0 + 1..end.0] {
+            text.push_str(line);
+        }
+        text.push_str(&lines[end.0][..end.1]);
+        text
+    }
+}
+
+/// The position right after the given text, if it started at `position`.
+pub fn end_of(position: (usize, usize), text: &str) -> (usize, usize) {
+    match text.rfind('\n') {
+        Some(i) => (position.0 + text.matches('\n').count(), text.len() - i - 1),
+        None => (position.0, position.1 + text.len()),
+    }
+}
+
+/// Removes the text between two positions, joining the lines if they differ.
+fn remove_range(lines: &mut Vec<String>,
+                start_line: usize,
+                start_byte: usize,
+                end_line: usize,
+                end_byte: usize) {
+    if start_line == end_line {
+        let _ = lines[start_line].drain(start_byte..end_byte);
+    } else {
+        let tail = String::from(&lines[end_line][end_byte..]);
+        lines[start_line].truncate(start_byte);
+        lines[start_line].push_str(&tail);
+        let _ = lines.drain(start_line + 1..end_line + 1);
+    }
+}
+
+/// Inserts `text` at the given position, returning the position right after it.
+fn insert_text(lines: &mut Vec<String>, line: usize, byte: usize, text: &str) -> (usize, usize) {
+    let tail = lines[line].split_off(byte);
+    let mut line = line;
+    let mut parts = text.split('\n');
+    lines[line].push_str(parts.next().unwrap());
+    for part in parts {
+        lines[line].push('\n');
+        line += 1;
+        lines.insert(line, String::from(part));
+    }
+    let byte = lines[line].len();
+    lines[line].push_str(&tail);
+    (line, byte)
+}
+
+/// Prepares pasted text for insertion: tabs and line breaks are kept, `\r\n` becoming `\n`, and
+/// the rule decides what the other control characters become.
+fn sanitize(text: &str, rule: ControlCharacters) -> String {
+    let mut sanitized = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' | '\n' => sanitized.push(c),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            c if c.is_control() => {
+                if let Some(replacement) = rule.replacement(c) {
+                    sanitized.push(replacement);
+                }
+            }
+            c => sanitized.push(c),
+        }
+    }
+    sanitized
+}
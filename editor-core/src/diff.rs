@@ -0,0 +1,109 @@
+use std::cmp;
+use std::ops::Range;
+
+/// A line of a comparison between two texts, by its index in the old one, the new one, or both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+impl DiffLine {
+    pub fn is_change(&self) -> bool {
+        match *self {
+            DiffLine::Same(..) => false,
+            _ => true,
+        }
+    }
+}
+
+/// The lines of `old` and `new` in the order a unified diff shows them, with removed lines
+/// before the ones added in their place. Found with Myers' algorithm, which removes and adds
+/// as few lines as possible, in memory growing with the square of the number of changes rather
+/// than with the length of the texts.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<DiffLine> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = old.len() + new.len();
+    // `v[k + offset]` is how far in `old` the furthest path found so far on diagonal `k` goes,
+    // and `trace` keeps it from before each number of changes `d` was tried, to walk back from.
+    // Only the diagonals from `-d - 1` to `d + 1` are kept, as paths with `d` changes never
+    // leave them.
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+    'search: for d in 0..max as isize + 1 {
+        trace.push(v[(offset - d - 1) as usize..(offset + d + 2) as usize].to_vec());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, kept) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let furthest = |k: isize| kept[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            lines.push(DiffLine::Same(x as usize - 1, y as usize - 1));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                lines.push(DiffLine::Added(y as usize - 1));
+            } else {
+                lines.push(DiffLine::Removed(x as usize - 1));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    lines.reverse();
+    lines
+}
+
+/// The ranges of the diff lines making up its hunks: the changes along with up to `context`
+/// unchanged lines around them, merged when they are that close.
+pub fn hunks(lines: &[DiffLine], context: usize) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if !line.is_change() {
+            continue;
+        }
+        let start = i.saturating_sub(context);
+        let end = cmp::min(i + 1 + context, lines.len());
+        if let Some(last) = hunks.last_mut() {
+            if start <= last.end {
+                last.end = end;
+                continue;
+            }
+        }
+        hunks.push(start..end);
+    }
+    hunks
+}
@@ -0,0 +1,198 @@
+use std::time::SystemTime;
+
+use backend::Cursor;
+
+/// Typing that pauses for at least this long starts a new undo step, in seconds.
+const TYPING_PAUSE: u64 = 1;
+
+/// A change to the text of a buffer: `removed` was replaced by `inserted` at `position`.
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub position: (usize, usize),
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Change {
+    /// Whether the change typed a single character, so that it can be undone along with the
+    /// characters typed around it.
+    fn is_typing(&self) -> bool {
+        let mut chars = self.inserted.chars();
+        self.removed.is_empty() && chars.next().map_or(false, |c| c != '\n') &&
+        chars.next().is_none()
+    }
+
+    /// Whether the change removed a single character or an indentation level without joining
+    /// lines, so that it can be undone along with the ones removed around it. Joining lines is
+    /// undone on its own, bringing the line break back without the text deleted before it.
+    fn is_deleting(&self) -> bool {
+        let mut chars = self.removed.chars();
+        self.inserted.is_empty() && !self.removed.contains('\n') &&
+        chars.next().is_some() &&
+        (chars.next().is_none() || self.removed.chars().all(|c| c == ' '))
+    }
+}
+
+/// A state of the buffer, reached from its parent by applying the changes in order.
+#[derive(Clone, Debug)]
+pub struct Revision {
+    parent: Option<usize>,
+    /// The child that redoing goes to: the one created or left most recently.
+    redo: Option<usize>,
+    changes: Vec<Change>,
+    cursors_before: Vec<Cursor>,
+    cursors_after: Vec<Cursor>,
+    time: SystemTime,
+}
+
+impl Revision {
+    pub fn parent(&self) -> Option<usize> {
+        self.parent
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// The cursors right before the changes were made, restored when undoing them.
+    pub fn cursors_before(&self) -> &[Cursor] {
+        &self.cursors_before
+    }
+
+    /// The cursors right after the changes were made, restored when redoing them.
+    pub fn cursors_after(&self) -> &[Cursor] {
+        &self.cursors_after
+    }
+
+    /// When the last of the changes was made.
+    pub fn time(&self) -> SystemTime {
+        self.time
+    }
+}
+
+/// The undo tree of a buffer. Editing after undoing starts a new branch instead of throwing
+/// away what was undone, so every state the buffer has been in can be gone back to.
+#[derive(Clone, Debug)]
+pub struct History {
+    /// Every revision, in the order they were created. The first one is the text as it was
+    /// loaded.
+    revisions: Vec<Revision>,
+    current: usize,
+    /// The revision that was last saved, if it still is what the file holds.
+    saved: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            revisions: vec![Revision {
+                                parent: None,
+                                redo: None,
+                                changes: Vec::new(),
+                                cursors_before: Vec::new(),
+                                cursors_after: Vec::new(),
+                                time: SystemTime::now(),
+                            }],
+            current: 0,
+            saved: None,
+        }
+    }
+
+    pub fn revisions(&self) -> &[Revision] {
+        &self.revisions
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// The child of the current revision that redoing goes to.
+    pub fn redo_target(&self) -> Option<usize> {
+        self.revisions[self.current].redo
+    }
+
+    /// Records changes made to the current revision as a new one. Characters typed or deleted
+    /// right after others are added to the same revision, so that they are undone together.
+    pub fn record(&mut self, changes: Vec<Change>, before: Vec<Cursor>, after: Vec<Cursor>) {
+        if changes.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        let current = self.current;
+        let merges = {
+            let previous = &self.revisions[current].changes;
+            current != 0 && self.saved != Some(current) &&
+            self.revisions[current].redo.is_none() &&
+            (changes.iter().all(Change::is_typing) && previous.iter().all(Change::is_typing) ||
+             changes.iter().all(Change::is_deleting) &&
+             previous.iter().all(Change::is_deleting)) &&
+            now.duration_since(self.revisions[current].time)
+                .map(|pause| pause.as_secs() < TYPING_PAUSE)
+                .unwrap_or(false)
+        };
+        if merges {
+            let revision = &mut self.revisions[current];
+            revision.changes.extend(changes);
+            revision.cursors_after = after;
+            revision.time = now;
+            return;
+        }
+
+        self.revisions.push(Revision {
+            parent: Some(current),
+            redo: None,
+            changes: changes,
+            cursors_before: before,
+            cursors_after: after,
+            time: now,
+        });
+        self.current = self.revisions.len() - 1;
+        self.revisions[current].redo = Some(self.current);
+    }
+
+    /// The way from the current revision to the given one: the revisions to undo, in order, and
+    /// then the ones to redo.
+    pub fn path(&self, target: usize) -> (Vec<usize>, Vec<usize>) {
+        let mut ancestors = vec![target];
+        while let Some(parent) = self.revisions[*ancestors.last().unwrap()].parent {
+            ancestors.push(parent);
+        }
+
+        let mut undo = Vec::new();
+        let mut revision = self.current;
+        while !ancestors.contains(&revision) {
+            undo.push(revision);
+            revision = self.revisions[revision].parent.unwrap();
+        }
+        let common = ancestors.iter().position(|&a| a == revision).unwrap();
+        let redo = ancestors[..common].iter().rev().cloned().collect();
+
+        (undo, redo)
+    }
+
+    /// Makes the given revision the current one once the buffer has been brought to it, so
+    /// that redoing from any revision on the way goes back towards it.
+    pub fn set_current(&mut self, target: usize) {
+        let (undo, redo) = self.path(target);
+        for revision in undo.into_iter().chain(redo) {
+            if let Some(parent) = self.revisions[revision].parent {
+                self.revisions[parent].redo = Some(revision);
+            }
+        }
+        self.current = target;
+    }
+
+    /// Remembers that the current revision is what the file holds.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    /// Forgets which revision the file holds, when none of them do any more.
+    pub fn forget_saved(&mut self) {
+        self.saved = None;
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+}
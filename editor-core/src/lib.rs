@@ -0,0 +1,22 @@
+//! The text buffer engine of the editor: the lines of a file, the cursors and the edits made
+//! at them, their history, and the annotations kept in place as the text changes. It knows
+//! nothing about windows, fonts or input devices, so it can be used and tested on its own.
+
+// #![forbid(missing_docs, warnings)]
+#![deny(deprecated, drop_with_repr_extern, improper_ctypes,
+        non_shorthand_field_patterns, overflowing_literals, plugin_as_library,
+        private_no_mangle_fns, private_no_mangle_statics, stable_features, unconditional_recursion,
+        unknown_lints, unused_allocation, unused_attributes,
+        unused_comparisons, unused_features, unused_parens, while_true)]
+#![warn(trivial_casts, trivial_numeric_casts, unused, unused_extern_crates,
+        unused_import_braces, unused_qualifications, unused_results, variant_size_differences)]
+#![allow(missing_docs)]
+
+extern crate unicode_segmentation;
+
+pub mod annotations;
+pub mod backend;
+pub mod diff;
+pub mod history;
+pub mod words;
+mod snippets;
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+/// A snippet body with its placeholders taken out, ready to be inserted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    pub text: String,
+    /// The ranges of each tab stop, as byte offsets into the text, in the order Tab goes
+    /// through them: `$1`, `$2` and so on, and `$0` last, which is the end of the text if the
+    /// body has none.
+    pub stops: Vec<Vec<(usize, usize)>>,
+}
+
+/// Reads a snippet body. `$1` is a tab stop and `${1:text}` one with some text in place, which
+/// is selected when it is reached. A number used more than once puts a cursor at each place. A
+/// backslash takes the next character as it is, line breaks are followed by `indentation` and
+/// tabs are replaced with `indent`.
+pub fn parse(body: &str, indentation: &str, indent: &str) -> Template {
+    let mut text = String::new();
+    let mut stops: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+    // The number and start of the `${n:text}` placeholder being read, if any.
+    let mut placeholder: Option<(usize, usize)> = None;
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    text.push(next);
+                }
+            }
+            '$' => {
+                let braced = chars.peek() == Some(&'{');
+                if braced {
+                    let _ = chars.next();
+                }
+                let mut digits = String::new();
+                while let Some(&d) = chars.peek() {
+                    if !d.is_digit(10) {
+                        break;
+                    }
+                    digits.push(d);
+                    let _ = chars.next();
+                }
+                let number = match digits.parse::<usize>() {
+                    Ok(number) => number,
+                    Err(_) => {
+                        text.push('$');
+                        if braced {
+                            text.push('{');
+                        }
+                        continue;
+                    }
+                };
+                if braced && chars.peek() == Some(&':') {
+                    let _ = chars.next();
+                    placeholder = Some((number, text.len()));
+                } else {
+                    if braced && chars.peek() == Some(&'}') {
+                        let _ = chars.next();
+                    }
+                    stops.entry(number).or_insert_with(Vec::new).push((text.len(), text.len()));
+                }
+            }
+            '}' if placeholder.is_some() => {
+                let (number, start) = placeholder.take().unwrap();
+                stops.entry(number).or_insert_with(Vec::new).push((start, text.len()));
+            }
+            '\n' => {
+                text.push('\n');
+                text.push_str(indentation);
+            }
+            '\t' => text.push_str(indent),
+            c => text.push(c),
+        }
+    }
+
+    let last = stops.remove(&0).unwrap_or_else(|| vec![(text.len(), text.len())]);
+    let mut stops: Vec<_> = stops.into_iter().map(|(_, ranges)| ranges).collect();
+    stops.push(last);
+    Template {
+        text: text,
+        stops: stops,
+    }
+}
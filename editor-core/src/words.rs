@@ -0,0 +1,207 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+/// What a character is taken for when text is split into words.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+/// Which characters words are made of: letters, digits and underscores, along with the ones
+/// configured for the file type, such as `-` in CSS. Word motions, word deletion, word
+/// highlights and double-click selection all split words this way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WordChars {
+    extra: Vec<char>,
+}
+
+impl WordChars {
+    /// Also takes the given characters as part of words.
+    pub fn new(extra: &str) -> WordChars {
+        WordChars { extra: extra.chars().collect() }
+    }
+
+    pub fn class(&self, c: char) -> CharClass {
+        if c.is_whitespace() {
+            CharClass::Whitespace
+        } else if c.is_alphanumeric() || c == '_' || self.extra.contains(&c) {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+
+    /// The class of a grapheme cluster, which is the one of its base character.
+    pub fn class_of(&self, grapheme: &str) -> CharClass {
+        self.class(first_char(grapheme))
+    }
+
+    /// Byte index of the start of the word before `byte`, skipping any whitespace in between.
+    pub fn previous_boundary(&self, line: &str, byte: usize) -> usize {
+        let mut graphemes = line[..byte]
+            .grapheme_indices(true)
+            .rev()
+            .skip_while(|&(_, g)| self.class_of(g) == CharClass::Whitespace);
+        let class = match graphemes.next() {
+            Some((_, g)) => self.class_of(g),
+            None => return 0,
+        };
+        for (i, g) in graphemes {
+            if self.class_of(g) != class {
+                return i + g.len();
+            }
+        }
+        0
+    }
+
+    /// Byte index of the end of the word after `byte`, skipping any whitespace in between.
+    pub fn next_boundary(&self, line: &str, byte: usize) -> usize {
+        let end = content_len(line);
+        let mut graphemes = line[byte..end]
+            .grapheme_indices(true)
+            .skip_while(|&(_, g)| self.class_of(g) == CharClass::Whitespace);
+        let class = match graphemes.next() {
+            Some((_, g)) => self.class_of(g),
+            None => return end,
+        };
+        for (i, g) in graphemes {
+            if self.class_of(g) != class {
+                return byte + i;
+            }
+        }
+        end
+    }
+
+    /// Byte index of the start of the next word or run of punctuation after `byte`, where Vim's
+    /// `w` goes, or the end of the line if there is none.
+    pub fn next_start(&self, line: &str, byte: usize) -> usize {
+        let end = content_len(line);
+        let mut graphemes = line[byte..end].grapheme_indices(true);
+        let class = match graphemes.next() {
+            Some((_, g)) => self.class_of(g),
+            None => return end,
+        };
+        let mut left = class == CharClass::Whitespace;
+        for (i, g) in graphemes {
+            let current = self.class_of(g);
+            if !left && current == class {
+                continue;
+            }
+            left = true;
+            if current != CharClass::Whitespace {
+                return byte + i;
+            }
+        }
+        end
+    }
+
+    /// Byte range of the word touching `byte`, empty if there is none.
+    pub fn range(&self, line: &str, byte: usize) -> (usize, usize) {
+        let is_word = |&(_, g): &(usize, &str)| self.class_of(g) == CharClass::Word;
+        let start = match line[..byte].grapheme_indices(true).rev().take_while(&is_word).last() {
+            Some((i, _)) => i,
+            None => byte,
+        };
+        let end = match line[byte..].grapheme_indices(true).take_while(&is_word).last() {
+            Some((i, g)) => byte + i + g.len(),
+            None => byte,
+        };
+        (start, end)
+    }
+
+    /// Byte index of the start of the camelCase or snake_case part before `byte`, skipping any
+    /// whitespace and underscores in between.
+    pub fn previous_subword_boundary(&self, line: &str, byte: usize) -> usize {
+        let chars: Vec<(usize, char)> = line[..byte]
+            .grapheme_indices(true)
+            .map(|(i, g)| (i, first_char(g)))
+            .collect();
+        let position = |i: usize| if i < chars.len() { chars[i].0 } else { byte };
+        let mut i = chars.len();
+        while i > 0 && (chars[i - 1].1.is_whitespace() || chars[i - 1].1 == '_') {
+            i -= 1;
+        }
+        if i == 0 {
+            return 0;
+        }
+
+        let class = self.class(chars[i - 1].1);
+        if class != CharClass::Word {
+            while i > 0 && self.class(chars[i - 1].1) == class {
+                i -= 1;
+            }
+        } else if chars[i - 1].1.is_uppercase() {
+            while i > 0 && chars[i - 1].1.is_uppercase() {
+                i -= 1;
+            }
+        } else {
+            while i > 0 && self.is_subword_tail(chars[i - 1].1) {
+                i -= 1;
+            }
+            if i > 0 && chars[i - 1].1.is_uppercase() {
+                i -= 1;
+            }
+        }
+        position(i)
+    }
+
+    /// Byte index of the end of the camelCase or snake_case part after `byte`, skipping any
+    /// whitespace and underscores in between.
+    pub fn next_subword_boundary(&self, line: &str, byte: usize) -> usize {
+        let end = content_len(line);
+        let chars: Vec<(usize, char)> = line[byte..end]
+            .grapheme_indices(true)
+            .map(|(i, g)| (byte + i, first_char(g)))
+            .collect();
+        let position = |i: usize| if i < chars.len() { chars[i].0 } else { end };
+        let mut i = 0;
+        while i < chars.len() && (chars[i].1.is_whitespace() || chars[i].1 == '_') {
+            i += 1;
+        }
+        if i == chars.len() {
+            return end;
+        }
+
+        let class = self.class(chars[i].1);
+        if class != CharClass::Word {
+            while i < chars.len() && self.class(chars[i].1) == class {
+                i += 1;
+            }
+            return position(i);
+        }
+
+        let first = chars[i].1;
+        i += 1;
+        if first.is_uppercase() && i < chars.len() && chars[i].1.is_uppercase() {
+            // An acronym, which ends right before the capital letter of the next part, if any.
+            while i < chars.len() && chars[i].1.is_uppercase() &&
+                  !(i + 1 < chars.len() && chars[i + 1].1.is_lowercase()) {
+                i += 1;
+            }
+        } else {
+            while i < chars.len() && self.is_subword_tail(chars[i].1) {
+                i += 1;
+            }
+        }
+        position(i)
+    }
+
+    fn is_subword_tail(&self, c: char) -> bool {
+        self.class(c) == CharClass::Word && c != '_' && !c.is_uppercase()
+    }
+}
+
+/// The base character of a grapheme cluster, which decides how the whole cluster is treated.
+fn first_char(grapheme: &str) -> char {
+    grapheme.chars().next().unwrap()
+}
+
+/// Length in bytes of the line without its trailing line break.
+fn content_len(line: &str) -> usize {
+    if line.ends_with('\n') {
+        line.len() - 1
+    } else {
+        line.len()
+    }
+}
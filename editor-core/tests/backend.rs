@@ -0,0 +1,306 @@
+extern crate editor_core;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use editor_core::backend::{Cursor, EditOp, LineEnding, Move, TextBuffer, Transform};
+
+/// The whole text of the buffer, line breaks included.
+fn text(buf: &TextBuffer) -> String {
+    buf.lines().map(|line| line.as_str()).collect()
+}
+
+/// The start and end of every cursor, as lines and bytes.
+fn positions(buf: &TextBuffer) -> Vec<((usize, usize), (usize, usize))> {
+    buf.get_cursors()
+        .iter()
+        .map(|c| ((c.start_line, c.start_byte), (c.end_line, c.end_byte)))
+        .collect()
+}
+
+/// A buffer with the lines and an atomic cursor at each of the lines and characters.
+fn buffer(lines: &[&str], cursors: &[(usize, usize)]) -> TextBuffer {
+    let mut buf = TextBuffer::from_lines(lines.iter().map(|&l| String::from(l)).collect());
+    let cursors: Vec<Cursor> = cursors.iter().map(|&(l, c)| buf.cursor_at(l, c)).collect();
+    buf.set_cursors(cursors);
+    buf
+}
+
+/// A file in the temporary directory, named after the test using it so that tests running at
+/// the same time do not share one.
+fn temp_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("editor-core-test-{}.txt", name))
+}
+
+fn read(path: &Path) -> String {
+    let mut contents = String::new();
+    let _ = File::open(path).unwrap().read_to_string(&mut contents).unwrap();
+    contents
+}
+
+#[test]
+fn typing_inserts_at_every_cursor() {
+    let mut buf = buffer(&["ab", "cd"], &[(0, 1), (1, 1)]);
+    buf.apply(&EditOp::Insert(String::from("x")));
+    assert_eq!(text(&buf), "axb\ncxd\n");
+    assert_eq!(positions(&buf), vec![((0, 2), (0, 2)), ((1, 2), (1, 2))]);
+}
+
+#[test]
+fn new_lines_split_the_line_at_every_cursor() {
+    let mut buf = buffer(&["abcd"], &[(0, 1), (0, 3)]);
+    buf.apply(&EditOp::NewLine);
+    assert_eq!(text(&buf), "a\nbc\nd\n");
+    assert_eq!(positions(&buf), vec![((1, 0), (1, 0)), ((2, 0), (2, 0))]);
+}
+
+#[test]
+fn cursors_added_at_the_same_place_merge() {
+    let mut buf = buffer(&["abc"], &[(0, 1)]);
+    let cursor = buf.cursor_at(0, 1);
+    buf.add_cursor(cursor);
+    assert_eq!(positions(&buf), vec![((0, 1), (0, 1))]);
+}
+
+#[test]
+fn overlapping_selections_merge() {
+    let mut buf = buffer(&["abcd"], &[]);
+    let first = buf.cursor_between((0, 0), (0, 2));
+    let second = buf.cursor_between((0, 1), (0, 3));
+    buf.set_cursors(vec![first]);
+    buf.add_cursor(second);
+    assert_eq!(positions(&buf), vec![((0, 0), (0, 3))]);
+}
+
+#[test]
+fn cursors_meeting_after_deleting_merge() {
+    let mut buf = buffer(&["ab"], &[(0, 1), (0, 2)]);
+    buf.apply(&EditOp::Backspace);
+    assert_eq!(text(&buf), "\n");
+    assert_eq!(positions(&buf), vec![((0, 0), (0, 0))]);
+}
+
+#[test]
+fn cursors_in_text_deleted_by_another_merge_with_it() {
+    let mut buf = buffer(&["foobar baz"], &[(0, 0), (0, 2)]);
+    buf.apply(&EditOp::DeleteWordForward);
+    assert_eq!(text(&buf), " baz\n");
+    assert_eq!(positions(&buf), vec![((0, 0), (0, 0))]);
+    assert_eq!(buf.debug_validate(), Ok(()));
+}
+
+#[test]
+fn left_and_right_cross_line_breaks() {
+    let mut buf = buffer(&["ab", "c"], &[(0, 2)]);
+    buf.move_cursors(Move::Right);
+    assert_eq!(positions(&buf), vec![((1, 0), (1, 0))]);
+    buf.move_cursors(Move::Left);
+    assert_eq!(positions(&buf), vec![((0, 2), (0, 2))]);
+}
+
+#[test]
+fn left_and_right_stop_at_the_ends_of_the_buffer() {
+    let mut buf = buffer(&["ab"], &[(0, 0)]);
+    buf.move_cursors(Move::Left);
+    assert_eq!(positions(&buf), vec![((0, 0), (0, 0))]);
+    buf.move_cursors(Move::BufferEnd);
+    buf.move_cursors(Move::Right);
+    assert_eq!(positions(&buf), vec![((0, 2), (0, 2))]);
+}
+
+#[test]
+fn up_and_down_keep_the_column_through_shorter_lines() {
+    let mut buf = buffer(&["abcdef", "ab", "abcdef"], &[(0, 5)]);
+    buf.move_cursors(Move::Down);
+    assert_eq!(positions(&buf), vec![((1, 2), (1, 2))]);
+    buf.move_cursors(Move::Down);
+    assert_eq!(positions(&buf), vec![((2, 5), (2, 5))]);
+    buf.move_cursors(Move::Up);
+    buf.move_cursors(Move::Up);
+    assert_eq!(positions(&buf), vec![((0, 5), (0, 5))]);
+}
+
+#[test]
+fn line_start_goes_to_the_indentation_then_the_first_column() {
+    let mut buf = buffer(&["    foo"], &[(0, 6)]);
+    buf.move_cursors(Move::LineStart);
+    assert_eq!(positions(&buf), vec![((0, 4), (0, 4))]);
+    buf.move_cursors(Move::LineStart);
+    assert_eq!(positions(&buf), vec![((0, 0), (0, 0))]);
+    buf.move_cursors(Move::LineEnd);
+    assert_eq!(positions(&buf), vec![((0, 7), (0, 7))]);
+}
+
+#[test]
+fn buffer_start_and_end_go_to_the_first_and_last_characters() {
+    let mut buf = buffer(&["ab", "cd"], &[(0, 1)]);
+    buf.move_cursors(Move::BufferEnd);
+    assert_eq!(positions(&buf), vec![((1, 2), (1, 2))]);
+    buf.move_cursors(Move::BufferStart);
+    assert_eq!(positions(&buf), vec![((0, 0), (0, 0))]);
+}
+
+#[test]
+fn extending_selects_from_where_the_cursor_was() {
+    let mut buf = buffer(&["abc"], &[(0, 1)]);
+    buf.extend_cursors(Move::Right);
+    buf.extend_cursors(Move::Right);
+    assert_eq!(positions(&buf), vec![((0, 1), (0, 3))]);
+    assert_eq!(buf.selected_text(), "bc");
+}
+
+#[test]
+fn undo_and_redo_bring_back_the_text_and_cursors() {
+    let mut buf = buffer(&["ab"], &[(0, 2)]);
+    buf.write_str("cd");
+    assert_eq!(text(&buf), "abcd\n");
+    assert!(buf.undo());
+    assert_eq!(text(&buf), "ab\n");
+    assert_eq!(positions(&buf), vec![((0, 2), (0, 2))]);
+    assert!(!buf.undo());
+    assert!(buf.redo());
+    assert_eq!(text(&buf), "abcd\n");
+    assert_eq!(positions(&buf), vec![((0, 4), (0, 4))]);
+    assert!(!buf.redo());
+}
+
+#[test]
+fn characters_typed_together_are_undone_together() {
+    let mut buf = buffer(&[""], &[(0, 0)]);
+    buf.apply(&EditOp::Insert(String::from("abc")));
+    buf.apply(&EditOp::NewLine);
+    buf.apply(&EditOp::Insert(String::from("d")));
+    assert_eq!(text(&buf), "abc\nd\n");
+    assert!(buf.undo());
+    assert_eq!(text(&buf), "abc\n\n");
+    assert!(buf.undo());
+    assert_eq!(text(&buf), "abc\n");
+    assert!(buf.undo());
+    assert_eq!(text(&buf), "\n");
+}
+
+#[test]
+fn undoing_to_the_saved_revision_marks_the_buffer_saved() {
+    let mut buf = buffer(&["a"], &[(0, 1)]);
+    assert!(buf.is_saved());
+    buf.write_str("b");
+    assert!(!buf.is_saved());
+    assert!(buf.undo());
+    assert!(buf.is_saved());
+    assert!(buf.redo());
+    assert!(!buf.is_saved());
+}
+
+#[test]
+fn saved_text_loads_back_the_same() {
+    let path = temp_file("round-trip");
+    let mut buf = buffer(&["one", "", "three\tfour", "ünïcödé"], &[(0, 0)]);
+    buf.set_path(&path);
+    buf.save(|_, _| {}).unwrap();
+    assert!(buf.is_saved());
+    assert_eq!(read(&path), "one\n\nthree\tfour\nünïcödé\n");
+
+    let mut loaded = TextBuffer::new(Some(&path)).unwrap();
+    loaded.load(|_, _| {}).unwrap();
+    assert_eq!(text(&loaded), text(&buf));
+    assert!(loaded.is_saved());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn files_keep_their_line_endings_when_saved() {
+    let path = temp_file("line-endings");
+    File::create(&path).unwrap().write_all(b"a\r\nb\r\n").unwrap();
+
+    let mut buf = TextBuffer::new(Some(&path)).unwrap();
+    buf.load(|_, _| {}).unwrap();
+    assert_eq!(buf.get_line_ending(), LineEnding::CrLf);
+    assert_eq!(text(&buf), "a\nb\n");
+    let cursor = buf.cursor_at(0, 0);
+    buf.set_cursors(vec![cursor]);
+    buf.write_str("x");
+    buf.save(|_, _| {}).unwrap();
+    assert_eq!(read(&path), "xa\r\nb\r\n");
+    fs::remove_file(&path).unwrap();
+}
+
+/// A xorshift generator, so that the random edits are the same on every run.
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+const MOVES: [Move; 10] = [Move::Up,
+                           Move::Down,
+                           Move::Left,
+                           Move::Right,
+                           Move::WordLeft,
+                           Move::WordRight,
+                           Move::LineStart,
+                           Move::LineEnd,
+                           Move::BufferStart,
+                           Move::BufferEnd];
+
+const TEXTS: [&'static str; 7] = ["a", "é", " ", "\t", "(", "\n", "ab\ncd "];
+
+fn random_op(random: &mut Random) -> EditOp {
+    let text = String::from(TEXTS[random.below(TEXTS.len())]);
+    let movement = MOVES[random.below(MOVES.len())];
+    match random.below(27) {
+        0 | 1 | 2 => EditOp::Insert(text),
+        3 => EditOp::Paste(text),
+        4 => EditOp::NewLine,
+        5 | 6 => EditOp::Backspace,
+        7 => EditOp::Delete,
+        8 => EditOp::Tab,
+        9 => EditOp::BackTab,
+        10 => EditOp::DeleteWordBackward,
+        11 => EditOp::DuplicateLines,
+        12 => EditOp::DeleteLines,
+        13 => EditOp::MoveLinesUp,
+        14 => EditOp::MoveLinesDown,
+        15 => EditOp::AddCursorBelow,
+        16 => EditOp::SelectAll,
+        17 => EditOp::Undo,
+        18 => EditOp::Redo,
+        19 => EditOp::Move(movement),
+        20 => EditOp::Extend(movement),
+        21 => EditOp::DeleteWordForward,
+        22 => EditOp::DeleteSubwordBackward,
+        23 => EditOp::DeleteSubwordForward,
+        24 | 25 => EditOp::AddCursorAtNextOccurrence,
+        _ => EditOp::Transform(Transform::JoinLines),
+    }
+}
+
+/// Runs random edits on the buffer, checking it after each.
+fn apply_random(mut buf: TextBuffer, seed: u64) {
+    let mut random = Random(seed);
+    for step in 0..2000 {
+        let op = random_op(&mut random);
+        buf.apply(&op);
+        if let Err(e) = buf.debug_validate() {
+            panic!("seed {}, step {}, after {:?}: {}", seed, step, op, e);
+        }
+    }
+}
+
+#[test]
+fn random_edits_keep_the_buffer_valid() {
+    for seed in 1..9 {
+        apply_random(buffer(&["fn main() {", "    let a = 1;", "}"], &[(1, 4)]), seed);
+
+        let mut new = TextBuffer::new(None::<&str>).unwrap();
+        let cursor = new.cursor_at(0, 0);
+        new.set_cursors(vec![cursor]);
+        apply_random(new, seed);
+    }
+}
@@ -0,0 +1,517 @@
+use piston_window::{Button, Input, Key};
+
+use editor_core::backend::{EditOp, Move, Transform};
+
+use layout::Split;
+
+/// The modifier keys being held down.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl Modifiers {
+    pub fn update(&mut self, key: Key, pressed: bool) {
+        match key {
+            Key::LCtrl | Key::RCtrl => self.ctrl = pressed,
+            Key::LAlt | Key::RAlt => self.alt = pressed,
+            Key::LShift | Key::RShift => self.shift = pressed,
+            _ => {}
+        }
+    }
+}
+
+/// Something the editor can be told to do. Keyboard input is translated into actions before
+/// anything is done with it, so that every command goes through the same place.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Types the text at every cursor.
+    Insert(String),
+    NewLine,
+    /// Goes to the next tab stop of the snippet being filled in, expands the snippet whose
+    /// prefix is before the cursor, or inserts a tab.
+    Tab,
+    /// Goes back to the previous tab stop of the snippet being filled in.
+    BackTab,
+    Backspace,
+    Delete,
+    DeleteWordBackward,
+    DeleteWordForward,
+    DeleteSubwordBackward,
+    DeleteSubwordForward,
+    AddCursorAbove,
+    AddCursorBelow,
+    AddCursorAtNextOccurrence,
+    /// Grows or shrinks the block of text selected with a cursor per line by a line or a
+    /// column.
+    ExtendColumnSelection(Move),
+    CollapseCursors,
+    SelectAll,
+    /// Selects the lines with a cursor, or the next line too if whole lines are selected.
+    SelectLines,
+    /// Grows every selection to the word, line, paragraph or buffer around it.
+    ExpandSelection,
+    /// Copies the lines with a cursor below themselves.
+    DuplicateLines,
+    DeleteLines,
+    MoveLinesUp,
+    MoveLinesDown,
+    /// Comments out the lines with a cursor, or uncomments them.
+    ToggleComment,
+    TrimTrailingWhitespace,
+    /// Changes the case of the selections, or sorts, reverses, deduplicates or joins the lines
+    /// with a cursor.
+    Transform(Transform),
+    Undo,
+    Redo,
+    /// Moves every cursor.
+    Move(Move),
+    /// Moves the moving end of every selection, leaving the other one in place.
+    Extend(Move),
+    GoToSymbol,
+    /// Asks the language server of the buffer where the symbol under the cursor is defined, to
+    /// go there.
+    GoToDefinition,
+    /// Asks the language server of the buffer what it knows about the symbol under the cursor.
+    ShowHover,
+    /// Asks for a line and column to move the cursor to.
+    GoToLine,
+    OpenFile,
+    /// Lists the files of the project to open one of them by typing part of its path.
+    QuickOpen,
+    /// Lists the files opened last to open one of them.
+    OpenRecent,
+    /// Closes the active buffer, asking whether to save it first if it has changes.
+    CloseBuffer,
+    /// Opens the buffer closed last again, with its cursors and scroll position.
+    ReopenClosed,
+    SaveAs,
+    /// Asks for a path to move the file of the active buffer to.
+    RenameFile,
+    /// Asks for a path to write the active buffer to as a web page, in the colors of the theme.
+    ExportHtml,
+    /// Detects the line ending, indentation and file type of the buffer again.
+    Redetect,
+    ToggleHardTabs,
+    /// Turns wrapping long lines on or off for the buffer.
+    ToggleWrap,
+    /// Shows or hides spaces, tabs and the whitespace at the end of lines.
+    ToggleWhitespace,
+    /// Lists the words the misspelled one under the cursor could be, to replace it with one of
+    /// them or add it to the user dictionary.
+    ShowSpellingSuggestions,
+    /// Asks which language the buffer is spell checked in, if any.
+    SetSpellLanguage,
+    /// Lists the lines of the buffer longer than its maximum line length.
+    ShowLongLines,
+    /// Lists the files of the project with mixed line endings, other encodings than UTF-8 or no
+    /// final newline, to fix them.
+    AuditFiles,
+    SwitchToRelated,
+    /// Shows how the active buffer differs from its file as saved.
+    CompareWithSaved,
+    /// Asks for another open buffer to show how the active one differs from it.
+    CompareWithBuffer,
+    /// Goes to the next hunk of the diff in the active buffer.
+    NextHunk,
+    PreviousHunk,
+    /// Bookmarks the line of the cursor, or removes its bookmark.
+    ToggleBookmark,
+    /// Goes to the next bookmark, in this buffer or the ones after it.
+    NextBookmark,
+    PreviousBookmark,
+    /// Switches to the next of the built-in and configured themes.
+    NextTheme,
+    /// Makes the text bigger or smaller, or back to the size for the display.
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    /// Asks which of the configured tasks to run, showing its output in a buffer.
+    RunTask,
+    /// Edits every line of the project containing some text in a single buffer.
+    SearchEdit,
+    /// Lists every line of the project containing some text, grouped by file, to go to them.
+    FindInFiles,
+    ShowHistory,
+    /// Shows or hides the buffer logging the actions input was translated into.
+    ToggleInputTrace,
+    /// Shows or hides the frame rate, how long frames take to lay out and draw, and the size of
+    /// the buffer.
+    ToggleProfiler,
+    Save,
+    /// Lists every command to run one of them.
+    CommandPalette,
+    /// Runs the command with the given index of the ones the plugins registered.
+    RunPluginCommand(usize),
+    /// Starts recording the edits made into a macro, or stops and asks for a slot to keep it in.
+    ToggleMacroRecording,
+    /// Copies the text of the selections, for `Paste` to type it.
+    Copy,
+    /// Types the text copied last at every cursor.
+    Paste,
+    /// Runs the macro in the slot of the given digit.
+    PlayMacro(usize),
+    /// Starts recording the edits made, or stops and keeps them as the last macro without
+    /// saving it to a slot.
+    ToggleRecording,
+    /// Asks how many times to run the last macro recorded, and runs it that many times at
+    /// each cursor.
+    ReplayMacro,
+    /// Lists the saved macros to rename or delete them.
+    ShowMacros,
+    /// Splits the editor into two panes the given way, or joins them back if it is already
+    /// split that way.
+    ToggleSplit(Split),
+    /// Focuses the pane with the given index, counting from the left or top one.
+    FocusPane(usize),
+    /// Shows the welcome page, with the quick actions, the recent files and every key binding.
+    ShowWelcome,
+}
+
+/// An action that can be run from the command palette.
+#[derive(Clone, Debug)]
+pub struct Command {
+    pub name: &'static str,
+    /// The keys it is bound to, as shown to the user.
+    pub keys: &'static str,
+    pub action: Action,
+}
+
+/// Every action that can be run from the command palette, with the keys `from_input()` binds
+/// it to.
+pub fn commands() -> Vec<Command> {
+    let command = |name, keys, action| {
+        Command {
+            name: name,
+            keys: keys,
+            action: action,
+        }
+    };
+    vec![command("Save", "Ctrl+S", Action::Save),
+         command("Save as", "Ctrl+Shift+S", Action::SaveAs),
+         command("Rename file", "Shift+F6", Action::RenameFile),
+         command("Export as HTML", "", Action::ExportHtml),
+         command("Open file", "Ctrl+O", Action::OpenFile),
+         command("Go to file", "Ctrl+P", Action::QuickOpen),
+         command("Open recent", "", Action::OpenRecent),
+         command("Close buffer", "Ctrl+F4", Action::CloseBuffer),
+         command("Reopen closed buffer", "Ctrl+Shift+T", Action::ReopenClosed),
+         command("Switch to related file", "F4", Action::SwitchToRelated),
+         command("Compare with saved file", "", Action::CompareWithSaved),
+         command("Compare with open buffer", "", Action::CompareWithBuffer),
+         command("Next change in diff", "F7", Action::NextHunk),
+         command("Previous change in diff", "Shift+F7", Action::PreviousHunk),
+         command("Toggle bookmark", "Ctrl+F2", Action::ToggleBookmark),
+         command("Next bookmark", "F2", Action::NextBookmark),
+         command("Previous bookmark", "Shift+F2", Action::PreviousBookmark),
+         command("Go to symbol", "Ctrl+T", Action::GoToSymbol),
+         command("Go to line", "Ctrl+G", Action::GoToLine),
+         command("Go to definition", "Ctrl+B", Action::GoToDefinition),
+         command("Show symbol info", "Ctrl+K", Action::ShowHover),
+         command("Find in files", "Ctrl+Shift+F", Action::FindInFiles),
+         command("Edit lines matching", "", Action::SearchEdit),
+         command("Run task", "F5", Action::RunTask),
+         command("Copy", "", Action::Copy),
+         command("Paste", "", Action::Paste),
+         command("Undo", "Ctrl+Z", Action::Undo),
+         command("Redo", "Ctrl+Y", Action::Redo),
+         command("Show edit history", "Ctrl+Shift+H", Action::ShowHistory),
+         command("Add cursor above", "Ctrl+Alt+Up", Action::AddCursorAbove),
+         command("Add cursor below", "Ctrl+Alt+Down", Action::AddCursorBelow),
+         command("Add cursor at next occurrence", "Ctrl+D", Action::AddCursorAtNextOccurrence),
+         command("Extend block selection up",
+                 "Ctrl+Alt+Shift+Up",
+                 Action::ExtendColumnSelection(Move::Up)),
+         command("Extend block selection down",
+                 "Ctrl+Alt+Shift+Down",
+                 Action::ExtendColumnSelection(Move::Down)),
+         command("Extend block selection left",
+                 "Ctrl+Alt+Shift+Left",
+                 Action::ExtendColumnSelection(Move::Left)),
+         command("Extend block selection right",
+                 "Ctrl+Alt+Shift+Right",
+                 Action::ExtendColumnSelection(Move::Right)),
+         command("Collapse cursors", "Escape", Action::CollapseCursors),
+         command("Select all", "Ctrl+A", Action::SelectAll),
+         command("Select lines", "Ctrl+L", Action::SelectLines),
+         command("Expand selection", "Ctrl+W", Action::ExpandSelection),
+         command("Duplicate lines", "Ctrl+Shift+D", Action::DuplicateLines),
+         command("Delete lines", "Ctrl+Shift+K", Action::DeleteLines),
+         command("Move lines up", "Alt+Up", Action::MoveLinesUp),
+         command("Move lines down", "Alt+Down", Action::MoveLinesDown),
+         command("Toggle comment", "Ctrl+/", Action::ToggleComment),
+         command("Uppercase", "", Action::Transform(Transform::Uppercase)),
+         command("Lowercase", "", Action::Transform(Transform::Lowercase)),
+         command("Title case", "", Action::Transform(Transform::TitleCase)),
+         command("Sort lines", "", Action::Transform(Transform::SortLines)),
+         command("Reverse lines", "", Action::Transform(Transform::ReverseLines)),
+         command("Remove duplicate lines", "", Action::Transform(Transform::UniqueLines)),
+         command("Join lines", "Ctrl+J", Action::Transform(Transform::JoinLines)),
+         command("Toggle line wrapping", "Alt+Z", Action::ToggleWrap),
+         command("Toggle whitespace", "Alt+W", Action::ToggleWhitespace),
+         command("Spelling suggestions", "", Action::ShowSpellingSuggestions),
+         command("Set spelling language", "", Action::SetSpellLanguage),
+         command("Split side by side", "Ctrl+\\", Action::ToggleSplit(Split::Columns)),
+         command("Split one above the other",
+                 "Ctrl+Shift+\\",
+                 Action::ToggleSplit(Split::Rows)),
+         command("Focus first pane", "Ctrl+1", Action::FocusPane(0)),
+         command("Focus second pane", "Ctrl+2", Action::FocusPane(1)),
+         command("Remove trailing whitespace", "", Action::TrimTrailingWhitespace),
+         command("Toggle hard tabs", "Ctrl+Shift+I", Action::ToggleHardTabs),
+         command("Detect file settings again", "Ctrl+Shift+R", Action::Redetect),
+         command("Show long lines", "Ctrl+Shift+L", Action::ShowLongLines),
+         command("Check files for encoding and line ending problems",
+                 "Ctrl+Shift+E",
+                 Action::AuditFiles),
+         command("Record macro", "Ctrl+M", Action::ToggleMacroRecording),
+         command("Start or stop recording", "Ctrl+Q", Action::ToggleRecording),
+         command("Replay last macro", "Ctrl+Shift+Q", Action::ReplayMacro),
+         command("Manage macros", "Ctrl+Shift+M", Action::ShowMacros),
+         command("Next theme", "", Action::NextTheme),
+         command("Zoom in", "Ctrl+=", Action::ZoomIn),
+         command("Zoom out", "Ctrl+-", Action::ZoomOut),
+         command("Reset zoom", "Ctrl+0", Action::ResetZoom),
+         command("Show welcome page", "", Action::ShowWelcome),
+         command("Toggle performance overlay", "F12", Action::ToggleProfiler),
+         command("Toggle input trace", "Shift+F12", Action::ToggleInputTrace)]
+}
+
+impl Action {
+    /// Whether the action changes the text, which buffers that are read only refuse.
+    pub fn is_edit(&self) -> bool {
+        match *self {
+            Action::Insert(_) |
+            Action::NewLine |
+            Action::Tab |
+            Action::Backspace |
+            Action::Delete |
+            Action::DeleteWordBackward |
+            Action::DeleteWordForward |
+            Action::DeleteSubwordBackward |
+            Action::DeleteSubwordForward |
+            Action::DuplicateLines |
+            Action::DeleteLines |
+            Action::MoveLinesUp |
+            Action::MoveLinesDown |
+            Action::ToggleComment |
+            Action::TrimTrailingWhitespace |
+            Action::Transform(_) |
+            Action::Paste |
+            Action::Undo |
+            Action::Redo => true,
+            _ => false,
+        }
+    }
+
+    /// The change to the buffer the action makes, if it only depends on the buffer.
+    pub fn edit_op(&self) -> Option<EditOp> {
+        let op = match *self {
+            Action::Insert(ref text) => EditOp::Insert(text.clone()),
+            Action::NewLine => EditOp::NewLine,
+            Action::Tab => EditOp::Tab,
+            Action::BackTab => EditOp::BackTab,
+            Action::Backspace => EditOp::Backspace,
+            Action::Delete => EditOp::Delete,
+            Action::DeleteWordBackward => EditOp::DeleteWordBackward,
+            Action::DeleteWordForward => EditOp::DeleteWordForward,
+            Action::DeleteSubwordBackward => EditOp::DeleteSubwordBackward,
+            Action::DeleteSubwordForward => EditOp::DeleteSubwordForward,
+            Action::DuplicateLines => EditOp::DuplicateLines,
+            Action::DeleteLines => EditOp::DeleteLines,
+            Action::MoveLinesUp => EditOp::MoveLinesUp,
+            Action::MoveLinesDown => EditOp::MoveLinesDown,
+            Action::ToggleComment => EditOp::ToggleComment,
+            Action::TrimTrailingWhitespace => EditOp::TrimTrailingWhitespace,
+            Action::Transform(transform) => EditOp::Transform(transform),
+            Action::AddCursorAbove => EditOp::AddCursorAbove,
+            Action::AddCursorBelow => EditOp::AddCursorBelow,
+            Action::AddCursorAtNextOccurrence => EditOp::AddCursorAtNextOccurrence,
+            Action::ExtendColumnSelection(movement) => EditOp::ExtendColumnCursors(movement),
+            Action::CollapseCursors => EditOp::CollapseCursors,
+            Action::SelectAll => EditOp::SelectAll,
+            Action::SelectLines => EditOp::SelectLines,
+            Action::ExpandSelection => EditOp::ExpandSelection,
+            Action::Undo => EditOp::Undo,
+            Action::Redo => EditOp::Redo,
+            Action::Move(movement) => EditOp::Move(movement),
+            Action::Extend(movement) => EditOp::Extend(movement),
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// The action bound to the input, if any. `subwords` tells whether Alt moves by parts of
+    /// words.
+    pub fn from_input(input: &Input, modifiers: Modifiers, subwords: bool) -> Option<Action> {
+        let key = match *input {
+            Input::Text(ref text) => {
+                // Control characters come along with the keys that are bound to actions.
+                let text: String = text.chars().filter(|c| !c.is_control()).collect();
+                return if text.is_empty() {
+                    None
+                } else {
+                    Some(Action::Insert(text))
+                };
+            }
+            Input::Press(Button::Keyboard(key)) => key,
+            _ => return None,
+        };
+
+        let subwords = modifiers.alt && subwords;
+        let ctrl_shift = modifiers.ctrl && modifiers.shift;
+        let ctrl_alt_shift = ctrl_shift && modifiers.alt;
+        let action = match key {
+            Key::P if ctrl_shift => Action::CommandPalette,
+            Key::P if modifiers.ctrl => Action::QuickOpen,
+            Key::T if ctrl_shift => Action::ReopenClosed,
+            Key::T if modifiers.ctrl => Action::GoToSymbol,
+            Key::O if modifiers.ctrl => Action::OpenFile,
+            Key::G if modifiers.ctrl => Action::GoToLine,
+            Key::B if modifiers.ctrl => Action::GoToDefinition,
+            Key::S if ctrl_shift => Action::SaveAs,
+            Key::S if modifiers.ctrl => Action::Save,
+            Key::R if ctrl_shift => Action::Redetect,
+            Key::I if ctrl_shift => Action::ToggleHardTabs,
+            Key::Z if modifiers.alt => Action::ToggleWrap,
+            Key::W if modifiers.alt => Action::ToggleWhitespace,
+            Key::F if ctrl_shift => Action::FindInFiles,
+            Key::H if ctrl_shift => Action::ShowHistory,
+            Key::L if ctrl_shift => Action::ShowLongLines,
+            Key::E if ctrl_shift => Action::AuditFiles,
+            Key::M if ctrl_shift => Action::ShowMacros,
+            Key::M if modifiers.ctrl => Action::ToggleMacroRecording,
+            Key::Q if ctrl_shift => Action::ReplayMacro,
+            Key::Q if modifiers.ctrl => Action::ToggleRecording,
+            Key::Equals | Key::NumPadPlus if modifiers.ctrl => Action::ZoomIn,
+            Key::Minus | Key::NumPadMinus if modifiers.ctrl => Action::ZoomOut,
+            // The macros in the slots are played with Ctrl+Shift and their digit instead.
+            Key::D0 | Key::NumPad0 if modifiers.ctrl && !modifiers.shift => Action::ResetZoom,
+            Key::D1 if modifiers.ctrl && !modifiers.shift => Action::FocusPane(0),
+            Key::D2 if modifiers.ctrl && !modifiers.shift => Action::FocusPane(1),
+            key if ctrl_shift && digit(key).is_some() => Action::PlayMacro(digit(key).unwrap()),
+            Key::Backslash if ctrl_shift => Action::ToggleSplit(Split::Rows),
+            Key::Backslash if modifiers.ctrl => Action::ToggleSplit(Split::Columns),
+            Key::F2 if modifiers.ctrl => Action::ToggleBookmark,
+            Key::F2 if modifiers.shift => Action::PreviousBookmark,
+            Key::F2 => Action::NextBookmark,
+            Key::F6 if modifiers.shift => Action::RenameFile,
+            Key::F4 if modifiers.ctrl => Action::CloseBuffer,
+            Key::F4 => Action::SwitchToRelated,
+            Key::F7 if modifiers.shift => Action::PreviousHunk,
+            Key::F7 => Action::NextHunk,
+            Key::F5 => Action::RunTask,
+            Key::F12 if modifiers.shift => Action::ToggleInputTrace,
+            Key::F12 => Action::ToggleProfiler,
+            Key::Return => Action::NewLine,
+            Key::Tab if modifiers.shift => Action::BackTab,
+            Key::Tab => Action::Tab,
+            Key::Backspace if modifiers.ctrl => Action::DeleteWordBackward,
+            Key::Backspace if subwords => Action::DeleteSubwordBackward,
+            Key::Backspace => Action::Backspace,
+            Key::Delete if modifiers.ctrl => Action::DeleteWordForward,
+            Key::Delete if subwords => Action::DeleteSubwordForward,
+            Key::Delete => Action::Delete,
+            Key::Up if ctrl_alt_shift => Action::ExtendColumnSelection(Move::Up),
+            Key::Down if ctrl_alt_shift => Action::ExtendColumnSelection(Move::Down),
+            Key::Left if ctrl_alt_shift => Action::ExtendColumnSelection(Move::Left),
+            Key::Right if ctrl_alt_shift => Action::ExtendColumnSelection(Move::Right),
+            Key::Up if modifiers.ctrl && modifiers.alt => Action::AddCursorAbove,
+            Key::Down if modifiers.ctrl && modifiers.alt => Action::AddCursorBelow,
+            Key::D if ctrl_shift => Action::DuplicateLines,
+            Key::D if modifiers.ctrl => Action::AddCursorAtNextOccurrence,
+            Key::K if ctrl_shift => Action::DeleteLines,
+            Key::K if modifiers.ctrl => Action::ShowHover,
+            Key::J if modifiers.ctrl => Action::Transform(Transform::JoinLines),
+            Key::Up if modifiers.alt => Action::MoveLinesUp,
+            Key::Down if modifiers.alt => Action::MoveLinesDown,
+            Key::Slash if modifiers.ctrl => Action::ToggleComment,
+            Key::Z if ctrl_shift => Action::Redo,
+            Key::Z if modifiers.ctrl => Action::Undo,
+            Key::Y if modifiers.ctrl => Action::Redo,
+            Key::Escape => Action::CollapseCursors,
+            Key::A if modifiers.ctrl => Action::SelectAll,
+            Key::L if modifiers.ctrl => Action::SelectLines,
+            Key::W if modifiers.ctrl => Action::ExpandSelection,
+            key => {
+                let movement = match movement(key, modifiers, subwords) {
+                    Some(movement) => movement,
+                    None => return None,
+                };
+                if modifiers.shift {
+                    Action::Extend(movement)
+                } else {
+                    Action::Move(movement)
+                }
+            }
+        };
+        Some(action)
+    }
+}
+
+/// How the input is written in the input trace, if it is typing or a key press other than a
+/// modifier.
+pub fn describe(input: &Input, modifiers: Modifiers) -> Option<String> {
+    match *input {
+        Input::Text(ref text) if text.chars().any(|c| !c.is_control()) => {
+            Some(format!("{:?}", text))
+        }
+        Input::Press(Button::Keyboard(key)) => {
+            match key {
+                Key::LCtrl | Key::RCtrl | Key::LAlt | Key::RAlt | Key::LShift | Key::RShift => None,
+                key => {
+                    Some(format!("{}{}{}{:?}",
+                                 if modifiers.ctrl { "Ctrl+" } else { "" },
+                                 if modifiers.alt { "Alt+" } else { "" },
+                                 if modifiers.shift { "Shift+" } else { "" },
+                                 key))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The digit of a number key, if it is one.
+fn digit(key: Key) -> Option<usize> {
+    let digit = match key {
+        Key::D0 => 0,
+        Key::D1 => 1,
+        Key::D2 => 2,
+        Key::D3 => 3,
+        Key::D4 => 4,
+        Key::D5 => 5,
+        Key::D6 => 6,
+        Key::D7 => 7,
+        Key::D8 => 8,
+        Key::D9 => 9,
+        _ => return None,
+    };
+    Some(digit)
+}
+
+/// The cursor movement bound to a key, if any.
+fn movement(key: Key, modifiers: Modifiers, subwords: bool) -> Option<Move> {
+    let movement = match key {
+        Key::Left if modifiers.ctrl => Move::WordLeft,
+        Key::Left if subwords => Move::SubwordLeft,
+        Key::Left => Move::Left,
+        Key::Right if modifiers.ctrl => Move::WordRight,
+        Key::Right if subwords => Move::SubwordRight,
+        Key::Right => Move::Right,
+        Key::Up if modifiers.ctrl => Move::PreviousParagraph,
+        Key::Up => Move::Up,
+        Key::Down if modifiers.ctrl => Move::NextParagraph,
+        Key::Down => Move::Down,
+        Key::Home if modifiers.ctrl => Move::BufferStart,
+        Key::Home => Move::LineStart,
+        Key::End if modifiers.ctrl => Move::BufferEnd,
+        Key::End => Move::LineEnd,
+        Key::LeftBracket if modifiers.ctrl => Move::PreviousBlock,
+        Key::RightBracket if modifiers.ctrl => Move::NextBlock,
+        _ => return None,
+    };
+    Some(movement)
+}
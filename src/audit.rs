@@ -0,0 +1,124 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::str;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use git2::Repository;
+
+use super::Result;
+use dirs;
+use search;
+
+/// Something that makes a text file inconsistent with the rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// Some lines end with `\r\n` and others with `\n`.
+    MixedLineEndings,
+    /// The file is not valid UTF-8, and is taken to be Latin-1.
+    NotUtf8,
+    NoFinalNewline,
+}
+
+impl Problem {
+    pub fn description(self) -> &'static str {
+        match self {
+            Problem::MixedLineEndings => "mixed line endings",
+            Problem::NotUtf8 => "not UTF-8",
+            Problem::NoFinalNewline => "no final newline",
+        }
+    }
+}
+
+/// A file with problems.
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub path: PathBuf,
+    /// The path relative to the scanned directory.
+    pub name: String,
+    pub problems: Vec<Problem>,
+}
+
+impl Finding {
+    /// Rewrites the file as UTF-8, with the line ending most of its lines use on every line and
+    /// a final newline.
+    pub fn fix(&self) -> Result<()> {
+        let mut bytes = Vec::new();
+        let _ = try!(try!(File::open(&self.path)).read_to_end(&mut bytes));
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => e.into_bytes().into_iter().map(|b| b as char).collect(),
+        };
+
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        let line_ending = if crlf > lf { "\r\n" } else { "\n" };
+        let mut fixed = text.lines().collect::<Vec<_>>().join(line_ending);
+        if !fixed.is_empty() {
+            fixed.push_str(line_ending);
+        }
+        try!(dirs::write_atomically(&self.path, fixed.as_bytes()));
+
+        Ok(())
+    }
+}
+
+/// Looks for problems in the text files under `root` on another thread, skipping hidden files,
+/// the ones git ignores, binary files and the ones that cannot be read.
+pub fn scan(root: PathBuf) -> Receiver<Vec<Finding>> {
+    let (sender, receiver) = mpsc::channel();
+    let _ = thread::spawn(move || {
+        let (paths, queue) = mpsc::channel();
+        let repo = Repository::discover(&root).ok();
+        search::walk(&root, &root, repo.as_ref(), &paths, &AtomicBool::new(false));
+        drop(paths);
+        let findings = queue.iter().filter_map(|(path, name)| check(path, name)).collect();
+        let _ = sender.send(findings);
+    });
+    receiver
+}
+
+fn check(path: PathBuf, name: String) -> Option<Finding> {
+    let mut bytes = Vec::new();
+    if File::open(&path).and_then(|mut f| f.read_to_end(&mut bytes)).is_err() ||
+       bytes.contains(&0) {
+        return None;
+    }
+    let problems = problems(&bytes);
+    if problems.is_empty() {
+        None
+    } else {
+        Some(Finding {
+            path: path,
+            name: name,
+            problems: problems,
+        })
+    }
+}
+
+fn problems(bytes: &[u8]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut crlf = false;
+    let mut lf = false;
+    for (i, &b) in bytes.iter().enumerate() {
+        if b == b'\n' {
+            if i > 0 && bytes[i - 1] == b'\r' {
+                crlf = true;
+            } else {
+                lf = true;
+            }
+        }
+    }
+    if crlf && lf {
+        problems.push(Problem::MixedLineEndings);
+    }
+    if str::from_utf8(bytes).is_err() {
+        problems.push(Problem::NotUtf8);
+    }
+    if bytes.last().map_or(false, |&b| b != b'\n') {
+        problems.push(Problem::NoFinalNewline);
+    }
+    problems
+}
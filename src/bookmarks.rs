@@ -0,0 +1,65 @@
+use editor_core::annotations::{Annotation, Mark, Style};
+use editor_core::backend::TextBuffer;
+
+use buffers::Buffers;
+
+/// Who the bookmarks of buffers belong to among their annotations.
+pub const OWNER: &'static str = "bookmark";
+
+/// The bookmarked lines of the buffer, in order.
+pub fn lines(buf: &TextBuffer) -> Vec<usize> {
+    let mut lines: Vec<usize> = buf.annotations()
+        .on_lines(0..buf.line_count())
+        .into_iter()
+        .filter(|a| a.owner == OWNER)
+        .map(|a| a.start.0)
+        .collect();
+    // Edits can move two bookmarks to the same line.
+    lines.sort();
+    lines.dedup();
+    lines
+}
+
+/// Bookmarks the given lines of the buffer instead of the ones it had, leaving out the ones past
+/// its end.
+pub fn set_lines(buf: &mut TextBuffer, lines: &[usize]) {
+    let count = buf.line_count();
+    let marks = lines.iter()
+        .filter(|&&line| line < count)
+        .map(|&line| Annotation::new(OWNER, (line, 0), (line, 0), Style::Gutter(Mark::Bookmark)))
+        .collect();
+    buf.annotations_mut().set(OWNER, marks);
+}
+
+/// Bookmarks the line of the last cursor, or removes its bookmark if it has one.
+pub fn toggle(buf: &mut TextBuffer) {
+    let line = match buf.get_cursors().last() {
+        Some(cursor) => cursor.head_line(),
+        None => return,
+    };
+    let mut lines = lines(buf);
+    match lines.binary_search(&line) {
+        Ok(i) => {
+            let _ = lines.remove(i);
+        }
+        Err(i) => lines.insert(i, line),
+    }
+    set_lines(buf, &lines);
+}
+
+/// The buffer index and line of the bookmark after the line of the last cursor of the active
+/// buffer, or before it if not `forward`, going through the open buffers in order and wrapping
+/// around, if there is any bookmark.
+pub fn next(buffers: &Buffers, forward: bool) -> Option<(usize, usize)> {
+    let active = buffers.active_index();
+    let current = (active, buffers.active().get_cursors().last().map_or(0, |c| c.head_line()));
+    let mut all = Vec::new();
+    for index in 0..buffers.len() {
+        all.extend(lines(buffers.get(index)).into_iter().map(|line| (index, line)));
+    }
+    if forward {
+        all.iter().cloned().find(|&b| b > current).or_else(|| all.first().cloned())
+    } else {
+        all.iter().cloned().rev().find(|&b| b < current).or_else(|| all.last().cloned())
+    }
+}
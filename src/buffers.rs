@@ -0,0 +1,460 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+use editor_core::backend::{Loader, Options, Saver, TextBuffer};
+
+use git::{self, Branch};
+use multiedit::EditSession;
+use view::View;
+use super::{Error, Result};
+
+#[derive(Debug)]
+struct Entry {
+    /// Tells the buffer apart from every other one opened while the editor runs.
+    id: usize,
+    buffer: TextBuffer,
+    loader: Option<Loader>,
+    /// The save running in the background, if any.
+    saver: Option<Saver>,
+    /// The session the buffer edits, if it holds search results instead of a file.
+    session: Option<EditSession>,
+    view: View,
+    /// The comparison with the last commit running in the background, if any.
+    git: Option<Receiver<git::Status>>,
+    /// The branch of the repository the file is in, if it is in one.
+    branch: Option<Branch>,
+}
+
+impl Entry {
+    /// Writes the buffer back to the files of its session, or starts saving it to its file in
+    /// the background.
+    fn save(&mut self) -> Result<()> {
+        match self.session {
+            Some(ref mut session) => {
+                if !self.buffer.is_saved() {
+                    try!(session.write_back(&self.buffer));
+                    self.buffer.mark_saved();
+                }
+            }
+            None => {
+                // Buffers still being saved are saved again once that is over, and files
+                // changed by other programs are left until the user picks which version to keep.
+                if self.buffer.is_loaded() && self.buffer.get_path().is_some() &&
+                   self.saver.is_none() && !self.buffer.changed_on_disk() {
+                    self.saver = self.buffer.save_async();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts comparing the buffer with its file in the last commit, if it has a file.
+    fn check_git(&mut self) {
+        if let Some(path) = self.buffer.get_path() {
+            self.git = Some(git::check(path, self.buffer.text_snapshot()));
+        }
+    }
+}
+
+/// The open buffers, one of which is the active one being edited.
+#[derive(Debug)]
+pub struct Buffers {
+    entries: Vec<Entry>,
+    active: usize,
+    /// The id of the next buffer opened.
+    next_id: usize,
+}
+
+impl Buffers {
+    pub fn new() -> Buffers {
+        Buffers {
+            entries: Vec::new(),
+            active: 0,
+            next_id: 0,
+        }
+    }
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Makes the buffer for the given file the active one, opening it if it is not open yet, or
+    /// starting a new file at that path if it does not exist. Returns the index of the buffer.
+    pub fn open<P: AsRef<Path>>(&mut self, path: P, options: Options) -> Result<usize> {
+        if let Some(i) = self.position(path.as_ref()) {
+            self.active = i;
+            return Ok(i);
+        }
+        if path.as_ref().is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("{} is a folder", path.as_ref().display()))
+                .into());
+        }
+        if !path.as_ref().exists() {
+            let i = self.open_empty(options);
+            self.entries[i].buffer.set_path(path);
+            return Ok(i);
+        }
+
+        let mut buffer = try!(TextBuffer::new(Some(path.as_ref())));
+        buffer.set_options(options);
+        let loader = try!(buffer.load_async());
+        buffer.set_cursors(vec![Default::default()]);
+        let id = self.new_id();
+        self.entries.push(Entry {
+            id: id,
+            buffer: buffer,
+            loader: Some(loader),
+            saver: None,
+            session: None,
+            view: View::new(),
+            git: None,
+            branch: None,
+        });
+        self.active = self.entries.len() - 1;
+
+        Ok(self.active)
+    }
+
+    /// Opens an empty buffer that is not backed by a file as the active one. Returns the index
+    /// of the buffer.
+    pub fn open_empty(&mut self, options: Options) -> usize {
+        self.open_lines(Vec::new(), options)
+    }
+
+    /// Opens a buffer with the given lines that is not backed by a file as the active one.
+    /// Returns the index of the buffer.
+    pub fn open_lines(&mut self, lines: Vec<String>, options: Options) -> usize {
+        let mut buffer = TextBuffer::from_lines(lines);
+        buffer.set_options(options);
+        buffer.set_cursors(vec![Default::default()]);
+        let id = self.new_id();
+        self.entries.push(Entry {
+            id: id,
+            buffer: buffer,
+            loader: None,
+            saver: None,
+            session: None,
+            view: View::new(),
+            git: None,
+            branch: None,
+        });
+        self.active = self.entries.len() - 1;
+
+        self.active
+    }
+
+    /// Opens a buffer with the text recovered for the file at the path, or for a buffer without
+    /// a file, as the active one, as an unsaved change that saving writes to the file. Returns
+    /// the index of the buffer.
+    pub fn open_recovered(&mut self, path: Option<&Path>, text: &str, options: Options) -> usize {
+        let index = self.open_empty(options);
+        let buffer = &mut self.entries[index].buffer;
+        buffer.write_str(text);
+        if let Some(path) = path {
+            buffer.move_path(path);
+        }
+        buffer.set_cursors(vec![Default::default()]);
+
+        index
+    }
+
+    /// Opens the results of a multi-file search as the active buffer. Returns the index of the
+    /// buffer.
+    pub fn open_session(&mut self, session: EditSession, options: Options) -> usize {
+        let mut buffer = session.buffer();
+        buffer.set_options(options);
+        buffer.set_cursors(vec![Default::default()]);
+        let id = self.new_id();
+        self.entries.push(Entry {
+            id: id,
+            buffer: buffer,
+            loader: None,
+            saver: None,
+            session: Some(session),
+            view: View::new(),
+            git: None,
+            branch: None,
+        });
+        self.active = self.entries.len() - 1;
+
+        self.active
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> &TextBuffer {
+        &self.entries[index].buffer
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut TextBuffer {
+        &mut self.entries[index].buffer
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    /// Makes the buffer at the given index the active one.
+    pub fn activate(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.active = index;
+        }
+    }
+
+    /// Closes the buffer at the given index, which must not be the only one, moving the ones
+    /// after it down by one. If it was the active one, the buffer after it, or the one before
+    /// it if it was the last, becomes active.
+    pub fn close(&mut self, index: usize) {
+        let _ = self.entries.remove(index);
+        if self.active > index || self.active == self.entries.len() {
+            self.active -= 1;
+        }
+    }
+
+    pub fn active(&self) -> &TextBuffer {
+        self.get(self.active)
+    }
+
+    pub fn active_mut(&mut self) -> &mut TextBuffer {
+        let active = self.active;
+        self.get_mut(active)
+    }
+
+    /// The buffer at the given index along with the view showing it.
+    pub fn get_with_view(&mut self, index: usize) -> (&mut TextBuffer, &mut View) {
+        let entry = &mut self.entries[index];
+        (&mut entry.buffer, &mut entry.view)
+    }
+
+    pub fn active_with_view(&mut self) -> (&mut TextBuffer, &mut View) {
+        let active = self.active;
+        self.get_with_view(active)
+    }
+
+    /// The active buffer and its view, along with the buffer at the given index, which may be
+    /// the active one too.
+    pub fn active_with_view_and(&mut self, index: usize) -> (&TextBuffer, &mut View, &TextBuffer) {
+        let active = self.active;
+        let mut shown = None;
+        let mut other = None;
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            let Entry { ref buffer, ref mut view, .. } = *entry;
+            if i == index {
+                other = Some(buffer);
+            }
+            if i == active {
+                shown = Some((buffer, view));
+            }
+        }
+        let (buffer, view) = shown.unwrap();
+        (buffer, view, other.unwrap())
+    }
+
+    /// Loading progress of the active buffer, if it is still being loaded.
+    pub fn active_progress(&self) -> Option<f64> {
+        self.entries[self.active].loader.as_ref().map(Loader::progress)
+    }
+
+    /// Moves the lines read by the background loaders into their buffers. Returns the indices
+    /// of the buffers whose file could not be read, along with why, which stay unloaded.
+    pub fn receive_lines(&mut self) -> Vec<(usize, Error)> {
+        let mut failed = Vec::new();
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            let loaded = match entry.loader {
+                Some(ref mut loader) => entry.buffer.receive_lines(loader),
+                None => Ok(false),
+            };
+            match loaded {
+                Ok(true) => {
+                    entry.loader = None;
+                    entry.check_git();
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    entry.loader = None;
+                    failed.push((i, e.into()));
+                }
+            }
+        }
+        failed
+    }
+
+    /// Reads the file of the buffer at the given index again, dropping its text.
+    pub fn reload(&mut self, index: usize) -> Result<()> {
+        let entry = &mut self.entries[index];
+        let path = match entry.buffer.get_path() {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(()),
+        };
+        let mut buffer = try!(TextBuffer::new(Some(&path)));
+        buffer.set_options(entry.buffer.get_options().clone());
+        let loader = try!(buffer.load_async());
+        buffer.set_cursors(vec![Default::default()]);
+        entry.buffer = buffer;
+        entry.loader = Some(loader);
+        entry.view = View::new();
+
+        Ok(())
+    }
+
+    /// The index of a buffer whose file was changed by another program, if any.
+    pub fn changed_on_disk(&self) -> Option<usize> {
+        self.entries.iter().position(|e| {
+            e.loader.is_none() && e.saver.is_none() && e.session.is_none() &&
+            e.buffer.changed_on_disk()
+        })
+    }
+
+    /// The id of the buffer at the index, which unlike the index stays the same while it is
+    /// open and is never given to another buffer.
+    pub fn id(&self, index: usize) -> usize {
+        self.entries[index].id
+    }
+
+    /// The indices of the buffers with changes that are not saved.
+    pub fn unsaved(&self) -> Vec<usize> {
+        (0..self.entries.len()).filter(|&i| !self.entries[i].buffer.is_saved()).collect()
+    }
+
+    /// Whether any buffer is being saved in the background.
+    pub fn is_saving(&self) -> bool {
+        self.entries.iter().any(|e| e.saver.is_some())
+    }
+
+    /// Checks on the saves running in the background, forgetting the ones that are over. Returns
+    /// why the ones that failed did, leaving their buffers modified.
+    pub fn receive_saves(&mut self) -> Vec<Error> {
+        let mut failed = Vec::new();
+        for entry in self.entries.iter_mut() {
+            let finished = match entry.saver {
+                Some(ref saver) => entry.buffer.finish_save(saver),
+                None => Ok(false),
+            };
+            match finished {
+                Ok(true) => {
+                    entry.saver = None;
+                    entry.check_git();
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    entry.saver = None;
+                    failed.push(e.into());
+                }
+            }
+        }
+        failed
+    }
+
+    /// Replaces the gutter marks of the buffers whose comparison with the last commit is over,
+    /// unless they changed since it started. Returns whether any buffer got new marks or a new
+    /// branch.
+    pub fn receive_git_statuses(&mut self) -> bool {
+        let mut changed = false;
+        for entry in self.entries.iter_mut() {
+            let received = match entry.git {
+                Some(ref receiver) => receiver.try_recv(),
+                None => continue,
+            };
+            match received {
+                Ok(status) => {
+                    if status.version == entry.buffer.version() {
+                        entry.buffer.annotations_mut().set(git::OWNER, status.marks);
+                    }
+                    entry.git = None;
+                    entry.branch = Some(status.branch);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => {}
+                // Files outside of repositories send nothing.
+                Err(TryRecvError::Disconnected) => {
+                    entry.git = None;
+                    changed = changed || entry.branch.is_some();
+                    entry.branch = None;
+                }
+            }
+        }
+        changed
+    }
+
+    /// The branch of the repository the file of the active buffer is in, if it is in one.
+    pub fn active_branch(&self) -> Option<&Branch> {
+        self.entries[self.active].branch.as_ref()
+    }
+
+    /// Saves the active buffer to a new path, which it keeps from then on.
+    pub fn save_active_as<P: AsRef<Path>>(&mut self, path: P, options: Options) -> Result<()> {
+        let entry = &mut self.entries[self.active];
+        entry.session = None;
+        entry.buffer.set_path(path);
+        entry.buffer.set_options(options);
+        try!(entry.buffer.save(|_, _| {}));
+        entry.check_git();
+
+        Ok(())
+    }
+
+    /// Moves the file of the active buffer to the given path, creating the directories it needs,
+    /// and keeps editing it there with the options for the new path. Files that exist are not
+    /// replaced.
+    pub fn rename_active<P: AsRef<Path>>(&mut self, path: P, options: Options) -> Result<()> {
+        let path = path.as_ref();
+        let entry = &mut self.entries[self.active];
+        let old = match entry.buffer.get_path() {
+            Some(old) => old.to_path_buf(),
+            None => return Ok(()),
+        };
+        if path == old {
+            return Ok(());
+        }
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                      format!("{} already exists", path.display()))
+                .into());
+        }
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                try!(fs::create_dir_all(parent));
+            }
+        }
+        // Files that were never saved only change their path.
+        if old.exists() {
+            try!(fs::rename(&old, path));
+        }
+        entry.buffer.move_path(path);
+        entry.buffer.set_options(options);
+        entry.check_git();
+
+        Ok(())
+    }
+
+    pub fn save_active(&mut self) -> Result<()> {
+        self.entries[self.active].save()
+    }
+
+    pub fn save_all(&mut self) -> Result<()> {
+        for entry in self.entries.iter_mut() {
+            try!(entry.save());
+        }
+
+        Ok(())
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+        self.entries.iter().position(|e| match e.buffer.get_path() {
+            Some(p) => fs::canonicalize(p).unwrap_or(p.to_path_buf()) == path,
+            None => false,
+        })
+    }
+}
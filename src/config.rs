@@ -0,0 +1,663 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::iter;
+use std::path::Path;
+
+use toml::{Parser, Table, Value};
+
+use editor_core::backend::{ControlCharacters, Options};
+use editor_core::words::WordChars;
+
+use editorconfig;
+use super::{Error, Result};
+
+pub const CONFIG_FILE: &'static str = "config.toml";
+/// Settings shared by everyone working on the project the editor is started in, which override
+/// the ones in `CONFIG_FILE`, except for the commands the editor runs.
+pub const PROJECT_CONFIG_FILE: &'static str = ".editor/settings.toml";
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub backup: bool,
+    /// How long a save in the background may take before it is given up, in seconds.
+    pub save_timeout: u64,
+    /// Size in bytes from which the features that go through every line are turned off for a
+    /// file. The whole file is still read into memory.
+    pub large_file_size: u64,
+    /// When buffers are saved without being asked to, besides when the window loses focus.
+    pub autosave: Autosave,
+    /// Whether new lines copy the indentation of the previous one.
+    pub auto_indent: bool,
+    /// Whether typing an opening bracket adds the closing one.
+    pub auto_close_brackets: bool,
+    /// Whether the bracket matching the one next to the cursor is shown.
+    pub match_brackets: bool,
+    /// The text inserted for each indentation level.
+    pub indent: String,
+    /// Whether Backspace in leading spaces removes a whole indentation level.
+    pub smart_backspace: bool,
+    /// Whether Tab inserts a tab character instead of spaces.
+    pub hard_tabs: bool,
+    /// Number of columns between tab stops.
+    pub tab_width: usize,
+    /// What happens to the control characters typed or pasted into buffers.
+    pub control_characters: ControlCharacters,
+    /// Whether Alt+arrow keys move by camelCase and snake_case parts of words.
+    pub subword_motion: bool,
+    /// Whether keys go through the Vim-style normal, insert and visual modes.
+    pub vim: bool,
+    /// How long a navigation or editing key has to be held before it repeats, in milliseconds.
+    pub repeat_delay: u64,
+    /// How many times a second held keys repeat, or 0 for them not to.
+    pub repeat_rate: u64,
+    /// Whether long lines are wrapped at the width of the editor.
+    pub wrap: bool,
+    /// How long scrolling with the mouse wheel or jumping elsewhere in a buffer slides, in
+    /// milliseconds, or 0 to move at once.
+    pub scroll_animation: u64,
+    /// Whether spaces, tabs and the whitespace at the end of lines are shown.
+    pub show_whitespace: bool,
+    /// Whether the whitespace at the end of lines is removed on save.
+    pub trim_trailing_whitespace: bool,
+    /// Whether files are saved with a line break at the end.
+    pub final_newline: bool,
+    /// Number of columns past which lines are shown as too long, if any.
+    pub max_line_length: Option<usize>,
+    /// Groups of file name patterns of related files, such as headers and sources.
+    pub related_files: Vec<Vec<String>>,
+    /// Name of the theme to start with.
+    pub theme: String,
+    /// Theme files loaded along with the built-in themes.
+    pub theme_files: Vec<String>,
+    /// The font text is drawn with, as the path of a font file or the family name of an
+    /// installed font, or the font of the editor if empty.
+    pub font: String,
+    /// The fonts characters missing from the font are drawn with, tried in order.
+    pub font_fallbacks: Vec<String>,
+    /// The language buffers are spell checked in, such as `en_US`, or none if empty.
+    pub spell_language: String,
+    /// The directory of the Hunspell dictionaries, named after their language.
+    pub dictionaries: String,
+    /// The commands that can be run as tasks, such as builds, each followed by its arguments,
+    /// by name.
+    pub tasks: BTreeMap<String, Vec<String>>,
+    /// Settings per file extension.
+    pub filetypes: HashMap<String, FileType>,
+}
+
+/// When buffers are saved without being asked to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Autosave {
+    Off,
+    /// Once nothing was typed for this many seconds.
+    Idle(u64),
+    /// Every this many seconds.
+    Every(u64),
+}
+
+impl Autosave {
+    /// Whether it is time to save, given how long ago the last key was pressed and how long ago
+    /// buffers were last saved this way, in nanoseconds.
+    pub fn is_due(self, since_input: u64, since_save: u64) -> bool {
+        match self {
+            Autosave::Off => false,
+            // Only once per pause, so that a save that failed is not retried on every update.
+            Autosave::Idle(seconds) => {
+                since_input >= seconds * 1_000_000_000 && since_save > since_input
+            }
+            Autosave::Every(seconds) => since_save >= seconds * 1_000_000_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileType {
+    pub continue_comments: bool,
+    /// Overrides the maximum line length for files of this type.
+    pub max_line_length: Option<usize>,
+    pub line_comments: Vec<String>,
+    pub block_comment: Option<(String, String, String)>,
+    /// Characters other than letters, digits and underscores that words are made of.
+    pub word_characters: String,
+    /// The command starting the language server for files of this type, followed by its
+    /// arguments, if there is one.
+    pub language_server: Vec<String>,
+    /// The language the server is told the files are in, which is the extension if empty. Files
+    /// of the same language share a server.
+    pub language: String,
+    /// The bodies of the snippets Tab expands in files of this type, by their prefix.
+    pub snippets: BTreeMap<String, String>,
+    /// Whether the files are prose, whose words are all spell checked instead of only the ones
+    /// in comments.
+    pub prose: bool,
+}
+
+impl FileType {
+    fn new(line_comments: &[&str], block_comment: Option<(&str, &str, &str)>) -> FileType {
+        FileType {
+            continue_comments: true,
+            max_line_length: None,
+            line_comments: line_comments.iter().map(|&c| String::from(c)).collect(),
+            block_comment: block_comment.map(|(open, middle, close)| {
+                (String::from(open), String::from(middle), String::from(close))
+            }),
+            word_characters: String::new(),
+            language_server: Vec::new(),
+            language: String::new(),
+            snippets: BTreeMap::new(),
+            prose: false,
+        }
+    }
+}
+
+impl Default for FileType {
+    fn default() -> FileType {
+        FileType::new(&[], None)
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the given TOML file, using the defaults for everything that
+    /// is not set in it, or if the file does not exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+        Config::default().overridden_by(path)
+    }
+
+    /// Changes what the given TOML file sets, keeping the rest of the configuration. Nothing
+    /// changes if the file does not exist.
+    pub fn overridden_by<P: AsRef<Path>>(self, path: P) -> Result<Config> {
+        self.overridden(path.as_ref(), false)
+    }
+
+    /// Changes what the project settings in the given TOML file set over the user's, as
+    /// `overridden_by()` does. Projects come from whoever wrote them, and opening one must not
+    /// run their code, so the project settings may not set tasks or language servers.
+    pub fn overridden_by_project<P: AsRef<Path>>(self, path: P) -> Result<Config> {
+        self.overridden(path.as_ref(), true)
+    }
+
+    fn overridden(self, path: &Path, project: bool) -> Result<Config> {
+        let mut config = self;
+        if !path.exists() {
+            return Ok(config);
+        }
+
+        let mut contents = String::new();
+        let _ = try!(try!(File::open(path)).read_to_string(&mut contents));
+        let mut parser = Parser::new(&contents);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                return Err(Error::InvalidConfig(parser.errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")))
+            }
+        };
+        if let (true, Some(key)) = (project, command_key(&table)) {
+            let message = format!("`{}` runs commands and can only be set in {}", key, CONFIG_FILE);
+            return Err(Error::InvalidConfig(message));
+        }
+
+        if let Some(backup) = try!(lookup(&table, "save.backup", Value::as_bool)) {
+            config.backup = backup;
+        }
+        if let Some(timeout) = try!(lookup(&table, "save.timeout", Value::as_integer)) {
+            if timeout <= 0 {
+                return Err(Error::InvalidConfig(String::from("`save.timeout` must be positive")));
+            }
+            config.save_timeout = timeout as u64;
+        }
+        if let Some(size) = try!(lookup(&table, "large_file.size", Value::as_integer)) {
+            if size <= 0 {
+                return Err(Error::InvalidConfig(String::from("`large_file.size` must be \
+                                                              positive")));
+            }
+            config.large_file_size = size as u64;
+        }
+        let seconds = match try!(lookup(&table, "save.auto_seconds", Value::as_integer)) {
+            Some(seconds) if seconds <= 0 => {
+                return Err(Error::InvalidConfig(String::from("`save.auto_seconds` must be \
+                                                              positive")))
+            }
+            Some(seconds) => seconds as u64,
+            None => 5,
+        };
+        if let Some(auto) = try!(lookup(&table, "save.auto", |v| {
+            v.as_str().and_then(|name| match name {
+                "off" => Some(Autosave::Off),
+                "idle" => Some(Autosave::Idle(seconds)),
+                "every" => Some(Autosave::Every(seconds)),
+                _ => None,
+            })
+        })) {
+            config.autosave = auto;
+        }
+        if let Some(auto) = try!(lookup(&table, "indent.auto", Value::as_bool)) {
+            config.auto_indent = auto;
+        }
+        if let Some(smart) = try!(lookup(&table, "indent.smart_backspace", Value::as_bool)) {
+            config.smart_backspace = smart;
+        }
+        if let Some(auto_close) = try!(lookup(&table, "brackets.auto_close", Value::as_bool)) {
+            config.auto_close_brackets = auto_close;
+        }
+        if let Some(highlight) = try!(lookup(&table, "brackets.highlight", Value::as_bool)) {
+            config.match_brackets = highlight;
+        }
+        // Without an indentation string of its own, the file indents with its tab settings.
+        let mut tabs_changed = false;
+        if let Some(width) = try!(lookup(&table, "indent.tab_width", Value::as_integer)) {
+            if width <= 0 {
+                return Err(Error::InvalidConfig(String::from("`indent.tab_width` must be \
+                                                              positive")));
+            }
+            config.tab_width = width as usize;
+            tabs_changed = true;
+        }
+        if let Some(hard_tabs) = try!(lookup(&table, "indent.hard_tabs", Value::as_bool)) {
+            config.hard_tabs = hard_tabs;
+            tabs_changed = true;
+        }
+        if let Some(indent) = try!(lookup(&table, "indent.string", Value::as_str)) {
+            config.indent = String::from(indent);
+        } else if tabs_changed {
+            config.indent = indentation(config.hard_tabs, config.tab_width);
+        }
+        if let Some(rule) = try!(lookup(&table,
+                                        "text.control_characters",
+                                        |v| v.as_str().and_then(ControlCharacters::from_name))) {
+            config.control_characters = rule;
+        }
+        if let Some(subwords) = try!(lookup(&table, "motion.subwords", Value::as_bool)) {
+            config.subword_motion = subwords;
+        }
+        if let Some(vim) = try!(lookup(&table, "input.vim", Value::as_bool)) {
+            config.vim = vim;
+        }
+        if let Some(delay) = try!(lookup(&table, "input.repeat_delay", Value::as_integer)) {
+            if delay < 0 {
+                return Err(Error::InvalidConfig(String::from("`input.repeat_delay` must not be \
+                                                              negative")));
+            }
+            config.repeat_delay = delay as u64;
+        }
+        if let Some(rate) = try!(lookup(&table, "input.repeat_rate", Value::as_integer)) {
+            if rate < 0 {
+                return Err(Error::InvalidConfig(String::from("`input.repeat_rate` must not be \
+                                                              negative")));
+            }
+            config.repeat_rate = rate as u64;
+        }
+        if let Some(wrap) = try!(lookup(&table, "text.wrap", Value::as_bool)) {
+            config.wrap = wrap;
+        }
+        if let Some(duration) = try!(lookup(&table, "scroll.animation", Value::as_integer)) {
+            if duration < 0 {
+                return Err(Error::InvalidConfig(String::from("`scroll.animation` must not be \
+                                                              negative")));
+            }
+            config.scroll_animation = duration as u64;
+        }
+        if let Some(show) = try!(lookup(&table, "text.show_whitespace", Value::as_bool)) {
+            config.show_whitespace = show;
+        }
+        if let Some(trim) = try!(lookup(&table,
+                                        "save.trim_trailing_whitespace",
+                                        Value::as_bool)) {
+            config.trim_trailing_whitespace = trim;
+        }
+        if let Some(newline) = try!(lookup(&table, "save.final_newline", Value::as_bool)) {
+            config.final_newline = newline;
+        }
+        if let Some(max) = try!(lookup(&table, "text.max_line_length", line_length)) {
+            config.max_line_length = Some(max);
+        }
+
+        if let Some(theme) = try!(lookup(&table, "theme.name", Value::as_str)) {
+            config.theme = String::from(theme);
+        }
+        if let Some(files) = try!(lookup(&table, "theme.files", string_array)) {
+            config.theme_files = files;
+        }
+
+        if let Some(font) = try!(lookup(&table, "font.family", Value::as_str)) {
+            config.font = String::from(font);
+        }
+        if let Some(fallbacks) = try!(lookup(&table, "font.fallbacks", string_array)) {
+            config.font_fallbacks = fallbacks;
+        }
+
+        if let Some(language) = try!(lookup(&table, "spell.language", Value::as_str)) {
+            config.spell_language = String::from(language);
+        }
+        if let Some(dir) = try!(lookup(&table, "spell.dictionaries", Value::as_str)) {
+            config.dictionaries = String::from(dir);
+        }
+
+        if let Some(tasks) = try!(lookup(&table, "tasks", Value::as_table)) {
+            for (name, command) in tasks {
+                match string_array(command) {
+                    Some(ref command) if command.is_empty() => {
+                        return Err(Error::InvalidConfig(format!("`tasks.{}` needs a command",
+                                                                name)))
+                    }
+                    Some(command) => {
+                        let _ = config.tasks.insert(name.clone(), command);
+                    }
+                    None => {
+                        return Err(Error::InvalidConfig(format!("invalid value for `tasks.{}`",
+                                                                name)))
+                    }
+                }
+            }
+        }
+
+        if let Some(related) = try!(lookup(&table, "related.patterns", |v| {
+            v.as_slice().and_then(|groups| groups.iter().map(string_array).collect())
+        })) {
+            config.related_files = related;
+        }
+
+        if let Some(filetypes) = try!(lookup(&table, "filetypes", Value::as_table)) {
+            for (extension, settings) in filetypes {
+                let settings = match settings.as_table() {
+                    Some(t) => t,
+                    None => {
+                        return Err(Error::InvalidConfig(format!("invalid value for \
+                                                                 `filetypes.{}`",
+                                                                extension)))
+                    }
+                };
+                let filetype = config.filetypes
+                    .entry(extension.clone())
+                    .or_insert_with(FileType::default);
+
+                if let Some(c) = try!(lookup(settings, "continue_comments", Value::as_bool)) {
+                    filetype.continue_comments = c;
+                }
+                if let Some(max) = try!(lookup(settings, "max_line_length", line_length)) {
+                    filetype.max_line_length = Some(max);
+                }
+                if let Some(c) = try!(lookup(settings, "line_comments", string_array)) {
+                    filetype.line_comments = c;
+                }
+                if let Some(c) = try!(lookup(settings, "block_comment", string_array)) {
+                    if c.len() != 3 {
+                        return Err(Error::InvalidConfig(format!("`filetypes.{}.block_comment` \
+                                                                 needs an opening, a middle \
+                                                                 and a closing marker",
+                                                                extension)));
+                    }
+                    filetype.block_comment = Some((c[0].clone(), c[1].clone(), c[2].clone()));
+                }
+                if let Some(c) = try!(lookup(settings, "word_characters", Value::as_str)) {
+                    filetype.word_characters = String::from(c);
+                }
+                if let Some(command) = try!(lookup(settings, "language_server", string_array)) {
+                    if command.is_empty() {
+                        return Err(Error::InvalidConfig(format!("`filetypes.{}.language_server` \
+                                                                 needs a command",
+                                                                extension)));
+                    }
+                    filetype.language_server = command;
+                }
+                if let Some(language) = try!(lookup(settings, "language", Value::as_str)) {
+                    filetype.language = String::from(language);
+                }
+                if let Some(prose) = try!(lookup(settings, "prose", Value::as_bool)) {
+                    filetype.prose = prose;
+                }
+                if let Some(snippets) = try!(lookup(settings, "snippets", Value::as_table)) {
+                    for (prefix, body) in snippets {
+                        let body = match body.as_str() {
+                            Some(body) => String::from(body),
+                            None => {
+                                return Err(Error::InvalidConfig(format!("invalid value for \
+                                                                         `filetypes.{}.\
+                                                                         snippets.{}`",
+                                                                        extension,
+                                                                        prefix)))
+                            }
+                        };
+                        let _ = filetype.snippets.insert(prefix.clone(), body);
+                    }
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Buffer options for a file at the given path, as its `.editorconfig` files set them and
+    /// limited if the file is large.
+    pub fn options(&self, path: Option<&Path>) -> Options {
+        let extension = path.and_then(|p| p.extension()).map(|e| e.to_string_lossy());
+        let mut options = self.filetype_options(extension.as_ref().map(|e| &**e));
+        if let Some(path) = path {
+            editorconfig::apply(path, &mut options);
+        }
+        let size = path.and_then(|p| p.metadata().ok()).map_or(0, |m| m.len());
+        if size >= self.large_file_size {
+            options.limit_for_large_file();
+        }
+        options
+    }
+
+    /// The language of the file at the path and the command starting its language server, if
+    /// its type has one.
+    pub fn language_server(&self, path: &Path) -> Option<(String, Vec<String>)> {
+        let extension = match path.extension() {
+            Some(extension) => extension.to_string_lossy().into_owned(),
+            None => return None,
+        };
+        match self.filetypes.get(&extension) {
+            Some(filetype) if !filetype.language_server.is_empty() => {
+                let language = if filetype.language.is_empty() {
+                    extension.clone()
+                } else {
+                    filetype.language.clone()
+                };
+                Some((language, filetype.language_server.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Buffer options for files of the type configured under the given extension.
+    pub fn filetype_options(&self, extension: Option<&str>) -> Options {
+        let filetype = extension.and_then(|e| self.filetypes.get(e));
+        let mut options = Options {
+            backup: self.backup,
+            save_timeout: self.save_timeout,
+            auto_indent: self.auto_indent,
+            auto_close_brackets: self.auto_close_brackets,
+            match_brackets: self.match_brackets,
+            indent: self.indent.clone(),
+            smart_backspace: self.smart_backspace,
+            hard_tabs: self.hard_tabs,
+            tab_width: self.tab_width,
+            control_characters: self.control_characters,
+            wrap: self.wrap,
+            show_whitespace: self.show_whitespace,
+            trim_trailing_whitespace: self.trim_trailing_whitespace,
+            final_newline: self.final_newline,
+            max_line_length: self.max_line_length,
+            spell_language: self.spell_language.clone(),
+            ..Options::default()
+        };
+        if let Some(filetype) = filetype {
+            options.continue_comments = filetype.continue_comments;
+            options.line_comments = filetype.line_comments.clone();
+            options.block_comment = filetype.block_comment.clone();
+            options.word_chars = WordChars::new(&filetype.word_characters);
+            options.snippets = filetype.snippets.clone();
+            options.prose = filetype.prose;
+            if filetype.max_line_length.is_some() {
+                options.max_line_length = filetype.max_line_length;
+            }
+        }
+        options
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let c_like = FileType::new(&["//"], Some(("/*", "*", "*/")));
+        let hash = FileType::new(&["#"], None);
+
+        let mut filetypes = HashMap::new();
+        let _ = filetypes.insert(String::from("rs"),
+                                 FileType::new(&["///", "//!", "//"], Some(("/*", "*", "*/"))));
+        for extension in &["c", "h", "cpp", "hpp", "cc", "java", "js", "go", "cs", "swift"] {
+            let _ = filetypes.insert(String::from(*extension), c_like.clone());
+        }
+        for extension in &["py", "sh", "rb", "toml", "yml", "yaml", "pl"] {
+            let _ = filetypes.insert(String::from(*extension), hash.clone());
+        }
+        let _ = filetypes.insert(String::from("lua"), FileType::new(&["--"], None));
+        let _ = filetypes.insert(String::from("sql"), FileType::new(&["--"], None));
+        let mut css = FileType::new(&[], Some(("/*", "*", "*/")));
+        css.word_characters = String::from("-");
+        let _ = filetypes.insert(String::from("css"), css);
+        let mut shell = hash.clone();
+        shell.word_characters = String::from("$");
+        let _ = filetypes.insert(String::from("sh"), shell);
+        let mut prose = FileType::default();
+        prose.continue_comments = false;
+        prose.prose = true;
+        for extension in &["txt", "md", "markdown", "rst"] {
+            let _ = filetypes.insert(String::from(*extension), prose.clone());
+        }
+        // The language identifiers language servers expect, where they are not the extension.
+        for &(extension, language) in &[("rs", "rust"),
+                                        ("hpp", "cpp"),
+                                        ("cc", "cpp"),
+                                        ("js", "javascript"),
+                                        ("cs", "csharp"),
+                                        ("py", "python"),
+                                        ("sh", "shellscript"),
+                                        ("rb", "ruby"),
+                                        ("yml", "yaml"),
+                                        ("pl", "perl")] {
+            if let Some(filetype) = filetypes.get_mut(extension) {
+                filetype.language = String::from(language);
+            }
+        }
+
+        let mut tasks = BTreeMap::new();
+        for &(name, command) in &[("build", &["cargo", "build"]),
+                                  ("test", &["cargo", "test"]),
+                                  ("run", &["cargo", "run"])] {
+            let _ = tasks.insert(String::from(name),
+                                 command.iter().map(|&c| String::from(c)).collect());
+        }
+
+        let related_files = [&["*.h", "*.c", "*.cpp", "*.cc"][..],
+                             &["*.hpp", "*.cpp"][..],
+                             &["mod.rs", "tests.rs"][..],
+                             &["*.rs", "*_test.rs"][..],
+                             &["*.go", "*_test.go"][..],
+                             &["*.js", "*.test.js"][..],
+                             &["*.py", "test_*.py"][..]];
+
+        Config {
+            backup: false,
+            save_timeout: 10,
+            large_file_size: 50 * 1024 * 1024,
+            autosave: Autosave::Off,
+            auto_indent: true,
+            auto_close_brackets: true,
+            match_brackets: true,
+            indent: indentation(false, 4),
+            smart_backspace: true,
+            hard_tabs: false,
+            tab_width: 4,
+            control_characters: ControlCharacters::Visualize,
+            subword_motion: true,
+            vim: false,
+            repeat_delay: 500,
+            repeat_rate: 30,
+            wrap: false,
+            scroll_animation: 100,
+            show_whitespace: false,
+            trim_trailing_whitespace: false,
+            final_newline: true,
+            max_line_length: None,
+            related_files: related_files.iter()
+                .map(|group| group.iter().map(|&p| String::from(p)).collect())
+                .collect(),
+            theme: String::from("dark"),
+            theme_files: Vec::new(),
+            font: String::new(),
+            font_fallbacks: vec![String::from("DejaVu Sans Mono"),
+                                 String::from("Noto Sans CJK"),
+                                 String::from("Noto Emoji"),
+                                 String::from("Symbola")],
+            spell_language: String::from("en_US"),
+            dictionaries: String::from("dictionaries"),
+            tasks: tasks,
+            filetypes: filetypes,
+        }
+    }
+}
+
+/// The text of one indentation level: a tab, or a tab's width of spaces.
+pub fn indentation(hard_tabs: bool, tab_width: usize) -> String {
+    if hard_tabs {
+        String::from("\t")
+    } else {
+        iter::repeat(' ').take(tab_width).collect()
+    }
+}
+
+/// A positive number of columns.
+fn line_length(value: &Value) -> Option<usize> {
+    value.as_integer().and_then(|n| if n > 0 { Some(n as usize) } else { None })
+}
+
+fn string_array(value: &Value) -> Option<Vec<String>> {
+    value.as_slice().and_then(|values| {
+        values.iter()
+            .map(|v| v.as_str().map(String::from))
+            .collect()
+    })
+}
+
+/// The first key of the table that sets a command for the editor to run, if any.
+fn command_key(table: &Table) -> Option<String> {
+    if table.contains_key("tasks") {
+        return Some(String::from("tasks"));
+    }
+    let filetypes = table.get("filetypes").and_then(Value::as_table);
+    for (extension, settings) in filetypes.into_iter().flat_map(|t| t.iter()) {
+        if settings.as_table().map_or(false, |s| s.contains_key("language_server")) {
+            return Some(format!("filetypes.{}.language_server", extension));
+        }
+    }
+    None
+}
+
+/// Looks up a dotted key in the table, failing if it exists but has the wrong type.
+fn lookup<'t, T, F>(table: &'t Table, key: &str, convert: F) -> Result<Option<T>>
+    where F: Fn(&'t Value) -> Option<T>
+{
+    let mut parts = key.split('.');
+    let mut value = match table.get(parts.next().unwrap()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    for part in parts {
+        value = match value.as_table().and_then(|t| t.get(part)) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+    }
+
+    match convert(value) {
+        Some(v) => Ok(Some(v)),
+        None => Err(Error::InvalidConfig(format!("invalid value for `{}`", key))),
+    }
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use editor_core::backend::{LineEnding, TextBuffer};
+
+/// What the contents of a buffer look like, as far as its options are concerned.
+#[derive(Clone, Debug)]
+pub struct Detection {
+    pub line_ending: LineEnding,
+    /// The text used for each indentation level, if any line is indented.
+    pub indent: Option<String>,
+    /// The file type, as the file extension it is configured under.
+    pub filetype: Option<String>,
+}
+
+/// Inspects the buffer's path and contents.
+pub fn detect(buffer: &TextBuffer) -> Detection {
+    let line_ending = if buffer.lines().any(|l| l.ends_with("\r\n")) {
+        LineEnding::CrLf
+    } else {
+        buffer.get_line_ending()
+    };
+
+    Detection {
+        line_ending: line_ending,
+        indent: indentation(buffer),
+        filetype: filetype(buffer.get_path(), buffer.lines().next().map(String::as_str)),
+    }
+}
+
+/// Guesses the indentation unit: a tab if most indented lines start with one, otherwise the
+/// smallest step between the space indentations of consecutive lines.
+fn indentation(buffer: &TextBuffer) -> Option<String> {
+    let mut tabs = 0;
+    let mut spaces = 0;
+    let mut step = None;
+    let mut previous = 0;
+    for line in buffer.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with('\t') {
+            tabs += 1;
+            continue;
+        }
+        let width = line.len() - line.trim_left_matches(' ').len();
+        if width != 0 {
+            spaces += 1;
+        }
+        let difference = if width > previous {
+            width - previous
+        } else {
+            previous - width
+        };
+        if difference != 0 && step.map_or(true, |s| difference < s) {
+            step = Some(difference);
+        }
+        previous = width;
+    }
+
+    if tabs == 0 && spaces == 0 {
+        None
+    } else if tabs >= spaces {
+        Some(String::from("\t"))
+    } else {
+        step.map(|s| (0..s).map(|_| ' ').collect())
+    }
+}
+
+/// Takes the file type from the extension of the path, or from the interpreter in a `#!` line.
+fn filetype(path: Option<&Path>, first_line: Option<&str>) -> Option<String> {
+    if let Some(extension) = path.and_then(|p| p.extension()) {
+        return Some(extension.to_string_lossy().into_owned());
+    }
+
+    let first_line = match first_line {
+        Some(l) if l.starts_with("#!") => &l[2..],
+        _ => return None,
+    };
+    let mut words = first_line.split_whitespace();
+    let mut interpreter = words.next().and_then(|w| w.rsplit('/').next());
+    if interpreter == Some("env") {
+        interpreter = words.next();
+    }
+    let interpreter = match interpreter {
+        Some(i) => i.trim_right_matches(|c: char| c.is_digit(10) || c == '.'),
+        None => return None,
+    };
+    let extension = match interpreter {
+        "sh" | "bash" | "zsh" | "dash" => "sh",
+        "python" => "py",
+        "ruby" => "rb",
+        "perl" => "pl",
+        "lua" => "lua",
+        "node" | "nodejs" => "js",
+        _ => return None,
+    };
+    Some(String::from(extension))
+}
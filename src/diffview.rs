@@ -0,0 +1,142 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use editor_core::annotations::{Annotation, Mark, Style};
+use editor_core::backend::TextBuffer;
+use editor_core::diff::{self, DiffLine};
+
+/// Who the backgrounds of the lines added and removed in a diff belong to.
+pub const OWNER: &'static str = "diff";
+/// How many unchanged lines are shown around the changes.
+const CONTEXT: usize = 3;
+
+/// A diff along with the lines of its buffer and the backgrounds to annotate them with.
+pub type Compared = (Diff, Vec<String>, Vec<Annotation>);
+
+/// A comparison of two texts shown as a unified diff in a buffer, with the removed lines on a
+/// red background and the added ones on a green one.
+#[derive(Clone, Debug)]
+pub struct Diff {
+    /// The lines of the buffer with the header of each hunk.
+    hunks: Vec<usize>,
+}
+
+impl Diff {
+    /// Compares the lines of `old` with the ones of `new`, named as given in the header.
+    /// Returns the diff along with the lines of its buffer and the backgrounds to annotate them
+    /// with.
+    pub fn new<S: AsRef<str>>(old_name: &str,
+                              old: &[S],
+                              new_name: &str,
+                              new: &[S])
+                              -> Compared {
+        let old: Vec<&str> = old.iter().map(|l| l.as_ref()).collect();
+        let new: Vec<&str> = new.iter().map(|l| l.as_ref()).collect();
+        let lines = diff::diff(&old, &new);
+        let mut text = vec![format!("--- {}", old_name), format!("+++ {}", new_name)];
+        let mut backgrounds = Vec::new();
+        let mut hunks = Vec::new();
+
+        let ranges = diff::hunks(&lines, CONTEXT);
+        if ranges.is_empty() {
+            text.push(String::new());
+            text.push(String::from("No differences"));
+        }
+        for range in ranges {
+            let (mut old_start, mut new_start) = (old.len(), new.len());
+            let (mut old_count, mut new_count) = (0, 0);
+            for line in &lines[range.clone()] {
+                match *line {
+                    DiffLine::Same(i, j) => {
+                        old_start = old_start.min(i);
+                        new_start = new_start.min(j);
+                        old_count += 1;
+                        new_count += 1;
+                    }
+                    DiffLine::Removed(i) => {
+                        old_start = old_start.min(i);
+                        old_count += 1;
+                    }
+                    DiffLine::Added(j) => {
+                        new_start = new_start.min(j);
+                        new_count += 1;
+                    }
+                }
+            }
+            hunks.push(text.len());
+            text.push(format!("@@ -{} +{} @@",
+                              header_range(old_start, old_count),
+                              header_range(new_start, new_count)));
+            for line in &lines[range] {
+                let (shown, mark) = match *line {
+                    DiffLine::Same(i, _) => (format!(" {}", old[i]), None),
+                    DiffLine::Removed(i) => (format!("-{}", old[i]), Some(Mark::Removed)),
+                    DiffLine::Added(j) => (format!("+{}", new[j]), Some(Mark::Added)),
+                };
+                if let Some(mark) = mark {
+                    let line = text.len();
+                    backgrounds.push(Annotation::new(OWNER,
+                                                     (line, 0),
+                                                     (line, shown.len()),
+                                                     Style::Background(mark)));
+                }
+                text.push(shown);
+            }
+        }
+        (Diff { hunks: hunks }, text, backgrounds)
+    }
+
+    /// The line of the header of the hunk after the given line of the buffer, or before it if
+    /// not `forward`, wrapping around.
+    pub fn next_hunk(&self, line: usize, forward: bool) -> Option<usize> {
+        if forward {
+            self.hunks.iter().cloned().find(|&h| h > line).or_else(|| self.hunks.first().cloned())
+        } else {
+            self.hunks
+                .iter()
+                .cloned()
+                .rev()
+                .find(|&h| h < line)
+                .or_else(|| self.hunks.last().cloned())
+        }
+    }
+}
+
+/// Compares the lines as `Diff::new()` does on another thread, so that long texts with many
+/// changes do not hold up editing.
+pub fn compare(old_name: String,
+               old: Vec<String>,
+               new_name: String,
+               new: Vec<String>)
+               -> Receiver<Compared> {
+    let (sender, receiver) = mpsc::channel();
+    let _ = thread::spawn(move || {
+        let _ = sender.send(Diff::new(&old_name, &old[..], &new_name, &new[..]));
+    });
+    receiver
+}
+
+/// The lines of the buffer, without their line breaks.
+pub fn buffer_lines(buf: &TextBuffer) -> Vec<String> {
+    buf.lines().map(|l| String::from(l.trim_right_matches(|c| c == '\n' || c == '\r'))).collect()
+}
+
+/// The lines of the file as it is saved, without their line breaks.
+pub fn file_lines(path: &Path) -> io::Result<Vec<String>> {
+    let mut text = String::new();
+    let _ = try!(try!(File::open(path)).read_to_string(&mut text));
+    Ok(text.lines().map(String::from).collect())
+}
+
+/// How a hunk header writes the lines of one side: the first one, counted from 1, and how many
+/// there are, or the line before it if there are none.
+fn header_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
@@ -0,0 +1,63 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The `editor` directory in the data directory of the user, where what the editor remembers
+/// across restarts is kept.
+pub fn data_dir() -> Option<PathBuf> {
+    user_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// The `editor` directory in the configuration directory of the user, for what the user writes
+/// to change how the editor works.
+pub fn config_dir() -> Option<PathBuf> {
+    user_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// The `editor` directory in the cache directory of the user, for files that can be lost
+/// without harm.
+pub fn cache_dir() -> Option<PathBuf> {
+    user_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// The `editor` directory in the directory named by the environment variable, or in the given
+/// directory of the home directory if it is not set.
+fn user_dir(variable: &str, default: &str) -> Option<PathBuf> {
+    let dir = match env::var_os(variable) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => env::home_dir().map(|home| home.join(default)),
+    };
+    dir.map(|dir| dir.join("editor"))
+}
+
+/// The path as a file name, with the separators replaced as Vim does for swap files, so that
+/// files named after paths that end the same do not clash.
+pub fn flatten(path: &Path) -> String {
+    path.to_string_lossy()
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c == ':' { '%' } else { c })
+        .collect()
+}
+
+/// Writes the contents to a temporary file next to the path, creating the directories it needs,
+/// and renames it over the file, so that a crash never leaves it half written.
+pub fn write_atomically<P: AsRef<Path>>(path: P, contents: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            try!(fs::create_dir_all(parent));
+        }
+    }
+    let file_name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    let written = File::create(&temp_path).and_then(|mut file| {
+        try!(file.write_all(contents));
+        file.sync_all()
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, path)
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use editor_core::backend::{LineEnding, Options};
+
+use config::indentation;
+
+const EDITORCONFIG_FILE: &'static str = ".editorconfig";
+
+/// Applies the `.editorconfig` files of the directory of the file and the ones above it, up to
+/// the one marked as the root, to the options of its buffer. Closer files take precedence, and
+/// all of them over the configuration of the editor.
+pub fn apply(path: &Path, options: &mut Options) {
+    let path = match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => PathBuf::from(path),
+    };
+    let mut files = Vec::new();
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if let Some(file) = read(&current.join(EDITORCONFIG_FILE)) {
+            let root = file.root;
+            files.push((current, file));
+            if root {
+                break;
+            }
+        }
+        dir = current.parent();
+    }
+
+    let mut properties = HashMap::new();
+    for (dir, file) in files.into_iter().rev() {
+        let relative = match path.strip_prefix(dir) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        let name: String = relative.components()
+            .map(|c| format!("/{}", c.as_os_str().to_string_lossy()))
+            .collect();
+        for section in file.sections {
+            if section.matches(&name) {
+                properties.extend(section.properties);
+            }
+        }
+    }
+    set_options(&properties, options);
+}
+
+/// Sets the options named by the properties that apply to a file, leaving the ones they do not
+/// mention or that are `unset` as they were.
+fn set_options(properties: &HashMap<String, String>, options: &mut Options) {
+    // The tab width defaults to the indentation size, and an indentation size of `tab` to the
+    // tab width.
+    let indent_size = number(properties, "indent_size");
+    if let Some(width) = number(properties, "tab_width").or(indent_size) {
+        options.tab_width = width;
+    }
+    let style = property(properties, "indent_style");
+    match style {
+        Some("tab") => options.hard_tabs = true,
+        Some("space") => options.hard_tabs = false,
+        _ => {}
+    }
+    if style.is_some() || property(properties, "indent_size").is_some() {
+        let width = indent_size.unwrap_or(options.tab_width);
+        options.indent = indentation(options.hard_tabs, width);
+    }
+
+    match property(properties, "end_of_line") {
+        Some("lf") => options.line_ending = Some(LineEnding::Lf),
+        Some("crlf") => options.line_ending = Some(LineEnding::CrLf),
+        _ => {}
+    }
+    // Buffers are always saved as UTF-8: the character set only says whether with a byte order
+    // mark.
+    match property(properties, "charset") {
+        Some("utf-8") => options.bom = Some(false),
+        Some("utf-8-bom") => options.bom = Some(true),
+        _ => {}
+    }
+    if let Some(trim) = flag(properties, "trim_trailing_whitespace") {
+        options.trim_trailing_whitespace = trim;
+    }
+    if let Some(final_newline) = flag(properties, "insert_final_newline") {
+        options.final_newline = final_newline;
+    }
+}
+
+/// The value of the property, unless it is `unset`, which takes it back to the default of the
+/// editor.
+fn property<'a>(properties: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    match properties.get(key).map(String::as_str) {
+        Some("unset") => None,
+        value => value,
+    }
+}
+
+fn number(properties: &HashMap<String, String>, key: &str) -> Option<usize> {
+    property(properties, key).and_then(|v| v.parse().ok()).and_then(|n| if n > 0 {
+        Some(n)
+    } else {
+        None
+    })
+}
+
+fn flag(properties: &HashMap<String, String>, key: &str) -> Option<bool> {
+    match property(properties, key) {
+        Some("true") => Some(true),
+        Some("false") => Some(false),
+        _ => None,
+    }
+}
+
+/// An `.editorconfig` file.
+struct EditorConfig {
+    /// Whether the files above it are not read.
+    root: bool,
+    sections: Vec<Section>,
+}
+
+/// The properties set for the files matching a pattern.
+struct Section {
+    pattern: Vec<Token>,
+    properties: HashMap<String, String>,
+}
+
+impl Section {
+    /// Whether the section applies to the file at the path, given relative to the directory of
+    /// the `.editorconfig` file and starting with a slash.
+    fn matches(&self, path: &str) -> bool {
+        let path: Vec<char> = path.chars().collect();
+        matches(&self.pattern, &path)
+    }
+}
+
+/// Reads the file, if there is one. Keys and values are read in lower case, as they are not
+/// case sensitive, and lines that are not understood are skipped.
+fn read(path: &Path) -> Option<EditorConfig> {
+    let mut text = String::new();
+    match File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+        Ok(_) => {}
+        Err(_) => return None,
+    }
+    let mut file = EditorConfig {
+        root: false,
+        sections: Vec::new(),
+    };
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            file.sections.push(Section {
+                pattern: pattern(&line[1..line.len() - 1]),
+                properties: HashMap::new(),
+            });
+            continue;
+        }
+        let (key, value) = match line.find('=') {
+            Some(i) => (line[..i].trim().to_lowercase(), line[i + 1..].trim().to_lowercase()),
+            None => continue,
+        };
+        match file.sections.last_mut() {
+            Some(section) => {
+                let _ = section.properties.insert(key, value);
+            }
+            None if key == "root" => file.root = value == "true",
+            None => {}
+        }
+    }
+    Some(file)
+}
+
+/// A piece of the pattern of a section.
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    /// `?`: any character but a slash.
+    Any,
+    /// `*`: any characters but slashes.
+    Star,
+    /// `**`: any characters.
+    Stars,
+    /// `[name]`: any of the characters or ranges of characters, or any other character but a
+    /// slash if negated with `[!name]`.
+    Class(bool, Vec<(char, char)>),
+    /// `{s1,s2}`: any of the patterns.
+    Either(Vec<Vec<Token>>),
+    /// `{n1..n2}`: any integer between the two, both included.
+    Number(i64, i64),
+}
+
+/// The pattern of a section as matched against paths starting with a slash: patterns without
+/// a slash match files of that name in any directory.
+fn pattern(glob: &str) -> Vec<Token> {
+    let glob = if glob.starts_with('/') {
+        String::from(glob)
+    } else if glob.contains('/') {
+        format!("/{}", glob)
+    } else {
+        format!("**/{}", glob)
+    };
+    let chars: Vec<char> = glob.chars().collect();
+    parse(&chars, &mut 0, false)
+}
+
+/// Reads the pattern from `chars[*i]`, up to a `,` or `}` if `nested` in braces.
+fn parse(chars: &[char], i: &mut usize, nested: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    while *i < chars.len() {
+        let c = chars[*i];
+        if nested && (c == ',' || c == '}') {
+            break;
+        }
+        *i += 1;
+        match c {
+            '\\' if *i < chars.len() => {
+                tokens.push(Token::Char(chars[*i]));
+                *i += 1;
+            }
+            '?' => tokens.push(Token::Any),
+            '*' if chars.get(*i) == Some(&'*') => {
+                *i += 1;
+                tokens.push(Token::Stars);
+            }
+            '*' => tokens.push(Token::Star),
+            '[' => tokens.push(class(chars, i).unwrap_or(Token::Char('['))),
+            '{' => tokens.push(braces(chars, i)),
+            c => tokens.push(Token::Char(c)),
+        }
+    }
+    tokens
+}
+
+/// Reads the class of characters after a `[`, if it is closed.
+fn class(chars: &[char], i: &mut usize) -> Option<Token> {
+    let mut j = *i;
+    let negated = chars.get(j) == Some(&'!');
+    if negated {
+        j += 1;
+    }
+    let mut ranges = Vec::new();
+    while j < chars.len() && chars[j] != ']' {
+        if chars[j] == '/' {
+            return None;
+        }
+        if j + 2 < chars.len() && chars[j + 1] == '-' && chars[j + 2] != ']' {
+            ranges.push((chars[j], chars[j + 2]));
+            j += 3;
+        } else {
+            ranges.push((chars[j], chars[j]));
+            j += 1;
+        }
+    }
+    if j == chars.len() {
+        return None;
+    }
+    *i = j + 1;
+    Some(Token::Class(negated, ranges))
+}
+
+/// Reads the alternatives or range of numbers after a `{`, or takes the `{` as a plain
+/// character if it is not closed.
+fn braces(chars: &[char], i: &mut usize) -> Token {
+    let start = *i;
+    if let Some(end) = chars[start..].iter().position(|&c| c == '}') {
+        let inside: String = chars[start..start + end].iter().cloned().collect();
+        let bounds: Vec<&str> = inside.split("..").collect();
+        if bounds.len() == 2 {
+            if let (Ok(low), Ok(high)) = (bounds[0].parse(), bounds[1].parse()) {
+                *i = start + end + 1;
+                return Token::Number(low, high);
+            }
+        }
+    }
+
+    let mut alternatives = Vec::new();
+    loop {
+        alternatives.push(parse(chars, i, true));
+        match chars.get(*i) {
+            Some(&',') => *i += 1,
+            Some(&'}') => {
+                *i += 1;
+                break;
+            }
+            _ => {
+                *i = start;
+                return Token::Char('{');
+            }
+        }
+    }
+    // Without a comma, the braces are plain characters.
+    if alternatives.len() == 1 {
+        let mut tokens = vec![Token::Char('{')];
+        tokens.extend(alternatives.pop().unwrap());
+        tokens.push(Token::Char('}'));
+        alternatives.push(tokens);
+    }
+    Token::Either(alternatives)
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+    match *token {
+        Token::Char(c) => text.first() == Some(&c) && matches(rest, &text[1..]),
+        Token::Any => text.first().map_or(false, |&c| c != '/') && matches(rest, &text[1..]),
+        Token::Star => {
+            (0..text.len() + 1)
+                .take_while(|&n| n == 0 || text[n - 1] != '/')
+                .any(|n| matches(rest, &text[n..]))
+        }
+        Token::Stars => (0..text.len() + 1).any(|n| matches(rest, &text[n..])),
+        Token::Class(negated, ref ranges) => {
+            text.first().map_or(false, |&c| {
+                c != '/' && ranges.iter().any(|&(low, high)| low <= c && c <= high) != negated
+            }) && matches(rest, &text[1..])
+        }
+        Token::Either(ref patterns) => {
+            patterns.iter().any(|pattern| {
+                let mut joined = pattern.clone();
+                joined.extend(rest.iter().cloned());
+                matches(&joined, text)
+            })
+        }
+        Token::Number(low, high) => {
+            (1..text.len() + 1).any(|n| {
+                let number: String = text[..n].iter().cloned().collect();
+                number.parse().ok().map_or(false, |n: i64| low <= n && n <= high) &&
+                matches(rest, &text[n..])
+            })
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+use editor_core::annotations::{Mark, Style};
+use editor_core::backend::TextBuffer;
+
+use theme::{Color, Theme};
+
+/// Writes the buffer to a standalone HTML page, with line numbers and the colors of the theme.
+/// Highlighted and underlined text, such as errors, and the backgrounds of the lines of diffs
+/// are kept.
+pub fn write_html(buf: &TextBuffer, theme: &Theme, path: &Path) -> io::Result<()> {
+    let title = buf.get_path().map_or(String::from("untitled"), |p| p.display().to_string());
+    let mut file = try!(File::create(path));
+    try!(file.write_all(html(buf, theme, &title).as_bytes()));
+    Ok(())
+}
+
+fn html(buf: &TextBuffer, theme: &Theme, title: &str) -> String {
+    let number_width = buf.line_count().to_string().len();
+    let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                            <title>{}</title>\n<style>\n\
+                            body {{ margin: 0; background: {}; }}\n\
+                            pre {{ margin: 0; padding: 1em; color: {}; background: {}; \
+                            font-family: monospace; tab-size: {}; }}\n\
+                            .number {{ color: {}; user-select: none; }}\n\
+                            .highlight {{ background: {}; }}\n\
+                            .underline {{ text-decoration: underline wavy {}; }}\n\
+                            </style>\n</head>\n<body>\n<pre>",
+                           escape(title),
+                           css(theme.background),
+                           css(theme.text),
+                           css(theme.editor_background),
+                           buf.get_options().tab_width,
+                           css(theme.gutter),
+                           css(theme.highlight),
+                           css(theme.underline));
+    for (i, line) in buf.lines().enumerate() {
+        let text = line.trim_right_matches(|c| c == '\n' || c == '\r');
+        let annotations = buf.annotations().on_lines(i..i + 1);
+        let background = annotations.iter()
+            .filter_map(|a| match a.style {
+                Style::Background(mark) => Some(mark),
+                _ => None,
+            })
+            .last();
+        match background {
+            Some(mark) => {
+                let color = mark_color(theme, mark);
+                html.push_str(&format!("<div style=\"background: {}\">",
+                                       css([color[0], color[1], color[2], 0.2])))
+            }
+            None => html.push_str("<div>"),
+        }
+        html.push_str(&format!("<span class=\"number\">{:>width$}  </span>",
+                               i + 1,
+                               width = number_width));
+
+        // The text is cut wherever an annotation starts or ends, and each piece is styled by
+        // the annotations covering it.
+        let spans: Vec<(usize, usize, &'static str)> = annotations.iter()
+            .filter_map(|a| {
+                let class = match a.style {
+                    Style::Highlight => "highlight",
+                    Style::Underline => "underline",
+                    _ => return None,
+                };
+                let start = if a.start.0 == i { a.start.1 } else { 0 };
+                let end = if a.end.0 == i { a.end.1 } else { text.len() };
+                Some((start.min(text.len()), end.min(text.len()), class))
+            })
+            .collect();
+        let mut cuts: Vec<usize> = spans.iter()
+            .flat_map(|&(start, end, _)| vec![start, end])
+            .chain(vec![0, text.len()])
+            .filter(|&cut| text.is_char_boundary(cut))
+            .collect();
+        cuts.sort();
+        cuts.dedup();
+        for piece in cuts.windows(2) {
+            let (start, end) = (piece[0], piece[1]);
+            let mut classes: Vec<&str> = spans.iter()
+                .filter(|&&(s, e, _)| s <= start && end <= e)
+                .map(|&(_, _, class)| class)
+                .collect();
+            classes.sort();
+            classes.dedup();
+            if classes.is_empty() {
+                html.push_str(&escape(&text[start..end]));
+            } else {
+                html.push_str(&format!("<span class=\"{}\">{}</span>",
+                                       classes.join(" "),
+                                       escape(&text[start..end])));
+            }
+        }
+        html.push_str("</div>");
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+fn mark_color(theme: &Theme, mark: Mark) -> Color {
+    match mark {
+        Mark::Added => theme.added,
+        Mark::Modified => theme.modified,
+        Mark::Removed => theme.removed,
+        Mark::Bookmark => theme.bookmark,
+    }
+}
+
+/// The color as CSS.
+fn css(color: Color) -> String {
+    format!("rgba({}, {}, {}, {})",
+            (color[0] * 255.0).round() as u8,
+            (color[1] * 255.0).round() as u8,
+            (color[2] * 255.0).round() as u8,
+            color[3])
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,144 @@
+use std::cmp::{self, Ordering};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::Result;
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_dir: bool,
+    pub depth: usize,
+    pub expanded: bool,
+}
+
+/// The file tree shown in the side panel, stored as the list of its visible rows.
+#[derive(Clone, Debug)]
+pub struct FileTree {
+    root: PathBuf,
+    rows: Vec<Entry>,
+    scroll: usize,
+}
+
+impl FileTree {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<FileTree> {
+        Ok(FileTree {
+            root: root.as_ref().to_path_buf(),
+            rows: try!(read_entries(root.as_ref(), 0)),
+            scroll: 0,
+        })
+    }
+
+    /// A tree with no rows, for a folder that cannot be read.
+    pub fn empty<P: AsRef<Path>>(root: P) -> FileTree {
+        FileTree {
+            root: root.as_ref().to_path_buf(),
+            rows: Vec::new(),
+            scroll: 0,
+        }
+    }
+
+    /// Reads the tree again after files were added, moved or removed, keeping the directories
+    /// that were expanded open.
+    pub fn refresh(&mut self) -> Result<()> {
+        *self = try!(self.refreshed());
+
+        Ok(())
+    }
+
+    /// The tree as it is now on disk, with the same directories expanded and the same scroll.
+    pub fn refreshed(&self) -> Result<FileTree> {
+        let expanded: Vec<&PathBuf> = self.rows
+            .iter()
+            .filter(|e| e.expanded)
+            .map(|e| &e.path)
+            .collect();
+        let mut tree = FileTree {
+            root: self.root.clone(),
+            rows: try!(read_entries(&self.root, 0)),
+            scroll: 0,
+        };
+        let mut row = 0;
+        while row < tree.rows.len() {
+            if tree.rows[row].is_dir && expanded.contains(&&tree.rows[row].path) {
+                let _ = try!(tree.activate(row));
+            }
+            row += 1;
+        }
+        tree.scroll_to(self.scroll);
+
+        Ok(tree)
+    }
+
+    pub fn rows(&self) -> &[Entry] {
+        &self.rows
+    }
+
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll
+    }
+
+    pub fn scroll(&mut self, rows: isize) {
+        let scroll = self.scroll as isize + rows;
+        self.scroll_to(if scroll < 0 { 0 } else { scroll as usize });
+    }
+
+    /// Scrolls to show the given row at the top, or the last row if there are not that many.
+    pub fn scroll_to(&mut self, row: usize) {
+        self.scroll = cmp::min(row, self.rows.len().saturating_sub(1));
+    }
+
+    /// Expands or collapses the directory at the given row, or returns the path of the file in
+    /// it.
+    pub fn activate(&mut self, row: usize) -> Result<Option<PathBuf>> {
+        if row >= self.rows.len() {
+            return Ok(None);
+        }
+        if !self.rows[row].is_dir {
+            return Ok(Some(self.rows[row].path.clone()));
+        }
+
+        let depth = self.rows[row].depth;
+        if self.rows[row].expanded {
+            let end = self.rows[row + 1..]
+                .iter()
+                .position(|e| e.depth <= depth)
+                .map_or(self.rows.len(), |i| row + 1 + i);
+            let _ = self.rows.drain(row + 1..end);
+        } else {
+            let children = try!(read_entries(&self.rows[row].path, depth + 1));
+            for (i, child) in children.into_iter().enumerate() {
+                self.rows.insert(row + 1 + i, child);
+            }
+        }
+        self.rows[row].expanded = !self.rows[row].expanded;
+
+        Ok(None)
+    }
+}
+
+/// Reads the entries of a directory, directories first, skipping hidden ones.
+fn read_entries(dir: &Path, depth: usize) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in try!(fs::read_dir(dir)) {
+        let entry = try!(entry);
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        entries.push(Entry {
+            path: entry.path(),
+            name: name,
+            is_dir: try!(entry.file_type()).is_dir(),
+            depth: depth,
+            expanded: false,
+        });
+    }
+    entries.sort_by(|a, b| match b.is_dir.cmp(&a.is_dir) {
+        Ordering::Equal => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        ordering => ordering,
+    });
+
+    Ok(entries)
+}
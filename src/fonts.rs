@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use piston_window::character::{Character, CharacterCache};
+use piston_window::types::FontSize;
+use rusttype::{Codepoint, Font, FontCollection};
+
+/// The font shipped with the editor, used when no other is configured or found.
+pub const DEFAULT_FONT: &'static str = "fonts/cnr.otf";
+
+/// Where fonts are installed on Linux, macOS and Windows, under the home directory for the
+/// ones starting with `~`.
+const FONT_DIRS: [&'static str; 8] = ["/usr/share/fonts",
+                                      "/usr/local/share/fonts",
+                                      "~/.fonts",
+                                      "~/.local/share/fonts",
+                                      "/System/Library/Fonts",
+                                      "/Library/Fonts",
+                                      "~/Library/Fonts",
+                                      "C:\\Windows\\Fonts"];
+
+/// A chain of fonts drawn as one: each character is drawn with the first font having a glyph
+/// for it, so that emoji, CJK or box-drawing characters missing from the main font are still
+/// shown.
+pub struct Fonts<C> {
+    caches: Vec<C>,
+    fonts: Vec<Font<'static>>,
+    /// The font chosen for each character seen so far, by index.
+    chosen: HashMap<char, usize>,
+}
+
+impl<C: CharacterCache> Fonts<C> {
+    /// Loads the fonts of the files, as `find_all()` gives them, with `new_cache` making the
+    /// glyph cache of a file. Fonts that cannot be read are reported on the standard error, and
+    /// are only an error if none can be read before the font of the editor.
+    pub fn load<F>(paths: Vec<PathBuf>, mut new_cache: F) -> io::Result<Fonts<C>>
+        where F: FnMut(&Path) -> io::Result<C>
+    {
+        let mut fonts = Fonts {
+            caches: Vec::new(),
+            fonts: Vec::new(),
+            chosen: HashMap::new(),
+        };
+        for path in paths {
+            match read_font(&path).and_then(|font| new_cache(&path).map(|cache| (font, cache))) {
+                Ok((font, cache)) => {
+                    fonts.fonts.push(font);
+                    fonts.caches.push(cache);
+                }
+                Err(e) => {
+                    // Without the font of the editor there may be nothing left to draw with.
+                    if path == Path::new(DEFAULT_FONT) && fonts.caches.is_empty() {
+                        return Err(e);
+                    }
+                    let _ = writeln!(io::stderr(), "font {}: {}", path.display(), e);
+                }
+            }
+        }
+        Ok(fonts)
+    }
+
+    /// The index of the first font of the chain with a glyph for the character, or of the
+    /// primary font if none has one.
+    fn font_for(&mut self, ch: char) -> usize {
+        if let Some(&index) = self.chosen.get(&ch) {
+            return index;
+        }
+        let index = self.fonts
+            .iter()
+            .position(|f| f.glyph(Codepoint(ch as u32)).map_or(false, |g| g.id().0 != 0))
+            .unwrap_or(0);
+        let _ = self.chosen.insert(ch, index);
+        index
+    }
+}
+
+impl<C: CharacterCache> CharacterCache for Fonts<C> {
+    type Texture = C::Texture;
+
+    fn character<'a>(&'a mut self, font_size: FontSize, ch: char) -> Character<'a, C::Texture> {
+        let index = self.font_for(ch);
+        self.caches[index].character(font_size, ch)
+    }
+}
+
+/// The font files of the primary font followed by the fallbacks, each given as the path of a
+/// font file or the family name of an installed font, ending with the font of the editor.
+/// Fallbacks that are not installed are skipped, and a primary font that cannot be found is
+/// reported on the standard error. Going through the installed fonts takes a while, so this is
+/// done in the background while the window comes up.
+pub fn find_all(primary: &str, fallbacks: &[String]) -> Vec<PathBuf> {
+    let installed = installed_fonts();
+    let mut paths = Vec::new();
+    if !primary.is_empty() {
+        match find(primary, &installed) {
+            Some(path) => paths.push(path),
+            None => {
+                let _ = writeln!(io::stderr(), "font {}: not found", primary);
+            }
+        }
+    }
+    paths.extend(fallbacks.iter().filter_map(|name| find(name, &installed)));
+    if !paths.iter().any(|p| p == Path::new(DEFAULT_FONT)) {
+        paths.push(PathBuf::from(DEFAULT_FONT));
+    }
+    paths
+}
+
+/// The path of the font file of the given name, which is either a path to a file or the family
+/// name of an installed font such as `DejaVu Sans Mono`.
+fn find(name: &str, installed: &[PathBuf]) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+    if path.is_file() {
+        return Some(path);
+    }
+    // Font files are named after their family, followed by their style: the regular one is
+    // the shortest name.
+    let family = normalize(name);
+    installed.iter()
+        .filter(|p| {
+            p.file_stem().map_or(false, |s| normalize(&s.to_string_lossy()).starts_with(&family))
+        })
+        .min_by_key(|p| p.file_stem().map_or(0, |s| s.len()))
+        .cloned()
+}
+
+/// The name in lower case without spaces, dashes or underscores, so that `DejaVu Sans Mono`
+/// matches `DejaVuSansMono.ttf`.
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|&c| c != ' ' && c != '-' && c != '_')
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// The TrueType and OpenType files of the font directories and their subdirectories.
+fn installed_fonts() -> Vec<PathBuf> {
+    let home = env::home_dir();
+    let mut found = Vec::new();
+    let mut dirs: Vec<PathBuf> = FONT_DIRS.iter()
+        .filter_map(|dir| if dir.starts_with("~/") {
+            home.as_ref().map(|home| home.join(&dir[2..]))
+        } else {
+            Some(PathBuf::from(dir))
+        })
+        .collect();
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().map_or(false, |e| {
+                let e = e.to_string_lossy().to_lowercase();
+                e == "ttf" || e == "otf"
+            }) {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+fn read_font(path: &Path) -> io::Result<Font<'static>> {
+    let mut bytes = Vec::new();
+    let _ = try!(try!(File::open(path)).read_to_end(&mut bytes));
+    match FontCollection::from_bytes(bytes).into_font() {
+        Some(font) => Ok(font),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "not a font")),
+    }
+}
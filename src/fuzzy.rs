@@ -0,0 +1,37 @@
+/// Scores how well `pattern` matches `candidate` as a case-insensitive subsequence. Consecutive
+/// matches and matches at the start of words score higher. Returns `None` if it does not match.
+pub fn score(pattern: &str, candidate: &str) -> Option<usize> {
+    let mut pattern = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for c in candidate.chars() {
+        let matched = match pattern.peek() {
+            Some(&p) => c.to_lowercase().next() == Some(p),
+            None => break,
+        };
+        if matched {
+            let _ = pattern.next();
+            score += 1;
+            if previous_matched {
+                score += 5;
+            }
+            let word_start = match previous {
+                None => true,
+                Some(p) => !p.is_alphanumeric() || (p.is_lowercase() && c.is_uppercase()),
+            };
+            if word_start {
+                score += 3;
+            }
+        }
+        previous_matched = matched;
+        previous = Some(c);
+    }
+
+    if pattern.peek().is_none() {
+        Some(score)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,170 @@
+use std::cmp;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use git2::{self, Repository, StatusOptions};
+
+use editor_core::annotations::{Annotation, Mark, Style};
+use editor_core::backend::TextSnapshot;
+
+/// Who the gutter marks of the lines changed since the last commit belong to.
+pub const OWNER: &'static str = "git";
+
+/// Past this many pairs of lines between the first and the last change, the lines in between
+/// are all marked as changed instead of being matched one by one.
+const MAX_COMPARISONS: usize = 4_000_000;
+
+/// The repository of a file, as shown in the status bar.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Branch {
+    /// The name of the branch checked out, or `HEAD` if there is none.
+    pub name: String,
+    /// Whether any tracked file has changes that are not committed.
+    pub dirty: bool,
+}
+
+/// What comparing the text of a buffer with its file in the last commit found.
+#[derive(Debug)]
+pub struct Status {
+    /// The version of the buffer the text was taken at.
+    pub version: usize,
+    pub branch: Branch,
+    /// The gutter marks of the added, modified and removed lines.
+    pub marks: Vec<Annotation>,
+}
+
+/// Compares the text with the file at the path in `HEAD` on another thread. Nothing is sent for
+/// files outside of a git repository.
+pub fn check(path: &Path, text: TextSnapshot) -> Receiver<Status> {
+    let (sender, receiver) = mpsc::channel();
+    let path = path.to_path_buf();
+    let _ = thread::spawn(move || {
+        if let Ok(Some(status)) = status(&path, &text) {
+            let _ = sender.send(status);
+        }
+    });
+    receiver
+}
+
+fn status(path: &Path, text: &TextSnapshot) -> Result<Option<Status>, git2::Error> {
+    let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    let repo = try!(Repository::discover(&path));
+    let relative = match repo.workdir().and_then(|w| path.strip_prefix(w).ok()) {
+        Some(relative) => relative.to_path_buf(),
+        None => return Ok(None),
+    };
+
+    // A repository without commits yet has no `HEAD` to compare with.
+    let head = repo.head().ok();
+    let name = head.as_ref().and_then(|h| h.shorthand()).unwrap_or("HEAD").to_owned();
+    let committed = match head.as_ref().and_then(|h| h.target()) {
+        Some(oid) => {
+            let tree = try!(try!(repo.find_commit(oid)).tree());
+            match tree.get_path(&relative) {
+                Ok(entry) => {
+                    let blob = try!(repo.find_blob(entry.id()));
+                    String::from_utf8_lossy(blob.content()).into_owned()
+                }
+                // Files that were never committed are added as a whole.
+                Err(_) => String::new(),
+            }
+        }
+        None => String::new(),
+    };
+
+    let mut options = StatusOptions::new();
+    let _ = options.include_untracked(false);
+    let dirty = !try!(repo.statuses(Some(&mut options))).is_empty();
+
+    // Split the same way as the committed text, so that a final line break makes no line.
+    let current: String = text.lines().map(String::as_str).collect();
+    let old: Vec<&str> = committed.lines().collect();
+    let new: Vec<&str> = current.lines().collect();
+    Ok(Some(Status {
+        version: text.version(),
+        branch: Branch {
+            name: name,
+            dirty: dirty,
+        },
+        marks: marks(&old, &new),
+    }))
+}
+
+/// The marks of the lines of `new` that are not in `old`, matching the longest common sequence of
+/// lines between the first and the last change. Lines replacing removed ones are modified, the
+/// others are added, and removed lines with nothing in their place are marked on the next line,
+/// or on the last one if they were at the end.
+fn marks(old: &[&str], new: &[&str]) -> Vec<Annotation> {
+    let prefix = old.iter().zip(new).take_while(|&(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let old_changed = &old[prefix..old.len() - suffix];
+    let new_changed = &new[prefix..new.len() - suffix];
+
+    // Each hunk is the first line of `new` it is at, with the lines it removes and adds.
+    let mut hunks = Vec::new();
+    if old_changed.len() * new_changed.len() > MAX_COMPARISONS {
+        hunks.push((prefix, old_changed.len(), new_changed.len()));
+    } else {
+        // `common[i][j]` is the length of the longest common sequence of the changed lines from
+        // the `i`-th old one and the `j`-th new one on.
+        let (n, m) = (old_changed.len(), new_changed.len());
+        let mut common = vec![vec![0u32; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                common[i][j] = if old_changed[i] == new_changed[j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    cmp::max(common[i + 1][j], common[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        let mut hunk = (prefix, 0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_changed[i] == new_changed[j] {
+                if hunk.1 + hunk.2 != 0 {
+                    hunks.push(hunk);
+                }
+                i += 1;
+                j += 1;
+                hunk = (prefix + j, 0, 0);
+            } else if j < m && (i == n || common[i][j + 1] >= common[i + 1][j]) {
+                hunk.2 += 1;
+                j += 1;
+            } else {
+                hunk.1 += 1;
+                i += 1;
+            }
+        }
+        if hunk.1 + hunk.2 != 0 {
+            hunks.push(hunk);
+        }
+    }
+
+    let mark = |first: usize, count: usize, mark: Mark| {
+        let last = first + count - 1;
+        Annotation::new(OWNER, (first, 0), (last, new[last].len()), Style::Gutter(mark))
+    };
+    let mut marks = Vec::new();
+    for (line, removed, added) in hunks {
+        let modified = cmp::min(removed, added);
+        if modified != 0 {
+            marks.push(mark(line, modified, Mark::Modified));
+        }
+        if added > modified {
+            marks.push(mark(line + modified, added - modified, Mark::Added));
+        }
+        if added == 0 {
+            let line = cmp::min(line, new.len().saturating_sub(1));
+            marks.push(Annotation::new(OWNER, (line, 0), (line, 0), Style::Gutter(Mark::Removed)));
+        }
+    }
+    marks
+}
@@ -0,0 +1,324 @@
+use std::cmp;
+
+use piston_window::Size;
+
+/// A rectangle as `[x, y, width, height]` in window coordinates.
+pub type Rect = [f64; 4];
+
+/// Which way the editor is split into two panes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Split {
+    /// Side by side.
+    Columns,
+    /// One above the other.
+    Rows,
+}
+
+/// Where every part of the window goes. All of it is derived from the window size and the font
+/// size times the scale, so that resizing the window or zooming reflows every widget the same way.
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    em: f64,
+    /// The file tree.
+    pub sidebar: Rect,
+    /// Everything right of the sidebar and above the status bar: the gutter, the text and the
+    /// scrollbar.
+    pub editor: Rect,
+    pub gutter: Rect,
+    pub text: Rect,
+    /// The outline of the buffer left of the scrollbar.
+    pub minimap: Rect,
+    /// The track of the scrollbar along the right edge of the editor.
+    pub scrollbar: Rect,
+    pub status_bar: Rect,
+}
+
+impl Layout {
+    pub fn new(size: Size, em: f64, scale: f64) -> Layout {
+        let em = em * scale;
+        let width = size.width as f64;
+        let height = size.height as f64;
+        let status_height = em * 1.1;
+        let content_height = (height - status_height).max(0.0);
+        let sidebar_width = (em * 8.0).min(width / 3.0);
+        let editor = [sidebar_width, 0.0, (width - sidebar_width).max(0.0), content_height];
+
+        let layout = Layout {
+            em: em,
+            sidebar: [0.0, 0.0, sidebar_width, content_height],
+            editor: editor,
+            gutter: editor,
+            text: editor,
+            minimap: editor,
+            scrollbar: editor,
+            status_bar: [0.0, content_height, width, status_height],
+        };
+        layout.with_editor(editor)
+    }
+
+    /// The layouts of the two panes the editor is split into, left or top first, with a thin
+    /// gap between them. Everything but the editor stays where it is.
+    pub fn split(&self, split: Split) -> [Layout; 2] {
+        let editor = self.editor;
+        let gap = self.em * 0.1;
+        match split {
+            Split::Columns => {
+                let width = ((editor[2] - gap) / 2.0).max(0.0);
+                [self.with_editor([editor[0], editor[1], width, editor[3]]),
+                 self.with_editor([editor[0] + editor[2] - width, editor[1], width, editor[3]])]
+            }
+            Split::Rows => {
+                let height = ((editor[3] - gap) / 2.0).max(0.0);
+                [self.with_editor([editor[0], editor[1], editor[2], height]),
+                 self.with_editor([editor[0], editor[1] + editor[3] - height, editor[2], height])]
+            }
+        }
+    }
+
+    /// The same layout with the editor, along with its gutter, text, minimap and scrollbar, in
+    /// the given rectangle.
+    fn with_editor(&self, editor: Rect) -> Layout {
+        let gutter_width = (self.em * 2.5).min(editor[2]);
+        let scrollbar_width = (self.em * 0.4).min(editor[2] - gutter_width);
+        // The minimap never takes more than a fifth of the room left for the text.
+        let minimap_width = (self.em * 3.0).min((editor[2] - gutter_width - scrollbar_width) / 5.0);
+        let text_width = editor[2] - gutter_width - minimap_width - scrollbar_width;
+        let scrollbar_left = editor[0] + editor[2] - scrollbar_width;
+        Layout {
+            editor: editor,
+            gutter: [editor[0], editor[1], gutter_width, editor[3]],
+            text: [editor[0] + gutter_width, editor[1], text_width, editor[3]],
+            minimap: [scrollbar_left - minimap_width, editor[1], minimap_width, editor[3]],
+            scrollbar: [scrollbar_left, editor[1], scrollbar_width, editor[3]],
+            ..*self
+        }
+    }
+
+    /// The font size of the text, in pixels.
+    pub fn em(&self) -> f64 {
+        self.em
+    }
+
+    /// Space left between the edge of a widget and what is drawn in it.
+    pub fn padding(&self) -> f64 {
+        self.em * 0.3
+    }
+
+    /// Font size of the buffer text and the prompts.
+    pub fn text_size(&self) -> u32 {
+        (self.em * 0.7) as u32
+    }
+
+    /// Font size of the line numbers, the file tree and the tooltips.
+    pub fn small_text_size(&self) -> u32 {
+        (self.em * 0.5) as u32
+    }
+
+    pub fn status_text_size(&self) -> u32 {
+        (self.em * 0.6) as u32
+    }
+
+    pub fn line_height(&self) -> f64 {
+        self.em * 1.1
+    }
+
+    /// Top of the given row of text, counting from the top of the editor.
+    pub fn row_top(&self, row: usize) -> f64 {
+        self.text[1] + self.padding() + row as f64 * self.line_height()
+    }
+
+    /// Where the text of the given row sits.
+    pub fn row_baseline(&self, row: usize) -> f64 {
+        self.row_top(row) + self.em * 0.8
+    }
+
+    /// The row of text at the given height, if it is below the first one.
+    pub fn row_at(&self, y: f64) -> Option<usize> {
+        let row = ((y - self.row_top(0)) / self.line_height()).floor();
+        if row < 0.0 { None } else { Some(row as usize) }
+    }
+
+    /// How wide the text of a row can be before wrapped lines are broken.
+    pub fn wrap_width(&self) -> f64 {
+        (self.text[2] - self.padding()).max(0.0)
+    }
+
+    /// How many rows of text fit whole in the editor.
+    pub fn rows(&self) -> usize {
+        ((self.text[3] - self.padding()) / self.line_height()).max(0.0) as usize
+    }
+
+    /// The part of the scrollbar standing for the lines shown of a buffer with `line_count`
+    /// lines, scrolled to `first_line`.
+    pub fn scrollbar_thumb(&self, first_line: usize, line_count: usize) -> Rect {
+        let track = self.scrollbar;
+        let height = self.thumb_height(line_count);
+        let last_first = line_count.saturating_sub(1);
+        let top = if last_first == 0 {
+            0.0
+        } else {
+            (track[3] - height) * cmp::min(first_line, last_first) as f64 / last_first as f64
+        };
+        [track[0], track[1] + top, track[2], height]
+    }
+
+    /// The first line to show so that the top of the scrollbar thumb is at the given height.
+    pub fn scrollbar_line_at(&self, y: f64, line_count: usize) -> usize {
+        let track = self.scrollbar;
+        let free = track[3] - self.thumb_height(line_count);
+        if free <= 0.0 {
+            return 0;
+        }
+        let fraction = ((y - track[1]) / free).max(0.0).min(1.0);
+        (fraction * line_count.saturating_sub(1) as f64).round() as usize
+    }
+
+    /// Height of the scrollbar thumb: the fraction of the buffer that fits in the editor, but
+    /// never shorter than half an em so that it can be grabbed.
+    fn thumb_height(&self, line_count: usize) -> f64 {
+        // The last line can be scrolled up to the top, so there are that many rows more to
+        // scroll through.
+        let rows = cmp::max(self.rows(), 1);
+        let total = line_count.saturating_sub(1) + rows;
+        (self.scrollbar[3] * rows as f64 / total as f64).max(self.em * 0.5).min(self.scrollbar[3])
+    }
+
+    /// Height of the row of each line in the minimap.
+    pub fn minimap_row_height(&self) -> f64 {
+        self.em * 0.1
+    }
+
+    /// Width of a column of text in the minimap.
+    pub fn minimap_column_width(&self) -> f64 {
+        self.em * 0.03
+    }
+
+    /// How many lines fit in the minimap.
+    pub fn minimap_lines(&self) -> usize {
+        (self.minimap[3] / self.minimap_row_height()).max(0.0) as usize
+    }
+
+    /// The first line in the minimap of a buffer with `line_count` lines scrolled to
+    /// `first_line`. Buffers with more lines than fit scroll through it along with the view.
+    pub fn minimap_first_line(&self, first_line: usize, line_count: usize) -> usize {
+        let fit = self.minimap_lines();
+        let last_first = line_count.saturating_sub(1);
+        if line_count <= fit || last_first == 0 {
+            0
+        } else {
+            let scrolled = cmp::min(first_line, last_first) as f64 / last_first as f64;
+            ((line_count - fit) as f64 * scrolled).round() as usize
+        }
+    }
+
+    /// The line of the buffer at the given height of the minimap, if it reaches that far.
+    pub fn minimap_line_at(&self, y: f64, first_line: usize, line_count: usize) -> usize {
+        let row = ((y - self.minimap[1]) / self.minimap_row_height()).max(0.0) as usize;
+        cmp::min(self.minimap_first_line(first_line, line_count) + row,
+                 line_count.saturating_sub(1))
+    }
+
+    /// The part of the minimap standing for the lines shown.
+    pub fn minimap_viewport(&self, first_line: usize, line_count: usize) -> Rect {
+        let offset = first_line.saturating_sub(self.minimap_first_line(first_line, line_count));
+        let row_height = self.minimap_row_height();
+        [self.minimap[0],
+         self.minimap[1] + offset as f64 * row_height,
+         self.minimap[2],
+         self.rows() as f64 * row_height]
+    }
+
+    /// The first line to show so that the top of the minimap viewport is at the given height.
+    pub fn minimap_scroll_line_at(&self, y: f64, line_count: usize) -> usize {
+        let row = ((y - self.minimap[1]) / self.minimap_row_height()).max(0.0);
+        let fit = self.minimap_lines();
+        let last_first = line_count.saturating_sub(1);
+        // The viewport moves slower than the view once the minimap scrolls too.
+        let line = if line_count <= fit || fit <= 1 {
+            row
+        } else {
+            row * last_first as f64 / (fit - 1) as f64
+        };
+        cmp::min(line.round() as usize, last_first)
+    }
+
+    /// Height of the rows of the file tree and the panels.
+    pub fn list_row_height(&self) -> f64 {
+        self.em * 0.8
+    }
+
+    /// How much deeper each level of the file tree is indented.
+    pub fn tree_indent(&self) -> f64 {
+        self.em * 0.5
+    }
+
+    pub fn tree_row_top(&self, row: usize) -> f64 {
+        self.sidebar[1] + self.padding() + row as f64 * self.list_row_height()
+    }
+
+    /// The visible row of the file tree at the given height, if it is below the first one.
+    pub fn tree_row_at(&self, y: f64) -> Option<usize> {
+        let row = ((y - self.tree_row_top(0)) / self.list_row_height()).floor();
+        if row < 0.0 { None } else { Some(row as usize) }
+    }
+
+    /// How many rows of the file tree fit in the sidebar.
+    pub fn tree_rows(&self) -> usize {
+        ((self.sidebar[3] - self.padding()) / self.list_row_height()).max(0.0) as usize
+    }
+
+    /// A panel along the right edge of the editor, such as the edit history.
+    pub fn panel(&self) -> Rect {
+        let width = (self.editor[2] * 0.35).max(self.em * 8.0).min(self.editor[2]);
+        [self.editor[0] + self.editor[2] - width, self.editor[1], width, self.editor[3]]
+    }
+
+    pub fn panel_row_top(&self, row: usize) -> f64 {
+        self.panel()[1] + self.padding() + row as f64 * self.list_row_height()
+    }
+
+    /// The visible row of the panel at the given height, if it is below the first one.
+    pub fn panel_row_at(&self, y: f64) -> Option<usize> {
+        let row = ((y - self.panel_row_top(0)) / self.list_row_height()).floor();
+        if row < 0.0 { None } else { Some(row as usize) }
+    }
+
+    /// How many rows fit in the panel.
+    pub fn panel_rows(&self) -> usize {
+        ((self.panel()[3] - self.padding()) / self.list_row_height()).max(0.0) as usize
+    }
+
+    /// The bar showing the progress of a loading buffer, centered in the editor.
+    pub fn progress_bar(&self) -> Rect {
+        let width = self.editor[2] * 0.4;
+        let height = self.em / 2.0;
+        [self.editor[0] + (self.editor[2] - width) / 2.0,
+         self.editor[1] + (self.editor[3] - height) / 2.0,
+         width,
+         height]
+    }
+
+    /// A prompt with the given number of rows below its input, at the top of the editor.
+    pub fn prompt(&self, rows: usize) -> Rect {
+        let width = self.editor[2] * 0.6;
+        [self.editor[0] + (self.editor[2] - width) / 2.0,
+         self.editor[1],
+         width,
+         self.line_height() * (rows + 1) as f64 + self.padding()]
+    }
+
+    /// A tooltip of the given size next to the mouse, moved back inside the window if it would
+    /// not fit there.
+    pub fn tooltip(&self, position: [f64; 2], width: f64, height: f64) -> Rect {
+        let right = self.status_bar[0] + self.status_bar[2];
+        [(position[0] + self.em * 0.5).min(right - width),
+         (position[1] + self.em * 0.5).min(self.status_bar[1] - height),
+         width,
+         height]
+    }
+}
+
+pub fn contains(rect: Rect, point: [f64; 2]) -> bool {
+    point[0] >= rect[0] && point[0] < rect[0] + rect[2] && point[1] >= rect[1] &&
+    point[1] < rect[1] + rect[3]
+}
@@ -0,0 +1,123 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread;
+
+/// What a link opens.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A web page, opened in the browser of the system.
+    Url(String),
+    /// A file, along with the line and column to go to in it, counted from 0.
+    File(PathBuf, usize, usize),
+}
+
+/// A URL or a `path:line` reference on a line of text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    /// The bytes of the line it covers.
+    pub start: usize,
+    pub end: usize,
+    pub target: Target,
+}
+
+const SCHEMES: [&'static str; 4] = ["https://", "http://", "ftp://", "file://"];
+
+/// The link on the line of text covering the byte, if any: a URL, or a `file:line:column` or
+/// `file:line` naming a file found in one of the folders, the first one first.
+pub fn at(text: &str, byte: usize, dirs: &[PathBuf]) -> Option<Link> {
+    words(text)
+        .into_iter()
+        .find(|&(start, end)| start <= byte && byte < end)
+        .and_then(|(start, end)| link(&text[start..end], start, dirs))
+        .and_then(|link| if link.start <= byte && byte < link.end {
+            Some(link)
+        } else {
+            None
+        })
+}
+
+/// Opens the URL in the browser of the system, without waiting for it. Only URLs with one of
+/// the schemes links are found with are opened, and they are handed to the opener as they are
+/// rather than through a shell, so that nothing in them can run commands.
+pub fn open_url(url: &str) -> io::Result<()> {
+    if !is_url(url) || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("{} is not a web address", url)));
+    }
+    let mut command = if cfg!(target_os = "windows") {
+        // `start` would have to go through `cmd`, which runs what follows a `&` or `|`.
+        Command::new("explorer")
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    let mut child = try!(command.arg(url).spawn());
+    let _ = thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Whether the text starts with one of the schemes links are found with, followed by more.
+fn is_url(text: &str) -> bool {
+    SCHEMES.iter().any(|scheme| text.len() > scheme.len() && text.starts_with(scheme))
+}
+
+/// The start and end bytes of the runs of text between whitespace.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                words.push((s, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+    words
+}
+
+/// The link the word starting at `offset` of its line holds, if any. Quotes and brackets around
+/// it and punctuation after it are left out.
+fn link(word: &str, offset: usize, dirs: &[PathBuf]) -> Option<Link> {
+    let closing = |c: char| ".,;:!?)]}>'\"`".contains(c);
+    let url_start = SCHEMES.iter().filter_map(|scheme| word.find(scheme)).min();
+    if let Some(start) = url_start {
+        let url = word[start..].trim_right_matches(&closing);
+        if is_url(url) {
+            return Some(Link {
+                start: offset + start,
+                end: offset + start + url.len(),
+                target: Target::Url(String::from(url)),
+            });
+        }
+        return None;
+    }
+
+    let trimmed = word.trim_left_matches(|c: char| "([{<'\"`".contains(c));
+    let start = word.len() - trimmed.len();
+    let reference = trimmed.trim_right_matches(&closing);
+    let mut parts = reference.split(':');
+    let path = match parts.next() {
+        Some(path) if !path.is_empty() => path,
+        _ => return None,
+    };
+    let line = match parts.next().and_then(|l| l.parse::<usize>().ok()) {
+        Some(line) if line > 0 => line - 1,
+        _ => return None,
+    };
+    let column = parts.next().and_then(|c| c.parse::<usize>().ok()).unwrap_or(1);
+    dirs.iter().map(|dir| dir.join(path)).find(|path| path.is_file()).map(|path| {
+        Link {
+            start: offset + start,
+            end: offset + start + reference.len(),
+            target: Target::File(path, line, column.saturating_sub(1)),
+        }
+    })
+}
@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use rustc_serialize::json::Json;
+
+use editor_core::annotations::{Annotation, Style};
+use editor_core::backend::TextBuffer;
+
+use buffers::Buffers;
+use config::Config;
+
+/// Who the diagnostics of the language servers belong to.
+const OWNER: &'static str = "lsp";
+
+/// Something the language servers sent that changes what the editor shows.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The diagnostics of some buffer changed.
+    Diagnostics,
+    /// What is known about the symbol asked about, as lines of text.
+    Hover(Vec<String>),
+    /// Where the symbol asked about is defined: the file, and the line and column counted from
+    /// 0, in UTF-16 code units.
+    Definition(PathBuf, usize, usize),
+}
+
+/// What a request sent to a server was for, to know what to do with its answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Request {
+    Initialize,
+    Hover,
+    Definition,
+}
+
+/// A language server running as a child process, spoken to in JSON-RPC over its standard input
+/// and output.
+#[derive(Debug)]
+struct Server {
+    child: Child,
+    stdin: ChildStdin,
+    /// The messages read from the output of the server on another thread.
+    receiver: Receiver<Json>,
+    next_id: u64,
+    /// Whether the server answered the `initialize` request, before which nothing else is sent.
+    initialized: bool,
+    /// The messages waiting for the server to be initialized.
+    queued: Vec<Json>,
+    /// What the requests waiting for an answer were for, by their identifier.
+    pending: HashMap<u64, Request>,
+}
+
+impl Server {
+    /// Starts the server with the command and its arguments, in the project at `root`.
+    fn start(command: &[String], root: &Path) -> io::Result<Server> {
+        let mut child = try!(Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn());
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let _ = thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut server = Server {
+            child: child,
+            stdin: stdin,
+            receiver: receiver,
+            next_id: 0,
+            initialized: false,
+            queued: Vec::new(),
+            pending: HashMap::new(),
+        };
+        let params = object(vec![("processId", Json::Null),
+                                 ("rootUri", Json::String(uri(root))),
+                                 ("capabilities", object(Vec::new()))]);
+        try!(server.request(Request::Initialize, "initialize", params));
+        Ok(server)
+    }
+
+    fn request(&mut self, request: Request, method: &str, params: Json) -> io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let _ = self.pending.insert(id, request);
+        self.send(object(vec![("jsonrpc", Json::String(String::from("2.0"))),
+                              ("id", Json::U64(id)),
+                              ("method", Json::String(String::from(method))),
+                              ("params", params)]))
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> io::Result<()> {
+        self.send(object(vec![("jsonrpc", Json::String(String::from("2.0"))),
+                              ("method", Json::String(String::from(method))),
+                              ("params", params)]))
+    }
+
+    /// Writes the message, or keeps it until the server is initialized if it is not the
+    /// `initialize` request.
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        if !self.initialized &&
+           message.find("method").and_then(Json::as_string) != Some("initialize") {
+            self.queued.push(message);
+            return Ok(());
+        }
+        let body = message.to_string();
+        try!(write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body));
+        self.stdin.flush()
+    }
+
+    /// Handles a message from the server, adding what the editor has to show to `events` and
+    /// the published diagnostics to `diagnostics`, by the URI of their file.
+    fn handle(&mut self,
+              message: Json,
+              events: &mut Vec<Event>,
+              diagnostics: &mut Vec<(String, Vec<Json>)>) {
+        let method = message.find("method").and_then(Json::as_string).map(String::from);
+        let id = message.find("id").cloned();
+        match (method, id) {
+            (Some(method), None) => {
+                if method == "textDocument/publishDiagnostics" {
+                    let uri = message.find_path(&["params", "uri"]).and_then(Json::as_string);
+                    let list = message.find_path(&["params", "diagnostics"])
+                        .and_then(Json::as_array);
+                    if let (Some(uri), Some(list)) = (uri, list) {
+                        diagnostics.push((String::from(uri), list.clone()));
+                    }
+                }
+            }
+            // Requests from the server are answered with nothing, as none of them is supported.
+            (Some(_), Some(id)) => {
+                let _ = self.send(object(vec![("jsonrpc", Json::String(String::from("2.0"))),
+                                              ("id", id),
+                                              ("result", Json::Null)]));
+            }
+            (None, Some(id)) => {
+                let request = id.as_u64().and_then(|id| self.pending.remove(&id));
+                let null = Json::Null;
+                let result = message.find("result").unwrap_or(&null);
+                match request {
+                    Some(Request::Initialize) => {
+                        self.initialized = true;
+                        let _ = self.notify("initialized", object(Vec::new()));
+                        for message in mem::replace(&mut self.queued, Vec::new()) {
+                            let _ = self.send(message);
+                        }
+                    }
+                    Some(Request::Hover) => {
+                        let text = result.find("contents").map_or(String::new(), hover_text);
+                        if !text.trim().is_empty() {
+                            events.push(Event::Hover(text.lines().map(String::from).collect()));
+                        }
+                    }
+                    Some(Request::Definition) => {
+                        if let Some((path, line, column)) = location(result) {
+                            events.push(Event::Definition(path, line, column));
+                        }
+                    }
+                    None => {}
+                }
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// A buffer open in a language server.
+#[derive(Clone, Debug)]
+struct Document {
+    language: String,
+    uri: String,
+    /// The version of the buffer whose text the server has.
+    buffer_version: usize,
+    /// The version the server was told, which goes up by one with every change.
+    version: u64,
+}
+
+/// The language servers of the languages configured with one, and the buffers open in them.
+/// Servers are started when the first buffer of their language is loaded, are sent the whole
+/// text of the buffers as they change, and their diagnostics are underlined.
+#[derive(Debug)]
+pub struct Lsp {
+    root: PathBuf,
+    /// The server of each language, or `None` if it could not be started.
+    servers: HashMap<String, Option<Server>>,
+    /// The documents by the index of their buffer.
+    documents: HashMap<usize, Document>,
+}
+
+impl Lsp {
+    pub fn new(root: PathBuf) -> Lsp {
+        Lsp {
+            root: root,
+            servers: HashMap::new(),
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Tells the servers about the buffers loaded or changed since last time, starting the
+    /// servers they need.
+    pub fn sync(&mut self, buffers: &Buffers, config: &Config) {
+        for index in 0..buffers.len() {
+            let buf = buffers.get(index);
+            let path = match buf.get_path() {
+                Some(path) if buf.is_loaded() => path,
+                _ => continue,
+            };
+
+            if let Some(document) = self.documents.get_mut(&index) {
+                if document.buffer_version != buf.version() {
+                    document.buffer_version = buf.version();
+                    document.version += 1;
+                    let params =
+                        object(vec![("textDocument",
+                                     object(vec![("uri", Json::String(document.uri.clone())),
+                                                 ("version", Json::U64(document.version))])),
+                                    ("contentChanges",
+                                     Json::Array(vec![object(vec![("text", text(buf))])]))]);
+                    if let Some(&mut Some(ref mut server)) =
+                           self.servers.get_mut(&document.language) {
+                        let _ = server.notify("textDocument/didChange", params);
+                    }
+                }
+                continue;
+            }
+
+            let (language, command) = match config.language_server(path) {
+                Some(server) => server,
+                None => continue,
+            };
+            if !self.servers.contains_key(&language) {
+                let server = Server::start(&command, &self.root).ok();
+                let _ = self.servers.insert(language.clone(), server);
+            }
+            let document = Document {
+                language: language.clone(),
+                uri: uri(path),
+                buffer_version: buf.version(),
+                version: 1,
+            };
+            if let Some(&mut Some(ref mut server)) = self.servers.get_mut(&language) {
+                let params = object(vec![("textDocument",
+                                          object(vec![("uri", Json::String(document.uri.clone())),
+                                                      ("languageId", Json::String(language)),
+                                                      ("version", Json::U64(1)),
+                                                      ("text", text(buf))]))]);
+                let _ = server.notify("textDocument/didOpen", params);
+            }
+            let _ = self.documents.insert(index, document);
+        }
+    }
+
+    /// Tells the server of the buffer at the given index that it was closed, and moves the
+    /// documents of the buffers after it down by one, as the buffers are.
+    pub fn close(&mut self, index: usize) {
+        if let Some(document) = self.documents.remove(&index) {
+            if let Some(&mut Some(ref mut server)) = self.servers.get_mut(&document.language) {
+                let params = object(vec![("textDocument",
+                                          object(vec![("uri", Json::String(document.uri))]))]);
+                let _ = server.notify("textDocument/didClose", params);
+            }
+        }
+        self.documents = self.documents
+            .drain()
+            .map(|(i, document)| (if i > index { i - 1 } else { i }, document))
+            .collect();
+    }
+
+    /// Handles what the servers sent since last time, underlining the diagnostics in their
+    /// buffers. Returns what the editor has to show.
+    pub fn receive(&mut self, buffers: &mut Buffers) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut diagnostics = Vec::new();
+        for server in self.servers.values_mut() {
+            if let Some(ref mut server) = *server {
+                loop {
+                    match server.receiver.try_recv() {
+                        Ok(message) => server.handle(message, &mut events, &mut diagnostics),
+                        Err(TryRecvError::Empty) |
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                }
+            }
+        }
+
+        for (uri, list) in diagnostics {
+            let index = match self.documents.iter().find(|&(_, d)| d.uri == uri) {
+                Some((&index, _)) => index,
+                None => continue,
+            };
+            let buf = buffers.get_mut(index);
+            let annotations = {
+                let lines: Vec<&String> = buf.lines().collect();
+                list.iter().filter_map(|d| diagnostic(d, &lines)).collect()
+            };
+            buf.annotations_mut().set(OWNER, annotations);
+            events.push(Event::Diagnostics);
+        }
+        events
+    }
+
+    /// Asks the server of the buffer what it knows about the symbol at its first cursor.
+    pub fn hover(&mut self, index: usize, buf: &TextBuffer) {
+        self.request_at_cursor(index, buf, Request::Hover, "textDocument/hover");
+    }
+
+    /// Asks the server of the buffer where the symbol at its first cursor is defined.
+    pub fn definition(&mut self, index: usize, buf: &TextBuffer) {
+        self.request_at_cursor(index, buf, Request::Definition, "textDocument/definition");
+    }
+
+    fn request_at_cursor(&mut self,
+                         index: usize,
+                         buf: &TextBuffer,
+                         request: Request,
+                         method: &str) {
+        let document = match self.documents.get(&index) {
+            Some(document) => document,
+            None => return,
+        };
+        let (line, byte) = match buf.get_cursors().first() {
+            Some(cursor) => cursor.head(),
+            None => return,
+        };
+        let column = buf.lines().nth(line).map_or(0, |text| text[..byte].encode_utf16().count());
+        let params = object(vec![("textDocument",
+                                  object(vec![("uri", Json::String(document.uri.clone()))])),
+                                 ("position",
+                                  object(vec![("line", Json::U64(line as u64)),
+                                              ("character", Json::U64(column as u64))]))]);
+        if let Some(&mut Some(ref mut server)) = self.servers.get_mut(&document.language) {
+            let _ = server.request(request, method, params);
+        }
+    }
+}
+
+/// Reads a message framed by its `Content-Length` header, or `None` once the server is gone.
+/// Messages that are not JSON are read as `null`.
+fn read_message<R: BufRead>(reader: &mut R) -> Option<Json> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {}
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.trim().to_lowercase() == "content-length" {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    if reader.read_exact(&mut body).is_err() {
+        return None;
+    }
+    Some(Json::from_str(&String::from_utf8_lossy(&body)).unwrap_or(Json::Null))
+}
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter()
+        .map(|(name, value)| (String::from(name), value))
+        .collect::<BTreeMap<_, _>>())
+}
+
+fn text(buf: &TextBuffer) -> Json {
+    Json::String(buf.lines().map(String::as_str).collect())
+}
+
+/// The `file://` URI of the path, with the bytes URIs cannot hold percent-encoded.
+fn uri(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'a'...b'z' | b'A'...b'Z' | b'0'...b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// The path of a `file://` URI.
+fn path_of(uri: &str) -> Option<PathBuf> {
+    if !uri.starts_with("file://") {
+        return None;
+    }
+    let encoded = uri["file://".len()..].as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < encoded.len() {
+        let decoded = if encoded[i] == b'%' && i + 2 < encoded.len() {
+            String::from_utf8_lossy(&encoded[i + 1..i + 3]).into_owned()
+        } else {
+            String::new()
+        };
+        match u8::from_str_radix(&decoded, 16) {
+            Ok(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            Err(_) => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// The text of the contents of a hover answer, which can be a string, a string along with its
+/// language, markup or a list of them.
+fn hover_text(contents: &Json) -> String {
+    match *contents {
+        Json::String(ref text) => text.clone(),
+        Json::Array(ref parts) => parts.iter().map(hover_text).collect::<Vec<_>>().join("\n\n"),
+        Json::Object(_) => {
+            contents.find("value").and_then(Json::as_string).unwrap_or("").to_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+/// The file, line and column of the first location of a definition answer.
+fn location(result: &Json) -> Option<(PathBuf, usize, usize)> {
+    let location = match *result {
+        Json::Array(ref locations) => locations.first(),
+        Json::Object(_) => Some(result),
+        _ => None,
+    };
+    location.and_then(|location| {
+        let uri = location.find("uri").or(location.find("targetUri")).and_then(Json::as_string);
+        let start = location.find_path(&["range", "start"])
+            .or(location.find_path(&["targetSelectionRange", "start"]));
+        match (uri.and_then(path_of), start.and_then(position)) {
+            (Some(path), Some((line, column))) => Some((path, line, column)),
+            _ => None,
+        }
+    })
+}
+
+/// The line and UTF-16 column of a position.
+fn position(position: &Json) -> Option<(usize, usize)> {
+    let line = position.find("line").and_then(Json::as_u64);
+    let column = position.find("character").and_then(Json::as_u64);
+    match (line, column) {
+        (Some(line), Some(column)) => Some((line as usize, column as usize)),
+        _ => None,
+    }
+}
+
+/// A diagnostic as an underline holding its message, on the given lines of its buffer.
+fn diagnostic(diagnostic: &Json, lines: &[&String]) -> Option<Annotation> {
+    let start = diagnostic.find_path(&["range", "start"]).and_then(position);
+    let end = diagnostic.find_path(&["range", "end"]).and_then(position);
+    let message = diagnostic.find("message").and_then(Json::as_string).unwrap_or("");
+    let byte = |(line, column): (usize, usize)| -> Option<(usize, usize)> {
+        lines.get(line).map(|text| (line, byte_at(text, column)))
+    };
+    match (start.and_then(&byte), end.and_then(&byte)) {
+        (Some(start), Some(end)) => {
+            let mut annotation = Annotation::new(OWNER, start, end, Style::Underline);
+            annotation.data = String::from(message);
+            Some(annotation)
+        }
+        _ => None,
+    }
+}
+
+/// The byte index of a column counted in UTF-16 code units, or the end of the line without its
+/// line break past it.
+fn byte_at(line: &str, column: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.trim_right_matches('\n').char_indices() {
+        if units >= column {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.trim_right_matches('\n').len()
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use toml::{Parser, Value};
+
+use editor_core::backend::{Move, Transform};
+
+use action::Action;
+use dirs;
+use super::{Error, Result};
+
+/// Where the macros are kept: `macros.toml` in the data directory of the user, so that they are
+/// the same whatever directory the editor is started from.
+pub fn file() -> Option<PathBuf> {
+    dirs::data_dir().map(|data| data.join("macros.toml"))
+}
+
+/// Number of slots, one for every digit key.
+pub const SLOTS: usize = 10;
+
+const MOVES: [Move; 18] = [Move::Up,
+                           Move::Down,
+                           Move::Left,
+                           Move::Right,
+                           Move::WordLeft,
+                           Move::WordRight,
+                           Move::SubwordLeft,
+                           Move::SubwordRight,
+                           Move::LineStart,
+                           Move::LineEnd,
+                           Move::FirstColumn,
+                           Move::NextWordStart,
+                           Move::PreviousParagraph,
+                           Move::NextParagraph,
+                           Move::PreviousBlock,
+                           Move::NextBlock,
+                           Move::BufferStart,
+                           Move::BufferEnd];
+
+const TRANSFORMS: [Transform; 7] = [Transform::Uppercase,
+                                    Transform::Lowercase,
+                                    Transform::TitleCase,
+                                    Transform::SortLines,
+                                    Transform::ReverseLines,
+                                    Transform::UniqueLines,
+                                    Transform::JoinLines];
+
+/// A sequence of edits recorded to be run again.
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub name: String,
+    pub actions: Vec<Action>,
+}
+
+/// The macros saved in the slots bound to Ctrl+Shift and a digit, where slot `n` is
+/// Ctrl+Shift+`n`.
+#[derive(Clone, Debug)]
+pub struct Macros {
+    slots: Vec<Option<Macro>>,
+}
+
+impl Macros {
+    /// Every slot empty.
+    pub fn new() -> Macros {
+        Macros { slots: vec![None; SLOTS] }
+    }
+
+    /// Reads the macros from the given file, leaving every slot empty if there is none.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Macros> {
+        let mut macros = Macros::new();
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(macros);
+        }
+
+        let mut contents = String::new();
+        let _ = try!(try!(File::open(path)).read_to_string(&mut contents));
+        let table = match Parser::new(&contents).parse() {
+            Some(t) => t,
+            None => return Err(invalid()),
+        };
+        for saved in table.get("macros").and_then(Value::as_slice).unwrap_or(&[]) {
+            let (slot, saved) = try!(from_value(saved).ok_or_else(invalid));
+            macros.slots[slot] = Some(saved);
+        }
+
+        Ok(macros)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let saved = self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, saved)| saved.as_ref().map(|m| to_value(slot, m)))
+            .collect();
+        let mut table = BTreeMap::new();
+        let _ = table.insert(String::from("macros"), Value::Array(saved));
+        try!(dirs::write_atomically(path, Value::Table(table).to_string().as_bytes()));
+
+        Ok(())
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&Macro> {
+        self.slots.get(slot).and_then(Option::as_ref)
+    }
+
+    /// Puts the macro in the slot, replacing the one there.
+    pub fn set(&mut self, slot: usize, saved: Macro) {
+        self.slots[slot] = Some(saved);
+    }
+
+    pub fn rename(&mut self, slot: usize, name: &str) {
+        if let Some(ref mut saved) = self.slots[slot] {
+            saved.name = String::from(name);
+        }
+    }
+
+    pub fn remove(&mut self, slot: usize) {
+        self.slots[slot] = None;
+    }
+
+    /// Every slot along with its macro, in the order of the digit keys, from 1 to 0.
+    pub fn slots(&self) -> Vec<(usize, Option<&Macro>)> {
+        (1..SLOTS).chain(0..1).map(|slot| (slot, self.get(slot))).collect()
+    }
+}
+
+/// Whether the action edits the buffer or moves its cursors, which is what macros record.
+pub fn is_recordable(action: &Action) -> bool {
+    encode(action).is_some()
+}
+
+/// The action as a TOML table, if it can be recorded.
+fn encode(action: &Action) -> Option<Value> {
+    let (name, argument) = match *action {
+        Action::Insert(ref text) => ("Insert", Some(("text", text.clone()))),
+        Action::Move(movement) => ("Move", Some(("to", format!("{:?}", movement)))),
+        Action::Extend(movement) => ("Extend", Some(("to", format!("{:?}", movement)))),
+        Action::NewLine => ("NewLine", None),
+        Action::Tab => ("Tab", None),
+        Action::BackTab => ("BackTab", None),
+        Action::Backspace => ("Backspace", None),
+        Action::Delete => ("Delete", None),
+        Action::DeleteWordBackward => ("DeleteWordBackward", None),
+        Action::DeleteWordForward => ("DeleteWordForward", None),
+        Action::DeleteSubwordBackward => ("DeleteSubwordBackward", None),
+        Action::DeleteSubwordForward => ("DeleteSubwordForward", None),
+        Action::AddCursorAbove => ("AddCursorAbove", None),
+        Action::AddCursorBelow => ("AddCursorBelow", None),
+        Action::AddCursorAtNextOccurrence => ("AddCursorAtNextOccurrence", None),
+        Action::CollapseCursors => ("CollapseCursors", None),
+        Action::SelectAll => ("SelectAll", None),
+        Action::SelectLines => ("SelectLines", None),
+        Action::ExpandSelection => ("ExpandSelection", None),
+        Action::DuplicateLines => ("DuplicateLines", None),
+        Action::DeleteLines => ("DeleteLines", None),
+        Action::MoveLinesUp => ("MoveLinesUp", None),
+        Action::MoveLinesDown => ("MoveLinesDown", None),
+        Action::ToggleComment => ("ToggleComment", None),
+        Action::TrimTrailingWhitespace => ("TrimTrailingWhitespace", None),
+        Action::Transform(transform) => ("Transform", Some(("to", format!("{:?}", transform)))),
+        _ => return None,
+    };
+    let mut table = BTreeMap::new();
+    let _ = table.insert(String::from("action"), Value::String(String::from(name)));
+    if let Some((key, value)) = argument {
+        let _ = table.insert(String::from(key), Value::String(value));
+    }
+    Some(Value::Table(table))
+}
+
+fn decode(value: &Value) -> Option<Action> {
+    let table = match value.as_table() {
+        Some(t) => t,
+        None => return None,
+    };
+    let argument = |key: &str| table.get(key).and_then(Value::as_str);
+    let movement = || {
+        argument("to").and_then(|to| MOVES.iter().find(|m| format!("{:?}", m) == to).cloned())
+    };
+    let action = match table.get("action").and_then(Value::as_str) {
+        Some("Insert") => Action::Insert(String::from(argument("text").unwrap_or(""))),
+        Some("Move") => {
+            match movement() {
+                Some(movement) => Action::Move(movement),
+                None => return None,
+            }
+        }
+        Some("Extend") => {
+            match movement() {
+                Some(movement) => Action::Extend(movement),
+                None => return None,
+            }
+        }
+        Some("NewLine") => Action::NewLine,
+        Some("Tab") => Action::Tab,
+        Some("BackTab") => Action::BackTab,
+        Some("Backspace") => Action::Backspace,
+        Some("Delete") => Action::Delete,
+        Some("DeleteWordBackward") => Action::DeleteWordBackward,
+        Some("DeleteWordForward") => Action::DeleteWordForward,
+        Some("DeleteSubwordBackward") => Action::DeleteSubwordBackward,
+        Some("DeleteSubwordForward") => Action::DeleteSubwordForward,
+        Some("AddCursorAbove") => Action::AddCursorAbove,
+        Some("AddCursorBelow") => Action::AddCursorBelow,
+        Some("AddCursorAtNextOccurrence") => Action::AddCursorAtNextOccurrence,
+        Some("CollapseCursors") => Action::CollapseCursors,
+        Some("SelectAll") => Action::SelectAll,
+        Some("SelectLines") => Action::SelectLines,
+        Some("ExpandSelection") => Action::ExpandSelection,
+        Some("DuplicateLines") => Action::DuplicateLines,
+        Some("DeleteLines") => Action::DeleteLines,
+        Some("MoveLinesUp") => Action::MoveLinesUp,
+        Some("MoveLinesDown") => Action::MoveLinesDown,
+        Some("ToggleComment") => Action::ToggleComment,
+        Some("TrimTrailingWhitespace") => Action::TrimTrailingWhitespace,
+        Some("Transform") => {
+            let to = argument("to");
+            match TRANSFORMS.iter().find(|t| Some(&*format!("{:?}", t)) == to) {
+                Some(&transform) => Action::Transform(transform),
+                None => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some(action)
+}
+
+fn to_value(slot: usize, saved: &Macro) -> Value {
+    let mut table = BTreeMap::new();
+    let _ = table.insert(String::from("slot"), Value::Integer(slot as i64));
+    let _ = table.insert(String::from("name"), Value::String(saved.name.clone()));
+    let _ = table.insert(String::from("actions"),
+                         Value::Array(saved.actions.iter().filter_map(encode).collect()));
+    Value::Table(table)
+}
+
+fn from_value(value: &Value) -> Option<(usize, Macro)> {
+    let table = match value.as_table() {
+        Some(t) => t,
+        None => return None,
+    };
+    let slot = match table.get("slot").and_then(Value::as_integer) {
+        Some(slot) if slot >= 0 && (slot as usize) < SLOTS => slot as usize,
+        _ => return None,
+    };
+    let name = table.get("name").and_then(Value::as_str).unwrap_or("");
+    let actions: Option<Vec<Action>> = table.get("actions")
+        .and_then(Value::as_slice)
+        .unwrap_or(&[])
+        .iter()
+        .map(decode)
+        .collect();
+
+    actions.map(|actions| {
+        (slot,
+         Macro {
+            name: String::from(name),
+            actions: actions,
+        })
+    })
+}
+
+fn invalid() -> Error {
+    Error::InvalidConfig(String::from("invalid macros file"))
+}
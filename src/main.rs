@@ -12,174 +12,3027 @@ extern crate piston_window;
 extern crate glutin;
 extern crate fps_counter;
 extern crate time;
+extern crate toml;
+extern crate unicode_segmentation;
+extern crate git2;
+extern crate rustc_serialize;
+extern crate hlua;
+extern crate rusttype;
+extern crate editor_core;
 
-mod backend;
+mod action;
+mod audit;
+mod bookmarks;
+mod buffers;
+mod config;
+mod detect;
+mod diffview;
+mod dirs;
+mod editorconfig;
+mod export;
+mod filetree;
+mod fonts;
+mod fuzzy;
+mod git;
+mod layout;
+mod links;
+mod lsp;
+mod macros;
+mod metrics;
+mod multiedit;
+mod panes;
+mod plugins;
+mod profiler;
+mod prompt;
+mod quickopen;
+mod recent;
+mod recovery;
+mod related;
+mod render;
+mod repeat;
+mod scheduler;
+mod search;
+mod session;
+mod spell;
+mod tags;
+mod tasks;
+mod theme;
+mod timeline;
+mod view;
+mod vim;
+mod welcome;
 
-use std::{io, fmt, u8};
+use std::{cmp, env, io, fmt, thread, usize};
+use std::collections::VecDeque;
 use std::error::Error as StdErr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 
 use piston_window::*;
 use piston_window::character::CharacterCache;
 use glutin::MouseCursor;
 
-use fps_counter::FPSCounter;
-
-use backend::*;
-
-const BACKGROUND_COLOR: [f32; 4] = [33 as f32 / u8::MAX as f32,
-                                    37 as f32 / u8::MAX as f32,
-                                    43 as f32 / u8::MAX as f32,
-                                    255 as f32 / u8::MAX as f32];
-const EDITOR_BG_COLOR: [f32; 4] = [40 as f32 / u8::MAX as f32,
-                                   44 as f32 / u8::MAX as f32,
-                                   52 as f32 / u8::MAX as f32,
-                                   255 as f32 / u8::MAX as f32];
-const BG_COLOR_LIGHT: [f32; 4] = [44 as f32 / u8::MAX as f32,
-                                  50 as f32 / u8::MAX as f32,
-                                  60 as f32 / u8::MAX as f32,
-                                  255 as f32 / u8::MAX as f32];
-const CURSOR_COLOR: [f32; 4] = [82 as f32 / u8::MAX as f32,
-                                139 as f32 / u8::MAX as f32,
-                                255 as f32 / u8::MAX as f32,
-                                255 as f32 / u8::MAX as f32];
+
+use editor_core::annotations::Mark;
+use editor_core::backend::*;
+
+use action::{Action, Modifiers};
+use buffers::Buffers;
+use config::Config;
+use diffview::Diff;
+use filetree::FileTree;
+use fonts::Fonts;
+use git::Branch;
+use layout::Layout;
+use links::{Link, Target};
+use lsp::Lsp;
+use macros::{Macro, Macros};
+use metrics::TextMetrics;
+use multiedit::EditSession;
+use panes::Panes;
+use plugins::Plugins;
+use profiler::{Profiler, Stage};
+use prompt::Prompt;
+use quickopen::FileIndex;
+use recent::RecentFiles;
+use recovery::{Recovery, Swap};
+use render::{Paint, RenderPlan};
+use repeat::KeyRepeat;
+use scheduler::{LayoutWarmup, Scheduler};
+use search::Results;
+use session::{BufferState, Session};
+use spell::{Misspelling, SpellChecker};
+use tags::Tag;
+use tasks::Task;
+use theme::Theme;
+use timeline::Timeline;
+use vim::{Mode, Vim};
+use view::{RevealMode, View};
+use welcome::{Item, Welcome};
 
 const EM: u32 = 32;
-const MENU_WIDTH: f64 = 250.0;
+/// How much each zoom step changes the size of the text, and the smallest and largest zoom.
+const ZOOM_STEP: f64 = 0.1;
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 3.0;
+const PROMPT_ROWS: usize = 10;
+/// How long the mouse has to rest before a tooltip is shown, in nanoseconds.
+const HOVER_DELAY: u64 = 500_000_000;
+/// How soon a second click has to follow the first one to select a word, in nanoseconds.
+const DOUBLE_CLICK_TIME: u64 = 400_000_000;
+/// How often the files of the open buffers are checked for changes by other programs, in
+/// nanoseconds.
+const DISK_CHECK_INTERVAL: u64 = 1_000_000_000;
+/// How long a frame lasts at 60 frames per second, in nanoseconds.
+const FRAME_TIME: u64 = 16_666_667;
+/// How long no key has to be pressed before the file tree is read again in the background, and
+/// how often it is read again while nothing is typed, in nanoseconds.
+const IDLE_REFRESH_DELAY: u64 = 2_000_000_000;
+const REFRESH_INTERVAL: u64 = 10_000_000_000;
+/// How often the unsaved text of the buffers is written to their swap files, in nanoseconds.
+const SWAP_INTERVAL: u64 = 5_000_000_000;
 
-const SOFT_TABS: &'static str = "    ";
-const TAB_FILL: &'static str = SOFT_TABS;
+/// Everything shown in a frame that can change without an input event, to tell whether the
+/// window has to be drawn again.
+#[derive(Clone, Debug, PartialEq)]
+struct Frame {
+    size: (u32, u32),
+    version: usize,
+    first_line: usize,
+    /// The version of the buffer in the pane out of focus and the first line shown of it, if
+    /// the editor is split.
+    other_pane: Option<(usize, usize)>,
+    progress: Option<f64>,
+    caret_on: bool,
+    tooltip: Option<Vec<String>>,
+}
 
-fn main() {
-    // TODO read config
+/// Where the mouse is, and since when it has been resting there.
+struct Hover {
+    position: [f64; 2],
+    since: u64,
+}
+
+impl Hover {
+    fn new() -> Hover {
+        Hover {
+            position: [0.0; 2],
+            since: time::precise_time_ns(),
+        }
+    }
+
+    fn move_to(&mut self, position: [f64; 2]) {
+        self.position = position;
+        self.reset();
+    }
+
+    /// Restarts the delay, hiding the tooltip until the mouse rests again.
+    fn reset(&mut self) {
+        self.since = time::precise_time_ns();
+    }
+
+    fn is_resting(&self) -> bool {
+        time::precise_time_ns() - self.since >= HOVER_DELAY
+    }
+}
+
+/// The link under the mouse, in the buffer at the index and version it was found in.
+struct HoveredLink {
+    buffer: usize,
+    version: usize,
+    line: usize,
+    link: Link,
+}
+
+impl HoveredLink {
+    /// Whether the link is still where it was found, in the active buffer.
+    fn is_current(&self, buffers: &Buffers) -> bool {
+        self.buffer == buffers.active_index() && self.version == buffers.active().version()
+    }
+}
+
+/// The buffer logging every action input is translated into, along with how long it took.
+struct InputTrace {
+    buffer: usize,
+    /// When tracing started, which the entries are timed from.
+    started: u64,
+    /// The buffer that was active when the trace was shown, to go back to when hiding it.
+    previous: usize,
+}
+
+impl InputTrace {
+    /// Logs the action that the input described as `input` fired, if any, and how long it took,
+    /// in nanoseconds.
+    fn log(&self,
+           buffers: &mut Buffers,
+           rows: usize,
+           input: &str,
+           action: Option<&Action>,
+           took: u64) {
+        let at = time::precise_time_ns().saturating_sub(self.started);
+        let entry = match action {
+            Some(action) => {
+                format!("+{:.3} s  {}  {:?}  ({:.2} ms)\n",
+                        at as f64 / 1e9,
+                        input,
+                        action,
+                        took as f64 / 1e6)
+            }
+            None => format!("+{:.3} s  {}  (unbound)\n", at as f64 / 1e9, input),
+        };
+        let (buf, view) = buffers.get_with_view(self.buffer);
+        buf.append(entry);
+        let last = buf.line_count() - 1;
+        view.reveal_range(last..last + 1, RevealMode::Minimal, rows, buf.line_count());
+    }
+}
+
+/// What is read from disk before the editor can be used, which is loaded in the background
+/// while the window comes up and shows an empty frame.
+struct Startup {
+    config: Config,
+    themes: Vec<Theme>,
+    macros: Macros,
+    file_tree: FileTree,
+    /// The files of the fonts to draw with.
+    fonts: Vec<PathBuf>,
+    /// What could not be read, which the defaults stand in for.
+    errors: Vec<String>,
+}
+
+impl Startup {
+    fn load() -> Startup {
+        let mut errors = Vec::new();
+        let user_config = Config::load(config::CONFIG_FILE).unwrap_or_else(|e| {
+            errors.push(format!("{}: {}", config::CONFIG_FILE, e));
+            Config::default()
+        });
+        let config = user_config.clone()
+            .overridden_by_project(config::PROJECT_CONFIG_FILE)
+            .unwrap_or_else(|e| {
+                errors.push(format!("{}: {}", config::PROJECT_CONFIG_FILE, e));
+                user_config
+            });
+        let mut themes = vec![Theme::dark(), Theme::light()];
+        for path in &config.theme_files {
+            match Theme::load(path) {
+                Ok(theme) => themes.push(theme),
+                Err(e) => errors.push(format!("{}: {}", path, e)),
+            }
+        }
+        let macros = match macros::file() {
+            Some(file) => {
+                Macros::load(&file).unwrap_or_else(|e| {
+                    errors.push(format!("{}: {}", file.display(), e));
+                    Macros::new()
+                })
+            }
+            None => Macros::new(),
+        };
+        let current = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        let file_tree = FileTree::new(&current).unwrap_or_else(|e| {
+            errors.push(format!("{}: {}", current.display(), e));
+            FileTree::empty(&current)
+        });
+
+        Startup {
+            themes: themes,
+            macros: macros,
+            file_tree: file_tree,
+            fonts: fonts::find_all(&config.font, &config.font_fallbacks),
+            errors: errors,
+            config: config,
+        }
+    }
+}
 
-    let mut buf = TextBuffer::new(Some("test.txt")).unwrap();
-    buf.load(|_, _| {}).unwrap();
-    buf.set_cursors(vec![Default::default()]);
+fn main() {
+    let (startup_sender, startup_receiver) = mpsc::channel();
+    let _ = thread::spawn(move || {
+        let _ = startup_sender.send(Startup::load());
+    });
+    // Taken before the current directory can change.
+    let session_file = session::file();
+    let recent_file = recent::file();
+    // Without files to open, the ones open when the editor last exited are opened again.
+    let session = if env::args().len() > 1 {
+        None
+    } else {
+        // A session file that cannot be read is left for the next exit to replace.
+        session_file.as_ref().and_then(|file| Session::load(file).unwrap_or(None))
+    };
+    let first_start = env::args().len() == 1 && session.is_none();
+    let window_size = session.as_ref().map_or([1920, 1080], |s| [s.width, s.height]);
 
-    let mut window: PistonWindow = WindowSettings::new("main.rs", [1920, 1080])
+    let mut window: PistonWindow = WindowSettings::new("Editor", window_size)
         .vsync(true)
         .build()
         .unwrap();
-
+    // Buffers are only swapped after a frame is drawn, so that skipped frames keep the last one.
+    let mut events = window.events().swap_buffers(false);
+    // An empty frame is shown right away, before the theme is even known, and drawn again
+    // whenever the window needs it until what is read in the background comes in, so that the
+    // window keeps responding meanwhile.
+    let mut startup = None;
+    while let Some(e) = events.next(&mut window) {
+        if let Event::Render(_) = e {
+            let _ = window.draw_2d(&e, |_, g| clear(Theme::dark().editor_background, g));
+            window.swap_buffers();
+        }
+        if let Ok(loaded) = startup_receiver.try_recv() {
+            startup = Some(loaded);
+            break;
+        }
+    }
+    // The window was closed before the editor could be used.
+    let Startup { config, themes, mut macros, mut file_tree, fonts, mut errors } = match startup {
+        Some(startup) => startup,
+        None => return,
+    };
     let factory = window.factory.clone();
-    let mut glyphs = Glyphs::new("fonts/cnr.otf", factory).unwrap();
+    let mut glyphs = Fonts::load(fonts, |path| Glyphs::new(path, factory.clone())).unwrap();
+    let mut file_index = FileIndex::new(env::current_dir().unwrap());
+    let mut lsp = Lsp::new(env::current_dir().unwrap());
+    let mut spelling = SpellChecker::new(PathBuf::from(&config.dictionaries));
+    let mut plugins = Plugins::load(plugins::dir());
+    // Recent files that cannot be read are forgotten rather than keeping the editor from
+    // starting.
+    let mut recent = recent_file.as_ref()
+        .and_then(|file| RecentFiles::load(file).ok())
+        .unwrap_or_else(RecentFiles::new);
+    let mut recovery = Recovery::new();
 
-    let mut fps_counter = FPSCounter::new();
-    let mut events = window.events();
-    while let Some(e) = events.next(&mut window) {
+    let mut buffers = Buffers::new();
+    for path in env::args().skip(1) {
+        if let Err(e) = buffers.open(&path, config.options(Some(Path::new(&path)))) {
+            errors.push(format!("{}: {}", path, e));
+        }
+    }
+    // The cursors and views of the buffers of the session are restored once they are loaded.
+    let mut pending_restores = Vec::new();
+    if let Some(session) = session {
+        let mut active = None;
+        for (i, state) in session.buffers.into_iter().enumerate() {
+            if state.path.exists() {
+                let index = match buffers.open(&state.path, config.options(Some(&state.path))) {
+                    Ok(index) => index,
+                    Err(e) => {
+                        errors.push(format!("{}: {}", state.path.display(), e));
+                        continue;
+                    }
+                };
+                if i == session.active {
+                    active = Some(index);
+                }
+                pending_restores.push((index, state));
+            }
+        }
+        if let Some(active) = active {
+            buffers.activate(active);
+        }
+    }
+    // The welcome page and its index, while it is open.
+    let mut welcome_page: Option<(usize, Welcome)> = None;
+    if buffers.is_empty() && first_start {
+        welcome_page = Some(open_welcome(&mut buffers, &config, &recent));
+    } else if buffers.is_empty() {
+        let _ = buffers.open_empty(config.options(None));
+    }
+    let mut theme_index = themes.iter().position(|t| t.name == config.theme).unwrap_or(0);
+    // The edits recorded since macro recording started, while it goes on.
+    let mut recording: Option<Vec<Action>> = None;
+    // The edits recorded last, which can be replayed without saving them to a slot.
+    let mut last_macro: Vec<Action> = Vec::new();
+
+    // How much the text is zoomed in, on top of the scale of the display.
+    let mut zoom = 1.0;
+    // The layout of the whole window, and the ones of the focused pane and of the other one
+    // if the editor is split.
+    let scale = window.window.window.hidpi_factor() as f64 * zoom;
+    let mut window_layout = Layout::new(window.draw_size(), EM as f64, scale);
+    let mut layout = window_layout;
+    let mut other_layout: Option<Layout> = None;
+    let mut panes: Option<Panes> = None;
+    let mut metrics = TextMetrics::new(layout.text_size());
+    // How long drawing and editing take, shown on top of the editor if `show_profiler`.
+    let mut profiler = Profiler::new();
+    let mut show_profiler = false;
+    let mut modifiers = Modifiers::default();
+    // The navigation or editing key held, and the press of it to go through next, if it is time
+    // to repeat it.
+    let mut key_repeat = KeyRepeat::new(config.repeat_delay, config.repeat_rate);
+    let mut repeated_key: Option<Key> = None;
+    let mut hover = Hover::new();
+    // Unsaved text left by an editor that did not exit cleanly is offered back first.
+    let leftovers = recovery.leftovers();
+    let mut overlay: Option<Overlay> = if !leftovers.is_empty() {
+        Some(recover_prompt(leftovers))
+    } else if !errors.is_empty() {
+        Some(Overlay::Error(Prompt::new("Could not read some files", errors)))
+    } else {
+        None
+    };
+    // The edit history of the active buffer, and the view of the revision previewed in it.
+    let mut timeline: Option<Timeline> = None;
+    let mut preview_view = View::new();
+    let mut pending_jump: Option<(usize, Tag)> = None;
+    // The buffer opened at a result of a search in files or at a definition, and the line and
+    // column to go to, until the buffer is loaded.
+    let mut pending_result: Option<(usize, usize, usize)> = None;
+    // The buffer listing the results of the last search in files, while they come in.
+    let mut find_results: Option<(usize, Results)> = None;
+    // The buffer showing a diff, along with where its hunks are.
+    let mut diff_view: Option<(usize, Diff)> = None;
+    // The diff being worked out on another thread, opened in a buffer once it is over.
+    let mut pending_diff: Option<mpsc::Receiver<diffview::Compared>> = None;
+    // The files being scanned for problems on another thread, listed once the scan is over.
+    let mut pending_audit: Option<mpsc::Receiver<Vec<audit::Finding>>> = None;
+    // The multi-file search being run on another thread, opened in a buffer once it is over.
+    let mut pending_session: Option<mpsc::Receiver<EditSession>> = None;
+    // The buffer showing the output of the last task run, and the task while it runs.
+    let mut task_output: Option<(usize, Task)> = None;
+    // The action chosen from the command palette, to run like one bound to a key.
+    let mut queued: VecDeque<Action> = VecDeque::new();
+    // The Vim-style modes keys go through, if they are turned on.
+    let mut vim = if config.vim { Some(Vim::new()) } else { None };
+    // The text copied last.
+    let mut clipboard = String::new();
+    // When a key was last pressed and when buffers were last saved automatically.
+    let mut last_key = time::precise_time_ns();
+    let mut last_autosave = last_key;
+    let mut last_disk_check = last_key;
+    let mut last_swap = last_key;
+    let mut last_drawn = last_key;
+    let mut last_refresh = last_key;
+    // The file tree being read again in the background, to catch changes made by other programs.
+    let mut tree_refresh: Option<mpsc::Receiver<Result<FileTree>>> = None;
+    // Background work run in the time left between frames, and the buffer, version, first line
+    // and font size the lines around the screen were last warmed up for.
+    let mut scheduler = Scheduler::new();
+    let mut warmed: Option<(usize, usize, usize, u32)> = None;
+    let mut trace: Option<InputTrace> = None;
+    // The buffer to close once no buffer is being saved, and where the buffers closed so far
+    // were left, the last one at the end, to open them again.
+    let mut to_close: Option<usize> = None;
+    let mut closed: Vec<BufferState> = Vec::new();
+    // While the scrollbar thumb is dragged, how far below its top it was grabbed.
+    let mut scrollbar_grab: Option<f64> = None;
+    // The same for the viewport of the minimap.
+    let mut minimap_grab: Option<f64> = None;
+    let mut text_drag: Option<TextDrag> = None;
+    let mut hovered_link: Option<HoveredLink> = None;
+    // The line and display column a block selection dragged with Alt was started from.
+    let mut column_drag: Option<(usize, usize)> = None;
+    // When and where in the buffer the editor was last clicked, to tell double clicks.
+    let mut last_click: Option<(u64, (usize, usize))> = None;
+    // Whether an event may have changed what is shown since the last frame was drawn.
+    let mut damaged = true;
+    let mut last_frame: Option<Frame> = None;
+    // Whether the user chose to close the window, which happens once the buffers being saved
+    // are.
+    let mut closing = false;
+    let mut title = String::new();
+    loop {
+        let repeating = repeated_key.is_some();
+        let e = match repeated_key.take() {
+            Some(key) => Event::Input(Input::Press(Button::Keyboard(key))),
+            // Closing the window with changes that are not saved asks what to do with them
+            // first.
+            None => {
+                match events.next(&mut window) {
+                    Some(e) => e,
+                    None if !closing && !buffers.unsaved().is_empty() => {
+                        window.set_should_close(false);
+                        queued.clear();
+                        timeline = None;
+                        overlay = Some(close_prompt(&buffers));
+                        damaged = true;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+        if let Event::Input(ref input) = e {
+            if !repeating && !key_repeat.input(input, time::precise_time_ns()) {
+                continue;
+            }
+        }
+        match e {
+            Event::Input(Input::Press(Button::Keyboard(key))) => {
+                modifiers.update(key, true);
+                hover.reset();
+                last_key = time::precise_time_ns();
+            }
+            Event::Input(Input::Release(Button::Keyboard(key))) => modifiers.update(key, false),
+            Event::Input(Input::Move(Motion::MouseCursor(x, y))) => hover.move_to([x, y]),
+            _ => {}
+        }
+        match e {
+            // The mouse moving only shows up once it rests and a tooltip appears, which the
+            // frame keeps track of.
+            Event::Input(Input::Move(Motion::MouseCursor(..))) => {}
+            Event::Input(_) => damaged = true,
+            _ => {}
+        }
+        // Input only goes through the actions when no prompt or panel takes it instead.
+        let action = match e {
+            // Keys can stand for several actions, which are run one after the other, after the
+            // ones waiting to be run.
+            Event::Input(ref input) if overlay.is_none() && timeline.is_none() => {
+                let subwords = config.subword_motion;
+                let waiting = queued.len();
+                match vim {
+                    Some(ref mut vim) => queued.extend(vim.input(input, modifiers, subwords)),
+                    None => queued.extend(Action::from_input(input, modifiers, subwords)),
+                }
+                if queued.len() > waiting {
+                    queued.pop_front()
+                } else {
+                    None
+                }
+            }
+            Event::Update(_) => queued.pop_front(),
+            _ => None,
+        };
+        let traced = match e {
+            Event::Input(ref input) if trace.is_some() && overlay.is_none() &&
+                                       timeline.is_none() => action::describe(input, modifiers),
+            _ => None,
+        };
+        let mut took = 0;
         match e {
             Event::Render(_) => {
+                let name = window_title(buffers.active());
+                if name != title {
+                    window.set_title(name.clone());
+                    title = name;
+                }
                 let draw_size = window.draw_size();
+                // The window may have moved to a display of another density.
+                let scale = window.window.window.hidpi_factor() as f64 * zoom;
+                window_layout = Layout::new(draw_size, EM as f64, scale);
+                if window_layout.text_size() != metrics.size() {
+                    metrics = TextMetrics::new(window_layout.text_size());
+                }
+                let (focused, other) = pane_layouts(window_layout, panes.as_ref());
+                layout = focused;
+                other_layout = other;
+                let progress = buffers.active_progress();
+                let branch = buffers.active_branch().cloned();
+                let other_index = panes.as_ref().map(Panes::other_buffer);
+                let link = match hovered_link {
+                    Some(ref hovered) if hovered.is_current(&buffers) && timeline.is_none() => {
+                        Some((hovered.line, hovered.link.start..hovered.link.end))
+                    }
+                    _ => None,
+                };
+                let (buf, view, other_buf) = match timeline {
+                    Some(ref timeline) => {
+                        (timeline.preview(), &mut preview_view, other_index.map(|i| buffers.get(i)))
+                    }
+                    None => {
+                        let active = buffers.active_index();
+                        let (buf, view, other) =
+                            buffers.active_with_view_and(other_index.unwrap_or(active));
+                        (buf, view, other_index.map(|_| other))
+                    }
+                };
+                let mut other_pane = match (panes.as_mut(), other_buf, other_layout) {
+                    (Some(panes), Some(other_buf), Some(other_layout)) => {
+                        let other_view = panes.other_view_mut();
+                        other_view.set_wrap_width(other_layout.wrap_width());
+                        Some((other_buf, other_view, other_layout))
+                    }
+                    _ => None,
+                };
+                view.set_wrap_width(layout.wrap_width());
+                let tooltip = if hover.is_resting() && overlay.is_none() && timeline.is_none() {
+                    hover_info(buf, view, &layout, hover.position, &mut metrics, &mut glyphs)
+                } else {
+                    None
+                };
+                let frame = Frame {
+                    size: (draw_size.width, draw_size.height),
+                    version: buf.version(),
+                    first_line: view.first_line(),
+                    other_pane: other_pane.as_ref()
+                        .map(|&(buf, ref view, _)| (buf.version(), view.first_line())),
+                    progress: progress,
+                    caret_on: (time::precise_time_ns() % 1_000_000_000) / 500_000_000 == 0,
+                    tooltip: tooltip,
+                };
+                if !damaged && last_frame.as_ref() == Some(&frame) {
+                    continue;
+                }
+                let render_started = time::precise_time_ns();
+                let report = if show_profiler {
+                    Some(profiler.report(buf))
+                } else {
+                    None
+                };
+                // The time spent laying out the lines to draw, out of the whole frame.
+                let mut laid_out = 0;
+                let theme = &themes[theme_index];
+                let caret_on = frame.caret_on;
+                let tooltip = frame.tooltip.as_ref();
 
                 let _ = window.draw_2d(&e, |c, g| {
-                    clear(BACKGROUND_COLOR, g);
-                    println!("Context: {{viewport: {{rect: {:?}, draw_size: {:?}, window_size: \
-                              {:?}}}, view: {:?}, transform: {:?}, draw_state: {:?}}}",
-                             c.viewport.unwrap().rect,
-                             c.viewport.unwrap().draw_size,
-                             c.viewport.unwrap().window_size,
-                             c.view,
-                             c.transform,
-                             c.draw_state);
-
-                    let transform = c.transform.trans(10.0, 100.0);
-                    Text::new_color([1.0; 4], (EM as f32 * 0.7) as u32)
-                        .draw(&format!("FPS: {}", fps_counter.tick()),
-                              &mut glyphs,
-                              &c.draw_state,
-                              transform,
-                              g);
-
-                    let transform = c.transform.trans(MENU_WIDTH, 0.0);
-                    rectangle(EDITOR_BG_COLOR,
-                              [0.0,
-                               0.0,
-                               draw_size.width as f64 - MENU_WIDTH,
-                               draw_size.height as f64],
-                              transform,
-                              g);
-
-                    for cursor in buf.get_cursors() {
-                        rectangle(BG_COLOR_LIGHT,
-                                  [0.0,
-                                   10.0 + EM as f64 * cursor.start_line as f64 * 1.1,
-                                   draw_size.width as f64 - MENU_WIDTH,
-                                   EM as f64 * (cursor.end_line - cursor.start_line + 1) as f64 *
-                                   1.1],
-                                  transform,
+                    clear(theme.background, g);
+                    draw_file_tree(&file_tree, &layout, theme, &mut glyphs, &c, g);
+
+                    if let Some((other_buf, ref mut other_view, ref other_layout)) = other_pane {
+                        rectangle(theme.editor_background, other_layout.editor, c.transform, g);
+                        if other_buf.is_loaded() {
+                            let started = time::precise_time_ns();
+                            let plan = RenderPlan::new(other_buf,
+                                                       other_view,
+                                                       false,
+                                                       false,
+                                                       None,
+                                                       None,
+                                                       other_layout,
+                                                       &mut metrics,
+                                                       &mut glyphs);
+                            laid_out += time::precise_time_ns() - started;
+                            draw_buffer(&plan, theme, &mut glyphs, &c, g);
+                            draw_scrollbar(other_view.first_line(),
+                                           other_buf.line_count(),
+                                           other_layout,
+                                           theme,
+                                           &c,
+                                           g);
+                        }
+                    }
+                    rectangle(theme.editor_background, layout.editor, c.transform, g);
+                    if let Some(progress) = progress {
+                        let bar = layout.progress_bar();
+                        rectangle(theme.panel_background, bar, c.transform, g);
+                        rectangle(theme.cursor,
+                                  [bar[0], bar[1], bar[2] * progress, bar[3]],
+                                  c.transform,
                                   g);
-                        if cursor.is_atomic() {
-                            let now_tick = (time::precise_time_ns() % 1_000_000_000) / 500_000_000;
-                            if now_tick == 0 {
-                                let c_transform = transform.trans(cursor.start_character as f64 *
-                                           glyphs.character((EM as f32 * 0.7) as u32, ' ').width(),
-                                           10.0 + cursor.start_line as f64 * EM as f64 * 1.1);
-                                line(CURSOR_COLOR,
-                                     EM as f64 / 15.0,
-                                     [0.0, 0.0, 0.0, EM as f64],
-                                     c_transform,
-                                     g);
+                    } else {
+                        let drop_target = text_drag.as_ref().and_then(|drag| drag.target);
+                        let started = time::precise_time_ns();
+                        let plan = RenderPlan::new(buf,
+                                                   view,
+                                                   true,
+                                                   caret_on,
+                                                   drop_target,
+                                                   link,
+                                                   &layout,
+                                                   &mut metrics,
+                                                   &mut glyphs);
+                        laid_out += time::precise_time_ns() - started;
+                        draw_buffer(&plan, theme, &mut glyphs, &c, g);
+                        draw_scrollbar(view.first_line(),
+                                       buf.line_count(),
+                                       &layout,
+                                       theme,
+                                       &c,
+                                       g);
+                    }
+
+                    if let Some(ref timeline) = timeline {
+                        draw_timeline(timeline, &layout, theme, &mut glyphs, &c, g);
+                    }
+
+                    draw_status_bar(buf,
+                                    vim.as_ref().map(Vim::mode),
+                                    branch.as_ref(),
+                                    tree_refresh.is_some(),
+                                    &layout,
+                                    theme,
+                                    &mut glyphs,
+                                    &c,
+                                    g);
+
+                    if let Some(ref overlay) = overlay {
+                        draw_prompt(overlay.prompt(), &layout, theme, &mut glyphs, &c, g);
+                    }
+
+                    if let Some(tooltip) = tooltip {
+                        draw_tooltip(tooltip, hover.position, &layout, theme, &mut glyphs, &c, g);
+                    }
+
+                    if let Some(ref report) = report {
+                        draw_profiler(report, &layout, theme, &mut glyphs, &c, g);
+                    }
+                });
+                window.swap_buffers();
+                let took = time::precise_time_ns() - render_started;
+                profiler.add(Stage::Layout, laid_out);
+                profiler.add(Stage::Drawing, took - laid_out);
+                profiler.end_frame(took);
+                last_drawn = time::precise_time_ns();
+                damaged = false;
+                last_frame = Some(frame);
+            }
+            // Actions chosen from the command palette are run on the next update.
+            _ if action.is_some() => {
+                damaged = true;
+                let started = time::precise_time_ns();
+                match action.clone().unwrap() {
+                    Action::GoToSymbol => {
+                        let tags = if Path::new(tags::TAGS_FILE).exists() {
+                            tags::load(tags::TAGS_FILE)
+                        } else {
+                            Ok(Vec::new())
+                        };
+                        match tags {
+                            Ok(tags) => {
+                                let items = tags.iter()
+                                    .map(|t| format!("{}  {}", t.name, t.path.display()))
+                                    .collect();
+                                let prompt = Prompt::new("Go to symbol", items);
+                                overlay = Some(Overlay::Symbols(prompt, tags));
                             }
+                            Err(e) => overlay = Some(error_prompt("Could not read the tags", e)),
                         }
                     }
-
-                    for (i, line) in buf.lines().enumerate() {
-                        let transform = transform.trans(0.0, EM as f64 * 1.1 * (i + 1) as f64);
-                        let line = if line.chars().rev().next() == Some('\n') {
-                            &line[..line.len() - 1]
+                    Action::ShowHover => {
+                        lsp.hover(buffers.active_index(), buffers.active());
+                    }
+                    Action::GoToDefinition => {
+                        lsp.definition(buffers.active_index(), buffers.active());
+                    }
+                    Action::ShowSpellingSuggestions => {
+                        let misspelling = {
+                            let buf = buffers.active();
+                            match buf.get_cursors().first() {
+                                Some(cursor) => spelling.misspelling_at(buf, cursor.head()),
+                                None => None,
+                            }
+                        };
+                        if let Some(misspelling) = misspelling {
+                            let mut items = misspelling.suggestions.clone();
+                            items.push(format!("Add \"{}\" to the dictionary", misspelling.word));
+                            let prompt = Prompt::new("Spelling", items);
+                            overlay = Some(Overlay::Spelling(prompt, misspelling));
+                        }
+                    }
+                    Action::SetSpellLanguage => {
+                        let mut languages = spelling.languages();
+                        languages.insert(0, String::from("Off"));
+                        let prompt = Prompt::new("Spelling language", languages.clone());
+                        overlay = Some(Overlay::SpellLanguage(prompt, languages));
+                    }
+                    Action::CommandPalette => {
+                        let commands = action::commands();
+                        let mut items: Vec<String> = commands.iter()
+                            .map(|c| if c.keys.is_empty() {
+                                String::from(c.name)
+                            } else {
+                                format!("{}  ({})", c.name, c.keys)
+                            })
+                            .collect();
+                        let mut actions: Vec<Action> =
+                            commands.into_iter().map(|c| c.action).collect();
+                        for (i, command) in plugins.commands().iter().enumerate() {
+                            items.push(command.name.clone());
+                            actions.push(Action::RunPluginCommand(i));
+                        }
+                        overlay = Some(Overlay::Commands(Prompt::new("Command", items), actions));
+                    }
+                    Action::RunPluginCommand(i) => plugins.run_command(i, &mut buffers),
+                    Action::Save => {
+                        if let Err(e) = buffers.save_active() {
+                            overlay = Some(error_prompt("Could not save", e));
+                        }
+                    }
+                    Action::GoToLine => {
+                        overlay = Some(Overlay::GoToLine(Prompt::new("Go to line[:column]",
+                                                                     Vec::new())));
+                    }
+                    Action::OpenFile => {
+                        overlay = Some(Overlay::OpenFile(Prompt::new("Open file", Vec::new())));
+                    }
+                    Action::QuickOpen => {
+                        let (prompt, paths) = file_index.prompt();
+                        overlay = Some(Overlay::QuickOpen(prompt, paths));
+                    }
+                    Action::OpenRecent => {
+                        let files = recent.files();
+                        let names = files.iter()
+                            .map(|p| p.to_string_lossy().into_owned())
+                            .collect();
+                        overlay = Some(Overlay::OpenRecent(Prompt::new("Open recent", names),
+                                                           files));
+                    }
+                    Action::CloseBuffer => {
+                        let active = buffers.active_index();
+                        if buffers.active().is_saved() {
+                            to_close = Some(active);
                         } else {
-                            &line
+                            overlay = Some(close_buffer_prompt(buffers.active(), active));
+                        }
+                    }
+                    Action::ReopenClosed => {
+                        if let Some(state) = closed.pop() {
+                            if state.path.exists() {
+                                let index = buffers.open(&state.path,
+                                                         config.options(Some(&state.path)))
+                                    .unwrap();
+                                pending_restores.push((index, state));
+                            }
+                        }
+                    }
+                    Action::SaveAs => {
+                        overlay = Some(Overlay::SaveAs(Prompt::new("Save as", Vec::new())));
+                    }
+                    Action::RenameFile => {
+                        if let Some(path) = buffers.active().get_path() {
+                            let mut prompt = Prompt::new("Rename to", Vec::new());
+                            for c in path.to_string_lossy().chars() {
+                                prompt.insert(c);
+                            }
+                            overlay = Some(Overlay::RenameFile(prompt));
+                        }
+                    }
+                    Action::ExportHtml => {
+                        let mut prompt = Prompt::new("Export to", Vec::new());
+                        if let Some(path) = buffers.active().get_path() {
+                            for c in path.to_string_lossy().chars().chain(".html".chars()) {
+                                prompt.insert(c);
+                            }
+                        }
+                        overlay = Some(Overlay::ExportHtml(prompt));
+                    }
+                    Action::Redetect => {
+                        let detection = detect::detect(buffers.active());
+                        let mut options = config.filetype_options(detection.filetype
+                            .as_ref()
+                            .map(String::as_str));
+                        if let Some(indent) = detection.indent {
+                            options.hard_tabs = indent == "\t";
+                            options.indent = indent;
+                        }
+                        let buf = buffers.active_mut();
+                        if buf.get_options().large_file {
+                            options.limit_for_large_file();
+                        }
+                        buf.set_options(options);
+                        buf.set_line_ending(detection.line_ending);
+                    }
+                    Action::ToggleHardTabs => {
+                        let buf = buffers.active_mut();
+                        let mut options = buf.get_options().clone();
+                        options.hard_tabs = !options.hard_tabs;
+                        options.indent = config::indentation(options.hard_tabs, options.tab_width);
+                        buf.set_options(options);
+                    }
+                    Action::ToggleWrap => {
+                        let buf = buffers.active_mut();
+                        let mut options = buf.get_options().clone();
+                        options.wrap = !options.wrap && !options.large_file;
+                        buf.set_options(options);
+                    }
+                    Action::ToggleWhitespace => {
+                        let buf = buffers.active_mut();
+                        let mut options = buf.get_options().clone();
+                        options.show_whitespace = !options.show_whitespace;
+                        buf.set_options(options);
+                    }
+                    Action::ShowLongLines => {
+                        let long_lines = buffers.active().long_lines();
+                        let items = long_lines.iter()
+                            .map(|&(line, columns)| {
+                                format!("Line {} is {} columns long", line + 1, columns)
+                            })
+                            .collect();
+                        let lines = long_lines.into_iter().map(|(line, _)| line).collect();
+                        overlay = Some(Overlay::LongLines(Prompt::new("Long lines", items), lines));
+                    }
+                    Action::AuditFiles => {
+                        match env::current_dir() {
+                            Ok(dir) => pending_audit = Some(audit::scan(dir)),
+                            Err(e) => overlay = Some(error_prompt("Could not scan the files", e)),
+                        }
+                    }
+                    Action::NextTheme => theme_index = (theme_index + 1) % themes.len(),
+                    Action::ZoomIn => zoom = (zoom + ZOOM_STEP).min(MAX_ZOOM),
+                    Action::ZoomOut => zoom = (zoom - ZOOM_STEP).max(MIN_ZOOM),
+                    Action::ResetZoom => zoom = 1.0,
+                    Action::SwitchToRelated => {
+                        let root = env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+                        let related = buffers.active().get_path().and_then(|path| {
+                            related::find(path, &root, &config.related_files)
+                        });
+                        if let Some(path) = related {
+                            if let Err(e) = buffers.open(&path, config.options(Some(&path))) {
+                                overlay = Some(error_prompt("Could not open the related file", e));
+                            }
+                        }
+                    }
+                    Action::ToggleBookmark => bookmarks::toggle(buffers.active_mut()),
+                    Action::NextBookmark | Action::PreviousBookmark => {
+                        let forward = action == Some(Action::NextBookmark);
+                        if let Some((index, line)) = bookmarks::next(&buffers, forward) {
+                            buffers.activate(index);
+                            pending_result = Some((index, line, 0));
+                        }
+                    }
+                    Action::CompareWithSaved => {
+                        let saved = buffers.active().get_path().map(|path| {
+                            (path.display().to_string(), diffview::file_lines(path))
+                        });
+                        if let Some((name, Ok(saved))) = saved {
+                            let current = diffview::buffer_lines(buffers.active());
+                            pending_diff = Some(diffview::compare(format!("{} (saved)", name),
+                                                                  saved,
+                                                                  name,
+                                                                  current));
+                        }
+                    }
+                    Action::CompareWithBuffer => {
+                        let active = buffers.active_index();
+                        let others: Vec<usize> =
+                            (0..buffers.len()).filter(|&i| i != active).collect();
+                        let names = others.iter().map(|&i| file_name(buffers.get(i))).collect();
+                        overlay = Some(Overlay::CompareWith(Prompt::new("Compare with", names),
+                                                            others));
+                    }
+                    Action::NextHunk | Action::PreviousHunk => {
+                        let forward = action == Some(Action::NextHunk);
+                        let line = buffers.active().get_cursors().last().map_or(0, |c| {
+                            c.head_line()
+                        });
+                        match diff_view {
+                            Some((index, ref diff)) if index == buffers.active_index() => {
+                                if let Some(line) = diff.next_hunk(line, forward) {
+                                    pending_result = Some((index, line, 0));
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    Action::RunTask => {
+                        let names: Vec<String> = config.tasks.keys().cloned().collect();
+                        let prompt = Prompt::new("Run task", names.clone());
+                        overlay = Some(Overlay::Tasks(prompt, names));
+                    }
+                    Action::FindInFiles => {
+                        overlay = Some(Overlay::FindInFiles(Prompt::new("Find in files",
+                                                                        Vec::new())));
+                    }
+                    Action::SearchEdit => {
+                        overlay = Some(Overlay::SearchEdit(Prompt::new("Edit lines matching",
+                                                                       Vec::new())));
+                    }
+                    Action::ShowHistory => {
+                        let (buf, view) = buffers.active_with_view();
+                        if buf.is_loaded() {
+                            timeline = Some(Timeline::new(buf));
+                            preview_view = view.clone();
+                        }
+                    }
+                    Action::ToggleProfiler => show_profiler = !show_profiler,
+                    Action::ToggleInputTrace => {
+                        let active = buffers.active_index();
+                        if trace.is_none() {
+                            trace = Some(InputTrace {
+                                buffer: buffers.open_empty(config.options(None)),
+                                started: time::precise_time_ns(),
+                                previous: active,
+                            });
+                        } else if let Some(ref mut trace) = trace {
+                            if active == trace.buffer {
+                                buffers.activate(trace.previous);
+                            } else {
+                                trace.previous = active;
+                                buffers.activate(trace.buffer);
+                            }
+                        }
+                    }
+                    Action::ToggleMacroRecording => {
+                        match recording.take() {
+                            Some(actions) => {
+                                if !actions.is_empty() {
+                                    last_macro = actions.clone();
+                                    let (items, slots) = macro_slots(&macros);
+                                    let prompt = Prompt::new("Save macro to", items);
+                                    overlay = Some(Overlay::SaveMacro(prompt, slots, actions));
+                                }
+                            }
+                            None => recording = Some(Vec::new()),
+                        }
+                    }
+                    Action::PlayMacro(slot) => {
+                        let actions = macros.get(slot).map(|m| m.actions.clone());
+                        if let (Some(actions), true) = (actions, buffers.active().is_loaded()) {
+                            let (buf, view) = buffers.active_with_view();
+                            for action in &actions {
+                                edit_buffer(buf, view, action, &layout, &mut metrics, &mut glyphs);
+                            }
+                        }
+                    }
+                    Action::ToggleRecording => {
+                        match recording.take() {
+                            Some(actions) => {
+                                if !actions.is_empty() {
+                                    last_macro = actions;
+                                }
+                            }
+                            None => recording = Some(Vec::new()),
+                        }
+                    }
+                    Action::ReplayMacro => {
+                        if !last_macro.is_empty() && recording.is_none() {
+                            let prompt = Prompt::new("Replay how many times", Vec::new());
+                            overlay = Some(Overlay::ReplayMacro(prompt));
+                        }
+                    }
+                    Action::Copy => {
+                        let text = buffers.active().selected_text();
+                        if !text.is_empty() {
+                            clipboard = text;
+                        }
+                    }
+                    Action::Paste => {
+                        let (buf, view) = buffers.active_with_view();
+                        if buf.is_loaded() && !buf.get_options().read_only {
+                            buf.write_str(&clipboard);
+                            if let Some(cursor) = buf.get_cursors().last() {
+                                view.reveal_wrapped(buf,
+                                                    cursor.head_line(),
+                                                    layout.rows(),
+                                                    &mut metrics,
+                                                    &mut glyphs);
+                            }
+                        }
+                    }
+                    Action::ShowMacros => {
+                        let (items, slots) = macro_slots(&macros);
+                        overlay = Some(Overlay::Macros(Prompt::new("Macro", items), slots));
+                    }
+                    Action::ToggleSplit(split) => {
+                        // Joining the panes keeps the focused one.
+                        let current = panes.as_ref().map(Panes::split);
+                        if current == Some(split) {
+                            panes = None;
+                        } else if current.is_some() {
+                            panes.as_mut().unwrap().set_split(split);
+                        } else {
+                            panes = Some(Panes::new(split, &mut buffers));
+                        }
+                        let (focused, other) = pane_layouts(window_layout, panes.as_ref());
+                        layout = focused;
+                        other_layout = other;
+                    }
+                    Action::FocusPane(index) => {
+                        if let Some(ref mut panes) = panes {
+                            panes.focus(index, &mut buffers);
+                            let (focused, other) = pane_layouts(window_layout, Some(&*panes));
+                            layout = focused;
+                            other_layout = other;
+                        }
+                    }
+                    Action::ShowWelcome => {
+                        match welcome_page.as_ref().map(|&(index, _)| index) {
+                            Some(index) => buffers.activate(index),
+                            None => {
+                                welcome_page = Some(open_welcome(&mut buffers, &config, &recent))
+                            }
+                        }
+                    }
+                    action => {
+                        // Enter on a line of the welcome page runs its item instead of editing.
+                        let item = match welcome_page {
+                            Some((index, ref welcome)) if index == buffers.active_index() &&
+                                                          action == Action::NewLine => {
+                                buffers.active()
+                                    .get_cursors()
+                                    .last()
+                                    .and_then(|c| welcome.item(c.head_line()))
+                                    .cloned()
+                            }
+                            _ => None,
+                        };
+                        // And on a result of a search in files or a location in the output of a
+                        // task goes to it.
+                        let result = match find_results {
+                            Some((index, ref results)) if index == buffers.active_index() &&
+                                                          action == Action::NewLine => {
+                                buffers.active()
+                                    .get_cursors()
+                                    .last()
+                                    .and_then(|c| results.target(c.head_line()))
+                                    .map(|(path, line)| (path.to_path_buf(), line, 0))
+                            }
+                            _ => None,
                         };
-                        Text::new_color([1.0; 4], (EM as f32 * 0.7) as u32)
-                            .draw(&line, &mut glyphs, &c.draw_state, transform, g);
+                        let result = match task_output {
+                            Some((index, ref task)) if index == buffers.active_index() &&
+                                                       action == Action::NewLine => {
+                                buffers.active()
+                                    .get_cursors()
+                                    .last()
+                                    .and_then(|c| task.target(c.head_line()))
+                                    .map(|(path, line, column)| (path.to_path_buf(), line, column))
+                            }
+                            _ => result,
+                        };
+                        if let Some(item) = item {
+                            match item {
+                                Item::Run(action) => queued.push_back(action),
+                                Item::Open(path) => {
+                                    let options = config.options(Some(&path));
+                                    if let Err(e) = buffers.open(&path, options) {
+                                        overlay = Some(error_prompt("Could not open the file", e));
+                                    }
+                                }
+                                Item::OpenFolder => {
+                                    let prompt = Prompt::new("Open folder", Vec::new());
+                                    overlay = Some(Overlay::OpenFolder(prompt));
+                                }
+                                Item::OpenSettings => {
+                                    let path = Path::new(config::CONFIG_FILE);
+                                    if let Err(e) = buffers.open(path, config.options(Some(path))) {
+                                        let title = "Could not open the settings";
+                                        overlay = Some(error_prompt(title, e));
+                                    }
+                                }
+                            }
+                        } else if let Some((path, line, column)) = result {
+                            match buffers.open(&path, config.options(Some(&path))) {
+                                Ok(index) => pending_result = Some((index, line, column)),
+                                Err(e) => {
+                                    overlay = Some(error_prompt("Could not open the file", e))
+                                }
+                            }
+                        } else if buffers.active().is_loaded() {
+                            if let Some(ref mut recording) = recording {
+                                if macros::is_recordable(&action) {
+                                    recording.push(action.clone());
+                                }
+                            }
+                            let (buf, view) = buffers.active_with_view();
+                            edit_buffer(buf, view, &action, &layout, &mut metrics, &mut glyphs);
+                        }
                     }
-                });
+                }
+                took = time::precise_time_ns() - started;
             }
-            Event::Input(Input::Text(ref s)) => {
-                for c in s.chars() {
-                    buf.write_character(c);
+            Event::Update(_) => {
+                for (i, e) in buffers.receive_lines() {
+                    overlay = Some(error_prompt("Could not read the file", e));
+                    // Whatever was read of it is not worth keeping.
+                    if to_close.is_none() {
+                        to_close = Some(i);
+                    }
+                    damaged = true;
+                }
+                for e in buffers.receive_saves() {
+                    overlay = Some(error_prompt("Could not save", e));
+                    // The window stays open to tell why.
+                    closing = false;
+                    damaged = true;
+                }
+                // Buffers without a file, changed on disk or whose save timed out are still
+                // not saved, so what to do with them is asked again.
+                if closing && !buffers.is_saving() && !window.should_close() {
+                    closing = false;
+                    if buffers.unsaved().is_empty() {
+                        window.set_should_close(true);
+                    } else {
+                        overlay = Some(close_prompt(&buffers));
+                        damaged = true;
+                    }
+                }
+                if to_close.is_some() && !buffers.is_saving() {
+                    let index = to_close.take().unwrap();
+                    {
+                        let (buf, view) = buffers.get_with_view(index);
+                        if let Some(state) = BufferState::capture(buf, view) {
+                            closed.push(state);
+                        }
+                    }
+                    // There is always a buffer open.
+                    if buffers.len() == 1 {
+                        let _ = buffers.open_empty(config.options(None));
+                    }
+                    buffers.close(index);
+                    lsp.close(index);
+                    plugins.close(index);
+                    // The buffers after the closed one move down, and whatever showed it goes.
+                    if !panes.as_mut().map_or(true, |p| p.close(index)) {
+                        panes = None;
+                        let (focused, other) = pane_layouts(window_layout, panes.as_ref());
+                        layout = focused;
+                        other_layout = other;
+                    }
+                    welcome_page = welcome_page.and_then(|(i, w)| moved(i, index).map(|i| (i, w)));
+                    pending_jump = pending_jump.and_then(|(i, t)| moved(i, index).map(|i| (i, t)));
+                    pending_result = pending_result.and_then(|(i, line, column)| {
+                        moved(i, index).map(|i| (i, line, column))
+                    });
+                    find_results = find_results.and_then(|(i, r)| moved(i, index).map(|i| (i, r)));
+                    diff_view = diff_view.and_then(|(i, d)| moved(i, index).map(|i| (i, d)));
+                    task_output = task_output.and_then(|(i, t)| moved(i, index).map(|i| (i, t)));
+                    pending_restores = pending_restores.into_iter()
+                        .filter_map(|(i, state)| moved(i, index).map(|i| (i, state)))
+                        .collect();
+                    trace = trace.and_then(|trace| {
+                        moved(trace.buffer, index).map(|buffer| {
+                            InputTrace {
+                                buffer: buffer,
+                                started: trace.started,
+                                previous: moved(trace.previous, index).unwrap_or(0),
+                            }
+                        })
+                    });
+                    warmed = None;
+                    hovered_link = None;
+                    damaged = true;
+                }
+                // The file of the active buffer goes first in the recent files.
+                if let Some(path) = buffers.active().get_path() {
+                    recent.opened(path);
+                }
+                if buffers.receive_git_statuses() {
+                    damaged = true;
+                }
+                let compared = pending_diff.as_ref().and_then(|receiver| receiver.try_recv().ok());
+                if let Some(compared) = compared {
+                    pending_diff = None;
+                    diff_view = Some(open_diff(&mut buffers, &config, compared));
+                    damaged = true;
+                }
+                let findings = pending_audit.as_ref().and_then(|receiver| receiver.try_recv().ok());
+                if let Some(findings) = findings {
+                    pending_audit = None;
+                    overlay = Some(audit_prompt(findings));
+                    damaged = true;
+                }
+                let searched = pending_session.as_ref().and_then(|r| r.try_recv().ok());
+                if let Some(session) = searched {
+                    pending_session = None;
+                    let _ = buffers.open_session(session, config.options(None));
+                    damaged = true;
+                }
+                let now = time::precise_time_ns();
+                repeated_key = key_repeat.update(now);
+                let duration = config.scroll_animation * 1_000_000;
+                if buffers.active_with_view().1.animate(now, duration) {
+                    damaged = true;
+                }
+                if let Some(ref mut panes) = panes {
+                    if panes.other_view_mut().animate(now, duration) {
+                        damaged = true;
+                    }
+                }
+                if config.autosave.is_due(now - last_key, now - last_autosave) {
+                    if let Err(e) = buffers.save_all() {
+                        overlay = Some(error_prompt("Could not save", e));
+                        damaged = true;
+                    }
+                    last_autosave = now;
+                }
+                if now - last_swap >= SWAP_INTERVAL {
+                    recovery.update(&buffers);
+                    last_swap = now;
+                }
+                if now - last_disk_check >= DISK_CHECK_INTERVAL && overlay.is_none() &&
+                   timeline.is_none() {
+                    last_disk_check = now;
+                    if let Some(i) = buffers.changed_on_disk() {
+                        overlay = Some(changed_on_disk_prompt(buffers.get(i), i));
+                        damaged = true;
+                    }
+                }
+                let refreshed = match tree_refresh {
+                    Some(ref receiver) => {
+                        match receiver.try_recv() {
+                            Ok(tree) => Some(tree.ok()),
+                            Err(mpsc::TryRecvError::Empty) => None,
+                            Err(mpsc::TryRecvError::Disconnected) => Some(None),
+                        }
+                    }
+                    None => None,
+                };
+                if let Some(tree) = refreshed {
+                    tree_refresh = None;
+                    if let Some(tree) = tree {
+                        let scroll = file_tree.scroll_offset();
+                        file_tree = tree;
+                        file_tree.scroll_to(scroll);
+                    }
+                    damaged = true;
+                } else if tree_refresh.is_none() && now - last_key >= IDLE_REFRESH_DELAY &&
+                          now - last_refresh >= REFRESH_INTERVAL {
+                    let tree = file_tree.clone();
+                    let (sender, receiver) = mpsc::channel();
+                    let _ = thread::spawn(move || {
+                        let _ = sender.send(tree.refreshed());
+                    });
+                    tree_refresh = Some(receiver);
+                    last_refresh = now;
+                    damaged = true;
+                }
+                let (restored, waiting): (Vec<_>, Vec<_>) = pending_restores.into_iter()
+                    .partition(|&(i, _)| buffers.get(i).is_loaded());
+                pending_restores = waiting;
+                for (i, state) in restored {
+                    let (buf, view) = buffers.get_with_view(i);
+                    state.restore(buf, view);
+                    damaged = true;
+                }
+                let found = file_index.receive();
+                // Files found while the project is listed are listed too.
+                if let Some(Overlay::QuickOpen(ref mut prompt, ref mut paths)) = overlay {
+                    if !found.is_empty() {
+                        let (names, bonuses, found_paths) = file_index.items(&found);
+                        prompt.extend(names, bonuses);
+                        paths.extend(found_paths);
+                        damaged = true;
+                    }
+                }
+                lsp.sync(&buffers, &config);
+                for event in lsp.receive(&mut buffers) {
+                    match event {
+                        lsp::Event::Diagnostics => damaged = true,
+                        lsp::Event::Hover(lines) => {
+                            if overlay.is_none() {
+                                let prompt = Prompt::new("Symbol info", lines);
+                                overlay = Some(Overlay::Hover(prompt));
+                                damaged = true;
+                            }
+                        }
+                        // The language server can name anything, so it is not trusted to
+                        // name a file that can be opened.
+                        lsp::Event::Definition(path, line, column) => {
+                            match buffers.open(&path, config.options(Some(&path))) {
+                                Ok(index) => pending_result = Some((index, line, column)),
+                                Err(e) => {
+                                    let title = "Could not open the definition";
+                                    overlay = Some(error_prompt(title, e));
+                                    damaged = true;
+                                }
+                            }
+                        }
+                    }
+                }
+                if plugins.update(&mut buffers) {
+                    damaged = true;
+                }
+                if spelling.update(&mut buffers) {
+                    damaged = true;
+                }
+                if let Some((index, ref mut results)) = find_results {
+                    let lines = results.receive();
+                    if !lines.is_empty() {
+                        buffers.get_mut(index).append_lines(lines);
+                        damaged = true;
+                    }
+                }
+                if let Some((index, ref mut task)) = task_output {
+                    let lines = task.receive();
+                    if !lines.is_empty() {
+                        buffers.get_mut(index).append_lines(lines);
+                        damaged = true;
+                    }
                 }
+                if let Some((i, line, column)) = pending_result.take() {
+                    if buffers.get(i).is_loaded() {
+                        let (buf, view) = buffers.get_with_view(i);
+                        let cursor = buf.goto(line, column);
+                        view.reveal_range(cursor.start_line..cursor.end_line + 1,
+                                          RevealMode::Center,
+                                          layout.rows(),
+                                          buf.line_count());
+                        damaged = true;
+                    } else {
+                        pending_result = Some((i, line, column));
+                    }
+                }
+                if let Some((i, tag)) = pending_jump.take() {
+                    if buffers.get(i).is_loaded() {
+                        let (buf, view) = buffers.get_with_view(i);
+                        let line = tag.line(buf.lines()).unwrap_or(0);
+                        let cursor = buf.goto(line, 0);
+                        // The definition goes at the top, with as much of its body as fits.
+                        view.reveal_range(cursor.start_line..cursor.end_line + 1,
+                                          RevealMode::Top,
+                                          layout.rows(),
+                                          buf.line_count());
+                        damaged = true;
+                    } else {
+                        pending_jump = Some((i, tag));
+                    }
+                }
+                if timeline.is_none() && buffers.active().is_loaded() {
+                    let active = buffers.active_index();
+                    let (buf, view) = buffers.active_with_view();
+                    let shown = (active, buf.version(), view.first_line(), metrics.size());
+                    if warmed != Some(shown) {
+                        let warmup = LayoutWarmup::new(active, buf, view, layout.rows());
+                        scheduler.add(Box::new(warmup));
+                        warmed = Some(shown);
+                    }
+                }
+                // Half of what is left of the frame, leaving the rest to handle input in.
+                let budget = FRAME_TIME.saturating_sub(time::precise_time_ns() - last_drawn) / 2;
+                scheduler.run(budget, &mut buffers, &mut metrics, &mut glyphs);
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Return))) => {
-                buf.write_character('\n');
+            Event::Input(ref input) if overlay.is_some() => {
+                let event = prompt_input(overlay.as_mut().unwrap().prompt_mut(), input);
+                match event {
+                    PromptEvent::None => {}
+                    PromptEvent::Cancel => overlay = None,
+                    PromptEvent::Accept => {
+                        match overlay.take().unwrap() {
+                            Overlay::Symbols(prompt, tags) => {
+                                if let Some(i) = prompt.selected() {
+                                    let tag = tags[i].clone();
+                                    let i = buffers.open(&tag.path, config.options(Some(&tag.path)))
+                                        .unwrap();
+                                    pending_jump = Some((i, tag));
+                                }
+                            }
+                            Overlay::GoToLine(prompt) => {
+                                if let Some((line, column)) = parse_line_column(prompt.input()) {
+                                    let (buf, view) = buffers.active_with_view();
+                                    if buf.is_loaded() {
+                                        let cursor = buf.goto(line, column);
+                                        view.reveal_range(cursor.start_line..
+                                                          cursor.end_line + 1,
+                                                          RevealMode::Center,
+                                                          layout.rows(),
+                                                          buf.line_count());
+                                    }
+                                }
+                            }
+                            Overlay::QuickOpen(prompt, paths) => {
+                                if let Some(i) = prompt.selected() {
+                                    let _ = buffers.open(&paths[i], config.options(Some(&paths[i])))
+                                        .unwrap();
+                                    file_index.opened(&paths[i]);
+                                }
+                            }
+                            Overlay::OpenRecent(prompt, paths) => {
+                                if let Some(i) = prompt.selected() {
+                                    let _ = buffers.open(&paths[i], config.options(Some(&paths[i])))
+                                        .unwrap();
+                                }
+                            }
+                            Overlay::OpenFile(prompt) => {
+                                if !prompt.input().is_empty() {
+                                    let path = Path::new(prompt.input());
+                                    if let Err(e) = buffers.open(path, config.options(Some(path))) {
+                                        overlay = Some(error_prompt("Could not open the file", e));
+                                    }
+                                }
+                            }
+                            Overlay::OpenFolder(prompt) => {
+                                let path = Path::new(prompt.input());
+                                if !prompt.input().is_empty() && path.is_dir() {
+                                    match env::set_current_dir(path)
+                                        .and_then(|_| env::current_dir()) {
+                                        Ok(dir) => {
+                                            // A refresh still going on would bring the old
+                                            // folder back.
+                                            tree_refresh = None;
+                                            file_tree = FileTree::new(&dir).unwrap_or_else(|e| {
+                                                let title = "Could not read the folder";
+                                                overlay = Some(error_prompt(title, e));
+                                                FileTree::empty(&dir)
+                                            });
+                                            file_index = FileIndex::new(dir);
+                                        }
+                                        Err(e) => {
+                                            let title = "Could not open the folder";
+                                            overlay = Some(error_prompt(title, e));
+                                        }
+                                    }
+                                }
+                            }
+                            Overlay::SaveAs(prompt) => {
+                                if !prompt.input().is_empty() {
+                                    let path = Path::new(prompt.input());
+                                    let options = config.options(Some(path));
+                                    if let Err(e) = buffers.save_active_as(path, options) {
+                                        overlay = Some(error_prompt("Could not save", e));
+                                    }
+                                }
+                            }
+                            Overlay::RenameFile(prompt) => {
+                                if !prompt.input().is_empty() {
+                                    let path = Path::new(prompt.input());
+                                    let options = config.options(Some(path));
+                                    match buffers.rename_active(path, options) {
+                                        // The tree is refreshed on its own later if it cannot
+                                        // be read now.
+                                        Ok(()) => {
+                                            let _ = file_tree.refresh();
+                                        }
+                                        Err(e) => {
+                                            let title = "Could not rename the file";
+                                            overlay = Some(error_prompt(title, e));
+                                        }
+                                    }
+                                }
+                            }
+                            Overlay::ExportHtml(prompt) => {
+                                if !prompt.input().is_empty() {
+                                    match export::write_html(buffers.active(),
+                                                             &themes[theme_index],
+                                                             Path::new(prompt.input())) {
+                                        Ok(()) => {
+                                            let _ = file_tree.refresh();
+                                        }
+                                        Err(e) => {
+                                            let title = "Could not export the file";
+                                            overlay = Some(error_prompt(title, e));
+                                        }
+                                    }
+                                }
+                            }
+                            Overlay::Commands(prompt, actions) => {
+                                if let Some(i) = prompt.selected() {
+                                    queued.push_back(actions[i].clone());
+                                }
+                            }
+                            Overlay::Audit(prompt, findings) => {
+                                let fixed: &[audit::Finding] = match prompt.selected() {
+                                    Some(0) => &findings[..],
+                                    Some(i) => &findings[i - 1..i],
+                                    None => &[],
+                                };
+                                // Files that cannot be written are left as they are.
+                                let failed: Vec<String> = fixed.iter()
+                                    .filter_map(|f| {
+                                        f.fix().err().map(|e| format!("{}: {}", f.name, e))
+                                    })
+                                    .collect();
+                                if !failed.is_empty() {
+                                    let prompt = Prompt::new("Could not fix some files", failed);
+                                    overlay = Some(Overlay::Error(prompt));
+                                }
+                            }
+                            Overlay::SaveMacro(prompt, slots, actions) => {
+                                if let Some(i) = prompt.selected() {
+                                    let slot = slots[i];
+                                    macros.set(slot,
+                                               Macro {
+                                                   name: format!("Macro {}", slot),
+                                                   actions: actions,
+                                               });
+                                    overlay = save_macros(&macros);
+                                }
+                            }
+                            Overlay::ReplayMacro(prompt) => {
+                                let input = prompt.input().trim();
+                                let times = if input.is_empty() {
+                                    Some(1)
+                                } else {
+                                    input.parse::<usize>().ok()
+                                };
+                                if let (Some(times), true) = (times,
+                                                              buffers.active().is_loaded()) {
+                                    let (buf, view) = buffers.active_with_view();
+                                    buf.for_each_cursor(|buf| for _ in 0..times {
+                                        for action in &last_macro {
+                                            edit_buffer(buf,
+                                                        view,
+                                                        action,
+                                                        &layout,
+                                                        &mut metrics,
+                                                        &mut glyphs);
+                                        }
+                                    });
+                                }
+                            }
+                            Overlay::Macros(prompt, slots) => {
+                                let slot = prompt.selected().map(|i| slots[i]);
+                                let name = slot.and_then(|s| macros.get(s))
+                                    .map(|m| m.name.clone());
+                                if let (Some(slot), Some(name)) = (slot, name) {
+                                    let items = vec![String::from("Rename"),
+                                                     String::from("Delete")];
+                                    overlay = Some(Overlay::EditMacro(Prompt::new(name, items),
+                                                                      slot));
+                                }
+                            }
+                            Overlay::EditMacro(prompt, slot) => {
+                                match prompt.selected() {
+                                    Some(0) => {
+                                        let prompt = Prompt::new("Rename macro to", Vec::new());
+                                        overlay = Some(Overlay::RenameMacro(prompt, slot));
+                                    }
+                                    Some(_) => {
+                                        macros.remove(slot);
+                                        overlay = save_macros(&macros);
+                                    }
+                                    None => {}
+                                }
+                            }
+                            Overlay::RenameMacro(prompt, slot) => {
+                                if !prompt.input().is_empty() {
+                                    macros.rename(slot, prompt.input());
+                                    overlay = save_macros(&macros);
+                                }
+                            }
+                            Overlay::ChangedOnDisk(prompt, i) => {
+                                match prompt.selected() {
+                                    Some(0) => {
+                                        if let Err(e) = buffers.reload(i) {
+                                            let title = "Could not reload the file";
+                                            overlay = Some(error_prompt(title, e));
+                                        }
+                                    }
+                                    Some(_) => buffers.get_mut(i).ignore_disk_change(),
+                                    None => {}
+                                }
+                            }
+                            Overlay::Hover(_) | Overlay::Error(_) => {}
+                            Overlay::CompareWith(prompt, others) => {
+                                if let Some(i) = prompt.selected() {
+                                    let (old, old_name) = {
+                                        let buf = buffers.get(others[i]);
+                                        (diffview::buffer_lines(buf), file_name(buf))
+                                    };
+                                    let new = diffview::buffer_lines(buffers.active());
+                                    let new_name = file_name(buffers.active());
+                                    pending_diff =
+                                        Some(diffview::compare(old_name, old, new_name, new));
+                                }
+                            }
+                            Overlay::Recover(prompt, mut swaps) => {
+                                match prompt.selected() {
+                                    Some(i) if i < swaps.len() => {
+                                        let swap = swaps.remove(i);
+                                        let path = swap.path.as_ref().map(|p| p.as_path());
+                                        match swap.text() {
+                                            Ok(text) => {
+                                                let options = config.options(path);
+                                                let _ =
+                                                    buffers.open_recovered(path, &text, options);
+                                                swap.remove();
+                                                // The other files are offered again.
+                                                if !swaps.is_empty() {
+                                                    overlay = Some(recover_prompt(swaps));
+                                                }
+                                            }
+                                            Err(e) => {
+                                                let title = "Could not read the recovery file";
+                                                overlay = Some(error_prompt(title, e));
+                                            }
+                                        }
+                                    }
+                                    Some(_) => {
+                                        for swap in swaps {
+                                            swap.remove();
+                                        }
+                                    }
+                                    None => {}
+                                }
+                            }
+                            Overlay::CloseBuffer(prompt, i) => {
+                                match prompt.selected() {
+                                    // There is nowhere to save a buffer without a file yet.
+                                    Some(0) if buffers.get(i).get_path().is_none() => {
+                                        overlay = Some(Overlay::SaveAs(Prompt::new("Save as",
+                                                                                   Vec::new())));
+                                    }
+                                    Some(0) => {
+                                        match buffers.save_active() {
+                                            Ok(()) => to_close = Some(i),
+                                            Err(e) => {
+                                                overlay = Some(error_prompt("Could not save", e))
+                                            }
+                                        }
+                                    }
+                                    Some(1) => to_close = Some(i),
+                                    _ => {}
+                                }
+                            }
+                            Overlay::Close(prompt) => {
+                                match prompt.selected() {
+                                    Some(0) => {
+                                        match buffers.save_all() {
+                                            Ok(()) => closing = true,
+                                            Err(e) => {
+                                                overlay = Some(error_prompt("Could not save", e))
+                                            }
+                                        }
+                                    }
+                                    Some(1) => {
+                                        closing = true;
+                                        window.set_should_close(true);
+                                    }
+                                    _ => {}
+                                }
+                            }
+                            Overlay::Spelling(prompt, misspelling) => {
+                                match prompt.selected() {
+                                    Some(i) if i < misspelling.suggestions.len() => {
+                                        let Misspelling { line, start, end, .. } = misspelling;
+                                        let buf = buffers.active_mut();
+                                        let text = {
+                                            let text = buf.lines().nth(line).unwrap();
+                                            let text = text.trim_right_matches(|c| {
+                                                c == '\n' || c == '\r'
+                                            });
+                                            format!("{}{}{}",
+                                                    &text[..start],
+                                                    misspelling.suggestions[i],
+                                                    &text[end..])
+                                        };
+                                        if !buf.get_options().read_only {
+                                            buf.replace_line(line, &text);
+                                            let byte = start + misspelling.suggestions[i].len();
+                                            let cursor = buf.cursor_at_byte(line, byte);
+                                            buf.set_cursors(vec![cursor]);
+                                        }
+                                    }
+                                    Some(_) => {
+                                        if let Err(e) = spelling.add_word(&misspelling.word) {
+                                            let title = "Could not add the word to the dictionary";
+                                            overlay = Some(error_prompt(title, e));
+                                        }
+                                    }
+                                    None => {}
+                                }
+                            }
+                            Overlay::SpellLanguage(prompt, languages) => {
+                                if let Some(i) = prompt.selected() {
+                                    let buf = buffers.active_mut();
+                                    let mut options = buf.get_options().clone();
+                                    options.spell_language = if i == 0 {
+                                        String::new()
+                                    } else {
+                                        languages[i].clone()
+                                    };
+                                    buf.set_options(options);
+                                }
+                            }
+                            Overlay::LongLines(prompt, lines) => {
+                                if let Some(i) = prompt.selected() {
+                                    let (buf, view) = buffers.active_with_view();
+                                    let line = lines[i];
+                                    let byte = buf.overflow_start(line).unwrap_or(0);
+                                    let cursor = buf.cursor_at_byte(line, byte);
+                                    buf.set_cursors(vec![cursor]);
+                                    view.reveal_range(line..line + 1,
+                                                      RevealMode::Center,
+                                                      layout.rows(),
+                                                      buf.line_count());
+                                }
+                            }
+                            Overlay::FindInFiles(prompt) => {
+                                if !prompt.input().is_empty() {
+                                    let results = Results::new(env::current_dir().unwrap(),
+                                                               prompt.input());
+                                    let mut options = config.options(None);
+                                    options.read_only = true;
+                                    let index = buffers.open_lines(Results::header(prompt.input()),
+                                                                   options);
+                                    find_results = Some((index, results));
+                                }
+                            }
+                            Overlay::Tasks(prompt, names) => {
+                                if let Some(i) = prompt.selected() {
+                                    let command = &config.tasks[&names[i]];
+                                    let mut lines = Task::header(command);
+                                    let started = Task::start(command, env::current_dir().unwrap());
+                                    if let Err(ref e) = started {
+                                        lines.push(format!("Failed to start: {}", e));
+                                    }
+                                    let mut options = config.options(None);
+                                    options.read_only = true;
+                                    let index = buffers.open_lines(lines, options);
+                                    task_output = started.ok().map(|task| (index, task));
+                                }
+                            }
+                            Overlay::SearchEdit(prompt) => {
+                                if !prompt.input().is_empty() {
+                                    match env::current_dir() {
+                                        Ok(dir) => {
+                                            let session = EditSession::search(dir, prompt.input());
+                                            pending_session = Some(session);
+                                        }
+                                        Err(e) => {
+                                            let title = "Could not search the files";
+                                            overlay = Some(error_prompt(title, e));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Event::Input(ref input) if timeline.is_some() => {
+                let apply = match *input {
+                    Input::Press(Button::Keyboard(Key::Up)) => {
+                        timeline.as_mut().unwrap().select_previous();
+                        false
+                    }
+                    Input::Press(Button::Keyboard(Key::Down)) => {
+                        timeline.as_mut().unwrap().select_next();
+                        false
+                    }
+                    Input::Press(Button::Keyboard(Key::Return)) => true,
+                    Input::Press(Button::Keyboard(Key::Escape)) => {
+                        timeline = None;
+                        false
+                    }
+                    Input::Press(Button::Keyboard(Key::H)) if modifiers.ctrl && modifiers.shift => {
+                        timeline = None;
+                        false
+                    }
+                    Input::Press(Button::Mouse(MouseButton::Left)) if
+                        layout::contains(layout.panel(), hover.position) => {
+                        let timeline = timeline.as_mut().unwrap();
+                        match layout.panel_row_at(hover.position[1]) {
+                            // Clicking an entry previews it, and clicking it again goes to it.
+                            Some(row) => {
+                                let entry = timeline.first_shown(layout.panel_rows()) + row;
+                                if entry == timeline.selected() {
+                                    true
+                                } else {
+                                    timeline.select(entry);
+                                    false
+                                }
+                            }
+                            None => false,
+                        }
+                    }
+                    _ => false,
+                };
+
+                if apply {
+                    let revision = timeline.take().unwrap().selected_revision();
+                    let (buf, view) = buffers.active_with_view();
+                    buf.go_to_revision(revision);
+                    if let Some(cursor) = buf.get_cursors().last() {
+                        let line = cursor.head_line();
+                        view.reveal_range(line..line + 1,
+                                          RevealMode::Minimal,
+                                          layout.rows(),
+                                          buf.line_count());
+                    }
+                } else if let Some(ref timeline) = timeline {
+                    if let Some(cursor) = timeline.preview().get_cursors().last() {
+                        let line = cursor.head_line();
+                        preview_view.reveal_range(line..line + 1,
+                                                  RevealMode::Minimal,
+                                                  layout.rows(),
+                                                  timeline.preview().line_count());
+                    }
+                }
+            }
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                layout::contains(layout.sidebar, hover.position) => {
+                let row = layout.tree_row_at(hover.position[1]).unwrap_or(0);
+                match file_tree.activate(file_tree.scroll_offset() + row) {
+                    Ok(Some(path)) => {
+                        if let Err(e) = buffers.open(&path, config.options(Some(&path))) {
+                            overlay = Some(error_prompt("Could not open the file", e));
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => overlay = Some(error_prompt("Could not read the folder", e)),
+                }
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Tab))) => {
-                // TODO optimize with write_str
-                for c in TAB_FILL.chars() {
-                    buf.write_character(c);
+            Event::Input(Input::Move(Motion::MouseScroll(_, y))) if
+                layout::contains(layout.sidebar, hover.position) => {
+                file_tree.scroll(-y as isize * 3);
+            }
+            // Clicking the pane out of focus focuses it, and scrolling over it scrolls it.
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                other_layout.map_or(false, |l| layout::contains(l.editor, hover.position)) => {
+                if let Some(ref mut panes) = panes {
+                    let other = panes.other_index();
+                    panes.focus(other, &mut buffers);
+                    let (focused, other) = pane_layouts(window_layout, Some(&*panes));
+                    layout = focused;
+                    other_layout = other;
                 }
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Backspace))) => {
-                buf.write_character(BACKSPACE);
+            Event::Input(Input::Move(Motion::MouseScroll(_, y))) if
+                other_layout.map_or(false, |l| layout::contains(l.editor, hover.position)) => {
+                if let Some(ref mut panes) = panes {
+                    let line_count = buffers.get(panes.other_buffer()).line_count();
+                    panes.other_view_mut().scroll_by(-y as isize * 3, line_count);
+                }
+            }
+            Event::Input(Input::Move(Motion::MouseScroll(_, y))) if
+                layout::contains(layout.editor, hover.position) => {
+                let (buf, view) = buffers.active_with_view();
+                view.scroll_by(-y as isize * 3, buf.line_count());
+            }
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                layout::contains(layout.scrollbar, hover.position) => {
+                let (buf, view) = buffers.active_with_view();
+                let thumb = layout.scrollbar_thumb(view.first_line(), buf.line_count());
+                let y = hover.position[1];
+                // Clicking the track moves the middle of the thumb there, and drags it from then.
+                let grab = if y >= thumb[1] && y < thumb[1] + thumb[3] {
+                    y - thumb[1]
+                } else {
+                    thumb[3] / 2.0
+                };
+                view.scroll_to(layout.scrollbar_line_at(y - grab, buf.line_count()),
+                               buf.line_count());
+                scrollbar_grab = Some(grab);
+            }
+            // Clicking the minimap centers the line there, and dragging its viewport scrolls.
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                layout::contains(layout.minimap, hover.position) => {
+                let (buf, view) = buffers.active_with_view();
+                let y = hover.position[1];
+                let line_count = buf.line_count();
+                let viewport = layout.minimap_viewport(view.first_line(), line_count);
+                if y < viewport[1] || y >= viewport[1] + viewport[3] {
+                    let line = layout.minimap_line_at(y, view.first_line(), line_count);
+                    view.reveal_range(line..line + 1,
+                                      RevealMode::Center,
+                                      layout.rows(),
+                                      line_count);
+                }
+                let top = layout.minimap_viewport(view.first_line(), line_count)[1];
+                minimap_grab = Some(y - top);
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Delete))) => {
-                buf.write_character(DEL);
+            Event::Input(Input::Release(Button::Mouse(MouseButton::Left))) => {
+                scrollbar_grab = None;
+                minimap_grab = None;
+                column_drag = None;
+                if let Some(drag) = text_drag.take() {
+                    // Ctrl copies the text instead of moving it.
+                    let buf = buffers.active_mut();
+                    let dropped = match drag.target {
+                        Some(target) if !buf.get_options().read_only => {
+                            buf.drop_selection(drag.selection, target, modifiers.ctrl)
+                        }
+                        _ => false,
+                    };
+                    if !dropped {
+                        buf.set_cursors(vec![drag.pressed]);
+                    }
+                }
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Left))) => {
-                buf.move_cursors(Move::Left);
+            Event::Input(Input::Move(Motion::MouseCursor(_, y))) if scrollbar_grab.is_some() => {
+                let (buf, view) = buffers.active_with_view();
+                let line = layout.scrollbar_line_at(y - scrollbar_grab.unwrap(), buf.line_count());
+                view.scroll_to(line, buf.line_count());
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Right))) => {
-                buf.move_cursors(Move::Right);
+            Event::Input(Input::Move(Motion::MouseCursor(_, y))) if minimap_grab.is_some() => {
+                let (buf, view) = buffers.active_with_view();
+                let line_count = buf.line_count();
+                let line = layout.minimap_scroll_line_at(y - minimap_grab.unwrap(), line_count);
+                view.scroll_to(line, line_count);
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Up))) => {
-                buf.move_cursors(Move::Up);
+            Event::Input(Input::Move(Motion::MouseCursor(x, y))) if text_drag.is_some() => {
+                let (buf, view) = buffers.active_with_view();
+                let cursor =
+                    cursor_under_mouse(buf, view, [x, y], &layout, &mut metrics, &mut glyphs);
+                text_drag.as_mut().unwrap().target = Some((cursor.start_line, cursor.start_byte));
+                damaged = true;
             }
-            Event::Input(Input::Press(Button::Keyboard(Key::Down))) => {
-                buf.move_cursors(Move::Down);
+            Event::Input(Input::Move(Motion::MouseCursor(x, y))) if column_drag.is_some() => {
+                let (buf, view) = buffers.active_with_view();
+                let anchor = column_drag.unwrap();
+                let head =
+                    column_under_mouse(buf, view, [x, y], &layout, &mut metrics, &mut glyphs);
+                // Alt clicking without dragging only adds a cursor.
+                if head != anchor {
+                    buf.set_cursors(Vec::new());
+                    buf.add_column_cursors(ColumnRect {
+                        anchor: anchor,
+                        head: head,
+                    });
+                    damaged = true;
+                }
             }
-            Event::Input(Input::Move(Motion::MouseCursor(x, _y))) => {
-                if x > MENU_WIDTH {
+            Event::Input(Input::Move(Motion::MouseCursor(x, y))) => {
+                let link = if layout::contains(layout.text, [x, y]) &&
+                              buffers.active().is_loaded() {
+                    let active = buffers.active_index();
+                    let (buf, view) = buffers.active_with_view();
+                    let cursor =
+                        cursor_under_mouse(buf, view, [x, y], &layout, &mut metrics, &mut glyphs);
+                    link_at(buf, cursor.start_line, cursor.start_byte).map(|link| {
+                        HoveredLink {
+                            buffer: active,
+                            version: buf.version(),
+                            line: cursor.start_line,
+                            link: link,
+                        }
+                    })
+                } else {
+                    None
+                };
+                let shown = |hovered: &Option<HoveredLink>| {
+                    hovered.as_ref().map(|h| (h.buffer, h.line, h.link.start))
+                };
+                if shown(&link) != shown(&hovered_link) {
+                    damaged = true;
+                }
+                hovered_link = link;
+                if hovered_link.is_some() {
+                    window.window.window.set_cursor(MouseCursor::Hand);
+                } else if layout::contains(layout.text, [x, y]) ||
+                          layout::contains(layout.gutter, [x, y]) {
                     window.window.window.set_cursor(MouseCursor::Text);
                 } else {
                     window.window.window.set_cursor(MouseCursor::Default);
                 }
             }
-            Event::Input(Input::Move(Motion::MouseScroll(_x, _y))) => {}
-            Event::Input(Input::Focus(false)) => buf.save(|_, _| {}).unwrap(),
+            Event::Input(Input::Focus(false)) => {
+                if let Err(e) = buffers.save_all() {
+                    overlay = Some(error_prompt("Could not save", e));
+                    damaged = true;
+                }
+            }
+            // Ctrl+Click on a link opens the web page in the browser, or the file at the line.
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                modifiers.ctrl && layout::contains(layout.text, hover.position) &&
+                hovered_link.as_ref().map_or(false, |h| h.is_current(&buffers)) => {
+                match hovered_link.as_ref().unwrap().link.target.clone() {
+                    Target::Url(url) => {
+                        if let Err(e) = links::open_url(&url) {
+                            overlay = Some(error_prompt("Could not open the link", e));
+                            damaged = true;
+                        }
+                    }
+                    Target::File(path, line, column) => {
+                        match buffers.open(&path, config.options(Some(&path))) {
+                            Ok(index) => pending_result = Some((index, line, column)),
+                            Err(e) => {
+                                overlay = Some(error_prompt("Could not open the file", e));
+                                damaged = true;
+                            }
+                        }
+                    }
+                }
+            }
+            // Clicking a result of a search in files or a location in the output of a task goes
+            // to it.
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                layout::contains(layout.text, hover.position) &&
+                (find_results.as_ref().map_or(false, |&(i, _)| i == buffers.active_index()) ||
+                 task_output.as_ref().map_or(false, |&(i, _)| i == buffers.active_index())) => {
+                let line = {
+                    let (buf, view) = buffers.active_with_view();
+                    let cursor = cursor_under_mouse(buf,
+                                                    view,
+                                                    hover.position,
+                                                    &layout,
+                                                    &mut metrics,
+                                                    &mut glyphs);
+                    click_buffer(buf, cursor, modifiers);
+                    cursor.start_line
+                };
+                let active = buffers.active_index();
+                let target = match find_results {
+                    Some((i, ref results)) if i == active => {
+                        results.target(line).map(|(path, line)| (path.to_path_buf(), line, 0))
+                    }
+                    _ => None,
+                };
+                let target = match task_output {
+                    Some((i, ref task)) if i == active => {
+                        task.target(line)
+                            .map(|(path, line, column)| (path.to_path_buf(), line, column))
+                    }
+                    _ => target,
+                };
+                // Tasks can print any path, folders included.
+                if let Some((path, line, column)) = target {
+                    match buffers.open(&path, config.options(Some(&path))) {
+                        Ok(index) => pending_result = Some((index, line, column)),
+                        Err(e) => {
+                            overlay = Some(error_prompt("Could not open the file", e));
+                            damaged = true;
+                        }
+                    }
+                }
+            }
+            // Right clicking a misspelled word lists what it could be.
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Right))) if
+                layout::contains(layout.text, hover.position) &&
+                buffers.active().is_loaded() => {
+                {
+                    let (buf, view) = buffers.active_with_view();
+                    let cursor = cursor_under_mouse(buf,
+                                                    view,
+                                                    hover.position,
+                                                    &layout,
+                                                    &mut metrics,
+                                                    &mut glyphs);
+                    buf.set_cursors(vec![cursor]);
+                }
+                queued.push_back(Action::ShowSpellingSuggestions);
+            }
+            Event::Input(Input::Press(Button::Mouse(MouseButton::Left))) if
+                layout::contains(layout.editor, hover.position) &&
+                buffers.active().is_loaded() => {
+                let (buf, view) = buffers.active_with_view();
+                let cursor = cursor_under_mouse(buf,
+                                                view,
+                                                hover.position,
+                                                &layout,
+                                                &mut metrics,
+                                                &mut glyphs);
+                let now = time::precise_time_ns();
+                let position = (cursor.start_line, cursor.start_byte);
+                let double = match last_click {
+                    Some((at, clicked)) => clicked == position && now - at < DOUBLE_CLICK_TIME,
+                    None => false,
+                };
+                last_click = if double { None } else { Some((now, position)) };
+                // Pressing on a selection starts dragging it instead of placing the cursor.
+                match buf.selection_at(position) {
+                    _ if double => {
+                        let word = buf.word_at(cursor);
+                        click_buffer(buf, word, modifiers);
+                    }
+                    Some(i) if !modifiers.alt => {
+                        text_drag = Some(TextDrag {
+                            selection: i,
+                            pressed: cursor,
+                            target: None,
+                        })
+                    }
+                    _ => {
+                        click_buffer(buf, cursor, modifiers);
+                        // Dragging with Alt selects a block.
+                        if modifiers.alt {
+                            column_drag = Some(column_under_mouse(buf,
+                                                                  view,
+                                                                  hover.position,
+                                                                  &layout,
+                                                                  &mut metrics,
+                                                                  &mut glyphs));
+                        }
+                    }
+                }
+            }
             _ => {}
         }
+        profiler.add(Stage::Editing, took);
+        // Key presses firing nothing are only logged along with Ctrl or Alt, as they would
+        // otherwise be followed by the text they type.
+        if let (Some(input), Some(trace)) = (traced, trace.as_ref()) {
+            if action.is_some() || modifiers.ctrl || modifiers.alt {
+                trace.log(&mut buffers, layout.rows(), &input, action.as_ref(), took);
+            }
+        }
         let _ = e.update(|_| {});
     }
+
+    let size = window.size();
+    let mut session = Session {
+        width: size.width,
+        height: size.height,
+        active: 0,
+        buffers: Vec::new(),
+    };
+    let active = buffers.active_index();
+    for i in 0..buffers.len() {
+        let (buf, view) = buffers.get_with_view(i);
+        if let Some(state) = BufferState::capture(buf, view) {
+            if i == active {
+                session.active = session.buffers.len();
+            }
+            session.buffers.push(state);
+        }
+    }
+    if let Some(file) = session_file {
+        let _ = session.save(file);
+    }
+    if let Some(file) = recent_file {
+        let _ = recent.save(file);
+    }
+    recovery.clear();
+}
+
+/// Runs an action on the active buffer, scrolling to the last cursor afterwards.
+fn edit_buffer(buf: &mut TextBuffer,
+               view: &mut View,
+               action: &Action,
+               layout: &Layout,
+               metrics: &mut TextMetrics,
+               glyphs: &mut Fonts<Glyphs>) {
+    view.set_wrap_width(layout.wrap_width());
+    if buf.get_options().read_only && action.is_edit() {
+        return;
+    }
+    match *action {
+        Action::Move(movement) |
+        Action::Extend(movement) if buf.get_options().wrap &&
+                                    (movement == Move::Up || movement == Move::Down) => {
+            let extend = *action == Action::Extend(movement);
+            let up = movement == Move::Up;
+            let targets = wrapped_row_targets(buf, view, up, extend, metrics, glyphs);
+            buf.move_cursors_to(&targets, extend);
+        }
+        _ => {
+            if let Some(op) = action.edit_op() {
+                buf.apply(&op);
+            }
+        }
+    }
+
+    if let Some(cursor) = buf.get_cursors().last() {
+        view.reveal_wrapped(buf, cursor.head_line(), layout.rows(), metrics, glyphs);
+    }
+}
+
+/// Where each cursor goes when moved to the row above or below it in a buffer whose lines are
+/// wrapped, as a line and character, staying as far from the left edge as it was.
+fn wrapped_row_targets(buf: &TextBuffer,
+                       view: &mut View,
+                       up: bool,
+                       extend: bool,
+                       metrics: &mut TextMetrics,
+                       glyphs: &mut Fonts<Glyphs>)
+                       -> Vec<(usize, usize)> {
+    let mut targets = Vec::with_capacity(buf.get_cursors().len());
+    for cursor in buf.get_cursors() {
+        let (line, byte) = if extend {
+            cursor.head()
+        } else if up {
+            (cursor.start_line, cursor.start_byte)
+        } else {
+            (cursor.end_line, cursor.end_byte)
+        };
+        let (row, rows, x) = {
+            let layout = &view.line(buf, line, metrics, glyphs).layout;
+            (layout.row_of(byte), layout.rows(), layout.row_offset(byte))
+        };
+        let (line, row) = if up && row != 0 {
+            (line, row - 1)
+        } else if up && line != 0 {
+            (line - 1, view.line(buf, line - 1, metrics, glyphs).layout.rows() - 1)
+        } else if up {
+            targets.push((0, 0));
+            continue;
+        } else if row + 1 < rows {
+            (line, row + 1)
+        } else if line + 1 < buf.line_count() {
+            (line + 1, 0)
+        } else {
+            targets.push((line, usize::MAX));
+            continue;
+        };
+        targets.push((line, view.line(buf, line, metrics, glyphs).layout.grapheme_at(row, x)));
+    }
+    targets
+}
+
+/// The cursor at the point of the editor under the mouse.
+fn cursor_under_mouse(buf: &TextBuffer,
+                      view: &mut View,
+                      mouse_position: [f64; 2],
+                      layout: &Layout,
+                      metrics: &mut TextMetrics,
+                      glyphs: &mut Fonts<Glyphs>)
+                      -> Cursor {
+    view.set_wrap_width(layout.wrap_width());
+    let row = layout.row_at(mouse_position[1]).unwrap_or(0);
+    let x = mouse_position[0] - layout.text[0];
+    match view.line_at_row(buf, row, metrics, glyphs) {
+        Some((line, row)) => {
+            let character = view.line(buf, line, metrics, glyphs).layout.grapheme_at(row, x);
+            buf.cursor_at(line, character)
+        }
+        None => buf.cursor_at(usize::MAX, usize::MAX),
+    }
+}
+
+/// The line and display column under the mouse, counting the columns past the end of the line
+/// as if it went on with spaces.
+fn column_under_mouse(buf: &TextBuffer,
+                      view: &mut View,
+                      mouse_position: [f64; 2],
+                      layout: &Layout,
+                      metrics: &mut TextMetrics,
+                      glyphs: &mut Fonts<Glyphs>)
+                      -> (usize, usize) {
+    let cursor = cursor_under_mouse(buf, view, mouse_position, layout, metrics, glyphs);
+    let line = cursor.start_line;
+    let column = buf.display_column(line, cursor.start_byte);
+    if cursor.start_byte != buf.cursor_at(line, usize::MAX).start_byte {
+        return (line, column);
+    }
+    let x = mouse_position[0] - layout.text[0];
+    let past_end = (x / metrics.advance(glyphs, ' ')).round().max(0.0) as usize;
+    (line, cmp::max(column, past_end))
+}
+
+/// The layouts of the focused pane and of the other one, if the editor is split, out of the
+/// layout of the whole window.
+fn pane_layouts(window: Layout, panes: Option<&Panes>) -> (Layout, Option<Layout>) {
+    match panes {
+        Some(panes) => {
+            let (focused, other) = panes.layouts(&window);
+            (focused, Some(other))
+        }
+        None => (window, None),
+    }
+}
+
+/// Opens the welcome page as the active buffer, read only, listing the recent files. Returns its
+/// index along with the page.
+fn open_welcome(buffers: &mut Buffers, config: &Config, recent: &RecentFiles) -> (usize, Welcome) {
+    let welcome = Welcome::new(recent.files());
+    let mut options = config.options(None);
+    options.read_only = true;
+    (buffers.open_lines(welcome.lines(), options), welcome)
+}
+
+/// The link at the line and byte of the buffer, if any, with paths looked up from the folder of
+/// its file and then from the current one.
+fn link_at(buf: &TextBuffer, line: usize, byte: usize) -> Option<Link> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = buf.get_path().and_then(Path::parent) {
+        dirs.push(dir.to_path_buf());
+    }
+    if let Ok(dir) = env::current_dir() {
+        dirs.push(dir);
+    }
+    buf.lines().nth(line).and_then(|text| links::at(text, byte, &dirs))
+}
+
+/// Places the cursor where the editor was clicked, or adds one there with Alt.
+fn click_buffer(buf: &mut TextBuffer, cursor: Cursor, modifiers: Modifiers) {
+    if modifiers.alt {
+        buf.add_cursor(cursor);
+    } else {
+        buf.set_cursors(vec![cursor]);
+    }
+}
+
+/// A selection being dragged with the mouse to move or copy it.
+struct TextDrag {
+    /// The index of the cursor holding the selection.
+    selection: usize,
+    /// Where the mouse was pressed, for when it is released without dragging.
+    pressed: Cursor,
+    /// The line and byte the text would be dropped at.
+    target: Option<(usize, usize)>,
+}
+
+/// A prompt shown over the editor, and what accepting it does.
+enum Overlay {
+    /// Jumps to the selected tag.
+    Symbols(Prompt, Vec<Tag>),
+    /// Moves the cursor to the typed line and column.
+    GoToLine(Prompt),
+    /// Opens the file at the typed path.
+    OpenFile(Prompt),
+    /// Opens the selected one of the files of the project, which are at the paths.
+    QuickOpen(Prompt, Vec<PathBuf>),
+    /// Opens the selected one of the recent files, which are at the paths.
+    OpenRecent(Prompt, Vec<PathBuf>),
+    /// Makes the typed folder the current one, shown in the file tree.
+    OpenFolder(Prompt),
+    /// Saves the active buffer to the typed path.
+    SaveAs(Prompt),
+    /// Moves the file of the active buffer to the typed path.
+    RenameFile(Prompt),
+    /// Writes the active buffer as a web page to the typed path.
+    ExportHtml(Prompt),
+    /// Opens a buffer with every line in the project containing the input, to edit them all.
+    SearchEdit(Prompt),
+    /// Lists every line in the project containing the input, to go to them.
+    FindInFiles(Prompt),
+    /// Runs the selected one of the tasks with the names.
+    Tasks(Prompt, Vec<String>),
+    /// Goes to where the selected line of the active buffer gets too long.
+    LongLines(Prompt, Vec<usize>),
+    /// Runs the selected action.
+    Commands(Prompt, Vec<Action>),
+    /// Fixes the selected file with encoding or line ending problems, or all of them from the
+    /// first entry.
+    Audit(Prompt, Vec<audit::Finding>),
+    /// Keeps the recorded edits in the selected one of the slots.
+    SaveMacro(Prompt, Vec<usize>, Vec<Action>),
+    /// Runs the last macro recorded the typed number of times at each cursor, once if empty.
+    ReplayMacro(Prompt),
+    /// Asks what to do with the macro in the selected one of the slots.
+    Macros(Prompt, Vec<usize>),
+    /// Renames or deletes the macro in the slot.
+    EditMacro(Prompt, usize),
+    /// Gives the macro in the slot the typed name.
+    RenameMacro(Prompt, usize),
+    /// Reloads the buffer at the index, whose file another program changed, or keeps its text.
+    ChangedOnDisk(Prompt, usize),
+    /// Shows what the language server knows about a symbol, and does nothing when accepted.
+    Hover(Prompt),
+    /// Replaces the misspelled word with the selected suggestion, or adds it to the user
+    /// dictionary from the last entry.
+    Spelling(Prompt, Misspelling),
+    /// Spell checks the active buffer in the selected language, or turns checking off from the
+    /// first entry.
+    SpellLanguage(Prompt, Vec<String>),
+    /// Saves every buffer and closes the window, closes it losing the changes, or keeps it open.
+    Close(Prompt),
+    /// Opens the unsaved text of the selected one of the files left in swap files, or deletes
+    /// them all from the last entry.
+    Recover(Prompt, Vec<Swap>),
+    /// Saves the buffer at the index and closes it, closes it losing the changes, or keeps it
+    /// open.
+    CloseBuffer(Prompt, usize),
+    /// Shows how the active buffer differs from the selected one of the buffers at the indices.
+    CompareWith(Prompt, Vec<usize>),
+    /// Tells what went wrong, and does nothing when accepted.
+    Error(Prompt),
+}
+
+impl Overlay {
+    fn prompt(&self) -> &Prompt {
+        match *self {
+            Overlay::Symbols(ref prompt, _) |
+            Overlay::GoToLine(ref prompt) |
+            Overlay::OpenFile(ref prompt) |
+            Overlay::QuickOpen(ref prompt, _) |
+            Overlay::OpenRecent(ref prompt, _) |
+            Overlay::OpenFolder(ref prompt) |
+            Overlay::SaveAs(ref prompt) |
+            Overlay::RenameFile(ref prompt) |
+            Overlay::ExportHtml(ref prompt) |
+            Overlay::SearchEdit(ref prompt) |
+            Overlay::FindInFiles(ref prompt) |
+            Overlay::Tasks(ref prompt, _) |
+            Overlay::LongLines(ref prompt, _) |
+            Overlay::Commands(ref prompt, _) |
+            Overlay::Audit(ref prompt, _) |
+            Overlay::SaveMacro(ref prompt, _, _) |
+            Overlay::ReplayMacro(ref prompt) |
+            Overlay::Macros(ref prompt, _) |
+            Overlay::EditMacro(ref prompt, _) |
+            Overlay::RenameMacro(ref prompt, _) |
+            Overlay::ChangedOnDisk(ref prompt, _) |
+            Overlay::Hover(ref prompt) |
+            Overlay::Spelling(ref prompt, _) |
+            Overlay::SpellLanguage(ref prompt, _) |
+            Overlay::Close(ref prompt) |
+            Overlay::CloseBuffer(ref prompt, _) |
+            Overlay::Recover(ref prompt, _) |
+            Overlay::CompareWith(ref prompt, _) |
+            Overlay::Error(ref prompt) => prompt,
+        }
+    }
+
+    fn prompt_mut(&mut self) -> &mut Prompt {
+        match *self {
+            Overlay::Symbols(ref mut prompt, _) |
+            Overlay::GoToLine(ref mut prompt) |
+            Overlay::OpenFile(ref mut prompt) |
+            Overlay::QuickOpen(ref mut prompt, _) |
+            Overlay::OpenRecent(ref mut prompt, _) |
+            Overlay::OpenFolder(ref mut prompt) |
+            Overlay::SaveAs(ref mut prompt) |
+            Overlay::RenameFile(ref mut prompt) |
+            Overlay::ExportHtml(ref mut prompt) |
+            Overlay::SearchEdit(ref mut prompt) |
+            Overlay::FindInFiles(ref mut prompt) |
+            Overlay::Tasks(ref mut prompt, _) |
+            Overlay::LongLines(ref mut prompt, _) |
+            Overlay::Commands(ref mut prompt, _) |
+            Overlay::Audit(ref mut prompt, _) |
+            Overlay::SaveMacro(ref mut prompt, _, _) |
+            Overlay::ReplayMacro(ref mut prompt) |
+            Overlay::Macros(ref mut prompt, _) |
+            Overlay::EditMacro(ref mut prompt, _) |
+            Overlay::RenameMacro(ref mut prompt, _) |
+            Overlay::ChangedOnDisk(ref mut prompt, _) |
+            Overlay::Hover(ref mut prompt) |
+            Overlay::Spelling(ref mut prompt, _) |
+            Overlay::SpellLanguage(ref mut prompt, _) |
+            Overlay::Close(ref mut prompt) |
+            Overlay::CloseBuffer(ref mut prompt, _) |
+            Overlay::Recover(ref mut prompt, _) |
+            Overlay::CompareWith(ref mut prompt, _) |
+            Overlay::Error(ref mut prompt) => prompt,
+        }
+    }
+}
+
+/// Reads `line` or `line:column`, both counted from 1, as a line and column counted from 0.
+fn parse_line_column(input: &str) -> Option<(usize, usize)> {
+    let mut parts = input.trim().splitn(2, ':');
+    let line = match parts.next().and_then(|l| l.trim().parse::<usize>().ok()) {
+        Some(line) => line,
+        None => return None,
+    };
+    let column = match parts.next() {
+        Some(column) => {
+            match column.trim().parse::<usize>() {
+                Ok(column) => column,
+                Err(_) => return None,
+            }
+        }
+        None => 1,
+    };
+    Some((line.saturating_sub(1), column.saturating_sub(1)))
+}
+
+/// The macro slots as they are listed, in the order of the digit keys, along with the slot of
+/// each entry.
+fn macro_slots(macros: &Macros) -> (Vec<String>, Vec<usize>) {
+    macros.slots()
+        .into_iter()
+        .map(|(slot, saved)| {
+            let item = match saved {
+                Some(saved) => {
+                    format!("Ctrl+Shift+{}  {}  ({} edits)", slot, saved.name, saved.actions.len())
+                }
+                None => format!("Ctrl+Shift+{}  (empty)", slot),
+            };
+            (item, slot)
+        })
+        .unzip()
+}
+
+/// Asks whether to reload a buffer whose file another program changed, warning that its changes
+/// are lost if it has any.
+fn changed_on_disk_prompt(buf: &TextBuffer, index: usize) -> Overlay {
+    let name = buf.get_path().and_then(Path::file_name).map_or(String::new(), |n| {
+        n.to_string_lossy().into_owned()
+    });
+    let items = if buf.is_saved() {
+        vec![String::from("Reload"), String::from("Keep the text here")]
+    } else {
+        vec![String::from("Reload, losing the changes here"),
+             String::from("Keep the changes here and overwrite the file on save")]
+    };
+    Overlay::ChangedOnDisk(Prompt::new(format!("{} changed on disk", name), items), index)
+}
+
+/// Writes the macros to their file, returning a prompt telling why if that fails.
+fn save_macros(macros: &Macros) -> Option<Overlay> {
+    // Without a data directory there is nowhere to keep them.
+    macros::file()
+        .and_then(|file| macros.save(file).err())
+        .map(|e| error_prompt("Could not save the macros", e))
+}
+
+/// Lists the files with problems, along with an item to fix them all if there are any.
+fn audit_prompt(findings: Vec<audit::Finding>) -> Overlay {
+    let mut items = Vec::new();
+    if !findings.is_empty() {
+        items.push(format!("Fix all {} files", findings.len()));
+    }
+    for finding in &findings {
+        let problems: Vec<_> = finding.problems.iter().map(|p| p.description()).collect();
+        items.push(format!("{}: {}", finding.name, problems.join(", ")));
+    }
+    Overlay::Audit(Prompt::new("Fix file", items), findings)
+}
+
+/// A prompt telling that what the title says failed, and why.
+fn error_prompt<E: fmt::Display>(title: &str, error: E) -> Overlay {
+    Overlay::Error(Prompt::new(title, vec![error.to_string()]))
+}
+
+/// Opens a read-only buffer showing a diff as the active buffer. Returns its index along with
+/// the diff.
+fn open_diff(buffers: &mut Buffers,
+             config: &Config,
+             (diff, lines, backgrounds): diffview::Compared)
+             -> (usize, Diff) {
+    let mut options = config.options(None);
+    options.read_only = true;
+    let index = buffers.open_lines(lines, options);
+    buffers.get_mut(index).annotations_mut().set(diffview::OWNER, backgrounds);
+    (index, diff)
+}
+
+/// The name of the file of the buffer, or `untitled` if it has none.
+fn file_name(buf: &TextBuffer) -> String {
+    buf.get_path()
+        .and_then(Path::file_name)
+        .map_or(String::from("untitled"), |n| n.to_string_lossy().into_owned())
+}
+
+/// The title of the window, naming the file of the active buffer, with a `*` in front while it
+/// has changes that are not saved.
+fn window_title(buf: &TextBuffer) -> String {
+    format!("{}{} — Editor",
+            if buf.is_saved() { "" } else { "*" },
+            file_name(buf))
+}
+
+/// Asks whether to save the buffers with changes that are not saved before closing the window.
+fn close_prompt(buffers: &Buffers) -> Overlay {
+    let names: Vec<String> =
+        buffers.unsaved().into_iter().map(|i| file_name(buffers.get(i))).collect();
+    let items = vec![String::from("Save all and close"),
+                     String::from("Close without saving"),
+                     String::from("Cancel")];
+    Overlay::Close(Prompt::new(format!("Unsaved changes in {}", names.join(", ")), items))
+}
+
+/// Asks whether to save the buffer at the index, which has changes that are not saved, before
+/// closing it.
+fn close_buffer_prompt(buf: &TextBuffer, index: usize) -> Overlay {
+    let items = vec![String::from("Save and close"),
+                     String::from("Close without saving"),
+                     String::from("Cancel")];
+    Overlay::CloseBuffer(Prompt::new(format!("Unsaved changes in {}", file_name(buf)), items),
+                         index)
+}
+
+/// Asks which of the files whose unsaved text was left in swap files to recover, if any.
+fn recover_prompt(swaps: Vec<Swap>) -> Overlay {
+    let mut items: Vec<String> = swaps.iter()
+        .map(|s| match s.path {
+            Some(ref path) => format!("Recover {}", path.display()),
+            None => String::from("Recover untitled"),
+        })
+        .collect();
+    items.push(String::from("Delete the recovery files"));
+    Overlay::Recover(Prompt::new("Unsaved changes were found", items), swaps)
+}
+
+/// Where the buffer at index `i` is once the one at `closed` is closed, if it is still open.
+fn moved(i: usize, closed: usize) -> Option<usize> {
+    if i == closed {
+        None
+    } else if i > closed {
+        Some(i - 1)
+    } else {
+        Some(i)
+    }
+}
+
+enum PromptEvent {
+    None,
+    Cancel,
+    Accept,
+}
+
+fn prompt_input(prompt: &mut Prompt, input: &Input) -> PromptEvent {
+    match *input {
+        Input::Text(ref s) => {
+            for c in s.chars().filter(|c| !c.is_control()) {
+                prompt.insert(c);
+            }
+        }
+        Input::Press(Button::Keyboard(Key::Backspace)) => prompt.backspace(),
+        Input::Press(Button::Keyboard(Key::Up)) => prompt.select_previous(),
+        Input::Press(Button::Keyboard(Key::Down)) => prompt.select_next(),
+        Input::Press(Button::Keyboard(Key::Escape)) => return PromptEvent::Cancel,
+        Input::Press(Button::Keyboard(Key::Return)) => return PromptEvent::Accept,
+        _ => {}
+    }
+    PromptEvent::None
+}
+
+fn draw_buffer(plan: &RenderPlan,
+               theme: &Theme,
+               glyphs: &mut Fonts<Glyphs>,
+               c: &Context,
+               g: &mut G2d) {
+    let mark_color = |mark| match mark {
+        Mark::Added => theme.added,
+        Mark::Modified => theme.modified,
+        Mark::Removed => theme.removed,
+        Mark::Bookmark => theme.bookmark,
+    };
+    let color = |paint| match paint {
+        Paint::CurrentLine => theme.panel_background,
+        Paint::Selection => theme.selection,
+        Paint::Overflow => theme.overflow,
+        Paint::Highlight => theme.highlight,
+        Paint::Underline => theme.underline,
+        Paint::Whitespace => theme.gutter,
+        Paint::TrailingWhitespace => theme.overflow,
+        Paint::Bracket => theme.cursor,
+        Paint::Link => theme.text,
+        Paint::Cursor => theme.cursor,
+        Paint::Minimap => theme.gutter,
+        Paint::MinimapMark => theme.underline,
+        Paint::MinimapViewport => theme.highlight,
+        Paint::Mark(mark) => mark_color(mark),
+        // The text stays readable over the colors of the marks.
+        Paint::Background(mark) => {
+            let color = mark_color(mark);
+            [color[0], color[1], color[2], 0.2]
+        }
+    };
+    for fill in &plan.fills {
+        rectangle(color(fill.paint), fill.rect, c.transform, g);
+    }
+    for stroke in &plan.strokes {
+        line(color(stroke.paint),
+             stroke.width,
+             [stroke.from[0], stroke.from[1], stroke.to[0], stroke.to[1]],
+             c.transform,
+             g);
+    }
+    for run in &plan.gutter {
+        Text::new_color(theme.gutter, run.size).draw(&run.text,
+                                                     glyphs,
+                                                     &c.draw_state,
+                                                     c.transform
+                                                         .trans(run.position[0], run.position[1]),
+                                                     g);
+    }
+    for run in &plan.text {
+        Text::new_color(theme.text, run.size).draw(&run.text,
+                                                   glyphs,
+                                                   &c.draw_state,
+                                                   c.transform
+                                                       .trans(run.position[0], run.position[1]),
+                                                   g);
+    }
+}
+
+fn draw_scrollbar(first_line: usize,
+                  line_count: usize,
+                  layout: &Layout,
+                  theme: &Theme,
+                  c: &Context,
+                  g: &mut G2d) {
+    rectangle(theme.panel_background, layout.scrollbar, c.transform, g);
+    rectangle(theme.highlight,
+              layout.scrollbar_thumb(first_line, line_count),
+              c.transform,
+              g);
+}
+
+/// The tooltip for the given mouse position: what is known about the line under it when over the
+/// gutter, or the line and column under it when over the text.
+fn hover_info(buf: &TextBuffer,
+              view: &mut View,
+              layout: &Layout,
+              position: [f64; 2],
+              metrics: &mut TextMetrics,
+              glyphs: &mut Fonts<Glyphs>)
+              -> Option<Vec<String>> {
+    if !(layout::contains(layout.text, position) || layout::contains(layout.gutter, position)) ||
+       !buf.is_loaded() {
+        return None;
+    }
+    let (line, row) = match layout.row_at(position[1])
+        .and_then(|row| view.line_at_row(buf, row, metrics, glyphs)) {
+        Some(shown) => shown,
+        None => return None,
+    };
+
+    let end = buf.cursor_at(line, usize::MAX);
+    if layout::contains(layout.gutter, position) {
+        let mut info = vec![format!("Line {} of {}", line + 1, buf.line_count()),
+                            format!("{} characters, {} columns",
+                                    end.start_character,
+                                    buf.display_column(line, end.start_byte))];
+        let cursors = buf.get_cursors()
+            .iter()
+            .filter(|c| c.start_line <= line && line <= c.end_line)
+            .count();
+        if cursors != 0 {
+            info.push(format!("{} cursor{}", cursors, if cursors == 1 { "" } else { "s" }));
+        }
+        Some(info)
+    } else {
+        let shown = &view.line(buf, line, metrics, glyphs).layout;
+        let x = position[0] - layout.text[0];
+        if x >= shown.row_width(row) {
+            return None;
+        }
+        let cursor = buf.cursor_at(line, shown.grapheme_at(row, x));
+        let mut info = vec![format!("Ln {}, Col {}",
+                                    line + 1,
+                                    buf.display_column(line, cursor.start_byte) + 1)];
+        // Along with the messages of the annotations under the mouse, like diagnostics.
+        let at = (line, cursor.start_byte);
+        for annotation in buf.annotations().on_lines(line..line + 1) {
+            if annotation.start <= at && at <= annotation.end && !annotation.data.is_empty() {
+                info.extend(annotation.data.lines().map(String::from));
+            }
+        }
+        Some(info)
+    }
+}
+
+fn draw_tooltip(lines: &[String],
+                position: [f64; 2],
+                layout: &Layout,
+                theme: &Theme,
+                glyphs: &mut Fonts<Glyphs>,
+                c: &Context,
+                g: &mut G2d) {
+    let size = layout.small_text_size();
+    let char_width = glyphs.character(size, ' ').width();
+    let padding = layout.padding();
+    let row_height = layout.em() * 0.7;
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f64 * char_width +
+                padding * 2.0;
+    let height = row_height * lines.len() as f64 + padding;
+    let rect = layout.tooltip(position, width, height);
+    let transform = c.transform.trans(rect[0], rect[1]);
+
+    rectangle(theme.panel_background, [0.0, 0.0, width, height], transform, g);
+    Rectangle::new_border(theme.highlight, 1.0)
+        .draw([0.0, 0.0, width, height], &c.draw_state, transform, g);
+    for (i, line) in lines.iter().enumerate() {
+        Text::new_color(theme.text, size).draw(line,
+                                             glyphs,
+                                             &c.draw_state,
+                                             transform.trans(padding,
+                                                             row_height * (i + 1) as f64),
+                                             g);
+    }
+}
+
+/// Draws the frame rate, frame times and buffer size in the top right corner of the editor.
+fn draw_profiler(lines: &[String],
+                 layout: &Layout,
+                 theme: &Theme,
+                 glyphs: &mut Fonts<Glyphs>,
+                 c: &Context,
+                 g: &mut G2d) {
+    let size = layout.small_text_size();
+    let char_width = glyphs.character(size, ' ').width();
+    let padding = layout.padding();
+    let row_height = layout.em() * 0.7;
+    let width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f64 * char_width +
+                padding * 2.0;
+    let height = row_height * lines.len() as f64 + padding;
+    let editor = layout.editor;
+    let transform = c.transform.trans(editor[0] + editor[2] - width - padding, editor[1] + padding);
+
+    rectangle(theme.panel_background, [0.0, 0.0, width, height], transform, g);
+    Rectangle::new_border(theme.highlight, 1.0)
+        .draw([0.0, 0.0, width, height], &c.draw_state, transform, g);
+    for (i, line) in lines.iter().enumerate() {
+        Text::new_color(theme.text, size).draw(line,
+                                             glyphs,
+                                             &c.draw_state,
+                                             transform.trans(padding,
+                                                             row_height * (i + 1) as f64),
+                                             g);
+    }
+}
+
+fn draw_timeline(timeline: &Timeline,
+                 layout: &Layout,
+                 theme: &Theme,
+                 glyphs: &mut Fonts<Glyphs>,
+                 c: &Context,
+                 g: &mut G2d) {
+    let panel = layout.panel();
+    let size = layout.small_text_size();
+    let row_height = layout.list_row_height();
+    rectangle(theme.panel_background, panel, c.transform, g);
+    Rectangle::new_border(theme.highlight, 1.0).draw(panel, &c.draw_state, c.transform, g);
+
+    let rows = layout.panel_rows();
+    let first = timeline.first_shown(rows);
+    for (i, entry) in timeline.entries().iter().enumerate().skip(first).take(rows) {
+        let top = layout.panel_row_top(i - first);
+        if i == timeline.selected() {
+            rectangle(theme.highlight,
+                      [panel[0], top, panel[2], row_height],
+                      c.transform,
+                      g);
+        }
+        // Revisions left behind by undoing and editing something else are dimmed.
+        let color = if entry.on_current_branch {
+            theme.text
+        } else {
+            theme.gutter
+        };
+        let marker = if entry.revision == timeline.current_revision() {
+            "*"
+        } else {
+            " "
+        };
+        Text::new_color(color, size).draw(&format!("{} {}  {}", marker, entry.time, entry.summary),
+                                          glyphs,
+                                          &c.draw_state,
+                                          c.transform.trans(panel[0] + layout.padding(),
+                                                            top + row_height * 0.75),
+                                          g);
+    }
+}
+
+fn draw_file_tree(file_tree: &FileTree,
+                  layout: &Layout,
+                  theme: &Theme,
+                  glyphs: &mut Fonts<Glyphs>,
+                  c: &Context,
+                  g: &mut G2d) {
+    let size = layout.small_text_size();
+    let row_height = layout.list_row_height();
+    let indent = layout.tree_indent();
+    let rows = file_tree.rows()
+        .iter()
+        .enumerate()
+        .skip(file_tree.scroll_offset())
+        .take(layout.tree_rows());
+    for (i, entry) in rows {
+        let y = layout.tree_row_top(i - file_tree.scroll_offset());
+        let x = layout.sidebar[0] + layout.padding() + entry.depth as f64 * indent;
+        let icon = [x + indent * 0.6, y + row_height * 0.25, size as f64 * 0.8, row_height * 0.5];
+        if entry.is_dir {
+            Text::new_color(theme.text, size).draw(if entry.expanded { "-" } else { "+" },
+                                                 glyphs,
+                                                 &c.draw_state,
+                                                 c.transform.trans(x, y + row_height * 0.75),
+                                                 g);
+            rectangle(theme.folder, icon, c.transform, g);
+        } else {
+            Rectangle::new_border(theme.file, 1.0).draw(icon, &c.draw_state, c.transform, g);
+        }
+        Text::new_color(theme.text, size).draw(&entry.name,
+                                             glyphs,
+                                             &c.draw_state,
+                                             c.transform.trans(icon[0] + icon[2] +
+                                                               layout.em() * 0.25,
+                                                               y + row_height * 0.75),
+                                             g);
+    }
+}
+
+fn draw_status_bar(buf: &TextBuffer,
+                   mode: Option<Mode>,
+                   branch: Option<&Branch>,
+                   refreshing: bool,
+                   layout: &Layout,
+                   theme: &Theme,
+                   glyphs: &mut Fonts<Glyphs>,
+                   c: &Context,
+                   g: &mut G2d) {
+    let size = layout.status_text_size();
+    let char_width = glyphs.character(size, ' ').width();
+    let bar = layout.status_bar;
+    let transform = c.transform.trans(bar[0], bar[1]);
+    rectangle(theme.panel_background, [0.0, 0.0, bar[2], bar[3]], transform, g);
+
+    let mut position = match mode {
+        Some(mode) => format!("{}    ", mode.name()),
+        None => String::new(),
+    };
+    if let Some(cursor) = buf.get_cursors().first() {
+        position.push_str(&format!("Ln {}, Col {}",
+                                   cursor.start_line + 1,
+                                   buf.display_column(cursor.start_line, cursor.start_byte) + 1));
+    }
+    if buf.get_cursors().len() > 1 {
+        position.push_str(&format!("    {} cursors", buf.get_cursors().len()));
+    }
+    let selected = buf.selected_characters();
+    if selected != 0 {
+        position.push_str(&format!("    ({} selected)", selected));
+    }
+    // Uncommitted changes are marked with a star after the branch.
+    let branch = match branch {
+        Some(branch) => format!("{}{}    ", branch.name, if branch.dirty { "*" } else { "" }),
+        None => String::new(),
+    };
+    let file = format!("{}{}{}{}{}: {}    UTF-8    {}",
+                       if refreshing { "Refreshing files    " } else { "" },
+                       branch,
+                       if buf.get_options().large_file { "Large file    " } else { "" },
+                       if buf.is_saved() { "" } else { "Modified    " },
+                       if buf.get_options().hard_tabs { "Tabs" } else { "Spaces" },
+                       buf.get_options().tab_width,
+                       buf.get_line_ending().name());
+
+    let baseline = bar[3] * 0.75;
+    Text::new_color(theme.text, size)
+        .draw(&position, glyphs, &c.draw_state, transform.trans(layout.padding(), baseline), g);
+    Text::new_color(theme.text, size).draw(&file,
+                                         glyphs,
+                                         &c.draw_state,
+                                         transform.trans(bar[2] - layout.padding() -
+                                                         file.chars().count() as f64 *
+                                                         char_width,
+                                                         baseline),
+                                         g);
+}
+
+fn draw_prompt(prompt: &Prompt,
+               layout: &Layout,
+               theme: &Theme,
+               glyphs: &mut Fonts<Glyphs>,
+               c: &Context,
+               g: &mut G2d) {
+    let row_height = layout.line_height();
+    let padding = layout.padding();
+    let matches = prompt.matches();
+    let rows = if matches.len() < PROMPT_ROWS {
+        matches.len()
+    } else {
+        PROMPT_ROWS
+    };
+    let first = if prompt.selected_position() < rows {
+        0
+    } else {
+        prompt.selected_position() + 1 - rows
+    };
+    let rect = layout.prompt(rows);
+    let transform = c.transform.trans(rect[0], rect[1]);
+
+    rectangle(theme.panel_background, [0.0, 0.0, rect[2], rect[3]], transform, g);
+    Text::new_color(theme.text, layout.text_size())
+        .draw(&format!("{}: {}", prompt.title(), prompt.input()),
+              glyphs,
+              &c.draw_state,
+              transform.trans(padding, row_height),
+              g);
+    for (i, item) in matches.iter().enumerate().skip(first).take(rows) {
+        let y = row_height * (i - first + 1) as f64 + padding;
+        if i == prompt.selected_position() {
+            rectangle(theme.highlight,
+                      [0.0, y, rect[2], row_height],
+                      transform,
+                      g);
+        }
+        Text::new_color(theme.text, layout.text_size())
+            .draw(item,
+                  glyphs,
+                  &c.draw_state,
+                  transform.trans(padding, y + layout.em() * 0.8),
+                  g);
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -187,12 +3040,17 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     BigFileSize,
+    InvalidConfig(String),
     IO(io::Error),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            &Error::BigFileSize => write!(f, "file is too big"),
+            &Error::InvalidConfig(ref message) => write!(f, "invalid configuration: {}", message),
+            &Error::IO(ref e) => write!(f, "{}", e),
+        }
     }
 }
 
@@ -206,6 +3064,7 @@ impl StdErr for Error {
     fn description(&self) -> &str {
         match self {
             &Error::BigFileSize => "file is too big",
+            &Error::InvalidConfig(_) => "invalid configuration",
             &Error::IO(ref e) => e.description(),
         }
     }
@@ -213,6 +3072,7 @@ impl StdErr for Error {
     fn cause(&self) -> Option<&StdErr> {
         match self {
             &Error::BigFileSize => None,
+            &Error::InvalidConfig(_) => None,
             &Error::IO(ref e) => Some(e),
         }
     }
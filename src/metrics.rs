@@ -0,0 +1,223 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use piston_window::character::CharacterCache;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Measures text of one font size, remembering the advance of every character it has seen so
+/// that the glyph cache is only asked once per character.
+#[derive(Clone, Debug)]
+pub struct TextMetrics {
+    size: u32,
+    advances: HashMap<char, f64>,
+}
+
+impl TextMetrics {
+    pub fn new(size: u32) -> TextMetrics {
+        TextMetrics {
+            size: size,
+            advances: HashMap::new(),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Horizontal advance of a character.
+    pub fn advance<C: CharacterCache>(&mut self, cache: &mut C, c: char) -> f64 {
+        if let Some(&advance) = self.advances.get(&c) {
+            return advance;
+        }
+        let advance = cache.character(self.size, c).width();
+        let _ = self.advances.insert(c, advance);
+        advance
+    }
+
+    /// Width of a piece of text without tabs.
+    pub fn width<C: CharacterCache>(&mut self, cache: &mut C, text: &str) -> f64 {
+        text.chars().fold(0.0, |width, c| width + self.advance(cache, c))
+    }
+
+    /// Measures where every grapheme cluster of a line without its line break goes. If a wrap
+    /// width is given, the line is broken into rows no wider than that, after the last
+    /// whitespace that fits where possible.
+    pub fn layout<C: CharacterCache>(&mut self,
+                                     cache: &mut C,
+                                     line: &str,
+                                     tab_width: usize,
+                                     wrap: Option<f64>)
+                                     -> LineLayout {
+        let graphemes: Vec<(usize, &str)> = line.grapheme_indices(true).collect();
+        let mut offsets = Vec::with_capacity(graphemes.len() + 1);
+        let mut x = 0.0;
+        for &(byte, grapheme) in &graphemes {
+            offsets.push((byte, x));
+            x = self.next_offset(cache, x, grapheme, tab_width);
+        }
+        offsets.push((line.len(), x));
+
+        // The grapheme cluster each row starts with.
+        let mut row_starts = vec![0];
+        if let Some(width) = wrap {
+            let mut start = 0;
+            let mut i = 0;
+            while i < graphemes.len() {
+                if i > start && offsets[i + 1].1 - offsets[start].1 > width {
+                    start = (start + 1..i + 1)
+                        .rev()
+                        .find(|&j| graphemes[j - 1].1.chars().all(char::is_whitespace))
+                        .unwrap_or(i);
+                    row_starts.push(start);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        let rows = row_starts.iter().map(|&g| offsets[g]).collect();
+        let mut layout = LineLayout {
+            pieces: Vec::new(),
+            offsets: offsets,
+            rows: rows,
+        };
+        for row in 0..layout.rows.len() {
+            let bytes = layout.row_bytes(row);
+            let mut byte = bytes.start;
+            for piece in line[bytes.clone()].split('\t') {
+                if !piece.is_empty() {
+                    let x = layout.row_offset(byte);
+                    layout.pieces.push((row, x, String::from(piece)));
+                }
+                byte += piece.len() + 1;
+            }
+        }
+        layout
+    }
+
+    fn next_offset<C: CharacterCache>(&mut self,
+                                      cache: &mut C,
+                                      offset: f64,
+                                      grapheme: &str,
+                                      tab_width: usize)
+                                      -> f64 {
+        if grapheme == "\t" {
+            let stop = self.advance(cache, ' ') * tab_width as f64;
+            ((offset / stop).floor() + 1.0) * stop
+        } else {
+            offset + self.width(cache, grapheme)
+        }
+    }
+}
+
+/// Where everything on a line goes, measured once so that drawing the line again is cheap.
+#[derive(Clone, Debug)]
+pub struct LineLayout {
+    /// The pieces of text between tabs and row breaks, with the row each is on and the offset
+    /// it starts at from the start of the row.
+    pieces: Vec<(usize, f64, String)>,
+    /// The byte and offset at which each grapheme cluster starts, followed by the end of the line.
+    offsets: Vec<(usize, f64)>,
+    /// The byte and offset at which each row starts. Lines that are not wrapped have one row.
+    rows: Vec<(usize, f64)>,
+}
+
+impl LineLayout {
+    /// The text to draw and where, since tab stops are measured rather than drawn.
+    pub fn pieces(&self) -> &[(usize, f64, String)] {
+        &self.pieces
+    }
+
+    /// Distance from the start of the line to the given byte.
+    pub fn offset(&self, byte: usize) -> f64 {
+        match self.offsets.binary_search_by(|&(b, _)| b.cmp(&byte)) {
+            Ok(i) => self.offsets[i].1,
+            Err(0) => 0.0,
+            Err(i) => self.offsets[i - 1].1,
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.offsets.last().map_or(0.0, |&(_, x)| x)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The row the given byte is shown on. A byte at which the line is broken starts the next
+    /// row.
+    pub fn row_of(&self, byte: usize) -> usize {
+        match self.rows.binary_search_by(|&(b, _)| b.cmp(&byte)) {
+            Ok(row) => row,
+            Err(row) => row.saturating_sub(1),
+        }
+    }
+
+    /// The bytes shown on the given row.
+    pub fn row_bytes(&self, row: usize) -> Range<usize> {
+        let end = match self.rows.get(row + 1) {
+            Some(&(byte, _)) => byte,
+            None => self.offsets.last().map_or(0, |&(byte, _)| byte),
+        };
+        self.rows[row].0..end
+    }
+
+    /// Distance from the start of the row the given byte is on to the byte.
+    pub fn row_offset(&self, byte: usize) -> f64 {
+        self.offset(byte) - self.rows[self.row_of(byte)].1
+    }
+
+    /// Width of the text on the given row.
+    pub fn row_width(&self, row: usize) -> f64 {
+        let bytes = self.row_bytes(row);
+        self.offset(bytes.end) - self.offset(bytes.start)
+    }
+
+    /// Where the given bytes are shown on each row they span, as the row and the offsets they
+    /// start and end at from the start of that row.
+    pub fn spans(&self, bytes: Range<usize>) -> Vec<(usize, f64, f64)> {
+        let mut spans = Vec::new();
+        for row in self.row_of(bytes.start)..self.row_of(bytes.end) + 1 {
+            let shown = self.row_bytes(row);
+            let start = cmp::max(bytes.start, shown.start);
+            let end = cmp::min(bytes.end, shown.end);
+            if start < end || bytes.start == bytes.end {
+                let row_x = self.rows[row].1;
+                spans.push((row, self.offset(start) - row_x, self.offset(end) - row_x));
+            }
+        }
+        spans
+    }
+
+    /// Index of the grapheme cluster of the line whose left edge is closest to `x` on the given
+    /// row. Past the end of a row that is followed by another, that is its last cluster, so that
+    /// the cursor stays on the row.
+    pub fn grapheme_at(&self, row: usize, x: f64) -> usize {
+        let bytes = self.row_bytes(row);
+        let row_x = self.rows[row].1;
+        let first = self.grapheme_index(bytes.start);
+        let last = if row + 1 < self.rows.len() {
+            self.grapheme_index(bytes.end) - 1
+        } else {
+            self.offsets.len() - 1
+        };
+        for i in first..last {
+            let start = self.offsets[i].1 - row_x;
+            let end = self.offsets[i + 1].1 - row_x;
+            if x < (start + end) / 2.0 {
+                return i;
+            }
+        }
+        last
+    }
+
+    /// Index of the grapheme cluster starting at the given byte.
+    fn grapheme_index(&self, byte: usize) -> usize {
+        match self.offsets.binary_search_by(|&(b, _)| b.cmp(&byte)) {
+            Ok(i) => i,
+            Err(i) => i.saturating_sub(1),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use git2::Repository;
+
+use editor_core::backend::TextBuffer;
+
+use super::Result;
+use search;
+
+#[derive(Clone, Debug)]
+struct Match {
+    path: PathBuf,
+    /// The path relative to the searched directory, as shown in the buffer.
+    name: String,
+    line: usize,
+    text: String,
+}
+
+impl Match {
+    /// The `path:line: ` prefix identifying the match in the session buffer.
+    fn prefix(&self) -> String {
+        format!("{}:{}: ", self.name, self.line + 1)
+    }
+}
+
+/// The lines of many files matching a search, edited together in a single buffer and written
+/// back to their files when it is saved.
+#[derive(Clone, Debug)]
+pub struct EditSession {
+    matches: Vec<Match>,
+}
+
+impl EditSession {
+    /// Searches the files under `root` for the lines containing `pattern` on another thread,
+    /// skipping hidden files, the ones git ignores and the ones that cannot be read as UTF-8 text.
+    pub fn search(root: PathBuf, pattern: &str) -> Receiver<EditSession> {
+        let (sender, receiver) = mpsc::channel();
+        let pattern = String::from(pattern);
+        let _ = thread::spawn(move || {
+            let (paths, queue) = mpsc::channel();
+            let repo = Repository::discover(&root).ok();
+            search::walk(&root, &root, repo.as_ref(), &paths, &AtomicBool::new(false));
+            drop(paths);
+            let mut matches = Vec::new();
+            for (path, name) in queue.iter() {
+                search_file(path, name, &pattern, &mut matches);
+            }
+            let _ = sender.send(EditSession { matches: matches });
+        });
+        receiver
+    }
+
+    /// Creates the buffer listing every match as a `path:line: text` line.
+    pub fn buffer(&self) -> TextBuffer {
+        TextBuffer::from_lines(self.matches.iter().map(|m| m.prefix() + &m.text).collect())
+    }
+
+    /// Writes the lines changed in the session buffer back to their files. Lines whose prefix
+    /// was edited, and lines whose file changed since the search, are left alone.
+    pub fn write_back(&mut self, buffer: &TextBuffer) -> Result<()> {
+        let prefixes: HashMap<String, usize> =
+            self.matches.iter().enumerate().map(|(i, m)| (m.prefix(), i)).collect();
+        let mut changes: BTreeMap<PathBuf, Vec<(usize, String)>> = BTreeMap::new();
+        for line in buffer.lines() {
+            let line = line.trim_right_matches('\n');
+            let found = line.match_indices(": ")
+                .filter_map(|(i, _)| prefixes.get(&line[..i + 2]).map(|&m| (m, &line[i + 2..])))
+                .next();
+            if let Some((i, text)) = found {
+                if self.matches[i].text != text {
+                    changes.entry(self.matches[i].path.clone())
+                        .or_insert_with(Vec::new)
+                        .push((i, String::from(text)));
+                }
+            }
+        }
+
+        for (path, edits) in changes {
+            let mut file = try!(TextBuffer::new(Some(&path)));
+            try!(file.load(|_, _| {}));
+            file.set_options(buffer.get_options().clone());
+            for (i, text) in edits {
+                let unchanged = match file.lines().nth(self.matches[i].line) {
+                    Some(line) => line.trim_right_matches('\n') == self.matches[i].text,
+                    None => false,
+                };
+                if unchanged {
+                    file.replace_line(self.matches[i].line, &text);
+                    self.matches[i].text = text;
+                }
+            }
+            try!(file.save(|_, _| {}));
+        }
+
+        Ok(())
+    }
+}
+
+fn search_file(path: PathBuf, name: String, pattern: &str, matches: &mut Vec<Match>) {
+    let mut contents = String::new();
+    if File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return;
+    }
+    for (i, line) in contents.lines().enumerate() {
+        if line.contains(pattern) {
+            matches.push(Match {
+                path: path.clone(),
+                name: name.clone(),
+                line: i,
+                text: String::from(line),
+            });
+        }
+    }
+}
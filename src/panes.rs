@@ -0,0 +1,114 @@
+use std::mem;
+
+use buffers::Buffers;
+use layout::{Layout, Split};
+use view::View;
+
+/// A pane out of focus: the buffer it shows, along with its view and cursors.
+#[derive(Clone, Debug)]
+struct Pane {
+    buffer: usize,
+    view: View,
+    /// The start and end of every cursor, as lines and characters, which go back into the
+    /// buffer once the pane is focused.
+    cursors: Vec<((usize, usize), (usize, usize))>,
+}
+
+impl Pane {
+    /// The pane showing the active buffer, as it is now.
+    fn active(buffers: &mut Buffers) -> Pane {
+        let buffer = buffers.active_index();
+        let (buf, view) = buffers.active_with_view();
+        Pane {
+            buffer: buffer,
+            view: view.clone(),
+            cursors: buf.get_cursors()
+                .iter()
+                .map(|c| ((c.start_line, c.start_character), (c.end_line, c.end_character)))
+                .collect(),
+        }
+    }
+}
+
+/// The editor split into two panes. The focused one is the active buffer with the view the
+/// buffers keep for it, edited just as without a split, and the other one is put aside until
+/// it is focused, so that both can show the same buffer with their own view and cursors.
+#[derive(Clone, Debug)]
+pub struct Panes {
+    split: Split,
+    /// 0 for the left or top pane, 1 for the other one.
+    focused: usize,
+    other: Pane,
+}
+
+impl Panes {
+    /// Splits the editor the given way, with both panes showing the active buffer as it is and
+    /// the first one focused.
+    pub fn new(split: Split, buffers: &mut Buffers) -> Panes {
+        Panes {
+            split: split,
+            focused: 0,
+            other: Pane::active(buffers),
+        }
+    }
+
+    pub fn split(&self) -> Split {
+        self.split
+    }
+
+    pub fn set_split(&mut self, split: Split) {
+        self.split = split;
+    }
+
+    /// The index of the pane out of focus.
+    pub fn other_index(&self) -> usize {
+        1 - self.focused
+    }
+
+    /// The index of the buffer shown in the pane out of focus.
+    pub fn other_buffer(&self) -> usize {
+        self.other.buffer
+    }
+
+    pub fn other_view_mut(&mut self) -> &mut View {
+        &mut self.other.view
+    }
+
+    /// Moves the buffer of the pane out of focus down by one if it came after the buffer at the
+    /// given index, which was closed. Returns `false` if that pane showed the closed buffer.
+    pub fn close(&mut self, index: usize) -> bool {
+        if self.other.buffer == index {
+            return false;
+        }
+        if self.other.buffer > index {
+            self.other.buffer -= 1;
+        }
+        true
+    }
+
+    /// Focuses the pane with the given index, making its buffer the active one again with the
+    /// view and cursors it had, as far as edits made from the other pane allow it.
+    pub fn focus(&mut self, index: usize, buffers: &mut Buffers) {
+        if index == self.focused || index > 1 {
+            return;
+        }
+        let entering = mem::replace(&mut self.other, Pane::active(buffers));
+        buffers.activate(entering.buffer);
+        let (buf, view) = buffers.active_with_view();
+        *view = entering.view;
+        if buf.is_loaded() && !entering.cursors.is_empty() {
+            let cursors = entering.cursors
+                .iter()
+                .map(|&(start, end)| buf.cursor_between(start, end))
+                .collect();
+            buf.set_cursors(cursors);
+        }
+        self.focused = index;
+    }
+
+    /// The layouts of the focused pane and of the other one.
+    pub fn layouts(&self, layout: &Layout) -> (Layout, Layout) {
+        let panes = layout.split(self.split);
+        (panes[self.focused], panes[self.other_index()])
+    }
+}
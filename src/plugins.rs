@@ -0,0 +1,257 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use hlua::{self, Lua, LuaError, LuaFunction};
+
+use editor_core::backend::TextBuffer;
+
+use buffers::Buffers;
+use dirs;
+
+/// Where the Lua scripts loaded on start are: the `plugins` directory of the configuration
+/// directory of the user. The current directory is never looked in, as it may be a project
+/// that would get to run its code just by being opened.
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|config| config.join("plugins"))
+}
+
+/// A change a script asked for, made to the buffer once the script returns.
+#[derive(Clone, Debug)]
+enum Edit {
+    /// Types the text at every cursor.
+    Insert(String),
+    /// Replaces the text of a line, counted from 1, with text without line breaks.
+    SetLine(usize, String),
+}
+
+/// What the functions given to the scripts share with the editor: the buffer a script is run
+/// for, which it can read but not change directly, and what it asked for.
+#[derive(Clone, Debug, Default)]
+struct State {
+    path: String,
+    lines: Vec<String>,
+    edits: Vec<Edit>,
+    /// The commands registered by the script being loaded, with the function each one calls.
+    commands: Vec<(String, String)>,
+}
+
+struct Plugin {
+    path: PathBuf,
+    lua: Lua<'static>,
+}
+
+/// A command added to the command palette by a plugin.
+#[derive(Clone, Debug)]
+pub struct PluginCommand {
+    pub name: String,
+    plugin: usize,
+    function: String,
+}
+
+/// The Lua scripts of the plugins directory of the user. Scripts can call:
+///
+/// - `register_command(name, function)` to add a command to the palette calling the global
+///   function of the given name,
+/// - `path()`, `line_count()` and `line(n)` to read the buffer they are run for, with lines
+///   counted from 1,
+/// - `insert(text)` and `set_line(n, text)` to change it, which happens once they return.
+///
+/// Global functions named `on_open`, `on_save` and `on_change` are called with the path of a
+/// buffer when it is opened, saved or changed. Each script runs in a state of its own.
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+    state: Rc<RefCell<State>>,
+    commands: Vec<PluginCommand>,
+    /// The version of each buffer and whether it was saved when the hooks last saw it, by index.
+    seen: HashMap<usize, (usize, bool)>,
+}
+
+impl Plugins {
+    /// Loads every `.lua` file of the directory, if there is one, reporting the ones that fail
+    /// on the standard error.
+    pub fn load(dir: Option<PathBuf>) -> Plugins {
+        let mut plugins = Plugins {
+            plugins: Vec::new(),
+            state: Rc::new(RefCell::new(State::default())),
+            commands: Vec::new(),
+            seen: HashMap::new(),
+        };
+        let mut paths: Vec<PathBuf> = match dir.map(fs::read_dir) {
+            Some(Ok(entries)) => {
+                entries.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().map_or(false, |e| e == "lua"))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        paths.sort();
+        for path in paths {
+            match plugins.load_script(&path) {
+                Ok(lua) => {
+                    let index = plugins.plugins.len();
+                    let registered: Vec<_> =
+                        plugins.state.borrow_mut().commands.drain(..).collect();
+                    for (name, function) in registered {
+                        plugins.commands.push(PluginCommand {
+                            name: name,
+                            plugin: index,
+                            function: function,
+                        });
+                    }
+                    plugins.plugins.push(Plugin {
+                        path: path,
+                        lua: lua,
+                    });
+                }
+                Err(e) => report(&path, &e),
+            }
+        }
+        plugins
+    }
+
+    /// The commands the plugins registered, in the order they did.
+    pub fn commands(&self) -> &[PluginCommand] {
+        &self.commands
+    }
+
+    /// Forgets the buffer at the given index, which was closed, moving the ones after it down
+    /// by one, as the buffers are.
+    pub fn close(&mut self, index: usize) {
+        let _ = self.seen.remove(&index);
+        self.seen = self.seen
+            .drain()
+            .map(|(i, seen)| (if i > index { i - 1 } else { i }, seen))
+            .collect();
+    }
+
+    /// Runs the command with the given index on the active buffer.
+    pub fn run_command(&mut self, index: usize, buffers: &mut Buffers) {
+        let (plugin, function) = match self.commands.get(index) {
+            Some(command) => (command.plugin, command.function.clone()),
+            None => return,
+        };
+        let active = buffers.active_index();
+        self.call(plugin, &function, buffers.get_mut(active));
+    }
+
+    /// Calls the hooks for the buffers opened, saved or changed since last time. Returns whether
+    /// any was called, which may have changed the buffers.
+    pub fn update(&mut self, buffers: &mut Buffers) -> bool {
+        let mut called = false;
+        if self.plugins.is_empty() {
+            return called;
+        }
+        for index in 0..buffers.len() {
+            let (version, saved) = {
+                let buf = buffers.get(index);
+                if !buf.is_loaded() {
+                    continue;
+                }
+                (buf.version(), buf.is_saved())
+            };
+            let hook = match self.seen.get(&index) {
+                None => Some("on_open"),
+                Some(&(_, false)) if saved => Some("on_save"),
+                Some(&(seen, _)) if seen != version => Some("on_change"),
+                _ => None,
+            };
+            if let Some(hook) = hook {
+                for plugin in 0..self.plugins.len() {
+                    self.call(plugin, hook, buffers.get_mut(index));
+                }
+                called = true;
+            }
+            // What the hooks changed does not call them again.
+            let buf = buffers.get(index);
+            let _ = self.seen.insert(index, (buf.version(), buf.is_saved()));
+        }
+        called
+    }
+
+    fn load_script(&mut self, path: &Path) -> Result<Lua<'static>, LuaError> {
+        let mut lua = Lua::new();
+        lua.openlibs();
+        let state = self.state.clone();
+        lua.set("register_command",
+                hlua::function2(move |name: String, function: String| {
+                    state.borrow_mut().commands.push((name, function));
+                }));
+        let state = self.state.clone();
+        lua.set("path", hlua::function0(move || state.borrow().path.clone()));
+        let state = self.state.clone();
+        lua.set("line_count",
+                hlua::function0(move || state.borrow().lines.len() as u32));
+        let state = self.state.clone();
+        lua.set("line",
+                hlua::function1(move |line: u32| match (line as usize).checked_sub(1) {
+                    Some(i) => state.borrow().lines.get(i).cloned().unwrap_or_else(String::new),
+                    None => String::new(),
+                }));
+        let state = self.state.clone();
+        lua.set("insert",
+                hlua::function1(move |text: String| {
+                    state.borrow_mut().edits.push(Edit::Insert(text));
+                }));
+        let state = self.state.clone();
+        lua.set("set_line",
+                hlua::function2(move |line: u32, text: String| {
+                    state.borrow_mut().edits.push(Edit::SetLine(line as usize, text));
+                }));
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(LuaError::ReadError(e)),
+        };
+        try!(lua.execute_from_reader::<(), _>(file));
+        Ok(lua)
+    }
+
+    /// Calls the global function of the plugin, if it has one, for the buffer, and makes the
+    /// edits it asked for unless the buffer is read only.
+    fn call(&mut self, plugin: usize, function: &str, buf: &mut TextBuffer) {
+        {
+            let mut state = self.state.borrow_mut();
+            state.path = buf.get_path()
+                .map_or(String::new(), |p| p.to_string_lossy().into_owned());
+            state.lines = buf.lines()
+                .map(|l| String::from(l.trim_right_matches(|c| c == '\n' || c == '\r')))
+                .collect();
+            state.edits.clear();
+        }
+        let path = self.state.borrow().path.clone();
+        let result = {
+            let plugin = &mut self.plugins[plugin];
+            let found: Option<LuaFunction<_>> = plugin.lua.get(function);
+            match found {
+                Some(mut found) => found.call_with_args::<(), _, _>(path),
+                None => Ok(()),
+            }
+        };
+        if let Err(e) = result {
+            report(&self.plugins[plugin].path, &e);
+        }
+
+        let edits: Vec<Edit> = self.state.borrow_mut().edits.drain(..).collect();
+        if buf.get_options().read_only {
+            return;
+        }
+        for edit in edits {
+            match edit {
+                Edit::Insert(text) => buf.write_str(text),
+                Edit::SetLine(line, text) => {
+                    if line >= 1 && line <= buf.line_count() && !text.contains('\n') {
+                        buf.replace_line(line - 1, &text);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn report(path: &Path, error: &LuaError) {
+    let _ = writeln!(io::stderr(), "plugin {}: {:?}", path.display(), error);
+}
@@ -0,0 +1,82 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::mem;
+
+use fps_counter::FPSCounter;
+
+use editor_core::backend::TextBuffer;
+
+/// How many frames the times are averaged over.
+const FRAMES: usize = 60;
+
+/// What the time of a frame is spent on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Measuring and wrapping the lines to draw.
+    Layout,
+    /// Drawing the glyphs and everything else of the window, once the lines are laid out.
+    Drawing,
+    /// Running the actions input was translated into, which edit the buffers.
+    Editing,
+}
+
+/// Keeps how long the last frames took, and how much of it went to each stage, to be shown on
+/// top of the editor.
+pub struct Profiler {
+    fps: FPSCounter,
+    frames_per_second: usize,
+    /// The time spent in each stage since the last frame was drawn, in nanoseconds.
+    current: [u64; 3],
+    /// The time spent in each stage for the last frames, and how long drawing each of them
+    /// took, from the oldest. Editing happens between frames, so it is not part of the latter.
+    frames: VecDeque<([u64; 3], u64)>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            fps: FPSCounter::new(),
+            frames_per_second: 0,
+            current: [0; 3],
+            frames: VecDeque::with_capacity(FRAMES),
+        }
+    }
+
+    /// Adds time spent in a stage to the frame being drawn, in nanoseconds.
+    pub fn add(&mut self, stage: Stage, took: u64) {
+        self.current[stage as usize] += took;
+    }
+
+    /// Finishes the frame, which took the given nanoseconds to draw.
+    pub fn end_frame(&mut self, took: u64) {
+        self.frames_per_second = self.fps.tick();
+        if self.frames.len() == FRAMES {
+            let _ = self.frames.pop_front();
+        }
+        self.frames.push_back((self.current, took));
+        self.current = [0; 3];
+    }
+
+    /// The lines of the overlay: the frame rate, the average times of the last frames and the
+    /// size of the text of the buffer.
+    pub fn report(&self, buf: &TextBuffer) -> Vec<String> {
+        let mut stages = [0; 3];
+        let mut total = 0;
+        for &(times, took) in &self.frames {
+            for (sum, time) in stages.iter_mut().zip(&times) {
+                *sum += *time;
+            }
+            total += took;
+        }
+        let ms = |ns: u64| ns as f64 / cmp::max(self.frames.len(), 1) as f64 / 1e6;
+        let memory = buf.lines()
+            .fold(0, |sum, line| sum + line.capacity() + mem::size_of::<String>());
+        vec![format!("FPS: {}", self.frames_per_second),
+             format!("Frame: {:.2} ms", ms(total)),
+             format!("  Layout: {:.2} ms", ms(stages[Stage::Layout as usize])),
+             format!("  Drawing: {:.2} ms", ms(stages[Stage::Drawing as usize])),
+             format!("Editing: {:.2} ms", ms(stages[Stage::Editing as usize])),
+             format!("Lines: {}", buf.line_count()),
+             format!("Text: {:.1} KiB", memory as f64 / 1024.0)]
+    }
+}
@@ -0,0 +1,109 @@
+use std::cmp::Ordering;
+
+use fuzzy;
+
+/// A text input overlay that fuzzily filters a list of items as the user types.
+#[derive(Clone, Debug)]
+pub struct Prompt {
+    title: String,
+    input: String,
+    items: Vec<String>,
+    /// Added to the score of each item, to rank some items above others that match as well.
+    bonuses: Vec<usize>,
+    matches: Vec<usize>,
+    selected: usize,
+}
+
+impl Prompt {
+    pub fn new<S: Into<String>>(title: S, items: Vec<String>) -> Prompt {
+        let bonuses = vec![0; items.len()];
+        Prompt::ranked(title, items, bonuses)
+    }
+
+    /// A prompt whose items score the given bonus, each, on top of how well they match.
+    pub fn ranked<S: Into<String>>(title: S, items: Vec<String>, bonuses: Vec<usize>) -> Prompt {
+        let mut prompt = Prompt {
+            title: title.into(),
+            input: String::new(),
+            items: items,
+            bonuses: bonuses,
+            matches: Vec::new(),
+            selected: 0,
+        };
+        prompt.update_matches();
+        prompt
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.input.push(c);
+        self.update_matches();
+    }
+
+    pub fn backspace(&mut self) {
+        let _ = self.input.pop();
+        self.update_matches();
+    }
+
+    /// Adds items, with their bonuses, keeping the input and the selected item.
+    pub fn extend(&mut self, items: Vec<String>, bonuses: Vec<usize>) {
+        let selected = self.selected();
+        self.items.extend(items);
+        self.bonuses.extend(bonuses);
+        self.update_matches();
+        if let Some(position) = selected.and_then(|s| self.matches.iter().position(|&i| i == s)) {
+            self.selected = position;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.selected != 0 {
+            self.selected -= 1;
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    /// Index in the item list of the selected match.
+    pub fn selected(&self) -> Option<usize> {
+        self.matches.get(self.selected).cloned()
+    }
+
+    pub fn selected_position(&self) -> usize {
+        self.selected
+    }
+
+    /// The items matching the input, best match first.
+    pub fn matches(&self) -> Vec<&str> {
+        self.matches.iter().map(|&i| self.items[i].as_str()).collect()
+    }
+
+    fn update_matches(&mut self) {
+        let mut scored = Vec::new();
+        for (i, item) in self.items.iter().enumerate() {
+            if let Some(score) = fuzzy::score(&self.input, item) {
+                scored.push((score + self.bonuses[i], i));
+            }
+        }
+        {
+            let items = &self.items;
+            scored.sort_by(|&(a_score, a), &(b_score, b)| match b_score.cmp(&a_score) {
+                Ordering::Equal => items[a].len().cmp(&items[b].len()),
+                ordering => ordering,
+            });
+        }
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use git2::Repository;
+
+use prompt::Prompt;
+use search;
+
+/// Number of files opened last that rank above the others.
+const RECENT_FILES: usize = 20;
+
+/// The files of a project, found in the background, to open one of them by typing part of its
+/// path. The files opened last from it rank higher, the last one first.
+#[derive(Debug)]
+pub struct FileIndex {
+    receiver: Option<Receiver<(PathBuf, String)>>,
+    cancelled: Arc<AtomicBool>,
+    /// Every file found so far, along with its path relative to the project, as it is listed.
+    files: Vec<(PathBuf, String)>,
+    /// The files opened from the index, the last one first.
+    recent: Vec<PathBuf>,
+}
+
+impl FileIndex {
+    /// Starts finding the files under `root` that are neither hidden nor ignored by git.
+    pub fn new(root: PathBuf) -> FileIndex {
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let walk_cancelled = cancelled.clone();
+        let _ = thread::spawn(move || {
+            let repo = Repository::discover(&root).ok();
+            search::walk(&root, &root, repo.as_ref(), &sender, &walk_cancelled);
+        });
+
+        FileIndex {
+            receiver: Some(receiver),
+            cancelled: cancelled,
+            files: Vec::new(),
+            recent: Vec::new(),
+        }
+    }
+
+    /// Adds the files found since last time to the index, returning them.
+    pub fn receive(&mut self) -> Vec<(PathBuf, String)> {
+        let mut found = Vec::new();
+        let mut over = false;
+        if let Some(ref receiver) = self.receiver {
+            loop {
+                match receiver.try_recv() {
+                    Ok(file) => found.push(file),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        over = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if over {
+            self.receiver = None;
+        }
+        self.files.extend(found.iter().cloned());
+        found
+    }
+
+    /// A prompt listing the files found so far, along with the path of each item.
+    pub fn prompt(&self) -> (Prompt, Vec<PathBuf>) {
+        let (names, bonuses, paths) = self.items(&self.files);
+        (Prompt::ranked("Go to file", names, bonuses), paths)
+    }
+
+    /// The names, bonuses and paths of the given files, as prompt items.
+    pub fn items(&self, files: &[(PathBuf, String)]) -> (Vec<String>, Vec<usize>, Vec<PathBuf>) {
+        let mut names = Vec::new();
+        let mut bonuses = Vec::new();
+        let mut paths = Vec::new();
+        for &(ref path, ref name) in files {
+            names.push(name.clone());
+            let recent = self.recent.iter().position(|p| p == path);
+            bonuses.push(recent.map_or(0, |i| RECENT_FILES - i));
+            paths.push(path.clone());
+        }
+        (names, bonuses, paths)
+    }
+
+    /// Ranks the file first from now on.
+    pub fn opened(&mut self, path: &Path) {
+        self.recent.retain(|p| p != path);
+        self.recent.insert(0, path.to_path_buf());
+        self.recent.truncate(RECENT_FILES);
+    }
+}
+
+impl Drop for FileIndex {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use toml::{Parser, Value};
+
+use dirs;
+use super::{Error, Result};
+
+/// Where the recent files of the current directory are kept: in the `recent` directory of the
+/// data directory of the user, named after the current directory as sessions are.
+pub fn file() -> Option<PathBuf> {
+    let current = match env::current_dir() {
+        Ok(current) => current,
+        Err(_) => return None,
+    };
+    dirs::data_dir()
+        .map(|data| data.join("recent").join(format!("{}.toml", dirs::flatten(&current))))
+}
+
+/// How many files are remembered.
+const MAX_FILES: usize = 20;
+
+/// The files opened last, the last one first, kept across restarts.
+#[derive(Clone, Debug)]
+pub struct RecentFiles {
+    files: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    /// No files.
+    pub fn new() -> RecentFiles {
+        RecentFiles { files: Vec::new() }
+    }
+
+    /// Reads the recent files from the given file, or starts with none if there is no file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<RecentFiles> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(RecentFiles::new());
+        }
+
+        let mut contents = String::new();
+        let _ = try!(try!(File::open(path)).read_to_string(&mut contents));
+        let table = match Parser::new(&contents).parse() {
+            Some(t) => t,
+            None => return Err(invalid()),
+        };
+        let mut files = Vec::new();
+        for file in table.get("files").and_then(Value::as_slice).unwrap_or(&[]) {
+            files.push(PathBuf::from(try!(file.as_str().ok_or_else(invalid))));
+        }
+
+        Ok(RecentFiles { files: files })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let files = self.files
+            .iter()
+            .map(|f| Value::String(f.to_string_lossy().into_owned()))
+            .collect();
+        let mut table = BTreeMap::new();
+        let _ = table.insert(String::from("files"), Value::Array(files));
+        try!(dirs::write_atomically(path, Value::Table(table).to_string().as_bytes()));
+
+        Ok(())
+    }
+
+    /// Puts the file first, forgetting the oldest one if there are too many.
+    pub fn opened(&mut self, path: &Path) {
+        if self.files.first().map_or(false, |f| f == path) {
+            return;
+        }
+        self.files.retain(|f| f != path);
+        self.files.insert(0, path.to_path_buf());
+        self.files.truncate(MAX_FILES);
+    }
+
+    /// The recent files that still exist, the last one opened first.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.iter().filter(|f| f.exists()).cloned().collect()
+    }
+}
+
+fn invalid() -> Error {
+    Error::InvalidConfig(String::from("invalid recent files file"))
+}
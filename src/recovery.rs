@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use editor_core::backend::TextSnapshot;
+
+use buffers::Buffers;
+use dirs;
+
+/// The extension of swap files.
+const EXTENSION: &'static str = "swp";
+
+/// A swap file left by an editor that did not exit cleanly, with the unsaved text of a file or
+/// of a buffer without one.
+#[derive(Clone, Debug)]
+pub struct Swap {
+    /// The file the text belongs to, if any.
+    pub path: Option<PathBuf>,
+    swap: PathBuf,
+}
+
+impl Swap {
+    /// The unsaved text the swap file holds.
+    pub fn text(&self) -> io::Result<String> {
+        let mut contents = String::new();
+        let _ = try!(try!(File::open(&self.swap)).read_to_string(&mut contents));
+        // The first line names the owner and the file.
+        Ok(match contents.find('\n') {
+            Some(i) => contents[i + 1..].to_owned(),
+            None => String::new(),
+        })
+    }
+
+    /// Deletes the swap file, once its text is recovered or not wanted.
+    pub fn remove(&self) {
+        let _ = fs::remove_file(&self.swap);
+    }
+}
+
+/// Swap files work queued for the writer thread, done in order so that a file is never written
+/// back after it was removed.
+enum Job {
+    Write(PathBuf, Option<PathBuf>, TextSnapshot),
+    Remove(PathBuf),
+}
+
+/// Keeps the text of the buffers with unsaved changes in swap files in the cache directory, to
+/// recover it if the editor does not exit cleanly. The files are written in the background.
+pub struct Recovery {
+    dir: Option<PathBuf>,
+    sender: Option<Sender<Job>>,
+    writer: Option<JoinHandle<()>>,
+    /// The version of the text last written for each file, by its swap file.
+    written: HashMap<PathBuf, usize>,
+}
+
+impl Recovery {
+    pub fn new() -> Recovery {
+        let dir = swap_dir();
+        let (sender, receiver) = mpsc::channel();
+        let writer = dir.clone().map(|dir| {
+            thread::spawn(move || {
+                for job in receiver {
+                    match job {
+                        Job::Write(swap, path, text) => {
+                            let _ = fs::create_dir_all(&dir)
+                                .and_then(|_| write(&swap, path.as_ref(), &text));
+                        }
+                        Job::Remove(swap) => {
+                            let _ = fs::remove_file(swap);
+                        }
+                    }
+                }
+            })
+        });
+
+        Recovery {
+            dir: dir,
+            sender: Some(sender),
+            writer: writer,
+            written: HashMap::new(),
+        }
+    }
+
+    /// The swap files left behind by editors that are no longer running. The ones of editors
+    /// still running are theirs to write and remove.
+    pub fn leftovers(&self) -> Vec<Swap> {
+        let entries = match self.dir.as_ref().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return Vec::new(),
+        };
+        let mut swaps: Vec<Swap> = entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|swap| swap.extension().map_or(false, |e| e == EXTENSION))
+            .filter_map(|swap| match read_header(&swap) {
+                Some((Some(pid), _)) if is_running(pid) => None,
+                Some((_, path)) => Some(Swap { path: path, swap: swap }),
+                None => None,
+            })
+            .collect();
+        swaps.sort_by(|a, b| a.path.cmp(&b.path));
+        swaps
+    }
+
+    /// Writes the text of the buffers with changes that are not saved, if it changed since last
+    /// time, and removes the swap files of the ones saved or closed since. Read-only buffers,
+    /// which show what the editor made rather than what the user wrote, are left out.
+    pub fn update(&mut self, buffers: &Buffers) {
+        let dir = match self.dir {
+            Some(ref dir) => dir.clone(),
+            None => return,
+        };
+        let mut unsaved = HashMap::new();
+        for i in buffers.unsaved() {
+            let buf = buffers.get(i);
+            if buf.is_loaded() && !buf.get_options().read_only {
+                let path = buf.get_path().map(absolute);
+                let name = swap_name(path.as_ref().map(|p| p.as_path()), buffers.id(i));
+                let _ = unsaved.insert(dir.join(name), (path, buf));
+            }
+        }
+
+        let gone: Vec<PathBuf> =
+            self.written.keys().filter(|swap| !unsaved.contains_key(*swap)).cloned().collect();
+        for swap in gone {
+            let _ = self.written.remove(&swap);
+            self.send(Job::Remove(swap));
+        }
+        for (swap, (path, buf)) in unsaved {
+            if self.written.get(&swap) != Some(&buf.version()) {
+                let _ = self.written.insert(swap.clone(), buf.version());
+                self.send(Job::Write(swap, path, buf.text_snapshot()));
+            }
+        }
+    }
+
+    /// Removes the swap files written so far, on a clean exit, and waits for it to be done.
+    pub fn clear(&mut self) {
+        let written: Vec<PathBuf> = self.written.drain().map(|(swap, _)| swap).collect();
+        for swap in written {
+            self.send(Job::Remove(swap));
+        }
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(ref sender) = self.sender {
+            let _ = sender.send(job);
+        }
+    }
+}
+
+/// Where swap files are kept: the `swap` directory of the cache directory of the user.
+fn swap_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|cache| cache.join("swap"))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    env::current_dir().map(|dir| dir.join(path)).unwrap_or(path.to_path_buf())
+}
+
+/// The name of the swap file of the file at the absolute path, or of the buffer with the id if
+/// it has no file. Files are named after their path as Vim does, so that files of the same name
+/// in different directories do not share one, and the id of the process is added so that
+/// editors running at the same time do not write over each other's.
+fn swap_name(path: Option<&Path>, id: usize) -> String {
+    match path {
+        Some(path) => format!("{}.{}.{}", dirs::flatten(path), process::id(), EXTENSION),
+        None => format!("untitled-{}.{}.{}", id, process::id(), EXTENSION),
+    }
+}
+
+/// Writes the id of the process and the path of the file, if any, on the first line, followed
+/// by the text.
+fn write(swap: &Path, path: Option<&PathBuf>, text: &TextSnapshot) -> io::Result<()> {
+    let mut file = try!(File::create(swap));
+    try!(writeln!(file,
+                  "{} {}",
+                  process::id(),
+                  path.map_or(String::new(), |p| p.display().to_string())));
+    for line in text.lines() {
+        try!(file.write_all(line.as_bytes()));
+    }
+    Ok(())
+}
+
+/// The id of the process that wrote the swap file, and the path of the file its text belongs
+/// to, if it has one. Swap files written before they named their owner only hold the path.
+fn read_header(swap: &Path) -> Option<(Option<u32>, Option<PathBuf>)> {
+    let mut line = String::new();
+    match File::open(swap).and_then(|f| BufReader::new(f).read_line(&mut line)) {
+        Ok(n) if n > 0 => {}
+        _ => return None,
+    }
+    let line = line.trim_right_matches('\n');
+    let owner = line.find(' ').and_then(|i| line[..i].parse().ok().map(|pid| (pid, i)));
+    let (pid, path) = match owner {
+        Some((pid, i)) => (Some(pid), &line[i + 1..]),
+        None => (None, line),
+    };
+    Some((pid, if path.is_empty() { None } else { Some(PathBuf::from(path)) }))
+}
+
+/// Whether the process with the id is running. It is taken to be if that cannot be told, so
+/// that the swap files of an editor still running are never offered for recovery.
+fn is_running(pid: u32) -> bool {
+    if cfg!(target_os = "windows") {
+        let filter = format!("PID eq {}", pid);
+        match Command::new("tasklist").args(&["/NH", "/FI", &filter]).output() {
+            Ok(output) => String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()),
+            Err(_) => true,
+        }
+    } else if Path::new("/proc/self").exists() {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        Command::new("kill")
+            .args(&["-0", &pid.to_string()])
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(true)
+    }
+}
@@ -0,0 +1,84 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Finds the file related to `path` by one of the groups of file name patterns, where `*`
+/// stands for the part of the name shared by the files of a group. The counterpart is looked for
+/// next to the file first and then, for patterns with a `*`, anywhere under `root`.
+///
+/// When several files of a group exist, the one after the current file in the group is chosen,
+/// so that toggling repeatedly cycles through all of them.
+pub fn find<P: AsRef<Path>>(path: P, root: &Path, groups: &[Vec<String>]) -> Option<PathBuf> {
+    let path = path.as_ref();
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return None,
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    for group in groups {
+        for (i, pattern) in group.iter().enumerate() {
+            let stem = match stem(pattern, &name) {
+                Some(stem) => stem,
+                None => continue,
+            };
+            let candidates = group[i + 1..]
+                .iter()
+                .chain(&group[..i])
+                .map(|pattern| pattern.replace('*', stem));
+            for candidate in candidates {
+                let next_to = dir.join(&candidate);
+                if next_to.is_file() {
+                    return Some(next_to);
+                }
+                if stem.is_empty() {
+                    continue;
+                }
+                if let Some(found) = search(root, &candidate) {
+                    return Some(found);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// The part of the name matching the `*` of the pattern, if the name matches it.
+fn stem<'a>(pattern: &str, name: &'a str) -> Option<&'a str> {
+    match pattern.find('*') {
+        Some(i) => {
+            let (prefix, suffix) = (&pattern[..i], &pattern[i + 1..]);
+            if name.len() > prefix.len() + suffix.len() && name.starts_with(prefix) &&
+               name.ends_with(suffix) {
+                Some(&name[prefix.len()..name.len() - suffix.len()])
+            } else {
+                None
+            }
+        }
+        None if pattern == name => Some(""),
+        None => None,
+    }
+}
+
+/// Looks for a file with the given name under `dir`, skipping hidden directories.
+fn search(dir: &Path, name: &str) -> Option<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return None,
+    };
+    let mut dirs = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            dirs.push(path);
+        } else if entry.file_name().to_string_lossy() == name {
+            return Some(path);
+        }
+    }
+    dirs.sort();
+
+    dirs.iter().filter_map(|d| search(d, name)).next()
+}
@@ -0,0 +1,423 @@
+use std::cmp;
+use std::ops::Range;
+
+use piston_window::character::CharacterCache;
+
+use editor_core::annotations::{self, Mark};
+use editor_core::backend::TextBuffer;
+
+use layout::{Layout, Rect};
+use metrics::TextMetrics;
+use view::View;
+
+/// What a shape stands for, so that each frontend can pick how to show it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Paint {
+    /// Behind the row with a caret, across the editor.
+    CurrentLine,
+    /// Behind the selected text of each line, along with the line break if it is selected.
+    Selection,
+    /// The part of lines past the maximum line length.
+    Overflow,
+    /// Highlighted annotations and the occurrences of the word under the cursor.
+    Highlight,
+    Underline,
+    /// The dots and arrows shown for spaces and tabs.
+    Whitespace,
+    TrailingWhitespace,
+    /// Under the bracket next to the cursor and the one matching it.
+    Bracket,
+    /// Under the link the mouse is over.
+    Link,
+    Cursor,
+    /// The outline of a line in the minimap.
+    Minimap,
+    /// The outline of a line with underlined annotations, such as errors, in the minimap.
+    MinimapMark,
+    /// Behind the lines shown, in the minimap.
+    MinimapViewport,
+    /// The gutter marks of the lines changed since the last commit.
+    Mark(Mark),
+    /// Behind the lines added or removed in a diff, across the editor.
+    Background(Mark),
+}
+
+/// A filled rectangle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub paint: Paint,
+    pub rect: Rect,
+}
+
+/// A straight line between two points, `width` wide.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub paint: Paint,
+    pub from: [f64; 2],
+    pub to: [f64; 2],
+    pub width: f64,
+}
+
+/// A piece of text, placed by the left end of its baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Run {
+    pub position: [f64; 2],
+    pub size: u32,
+    pub text: String,
+}
+
+/// Everything the editor shows of a buffer in one frame, in window coordinates. Fills go behind
+/// strokes, which go behind the text.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderPlan {
+    /// The lines of the buffer that are shown.
+    pub lines: Range<usize>,
+    pub fills: Vec<Fill>,
+    pub strokes: Vec<Stroke>,
+    /// The line numbers.
+    pub gutter: Vec<Run>,
+    pub text: Vec<Run>,
+}
+
+impl RenderPlan {
+    /// Plans the part of the buffer the view shows, measuring and caching the lines that need
+    /// it. The caret is left out while it blinks off, and another one is shown at the line and
+    /// byte text dragged with the mouse would be dropped at. The link under the mouse, given
+    /// by its line and bytes, is underlined. Panes out of focus show no cursors, nor the
+    /// highlights following them, as the cursors of the buffer are the ones of the focused
+    /// pane.
+    pub fn new<C: CharacterCache>(buf: &TextBuffer,
+                                  view: &mut View,
+                                  focused: bool,
+                                  caret_on: bool,
+                                  drop_target: Option<(usize, usize)>,
+                                  link: Option<(usize, Range<usize>)>,
+                                  layout: &Layout,
+                                  metrics: &mut TextMetrics,
+                                  cache: &mut C)
+                                  -> RenderPlan {
+        let em = layout.em();
+        let left = layout.text[0];
+        // The row cut by the bottom of the editor is shown too.
+        let visible = view.visible(layout.rows() + 1, buf.line_count());
+        let (highlights, brackets) = if focused {
+            (buf.word_highlights(visible.clone()), buf.matching_brackets())
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let number_size = layout.small_text_size();
+        let number_width = cache.character(number_size, '0').width();
+        let numbers_right = layout.gutter[0] + layout.gutter[2] - layout.padding();
+        let space = metrics.advance(cache, ' ');
+        let mut plan = RenderPlan { lines: visible.clone(), ..Default::default() };
+
+        view.forget_hidden(&visible);
+        // The first row of the line being planned, as wrapped lines take more than one.
+        let mut row = 0;
+        for i in visible {
+            if row > layout.rows() {
+                plan.lines.end = i;
+                break;
+            }
+            let shown = view.line(buf, i, metrics, cache);
+            let line_end = shown.layout.row_bytes(shown.layout.rows() - 1).end;
+            let first_row = row;
+            let fill = |plan: &mut RenderPlan, paint: Paint, bytes: Range<usize>, with_break| {
+                let spans = shown.layout.spans(bytes);
+                let count = spans.len();
+                for (j, (r, start, mut end)) in spans.into_iter().enumerate() {
+                    if with_break && j + 1 == count {
+                        end += space;
+                    }
+                    plan.fills.push(Fill {
+                        paint: paint,
+                        rect: [left + start, layout.row_top(first_row + r), end - start, em],
+                    });
+                }
+            };
+
+            let cursors: Vec<_> = buf.get_cursors()
+                .iter()
+                .filter(|cursor| focused && cursor.start_line <= i && i <= cursor.end_line)
+                .collect();
+            // Selections show their own extent, so only the rows of wrapped lines holding a
+            // caret are highlighted.
+            let mut current_rows: Vec<usize> = cursors.iter()
+                .filter(|cursor| cursor.is_atomic())
+                .map(|cursor| shown.layout.row_of(cursor.start_byte))
+                .collect();
+            current_rows.sort();
+            current_rows.dedup();
+            for r in current_rows {
+                plan.fills.push(Fill {
+                    paint: Paint::CurrentLine,
+                    rect: [layout.editor[0],
+                           layout.row_top(row + r),
+                           layout.editor[2],
+                           layout.line_height()],
+                });
+            }
+            let carets: Vec<usize> = cursors.iter()
+                .filter(|cursor| cursor.is_atomic() && caret_on)
+                .map(|cursor| cursor.start_byte)
+                .chain(drop_target.into_iter().filter(|&(line, _)| line == i).map(|(_, b)| b))
+                .collect();
+            for byte in carets {
+                let r = shown.layout.row_of(byte);
+                let x = left + shown.layout.row_offset(byte);
+                let top = layout.row_top(row + r);
+                plan.strokes.push(Stroke {
+                    paint: Paint::Cursor,
+                    from: [x, top],
+                    to: [x, top + em],
+                    width: em / 15.0,
+                });
+            }
+            for cursor in cursors {
+                if !cursor.is_atomic() {
+                    let start = if i == cursor.start_line {
+                        cursor.start_byte
+                    } else {
+                        0
+                    };
+                    let end = if i == cursor.end_line {
+                        cursor.end_byte
+                    } else {
+                        line_end
+                    };
+                    // Include the line break, so that selected empty lines show up, but leave out
+                    // the last line when the selection ends at its start.
+                    let with_break = i != cursor.end_line;
+                    if start < end || with_break {
+                        fill(&mut plan, Paint::Selection, start..end, with_break);
+                    }
+                }
+            }
+
+            if let Some(start) = buf.overflow_start(i) {
+                fill(&mut plan, Paint::Overflow, start..line_end, false);
+            }
+
+            if buf.get_options().show_whitespace {
+                if let Some(start) = buf.trailing_whitespace_start(i) {
+                    fill(&mut plan, Paint::TrailingWhitespace, start..line_end, false);
+                }
+                for (byte, c) in buf.whitespace(i) {
+                    for (r, start, end) in shown.layout.spans(byte..byte + 1) {
+                        let (start, end) = (left + start, left + end);
+                        let middle = layout.row_top(row + r) + em / 2.0;
+                        if c == '\t' {
+                            let head = em / 6.0;
+                            let tip = end - em / 10.0;
+                            for &(from, to) in &[([start + em / 10.0, middle], [tip, middle]),
+                                                 ([tip - head, middle - head], [tip, middle]),
+                                                 ([tip - head, middle + head], [tip, middle])] {
+                                plan.strokes.push(Stroke {
+                                    paint: Paint::Whitespace,
+                                    from: from,
+                                    to: to,
+                                    width: em / 20.0,
+                                });
+                            }
+                        } else {
+                            let size = em / 8.0;
+                            plan.fills.push(Fill {
+                                paint: Paint::Whitespace,
+                                rect: [(start + end - size) / 2.0, middle - size / 2.0, size, size],
+                            });
+                        }
+                    }
+                }
+            }
+
+            for annotation in buf.annotations().on_lines(i..i + 1) {
+                let start = if annotation.start.0 == i {
+                    annotation.start.1
+                } else {
+                    0
+                };
+                let end = if annotation.end.0 == i {
+                    annotation.end.1
+                } else {
+                    line_end
+                };
+                match annotation.style {
+                    annotations::Style::Hidden => {}
+                    annotations::Style::Highlight => {
+                        fill(&mut plan, Paint::Highlight, start..end, false)
+                    }
+                    // A zigzag of strokes a quarter of an em wide, going up and down.
+                    annotations::Style::Underline => {
+                        for (r, start, end) in shown.layout.spans(start..end) {
+                            let bottom = layout.row_top(row + r) + em;
+                            let mut x = left + start;
+                            let mut up = false;
+                            let rise = em / 10.0;
+                            while x < left + end {
+                                let next = (x + em / 4.0).min(left + end);
+                                let (from, to) = if up { (rise, 0.0) } else { (0.0, rise) };
+                                plan.strokes.push(Stroke {
+                                    paint: Paint::Underline,
+                                    from: [x, bottom - from],
+                                    to: [next, bottom - to],
+                                    width: em / 15.0,
+                                });
+                                x = next;
+                                up = !up;
+                            }
+                        }
+                    }
+                    // Lines that were removed are marked between the rows around them.
+                    annotations::Style::Gutter(Mark::Removed) => {
+                        plan.fills.push(Fill {
+                            paint: Paint::Mark(Mark::Removed),
+                            rect: [layout.gutter[0],
+                                   layout.row_top(row) - em / 10.0,
+                                   em / 2.0,
+                                   em / 5.0],
+                        });
+                    }
+                    annotations::Style::Background(mark) => {
+                        plan.fills.push(Fill {
+                            paint: Paint::Background(mark),
+                            rect: [layout.editor[0],
+                                   layout.row_top(row),
+                                   layout.editor[2],
+                                   shown.layout.rows() as f64 * layout.line_height()],
+                        });
+                    }
+                    // Bookmarks are next to the marks of the changes, in the middle of the row.
+                    annotations::Style::Gutter(Mark::Bookmark) => {
+                        plan.fills.push(Fill {
+                            paint: Paint::Mark(Mark::Bookmark),
+                            rect: [layout.gutter[0] + em / 4.0,
+                                   layout.row_top(row) + em / 4.0,
+                                   em / 2.0,
+                                   em / 2.0],
+                        });
+                    }
+                    annotations::Style::Gutter(mark) => {
+                        plan.fills.push(Fill {
+                            paint: Paint::Mark(mark),
+                            rect: [layout.gutter[0],
+                                   layout.row_top(row),
+                                   em / 6.0,
+                                   shown.layout.rows() as f64 * layout.line_height()],
+                        });
+                    }
+                }
+            }
+
+            for span in highlights.iter().filter(|s| s.line == i) {
+                fill(&mut plan,
+                     Paint::Highlight,
+                     span.start_byte..span.end_byte,
+                     false);
+            }
+
+            if let Some((line, ref bytes)) = link {
+                if line == i {
+                    for (r, start, end) in shown.layout.spans(bytes.clone()) {
+                        let bottom = layout.row_top(row + r) + em;
+                        plan.strokes.push(Stroke {
+                            paint: Paint::Link,
+                            from: [left + start, bottom],
+                            to: [left + end, bottom],
+                            width: em / 15.0,
+                        });
+                    }
+                }
+            }
+
+            for span in brackets.iter().filter(|s| s.line == i) {
+                for (r, start, end) in shown.layout.spans(span.start_byte..span.end_byte) {
+                    let bottom = layout.row_top(row + r) + em;
+                    plan.strokes.push(Stroke {
+                        paint: Paint::Bracket,
+                        from: [left + start, bottom],
+                        to: [left + end, bottom],
+                        width: em / 10.0,
+                    });
+                }
+            }
+
+            plan.gutter.push(Run {
+                position: [numbers_right - shown.number.len() as f64 * number_width,
+                           layout.row_baseline(row)],
+                size: number_size,
+                text: shown.number.clone(),
+            });
+            for &(r, x, ref piece) in shown.layout.pieces() {
+                plan.text.push(Run {
+                    position: [left + x, layout.row_baseline(row + r)],
+                    size: metrics.size(),
+                    text: piece.clone(),
+                });
+            }
+            row += shown.layout.rows();
+        }
+        // While scrolling is animated, the lines move up by the part of the first one that is
+        // scrolled past.
+        let scrolled = view.scrolled_fraction() * layout.line_height();
+        if scrolled > 0.0 {
+            plan.shift_up(scrolled);
+        }
+
+        plan.plan_minimap(buf, view, layout);
+        plan
+    }
+
+    fn shift_up(&mut self, by: f64) {
+        for fill in &mut self.fills {
+            fill.rect[1] -= by;
+        }
+        for stroke in &mut self.strokes {
+            stroke.from[1] -= by;
+            stroke.to[1] -= by;
+        }
+        for run in self.gutter.iter_mut().chain(self.text.iter_mut()) {
+            run.position[1] -= by;
+        }
+    }
+
+    /// Outlines the text of the lines in the minimap, which only measures the lines again once
+    /// the buffer changes.
+    fn plan_minimap(&mut self, buf: &TextBuffer, view: &mut View, layout: &Layout) {
+        let line_count = buf.line_count();
+        let first = layout.minimap_first_line(view.first_line(), line_count);
+        let lines = first..cmp::min(first + layout.minimap_lines(), line_count);
+        self.fills.push(Fill {
+            paint: Paint::MinimapViewport,
+            rect: layout.minimap_viewport(view.first_line(), line_count),
+        });
+
+        let mut marked = vec![false; lines.end - lines.start];
+        for annotation in buf.annotations().on_lines(lines.clone()) {
+            if annotation.style == annotations::Style::Underline {
+                let end = cmp::min(annotation.end.0 + 1, lines.end);
+                for line in cmp::max(annotation.start.0, lines.start)..end {
+                    marked[line - lines.start] = true;
+                }
+            }
+        }
+
+        let row_height = layout.minimap_row_height();
+        let column = layout.minimap_column_width();
+        let right = layout.minimap[0] + layout.minimap[2];
+        let extents = view.minimap_extents(buf, lines);
+        for (i, (start, end)) in extents.into_iter().enumerate() {
+            let x = layout.minimap[0] + start as f64 * column;
+            let width = ((end - start) as f64 * column).min(right - x);
+            if width > 0.0 {
+                self.fills.push(Fill {
+                    paint: if marked[i] {
+                        Paint::MinimapMark
+                    } else {
+                        Paint::Minimap
+                    },
+                    rect: [x, layout.minimap[1] + i as f64 * row_height, width, row_height * 0.7],
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use piston_window::{Button, Input, Key};
+
+/// The keys that act again while they are held.
+const REPEATED: [Key; 10] = [Key::Up,
+                             Key::Down,
+                             Key::Left,
+                             Key::Right,
+                             Key::PageUp,
+                             Key::PageDown,
+                             Key::Backspace,
+                             Key::Delete,
+                             Key::Return,
+                             Key::Tab];
+
+/// Presses the navigation or editing key being held again, first after a delay and then at a
+/// steady rate, as the window only tells when keys go down and up.
+pub struct KeyRepeat {
+    /// In nanoseconds.
+    delay: u64,
+    interval: Option<u64>,
+    /// The key held, and when it is next pressed again.
+    held: Option<(Key, u64)>,
+}
+
+impl KeyRepeat {
+    /// Repeats keys held for `delay` milliseconds `rate` times a second, or never if `rate` is 0.
+    pub fn new(delay: u64, rate: u64) -> KeyRepeat {
+        KeyRepeat {
+            delay: delay * 1_000_000,
+            interval: if rate == 0 { None } else { Some(1_000_000_000 / rate) },
+            held: None,
+        }
+    }
+
+    /// Follows the keys going down and up, `now` being the time in nanoseconds. Returns `false`
+    /// for presses of the key already held, which some systems repeat on their own, as
+    /// `update()` takes care of them instead.
+    pub fn input(&mut self, input: &Input, now: u64) -> bool {
+        match *input {
+            Input::Press(Button::Keyboard(key)) => {
+                if self.held.map_or(false, |(held, _)| held == key) {
+                    return false;
+                }
+                self.held = if self.interval.is_some() && REPEATED.contains(&key) {
+                    Some((key, now + self.delay))
+                } else {
+                    None
+                };
+            }
+            Input::Release(Button::Keyboard(key)) => {
+                if self.held.map_or(false, |(held, _)| held == key) {
+                    self.held = None;
+                }
+            }
+            // Keys released while the window is out of focus never tell.
+            Input::Focus(false) => self.held = None,
+            _ => {}
+        }
+        true
+    }
+
+    /// The key to press again, if it is time to.
+    pub fn update(&mut self, now: u64) -> Option<Key> {
+        match (self.held, self.interval) {
+            (Some((key, next)), Some(interval)) if now >= next => {
+                // Frames that took long do not make up for the repeats they missed.
+                self.held = Some((key, now + interval));
+                Some(key)
+            }
+            _ => None,
+        }
+    }
+}
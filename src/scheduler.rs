@@ -0,0 +1,97 @@
+use std::cmp;
+use std::collections::VecDeque;
+
+use piston_window::character::CharacterCache;
+use time;
+
+use editor_core::backend::TextBuffer;
+
+use buffers::Buffers;
+use metrics::TextMetrics;
+use view::View;
+
+/// Work done in small steps between frames, so that it never holds up drawing or typing.
+pub trait Task<C: CharacterCache> {
+    /// Does a small piece of the work, returning `true` once all of it is done.
+    fn step(&mut self, buffers: &mut Buffers, metrics: &mut TextMetrics, cache: &mut C) -> bool;
+}
+
+/// Runs the queued tasks a step at a time, taking turns, for as long as a frame has time left.
+pub struct Scheduler<C: CharacterCache> {
+    tasks: VecDeque<Box<Task<C>>>,
+}
+
+impl<C: CharacterCache> Scheduler<C> {
+    pub fn new() -> Scheduler<C> {
+        Scheduler { tasks: VecDeque::new() }
+    }
+
+    pub fn add(&mut self, task: Box<Task<C>>) {
+        self.tasks.push_back(task);
+    }
+
+    /// Runs steps until the budget, in nanoseconds, is spent or there is nothing left to do.
+    pub fn run(&mut self,
+               budget: u64,
+               buffers: &mut Buffers,
+               metrics: &mut TextMetrics,
+               cache: &mut C) {
+        let started = time::precise_time_ns();
+        while time::precise_time_ns() - started < budget {
+            let mut task = match self.tasks.pop_front() {
+                Some(task) => task,
+                None => return,
+            };
+            if !task.step(buffers, metrics, cache) {
+                self.tasks.push_back(task);
+            }
+        }
+    }
+}
+
+/// Measures the lines a screen above and below the ones shown, so that scrolling to them does
+/// not have to. It gives up once the buffer is edited or scrolled, as the lines are then
+/// measured again or not near the screen any more.
+pub struct LayoutWarmup {
+    buffer: usize,
+    version: usize,
+    first_line: usize,
+    lines: Vec<usize>,
+}
+
+impl LayoutWarmup {
+    /// Warms up the lines around the ones shown of the buffer at the given index, `rows` being
+    /// how many fit on the screen.
+    pub fn new(buffer: usize, buf: &TextBuffer, view: &View, rows: usize) -> LayoutWarmup {
+        let visible = view.visible(rows, buf.line_count());
+        let below = visible.end..cmp::min(visible.end + rows, buf.line_count());
+        let above = visible.start.saturating_sub(rows)..visible.start;
+        // Popped from the end, so the lines right below the screen go first.
+        let lines = above.chain(below.rev()).collect();
+        LayoutWarmup {
+            buffer: buffer,
+            version: buf.version(),
+            first_line: view.first_line(),
+            lines: lines,
+        }
+    }
+}
+
+impl<C: CharacterCache> Task<C> for LayoutWarmup {
+    fn step(&mut self, buffers: &mut Buffers, metrics: &mut TextMetrics, cache: &mut C) -> bool {
+        if self.buffer >= buffers.len() {
+            return true;
+        }
+        let (buf, view) = buffers.get_with_view(self.buffer);
+        if buf.version() != self.version || view.first_line() != self.first_line {
+            return true;
+        }
+        match self.lines.pop() {
+            Some(line) => {
+                let _ = view.line(buf, line, metrics, cache);
+                self.lines.is_empty()
+            }
+            None => true,
+        }
+    }
+}
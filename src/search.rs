@@ -0,0 +1,206 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use git2::Repository;
+
+/// Number of threads reading and searching the files.
+const WORKERS: usize = 4;
+
+/// The lines of a file matching a search.
+#[derive(Clone, Debug)]
+struct FileMatches {
+    path: PathBuf,
+    /// The path relative to the searched directory, as shown in the results.
+    name: String,
+    /// The matching lines, counted from 0, along with their text.
+    lines: Vec<(usize, String)>,
+}
+
+/// A search for the lines containing some text in the files of a project, shown in a buffer
+/// grouped by file as the results come in. Hidden files, the ones git ignores and the ones that
+/// are not UTF-8 text are skipped.
+#[derive(Debug)]
+pub struct Results {
+    receiver: Option<Receiver<FileMatches>>,
+    cancelled: Arc<AtomicBool>,
+    /// The file and line each line of the buffer leads to, if any.
+    targets: Vec<Option<(PathBuf, usize)>>,
+    files: usize,
+    matches: usize,
+}
+
+impl Results {
+    /// Starts searching the files under `root` for `pattern` on a pool of threads.
+    pub fn new(root: PathBuf, pattern: &str) -> Results {
+        let (paths, queue) = mpsc::channel();
+        let (sender, receiver) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let walk_cancelled = cancelled.clone();
+        let _ = thread::spawn(move || {
+            let repo = Repository::discover(&root).ok();
+            walk(&root, &root, repo.as_ref(), &paths, &walk_cancelled);
+        });
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..WORKERS {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let cancelled = cancelled.clone();
+            let pattern = String::from(pattern);
+            let _ = thread::spawn(move || {
+                loop {
+                    // The lock is let go once a file is taken, for the others to take the next.
+                    let next = queue.lock().unwrap().recv();
+                    let (path, name) = match next {
+                        Ok(next) => next,
+                        Err(_) => return,
+                    };
+                    if cancelled.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    if let Some(found) = search_file(path, name, &pattern) {
+                        let _ = sender.send(found);
+                    }
+                }
+            });
+        }
+
+        Results {
+            receiver: Some(receiver),
+            cancelled: cancelled,
+            targets: vec![None, None],
+            files: 0,
+            matches: 0,
+        }
+    }
+
+    /// The first lines of the buffer, before any result comes in.
+    pub fn header(pattern: &str) -> Vec<String> {
+        vec![format!("Searching for \"{}\"", pattern), String::new()]
+    }
+
+    /// The lines for the files found since last time, a heading with the file name followed by
+    /// its matching lines, and the number of matches once the search is over.
+    pub fn receive(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut over = false;
+        if let Some(ref receiver) = self.receiver {
+            loop {
+                match receiver.try_recv() {
+                    Ok(found) => {
+                        lines.push(found.name);
+                        self.targets.push(Some((found.path.clone(), 0)));
+                        for (line, text) in found.lines {
+                            lines.push(format!("  {}: {}", line + 1, text));
+                            self.targets.push(Some((found.path.clone(), line)));
+                            self.matches += 1;
+                        }
+                        lines.push(String::new());
+                        self.targets.push(None);
+                        self.files += 1;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        over = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if over {
+            self.receiver = None;
+            lines.push(format!("{} matches in {} files", self.matches, self.files));
+            self.targets.push(None);
+        }
+        lines
+    }
+
+    /// The file and line the given line of the buffer leads to, if any.
+    pub fn target(&self, line: usize) -> Option<(&Path, usize)> {
+        match self.targets.get(line) {
+            Some(&Some((ref path, line))) => Some((path.as_path(), line)),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Results {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Sends every regular file under `dir` that is not hidden nor ignored by git, along with its
+/// path relative to `root`, until it is cancelled.
+pub fn walk(root: &Path,
+        dir: &Path,
+        repo: Option<&Repository>,
+        paths: &Sender<(PathBuf, String)>,
+        cancelled: &AtomicBool) {
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => {
+            entries.filter_map(|e| e.ok())
+                .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+                .map(|e| e.path())
+                .collect()
+        }
+        Err(_) => return,
+    };
+    entries.sort();
+
+    for path in entries {
+        if cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        if repo.map_or(false, |r| is_ignored(r, &path)) {
+            continue;
+        }
+        // Links to folders are not followed, as they can lead back up the tree, and only regular
+        // files are sent, as opening a pipe waits until something writes to it.
+        let file_type = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.file_type(),
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            walk(root, &path, repo, paths, cancelled);
+        } else if path.is_file() {
+            let name = path.strip_prefix(root).unwrap_or(path.as_path()).display().to_string();
+            let _ = paths.send((path, name));
+        }
+    }
+}
+
+/// Whether git ignores the path, which has to be in the working directory of the repository.
+fn is_ignored(repo: &Repository, path: &Path) -> bool {
+    let path = fs::canonicalize(path).unwrap_or(path.to_path_buf());
+    match repo.workdir().and_then(|w| path.strip_prefix(w).ok()) {
+        Some(relative) => repo.status_should_ignore(relative).unwrap_or(false),
+        None => false,
+    }
+}
+
+fn search_file(path: PathBuf, name: String, pattern: &str) -> Option<FileMatches> {
+    let mut contents = String::new();
+    if File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)).is_err() {
+        return None;
+    }
+    let lines: Vec<(usize, String)> = contents.lines()
+        .enumerate()
+        .filter(|&(_, line)| line.contains(pattern))
+        .map(|(i, line)| (i, String::from(line)))
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(FileMatches {
+            path: path,
+            name: name,
+            lines: lines,
+        })
+    }
+}
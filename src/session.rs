@@ -0,0 +1,191 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use toml::{Parser, Table, Value};
+
+use editor_core::backend::TextBuffer;
+
+use bookmarks;
+use dirs;
+use view::View;
+use super::{Error, Result};
+
+/// Where the session of the current directory is kept: in the `sessions` directory of the data
+/// directory of the user, named after the current directory so that each project has its own.
+pub fn file() -> Option<PathBuf> {
+    let current = match env::current_dir() {
+        Ok(current) => current,
+        Err(_) => return None,
+    };
+    dirs::data_dir()
+        .map(|data| data.join("sessions").join(format!("{}.toml", dirs::flatten(&current))))
+}
+
+/// The open files and the size of the window, written on exit to pick up from there on the next
+/// start.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub width: u32,
+    pub height: u32,
+    /// Index in `buffers` of the buffer that was active.
+    pub active: usize,
+    pub buffers: Vec<BufferState>,
+}
+
+/// Where a file was left.
+#[derive(Clone, Debug)]
+pub struct BufferState {
+    pub path: PathBuf,
+    pub first_line: usize,
+    /// The start and end of every cursor, as lines and characters.
+    pub cursors: Vec<((usize, usize), (usize, usize))>,
+    /// The bookmarked lines.
+    pub bookmarks: Vec<usize>,
+}
+
+impl BufferState {
+    /// The state of a buffer and the view showing it, if it is backed by a file.
+    pub fn capture(buf: &TextBuffer, view: &View) -> Option<BufferState> {
+        buf.get_path().map(|path| {
+            BufferState {
+                path: path.to_path_buf(),
+                first_line: view.first_line(),
+                cursors: buf.get_cursors()
+                    .iter()
+                    .map(|c| ((c.start_line, c.start_character), (c.end_line, c.end_character)))
+                    .collect(),
+                bookmarks: bookmarks::lines(buf),
+            }
+        })
+    }
+
+    /// Puts the cursors and the view back where they were, as far as the file still allows it.
+    pub fn restore(&self, buf: &mut TextBuffer, view: &mut View) {
+        let cursors: Vec<_> = self.cursors
+            .iter()
+            .map(|&(start, end)| buf.cursor_between(start, end))
+            .collect();
+        if !cursors.is_empty() {
+            buf.set_cursors(cursors);
+        }
+        bookmarks::set_lines(buf, &self.bookmarks);
+        view.scroll_to(cmp::min(self.first_line, buf.line_count() - 1),
+                       buf.line_count());
+    }
+
+    fn to_value(&self) -> Value {
+        let mut table = BTreeMap::new();
+        let _ = table.insert(String::from("path"),
+                             Value::String(self.path.to_string_lossy().into_owned()));
+        let _ = table.insert(String::from("first_line"),
+                             Value::Integer(self.first_line as i64));
+        let cursors = self.cursors
+            .iter()
+            .map(|&((start_line, start), (end_line, end))| {
+                Value::Array([start_line, start, end_line, end]
+                    .iter()
+                    .map(|&n| Value::Integer(n as i64))
+                    .collect())
+            })
+            .collect();
+        let _ = table.insert(String::from("cursors"), Value::Array(cursors));
+        let bookmarks = self.bookmarks.iter().map(|&line| Value::Integer(line as i64)).collect();
+        let _ = table.insert(String::from("bookmarks"), Value::Array(bookmarks));
+        Value::Table(table)
+    }
+
+    fn from_value(value: &Value) -> Option<BufferState> {
+        let table = match value.as_table() {
+            Some(t) => t,
+            None => return None,
+        };
+        let path = match table.get("path").and_then(Value::as_str) {
+            Some(path) => PathBuf::from(path),
+            None => return None,
+        };
+        let first_line = table.get("first_line").and_then(count).unwrap_or(0);
+        let mut cursors = Vec::new();
+        for cursor in table.get("cursors").and_then(Value::as_slice).unwrap_or(&[]) {
+            let numbers: Option<Vec<usize>> =
+                cursor.as_slice().and_then(|c| c.iter().map(count).collect());
+            match numbers {
+                Some(ref n) if n.len() == 4 => cursors.push(((n[0], n[1]), (n[2], n[3]))),
+                _ => return None,
+            }
+        }
+        // Sessions written before there were bookmarks have none.
+        let bookmarks = match table.get("bookmarks") {
+            Some(bookmarks) => {
+                let lines: Option<Vec<usize>> =
+                    bookmarks.as_slice().and_then(|b| b.iter().map(count).collect());
+                match lines {
+                    Some(lines) => lines,
+                    None => return None,
+                }
+            }
+            None => Vec::new(),
+        };
+
+        Some(BufferState {
+            path: path,
+            first_line: first_line,
+            cursors: cursors,
+            bookmarks: bookmarks,
+        })
+    }
+}
+
+impl Session {
+    /// Reads the session from the given file, if there is one.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Session>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut contents = String::new();
+        let _ = try!(try!(File::open(path)).read_to_string(&mut contents));
+        let table = match Parser::new(&contents).parse() {
+            Some(t) => t,
+            None => return Err(invalid()),
+        };
+        let number = |table: &Table, key: &str| {
+            table.get(key).and_then(count).ok_or_else(invalid)
+        };
+        let mut buffers = Vec::new();
+        for buffer in table.get("buffers").and_then(Value::as_slice).unwrap_or(&[]) {
+            buffers.push(try!(BufferState::from_value(buffer).ok_or_else(invalid)));
+        }
+
+        Ok(Some(Session {
+            width: try!(number(&table, "width")) as u32,
+            height: try!(number(&table, "height")) as u32,
+            active: try!(number(&table, "active")),
+            buffers: buffers,
+        }))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut table = BTreeMap::new();
+        let _ = table.insert(String::from("width"), Value::Integer(self.width as i64));
+        let _ = table.insert(String::from("height"), Value::Integer(self.height as i64));
+        let _ = table.insert(String::from("active"), Value::Integer(self.active as i64));
+        let buffers = self.buffers.iter().map(BufferState::to_value).collect();
+        let _ = table.insert(String::from("buffers"), Value::Array(buffers));
+        try!(dirs::write_atomically(path, Value::Table(table).to_string().as_bytes()));
+
+        Ok(())
+    }
+}
+
+fn count(value: &Value) -> Option<usize> {
+    value.as_integer().and_then(|n| if n >= 0 { Some(n as usize) } else { None })
+}
+
+fn invalid() -> Error {
+    Error::InvalidConfig(String::from("invalid session file"))
+}
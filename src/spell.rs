@@ -0,0 +1,549 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use editor_core::annotations::{Annotation, Style};
+use editor_core::backend::{Options, TextBuffer, TextSnapshot};
+
+use buffers::Buffers;
+use dirs;
+use super::Result;
+
+/// Who the underlines of the misspelled words belong to.
+pub const OWNER: &'static str = "spell";
+
+/// Where the words added to the dictionary by the user are kept, one per line: `dictionary.txt`
+/// in the data directory of the user, so that they are the same in every project.
+fn user_dictionary_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|data| data.join("dictionary.txt"))
+}
+
+/// Most suggestions offered for a misspelled word.
+const MAX_SUGGESTIONS: usize = 8;
+
+/// The words of a language, read from a Hunspell dictionary: the `.dic` file listing the stems
+/// and the flags of their affixes, and the `.aff` file with the prefix and suffix rules of the
+/// flags, which are applied when it is read.
+#[derive(Clone, Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Reads the `.dic` and `.aff` files of the language from the directory. The affix file is
+    /// optional.
+    pub fn load(dir: &Path, language: &str) -> Result<Dictionary> {
+        let affixes = match read(&dir.join(format!("{}.aff", language))) {
+            Ok(contents) => Affixes::parse(&contents),
+            Err(_) => Affixes::default(),
+        };
+        let contents = try!(read(&dir.join(format!("{}.dic", language))));
+        let mut words = HashSet::new();
+        // The first line is the number of words.
+        for entry in contents.lines().skip(1) {
+            let entry = entry.split_whitespace().next().unwrap_or("");
+            let mut parts = entry.splitn(2, '/');
+            let stem = parts.next().unwrap_or("");
+            if stem.is_empty() {
+                continue;
+            }
+            let flags: Vec<char> = parts.next().unwrap_or("").chars().collect();
+            affixes.expand(stem, &flags, &mut words);
+        }
+        Ok(Dictionary { words: words })
+    }
+
+    /// Whether the word is in the dictionary as it is written, or in lower case when it is
+    /// capitalized or all in capitals.
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(word) || {
+            let lower = word.to_lowercase();
+            lower != word && self.words.contains(&lower)
+        }
+    }
+
+    /// The words one change away from the given one, such as a missing, an extra, a swapped or
+    /// a wrong letter, or a missing space.
+    pub fn suggestions(&self, word: &str) -> Vec<String> {
+        let capitalized = word.chars().next().map_or(false, char::is_uppercase);
+        let lower = word.to_lowercase();
+        let chars: Vec<char> = lower.chars().collect();
+        let mut alphabet: Vec<char> = (b'a'..b'z' + 1).map(|b| b as char).collect();
+        alphabet.extend(chars.iter().filter(|&&c| c as u32 > 0x7f));
+
+        let mut candidates = Vec::new();
+        for i in 0..chars.len() + 1 {
+            let (before, after) = chars.split_at(i);
+            let text = |middle: &[char], rest: &[char]| -> String {
+                before.iter().chain(middle).chain(rest).cloned().collect()
+            };
+            if !after.is_empty() {
+                candidates.push(text(&[], &after[1..]));
+            }
+            if after.len() > 1 {
+                candidates.push(text(&[after[1], after[0]], &after[2..]));
+            }
+            for &c in &alphabet {
+                if !after.is_empty() && after[0] != c {
+                    candidates.push(text(&[c], &after[1..]));
+                }
+                candidates.push(text(&[c], after));
+            }
+            if i > 0 && !after.is_empty() {
+                candidates.push(text(&[' '], after));
+            }
+        }
+
+        let mut suggestions: Vec<String> = Vec::new();
+        for candidate in candidates {
+            let known = candidate.split(' ').all(|w| self.contains(w));
+            if known && !suggestions.contains(&candidate) {
+                suggestions.push(candidate);
+                if suggestions.len() == MAX_SUGGESTIONS {
+                    break;
+                }
+            }
+        }
+        if capitalized {
+            for suggestion in &mut suggestions {
+                *suggestion = capitalize(suggestion);
+            }
+        }
+        suggestions
+    }
+}
+
+/// A rule of the affix file, which adds a prefix or a suffix to the stems it applies to.
+#[derive(Clone, Debug)]
+struct Rule {
+    flag: char,
+    /// Whether it can be combined with a rule of the other kind.
+    cross: bool,
+    /// What is taken off the stem before adding the affix.
+    strip: String,
+    affix: String,
+    /// What the start of the stem, for prefixes, or its end, for suffixes, has to look like.
+    condition: Vec<CharSet>,
+}
+
+/// A character of an affix condition: `.`, a letter or a set like `[aeiou]` or `[^aeiou]`.
+#[derive(Clone, Debug)]
+enum CharSet {
+    Any,
+    Of(Vec<char>, bool),
+}
+
+impl CharSet {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            CharSet::Any => true,
+            CharSet::Of(ref chars, negated) => chars.contains(&c) != negated,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Affixes {
+    prefixes: Vec<Rule>,
+    suffixes: Vec<Rule>,
+}
+
+impl Affixes {
+    /// Reads the `PFX` and `SFX` rules of an affix file, ignoring everything else in it.
+    fn parse(contents: &str) -> Affixes {
+        let mut affixes = Affixes::default();
+        // Whether each flag can be combined, from the header line of its rules.
+        let mut cross = HashMap::new();
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 4 || (fields[0] != "PFX" && fields[0] != "SFX") {
+                continue;
+            }
+            let flag = match fields[1].chars().next() {
+                Some(flag) => flag,
+                None => continue,
+            };
+            if fields.len() == 4 {
+                let _ = cross.insert((fields[0], flag), fields[2] == "Y");
+                continue;
+            }
+            let empty = |field: &str| if field == "0" {
+                String::new()
+            } else {
+                String::from(field)
+            };
+            let rule = Rule {
+                flag: flag,
+                cross: cross.get(&(fields[0], flag)).cloned().unwrap_or(false),
+                strip: empty(fields[2]),
+                affix: empty(fields[3].split('/').next().unwrap_or("")),
+                condition: parse_condition(fields[4]),
+            };
+            if fields[0] == "PFX" {
+                affixes.prefixes.push(rule);
+            } else {
+                affixes.suffixes.push(rule);
+            }
+        }
+        affixes
+    }
+
+    /// Adds the stem and every word its flags make of it to `words`.
+    fn expand(&self, stem: &str, flags: &[char], words: &mut HashSet<String>) {
+        let _ = words.insert(String::from(stem));
+        let mut crossed = Vec::new();
+        for rule in self.suffixes.iter().filter(|r| flags.contains(&r.flag)) {
+            let chars: Vec<char> = stem.chars().collect();
+            if stem.ends_with(rule.strip.as_str()) && rule.condition.len() <= chars.len() &&
+               rule.condition
+                .iter()
+                .zip(&chars[chars.len() - rule.condition.len()..])
+                .all(|(set, &c)| set.matches(c)) {
+                let word = format!("{}{}", &stem[..stem.len() - rule.strip.len()], rule.affix);
+                if rule.cross {
+                    crossed.push(word.clone());
+                }
+                let _ = words.insert(word);
+            }
+        }
+        for rule in self.prefixes.iter().filter(|r| flags.contains(&r.flag)) {
+            let chars: Vec<char> = stem.chars().collect();
+            if stem.starts_with(rule.strip.as_str()) && rule.condition.len() <= chars.len() &&
+               rule.condition.iter().zip(&chars).all(|(set, &c)| set.matches(c)) {
+                let _ = words.insert(format!("{}{}", rule.affix, &stem[rule.strip.len()..]));
+                if rule.cross {
+                    for word in crossed.iter().filter(|w| w.starts_with(rule.strip.as_str())) {
+                        let word = &word[rule.strip.len()..];
+                        let _ = words.insert(format!("{}{}", rule.affix, word));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn parse_condition(condition: &str) -> Vec<CharSet> {
+    let mut sets = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => sets.push(CharSet::Any),
+            '[' => {
+                let mut set = Vec::new();
+                let mut negated = false;
+                for c in &mut chars {
+                    match c {
+                        ']' => break,
+                        '^' if set.is_empty() && !negated => negated = true,
+                        c => set.push(c),
+                    }
+                }
+                sets.push(CharSet::Of(set, negated));
+            }
+            c => sets.push(CharSet::Of(vec![c], false)),
+        }
+    }
+    sets
+}
+
+/// A word that is not in the dictionary, where it is in its buffer and what it could be
+/// instead.
+#[derive(Clone, Debug)]
+pub struct Misspelling {
+    pub line: usize,
+    /// The byte range of the word in its line.
+    pub start: usize,
+    pub end: usize,
+    pub word: String,
+    pub suggestions: Vec<String>,
+}
+
+/// Underlines the words that are not in the dictionary of the language of each buffer, in the
+/// comments of code, or everywhere in prose files. The buffers are checked on other threads as
+/// they change.
+#[derive(Debug)]
+pub struct SpellChecker {
+    /// Where the dictionaries are, as `<language>.dic` and `<language>.aff`.
+    dir: PathBuf,
+    /// The dictionary of each language, or `None` if it could not be read.
+    dictionaries: HashMap<String, Option<Arc<Dictionary>>>,
+    /// The words added by the user, which are right in every language.
+    user: Arc<HashSet<String>>,
+    /// The version and language each buffer was last checked at, by its index.
+    checked: HashMap<usize, (usize, String)>,
+    /// The checks running, with the index and version of their buffer.
+    running: Vec<(usize, usize, Receiver<Vec<Annotation>>)>,
+}
+
+impl SpellChecker {
+    pub fn new(dir: PathBuf) -> SpellChecker {
+        let user = user_dictionary_file()
+            .and_then(|file| read(&file).ok())
+            .map(|contents| contents.lines().map(String::from).collect())
+            .unwrap_or_else(HashSet::new);
+        SpellChecker {
+            dir: dir,
+            dictionaries: HashMap::new(),
+            user: Arc::new(user),
+            checked: HashMap::new(),
+            running: Vec::new(),
+        }
+    }
+
+    /// Starts checking the buffers that changed since they were last checked, and underlines
+    /// the words of the checks that are over. Returns whether any underline changed.
+    pub fn update(&mut self, buffers: &mut Buffers) -> bool {
+        let mut changed = false;
+        let mut running = Vec::new();
+        for (index, version, receiver) in self.running.drain(..) {
+            match receiver.try_recv() {
+                Ok(underlines) => {
+                    let buf = buffers.get_mut(index);
+                    // Underlines found for an older version are out of place.
+                    if buf.version() == version {
+                        buf.annotations_mut().set(OWNER, underlines);
+                        changed = true;
+                    } else {
+                        let _ = self.checked.remove(&index);
+                    }
+                }
+                Err(TryRecvError::Empty) => running.push((index, version, receiver)),
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        self.running = running;
+
+        for index in 0..buffers.len() {
+            let (version, language) = {
+                let buf = buffers.get(index);
+                let options = buf.get_options();
+                if !buf.is_loaded() || options.large_file {
+                    continue;
+                }
+                (buf.version(), options.spell_language.clone())
+            };
+            let up_to_date = self.checked.get(&index) == Some(&(version, language.clone()));
+            if up_to_date || self.running.iter().any(|&(i, _, _)| i == index) {
+                continue;
+            }
+            let _ = self.checked.insert(index, (version, language.clone()));
+
+            let dictionary = match self.dictionary(&language) {
+                Some(dictionary) => dictionary,
+                None => {
+                    // Buffers whose language was turned off lose their underlines.
+                    let buf = buffers.get_mut(index);
+                    let underlined = buf.annotations()
+                        .on_lines(0..buf.line_count())
+                        .iter()
+                        .any(|a| a.owner == OWNER);
+                    if underlined {
+                        buf.annotations_mut().remove(OWNER);
+                        changed = true;
+                    }
+                    continue;
+                }
+            };
+            let buf = buffers.get(index);
+            let snapshot = buf.text_snapshot();
+            let options = buf.get_options().clone();
+            let user = self.user.clone();
+            let (sender, receiver) = mpsc::channel();
+            let _ = thread::spawn(move || {
+                let _ = sender.send(misspellings(&snapshot, &options, &dictionary, &user));
+            });
+            self.running.push((index, version, receiver));
+        }
+        changed
+    }
+
+    /// The misspelled word at the position of the buffer, if any, with the words it could be.
+    pub fn misspelling_at(&mut self, buf: &TextBuffer, position: (usize, usize))
+                          -> Option<Misspelling> {
+        let (line, start, end) = match buf.annotations()
+            .on_lines(position.0..position.0 + 1)
+            .into_iter()
+            .find(|a| a.owner == OWNER && a.start <= position && position <= a.end) {
+            Some(annotation) => (annotation.start.0, annotation.start.1, annotation.end.1),
+            None => return None,
+        };
+        let word = match buf.lines().nth(line) {
+            Some(text) => String::from(&text[start..end]),
+            None => return None,
+        };
+        let language = buf.get_options().spell_language.clone();
+        let suggestions = self.dictionary(&language).map_or(Vec::new(), |d| d.suggestions(&word));
+        Some(Misspelling {
+            line: line,
+            start: start,
+            end: end,
+            word: word,
+            suggestions: suggestions,
+        })
+    }
+
+    /// Adds the word to the user dictionary and checks every buffer again.
+    pub fn add_word(&mut self, word: &str) -> Result<()> {
+        let path = match user_dictionary_file() {
+            Some(path) => path,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "no data directory").into())
+            }
+        };
+        if let Some(parent) = path.parent() {
+            try!(fs::create_dir_all(parent));
+        }
+        let mut file = try!(OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path));
+        try!(writeln!(file, "{}", word));
+        let _ = Arc::make_mut(&mut self.user).insert(String::from(word));
+        self.checked.clear();
+        Ok(())
+    }
+
+    /// The languages there is a dictionary for.
+    pub fn languages(&self) -> Vec<String> {
+        let mut languages: Vec<String> = match fs::read_dir(&self.dir) {
+            Ok(entries) => {
+                entries.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().map_or(false, |e| e == "dic"))
+                    .filter_map(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                    .collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        languages.sort();
+        languages
+    }
+
+    /// The dictionary of the language, read the first time it is needed.
+    fn dictionary(&mut self, language: &str) -> Option<Arc<Dictionary>> {
+        if language.is_empty() {
+            return None;
+        }
+        if !self.dictionaries.contains_key(language) {
+            let dictionary = Dictionary::load(&self.dir, language).ok().map(Arc::new);
+            let _ = self.dictionaries.insert(String::from(language), dictionary);
+        }
+        self.dictionaries[language].clone()
+    }
+}
+
+/// The underlines of the words of the text that are in neither dictionary.
+fn misspellings(text: &TextSnapshot,
+                options: &Options,
+                dictionary: &Dictionary,
+                user: &HashSet<String>)
+                -> Vec<Annotation> {
+    let mut underlines = Vec::new();
+    let mut in_block = false;
+    for (i, line) in text.lines().enumerate() {
+        for (start, end) in checked_ranges(line, options, &mut in_block) {
+            for (word_start, word) in words(&line[start..end]) {
+                if !dictionary.contains(word) && !user.contains(word) {
+                    let byte = start + word_start;
+                    let mut underline =
+                        Annotation::new(OWNER, (i, byte), (i, byte + word.len()), Style::Underline);
+                    underline.data = format!("\"{}\" is not in the dictionary", word);
+                    underlines.push(underline);
+                }
+            }
+        }
+    }
+    underlines
+}
+
+/// The byte ranges of the line whose words are checked: all of it in prose files, and the
+/// comments elsewhere. `in_block` tells whether a block comment is open at the start of the
+/// line, and is updated for the next one.
+fn checked_ranges(line: &str, options: &Options, in_block: &mut bool) -> Vec<(usize, usize)> {
+    if options.prose {
+        return vec![(0, line.len())];
+    }
+    let mut ranges = Vec::new();
+    let mut byte = 0;
+    while byte < line.len() {
+        let rest = &line[byte..];
+        if *in_block {
+            let close = options.block_comment.as_ref().map(|&(_, _, ref close)| close.as_str());
+            match close.and_then(|close| rest.find(close).map(|i| (i, close.len()))) {
+                Some((i, length)) => {
+                    ranges.push((byte, byte + i));
+                    byte += i + length;
+                    *in_block = false;
+                }
+                None => {
+                    ranges.push((byte, line.len()));
+                    break;
+                }
+            }
+        } else {
+            let line_comment = options.line_comments
+                .iter()
+                .filter_map(|marker| rest.find(marker.as_str()).map(|i| (i, marker.len())))
+                .min();
+            let block = options.block_comment
+                .as_ref()
+                .and_then(|&(ref open, _, _)| rest.find(open.as_str()).map(|i| (i, open.len())));
+            match (line_comment, block) {
+                (Some((i, length)), block) if block.map_or(true, |(b, _)| i <= b) => {
+                    ranges.push((byte + i + length, line.len()));
+                    break;
+                }
+                (_, Some((i, length))) => {
+                    byte += i + length;
+                    *in_block = true;
+                }
+                _ => break,
+            }
+        }
+    }
+    ranges
+}
+
+/// The words of the text with the byte they start at, leaving out the ones with digits or
+/// underscores and the ones with capitals past their first letter, which are names in code
+/// rather than words.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(Some((text.len(), ' '))) {
+        let in_word = c.is_alphanumeric() || c == '_' || c == '\'';
+        match (start, in_word) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                let word = text[s..i].trim_matches('\'');
+                let offset = s + text[s..i].find(word).unwrap_or(0);
+                let name = word.chars().any(|c| c.is_numeric() || c == '_') ||
+                           word.chars().skip(1).any(char::is_uppercase);
+                if word.chars().count() > 1 && !name {
+                    words.push((offset, word));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    let mut contents = Vec::new();
+    let _ = try!(try!(File::open(path)).read_to_end(&mut contents));
+    Ok(String::from_utf8_lossy(&contents).into_owned())
+}
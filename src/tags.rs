@@ -0,0 +1,131 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use super::Result;
+
+pub const TAGS_FILE: &'static str = "tags";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Address {
+    Line(usize),
+    Pattern(String),
+}
+
+/// A symbol from a ctags `tags` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    pub path: PathBuf,
+    pub address: Address,
+}
+
+impl Tag {
+    /// Finds the line the tag points to, counting from 0.
+    pub fn line<'a, I: Iterator<Item = &'a String>>(&self, lines: I) -> Option<usize> {
+        match self.address {
+            Address::Line(n) => Some(if n == 0 { 0 } else { n - 1 }),
+            Address::Pattern(ref pattern) => {
+                let from_start = pattern.starts_with('^');
+                let to_end = pattern.ends_with('$') && !pattern.ends_with("\\$");
+                let pattern = &pattern[if from_start { 1 } else { 0 }..pattern.len() -
+                                                                       if to_end { 1 } else { 0 }];
+                for (i, line) in lines.enumerate() {
+                    let line = line.trim_right_matches('\n');
+                    let found = match (from_start, to_end) {
+                        (true, true) => line == pattern,
+                        (true, false) => line.starts_with(pattern),
+                        (false, true) => line.ends_with(pattern),
+                        (false, false) => line.contains(pattern),
+                    };
+                    if found {
+                        return Some(i);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// Reads every tag of a ctags file. Paths in it are taken as relative to the file's directory,
+/// and lines that are not UTF-8 are skipped.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Tag>> {
+    let path = path.as_ref();
+    let base = path.parent().unwrap_or(Path::new(""));
+    let reader = BufReader::new(try!(File::open(path)));
+
+    let mut tags = Vec::new();
+    for line in reader.split(b'\n') {
+        let line = match String::from_utf8(try!(line)) {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        let line = line.trim_right_matches('\r');
+        if line.starts_with("!_TAG_") {
+            continue;
+        }
+        if let Some(tag) = parse_line(line, base) {
+            tags.push(tag);
+        }
+    }
+
+    Ok(tags)
+}
+
+fn parse_line(line: &str, base: &Path) -> Option<Tag> {
+    let mut fields = line.splitn(3, '\t');
+    let name = match fields.next() {
+        Some(n) if !n.is_empty() => n,
+        _ => return None,
+    };
+    let file = match fields.next() {
+        Some(f) => f,
+        None => return None,
+    };
+    let address = match fields.next() {
+        Some(a) => a,
+        None => return None,
+    };
+
+    let address = match address.chars().next() {
+        Some(delimiter) if delimiter == '/' || delimiter == '?' => {
+            let mut pattern = String::new();
+            let mut escaped = false;
+            let mut closed = false;
+            for c in address[1..].chars() {
+                if escaped {
+                    if c != delimiter && c != '\\' {
+                        pattern.push('\\');
+                    }
+                    pattern.push(c);
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == delimiter {
+                    closed = true;
+                    break;
+                } else {
+                    pattern.push(c);
+                }
+            }
+            if !closed {
+                return None;
+            }
+            Address::Pattern(pattern)
+        }
+        _ => {
+            let digits: String = address.chars().take_while(|c| c.is_digit(10)).collect();
+            match digits.parse() {
+                Ok(n) => Address::Line(n),
+                Err(_) => return None,
+            }
+        }
+    };
+
+    Some(Tag {
+        name: String::from(name),
+        path: base.join(file),
+        address: address,
+    })
+}
@@ -0,0 +1,135 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// A command run in the background, such as a build, whose output is shown in a buffer as it
+/// comes in. The lines of the output naming a `file:line:column` of the project lead there.
+#[derive(Debug)]
+pub struct Task {
+    child: Option<Child>,
+    receiver: Receiver<String>,
+    root: PathBuf,
+    /// The file, line and column each line of the buffer leads to, if any, counted from 0.
+    targets: Vec<Option<(PathBuf, usize, usize)>>,
+}
+
+impl Task {
+    /// Starts the command, followed by its arguments, in the project at `root`.
+    pub fn start(command: &[String], root: PathBuf) -> io::Result<Task> {
+        let mut child = try!(Command::new(&command[0])
+            .args(&command[1..])
+            .current_dir(&root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn());
+        let (sender, receiver) = mpsc::channel();
+        forward(child.stdout.take().unwrap(), sender.clone());
+        forward(child.stderr.take().unwrap(), sender);
+        Ok(Task {
+            child: Some(child),
+            receiver: receiver,
+            root: root,
+            targets: vec![None, None],
+        })
+    }
+
+    /// The first lines of the buffer, before any output comes in.
+    pub fn header(command: &[String]) -> Vec<String> {
+        vec![format!("Running `{}`", command.join(" ")), String::new()]
+    }
+
+    /// The lines written since last time, and how the command exited once it is over.
+    pub fn receive(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.child.is_none() {
+            return lines;
+        }
+        loop {
+            match self.receiver.try_recv() {
+                Ok(line) => {
+                    self.targets.push(location(&line, &self.root));
+                    lines.push(line);
+                }
+                Err(TryRecvError::Empty) => break,
+                // Both outputs are closed, so the command is over or about to be.
+                Err(TryRecvError::Disconnected) => {
+                    let status = self.child.take().unwrap().wait();
+                    lines.push(String::new());
+                    lines.push(match status.map(|s| s.code()) {
+                        Ok(Some(0)) => String::from("Finished"),
+                        Ok(Some(code)) => format!("Failed with exit code {}", code),
+                        Ok(None) => String::from("Stopped by a signal"),
+                        Err(e) => format!("Failed: {}", e),
+                    });
+                    self.targets.push(None);
+                    self.targets.push(None);
+                    break;
+                }
+            }
+        }
+        lines
+    }
+
+    /// The file, line and column the given line of the buffer leads to, if any.
+    pub fn target(&self, line: usize) -> Option<(&Path, usize, usize)> {
+        match self.targets.get(line) {
+            Some(&Some((ref path, line, column))) => Some((path.as_path(), line, column)),
+            _ => None,
+        }
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        if let Some(ref mut child) = self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Sends the lines read from the output of a command until it is closed.
+fn forward<R: Read + Send + 'static>(output: R, sender: Sender<String>) {
+    let _ = thread::spawn(move || {
+        let mut reader = BufReader::new(output);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line);
+                    let text = text.trim_right_matches(|c| c == '\n' || c == '\r');
+                    if sender.send(String::from(text)).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+}
+
+/// The first `file:line:column` or `file:line` of the text naming a file of the project, such
+/// as `src/main.rs:10:5` in the errors of compilers, with the line and column counted from 0.
+fn location(text: &str, root: &Path) -> Option<(PathBuf, usize, usize)> {
+    for word in text.split_whitespace() {
+        let word = word.trim_matches(|c| c == '(' || c == ')' || c == ',' || c == '\'');
+        let mut parts = word.split(':');
+        let path = match parts.next() {
+            Some(path) if !path.is_empty() => root.join(path),
+            _ => continue,
+        };
+        let line = match parts.next().and_then(|l| l.parse::<usize>().ok()) {
+            Some(line) if line > 0 => line - 1,
+            _ => continue,
+        };
+        let column = parts.next().and_then(|c| c.parse::<usize>().ok()).unwrap_or(1);
+        if path.is_file() {
+            return Some((path, line, column.saturating_sub(1)));
+        }
+    }
+    None
+}
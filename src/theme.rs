@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::u8;
+
+use toml::{Parser, Value};
+
+use super::{Error, Result};
+
+/// A color as red, green, blue and alpha between 0 and 1.
+pub type Color = [f32; 4];
+
+/// The colors everything is drawn with.
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    /// Behind the file tree and around the editor.
+    pub background: Color,
+    pub editor_background: Color,
+    /// Behind the status bar, the prompts, the panels and the line with the cursor.
+    pub panel_background: Color,
+    /// The selected entry of lists, the occurrences of the word under the cursor and borders.
+    pub highlight: Color,
+    pub selection: Color,
+    pub cursor: Color,
+    pub text: Color,
+    /// The line numbers and other text that is less important.
+    pub gutter: Color,
+    pub folder: Color,
+    pub file: Color,
+    /// The part of lines past the maximum line length.
+    pub overflow: Color,
+    /// The line under annotated text, such as errors.
+    pub underline: Color,
+    /// The gutter marks of the lines added, modified and removed since the last commit.
+    pub added: Color,
+    pub modified: Color,
+    pub removed: Color,
+    /// The gutter mark of bookmarked lines.
+    pub bookmark: Color,
+}
+
+impl Theme {
+    pub fn dark() -> Theme {
+        Theme {
+            name: String::from("dark"),
+            background: rgba(33, 37, 43, 255),
+            editor_background: rgba(40, 44, 52, 255),
+            panel_background: rgba(44, 50, 60, 255),
+            highlight: rgba(62, 68, 81, 255),
+            selection: rgba(62, 81, 113, 255),
+            cursor: rgba(82, 139, 255, 255),
+            text: rgba(255, 255, 255, 255),
+            gutter: rgba(99, 109, 131, 255),
+            folder: rgba(229, 192, 123, 255),
+            file: rgba(171, 178, 191, 255),
+            overflow: rgba(224, 108, 117, 64),
+            underline: rgba(224, 108, 117, 255),
+            added: rgba(152, 195, 121, 255),
+            modified: rgba(229, 192, 123, 255),
+            removed: rgba(224, 108, 117, 255),
+            bookmark: rgba(97, 175, 239, 255),
+        }
+    }
+
+    pub fn light() -> Theme {
+        Theme {
+            name: String::from("light"),
+            background: rgba(234, 234, 235, 255),
+            editor_background: rgba(250, 250, 250, 255),
+            panel_background: rgba(240, 240, 241, 255),
+            highlight: rgba(219, 219, 220, 255),
+            selection: rgba(198, 216, 246, 255),
+            cursor: rgba(82, 111, 255, 255),
+            text: rgba(56, 58, 66, 255),
+            gutter: rgba(157, 157, 159, 255),
+            folder: rgba(193, 132, 1, 255),
+            file: rgba(105, 108, 119, 255),
+            overflow: rgba(228, 86, 73, 64),
+            underline: rgba(228, 86, 73, 255),
+            added: rgba(80, 161, 79, 255),
+            modified: rgba(193, 132, 1, 255),
+            removed: rgba(228, 86, 73, 255),
+            bookmark: rgba(64, 120, 242, 255),
+        }
+    }
+
+    /// Loads a theme from a TOML file, with a color as `"#rrggbb"` or `"#rrggbbaa"` for each of
+    /// the fields. The ones missing are taken from the theme named by `base`, or the dark one.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Theme> {
+        let path = path.as_ref();
+        let mut contents = String::new();
+        let _ = try!(try!(File::open(path)).read_to_string(&mut contents));
+        let mut parser = Parser::new(&contents);
+        let table = match parser.parse() {
+            Some(t) => t,
+            None => {
+                return Err(Error::InvalidConfig(parser.errors
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")))
+            }
+        };
+
+        let mut theme = match table.get("base").and_then(Value::as_str) {
+            Some("light") => Theme::light(),
+            _ => Theme::dark(),
+        };
+        theme.name = match table.get("name").and_then(Value::as_str) {
+            Some(name) => String::from(name),
+            None => path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+        };
+        for (key, value) in &table {
+            if key == "name" || key == "base" {
+                continue;
+            }
+            let color = match value.as_str().and_then(parse_color) {
+                Some(color) => color,
+                None => {
+                    return Err(Error::InvalidConfig(format!("invalid color for `{}` in the \
+                                                             theme",
+                                                            key)))
+                }
+            };
+            match key.as_str() {
+                "background" => theme.background = color,
+                "editor_background" => theme.editor_background = color,
+                "panel_background" => theme.panel_background = color,
+                "highlight" => theme.highlight = color,
+                "selection" => theme.selection = color,
+                "cursor" => theme.cursor = color,
+                "text" => theme.text = color,
+                "gutter" => theme.gutter = color,
+                "folder" => theme.folder = color,
+                "file" => theme.file = color,
+                "overflow" => theme.overflow = color,
+                "underline" => theme.underline = color,
+                "added" => theme.added = color,
+                "modified" => theme.modified = color,
+                "removed" => theme.removed = color,
+                "bookmark" => theme.bookmark = color,
+                _ => return Err(Error::InvalidConfig(format!("unknown theme color `{}`", key))),
+            }
+        }
+
+        Ok(theme)
+    }
+}
+
+fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+    [r as f32 / u8::MAX as f32,
+     g as f32 / u8::MAX as f32,
+     b as f32 / u8::MAX as f32,
+     a as f32 / u8::MAX as f32]
+}
+
+/// Parses a color written as `#rrggbb`, or `#rrggbbaa` with transparency.
+fn parse_color(text: &str) -> Option<Color> {
+    if !text.starts_with('#') || (text.len() != 7 && text.len() != 9) ||
+       !text[1..].chars().all(|c| c.is_digit(16)) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&text[i..i + 2], 16).ok();
+    let alpha = if text.len() == 9 { channel(7) } else { Some(u8::MAX) };
+    match (channel(1), channel(3), channel(5), alpha) {
+        (Some(r), Some(g), Some(b), Some(a)) => Some(rgba(r, g, b, a)),
+        _ => None,
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use time::{self, Timespec};
+
+use editor_core::backend::TextBuffer;
+use editor_core::history::Change;
+
+/// Longest excerpt of changed text shown for a revision, in characters.
+const EXCERPT_LENGTH: usize = 20;
+
+/// A revision as it is listed in the timeline.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub revision: usize,
+    /// When the revision was made, as `HH:MM:SS`.
+    pub time: String,
+    /// Where the text changed and what was removed and inserted there.
+    pub summary: String,
+    /// Whether the current revision was reached through this one, rather than it being on a
+    /// branch left by undoing and editing something else.
+    pub on_current_branch: bool,
+}
+
+/// The undo history of a buffer listed newest first, along with a copy of the buffer showing
+/// how it was at the selected revision, so that going back to it can be previewed.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    entries: Vec<Entry>,
+    selected: usize,
+    /// The revision the buffer is at.
+    current: usize,
+    preview: TextBuffer,
+}
+
+impl Timeline {
+    pub fn new(buffer: &TextBuffer) -> Timeline {
+        let history = buffer.get_history();
+        let mut current_branch = vec![history.current()];
+        while let Some(parent) = history.revisions()[*current_branch.last().unwrap()].parent() {
+            current_branch.push(parent);
+        }
+
+        let entries: Vec<Entry> = history.revisions()
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, revision)| {
+                Entry {
+                    revision: i,
+                    time: clock(revision.time()),
+                    summary: summary(revision.changes()),
+                    on_current_branch: current_branch.contains(&i),
+                }
+            })
+            .collect();
+        let selected = entries.iter().position(|e| e.revision == history.current()).unwrap();
+
+        Timeline {
+            entries: entries,
+            selected: selected,
+            current: history.current(),
+            preview: buffer.clone(),
+        }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn current_revision(&self) -> usize {
+        self.current
+    }
+
+    /// The first entry shown when only the given number of rows fit, so that the selected one is
+    /// among them.
+    pub fn first_shown(&self, rows: usize) -> usize {
+        if self.selected < rows {
+            0
+        } else {
+            self.selected + 1 - rows
+        }
+    }
+
+    pub fn selected_revision(&self) -> usize {
+        self.entries[self.selected].revision
+    }
+
+    /// The buffer as it was at the selected revision.
+    pub fn preview(&self) -> &TextBuffer {
+        &self.preview
+    }
+
+    /// Selects the entry at the given position of the list, bringing the preview to it.
+    pub fn select(&mut self, entry: usize) {
+        if entry < self.entries.len() {
+            self.selected = entry;
+            let revision = self.selected_revision();
+            self.preview.go_to_revision(revision);
+        }
+    }
+
+    /// Selects the newer revision listed above the selected one.
+    pub fn select_previous(&mut self) {
+        if self.selected > 0 {
+            let previous = self.selected - 1;
+            self.select(previous);
+        }
+    }
+
+    /// Selects the older revision listed below the selected one.
+    pub fn select_next(&mut self) {
+        let next = self.selected + 1;
+        self.select(next);
+    }
+}
+
+fn clock(at: SystemTime) -> String {
+    let seconds = at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    match time::at(Timespec::new(seconds as i64, 0)).strftime("%H:%M:%S") {
+        Ok(clock) => clock.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Describes the changes of a revision by the first of them. The characters typed right after it
+/// are shown along with it, and the rest of the changes, such as the ones made by other cursors,
+/// are only counted.
+fn summary(changes: &[Change]) -> String {
+    let first = match changes.first() {
+        Some(change) => change,
+        None => return String::from("Opened"),
+    };
+
+    let mut inserted = first.inserted.clone();
+    let mut shown = 1;
+    if !first.inserted.contains('\n') {
+        let mut next = (first.position.0, first.position.1 + first.inserted.len());
+        for change in &changes[1..] {
+            if change.removed.is_empty() && !change.inserted.contains('\n') &&
+               change.position == next {
+                inserted.push_str(&change.inserted);
+                next.1 += change.inserted.len();
+                shown += 1;
+            }
+        }
+    }
+
+    let mut summary = format!("Ln {}", first.position.0 + 1);
+    if !first.removed.is_empty() {
+        summary.push_str(&format!("  -{}", excerpt(&first.removed)));
+    }
+    if !inserted.is_empty() {
+        summary.push_str(&format!("  +{}", excerpt(&inserted)));
+    }
+    if changes.len() > shown {
+        summary.push_str(&format!("  ({} more)", changes.len() - shown));
+    }
+    summary
+}
+
+/// The start of a changed text, with line breaks and tabs spelled out.
+fn excerpt(text: &str) -> String {
+    let escaped = text.replace('\n', "\\n").replace('\t', "\\t");
+    if escaped.chars().count() > EXCERPT_LENGTH {
+        escaped.chars().take(EXCERPT_LENGTH).collect::<String>() + "..."
+    } else {
+        escaped
+    }
+}
@@ -0,0 +1,272 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use piston_window::character::CharacterCache;
+
+use editor_core::backend::TextBuffer;
+
+use metrics::{LineLayout, TextMetrics};
+
+/// A line as it is drawn.
+#[derive(Clone, Debug)]
+pub struct Line {
+    /// The line number shown in the gutter.
+    pub number: String,
+    pub layout: LineLayout,
+}
+
+/// Where lines end up on the screen when they are revealed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RevealMode {
+    /// In the middle of the screen, for jumps to somewhere else in the buffer.
+    Center,
+    /// At the top of the screen.
+    Top,
+    /// Scrolling as little as needed, or not at all if they are already shown, for the cursor
+    /// following what is typed.
+    Minimal,
+}
+
+/// The part of a buffer shown in the editor, along with the layout of the lines shown.
+#[derive(Clone, Debug, Default)]
+pub struct View {
+    /// The line scrolled to, which is the first one shown once any animation is over.
+    first_line: usize,
+    /// The line at the top of the editor as drawn, with the fraction of it scrolled past, which
+    /// trails `first_line` while scrolling to it is animated.
+    shown: f64,
+    /// Where the animated scrolling started from, and when, once the first update after it
+    /// came.
+    scrolling: Option<(f64, Option<u64>)>,
+    /// How wide lines can be before they are wrapped, if the buffer wraps them.
+    wrap_width: f64,
+    /// The buffer version, tab width, font size and wrap width the cached lines were measured
+    /// with.
+    measured: (usize, usize, u32, Option<f64>),
+    lines: HashMap<usize, Line>,
+    /// The buffer version and tab width the minimap extents were measured with.
+    outlined: (usize, usize),
+    extents: HashMap<usize, (usize, usize)>,
+}
+
+impl View {
+    pub fn new() -> View {
+        Default::default()
+    }
+
+    pub fn first_line(&self) -> usize {
+        self.first_line
+    }
+
+    /// The lines of a buffer with `line_count` lines that fit in `rows` rows, from the one drawn
+    /// at the top.
+    pub fn visible(&self, rows: usize, line_count: usize) -> Range<usize> {
+        let first = self.shown as usize;
+        let end = cmp::min(first + rows, line_count);
+        cmp::min(first, end)..end
+    }
+
+    /// How much of the line drawn at the top is scrolled past, from 0 to 1, while scrolling is
+    /// animated.
+    pub fn scrolled_fraction(&self) -> f64 {
+        self.shown.fract()
+    }
+
+    /// Scrolls down by the given number of lines, or up if negative, keeping the last line of
+    /// the buffer on the screen. The view slides there over the next updates.
+    pub fn scroll_by(&mut self, lines: isize, line_count: usize) {
+        let first = self.first_line as isize + lines;
+        let last = line_count.saturating_sub(1) as isize;
+        self.glide_to(cmp::max(0, cmp::min(first, last)) as usize, line_count);
+    }
+
+    /// Scrolls so that the given line is the first one shown, or the last line of the buffer
+    /// if it has fewer.
+    pub fn scroll_to(&mut self, line: usize, line_count: usize) {
+        self.first_line = cmp::min(line, line_count.saturating_sub(1));
+        self.snap();
+    }
+
+    /// Scrolls like `scroll_to()`, sliding there over the next updates.
+    fn glide_to(&mut self, line: usize, line_count: usize) {
+        let from = self.shown;
+        self.scroll_to(line, line_count);
+        if from != self.shown {
+            self.shown = from;
+            self.scrolling = Some((from, None));
+        }
+    }
+
+    /// Ends any animation, drawing the line scrolled to at the top.
+    fn snap(&mut self) {
+        self.shown = self.first_line as f64;
+        self.scrolling = None;
+    }
+
+    /// Moves the line drawn at the top towards the one scrolled to while scrolling is animated,
+    /// getting there `duration` nanoseconds after the first update following the scroll.
+    /// Returns whether it moved.
+    pub fn animate(&mut self, now: u64, duration: u64) -> bool {
+        let (from, started) = match self.scrolling {
+            Some((from, started)) => (from, started.unwrap_or(now)),
+            None => return false,
+        };
+        let elapsed = now - started;
+        if elapsed >= duration {
+            self.snap();
+        } else {
+            // Slowing down towards the end.
+            let progress = elapsed as f64 / duration as f64;
+            let eased = 1.0 - (1.0 - progress) * (1.0 - progress);
+            self.shown = from + (self.first_line as f64 - from) * eased;
+            self.scrolling = Some((from, Some(started)));
+        }
+        true
+    }
+
+    /// Scrolls so that the given lines of a buffer with `line_count` lines are among the `rows`
+    /// rows shown, placed as the mode says. Ranges taller than the screen are shown from their
+    /// first line.
+    pub fn reveal_range(&mut self,
+                        lines: Range<usize>,
+                        mode: RevealMode,
+                        rows: usize,
+                        line_count: usize) {
+        let (start, end) = (lines.start, cmp::max(lines.end, lines.start + 1));
+        let first = match mode {
+            RevealMode::Minimal if start >= self.first_line &&
+                                   end <= self.first_line + rows => return,
+            _ if end - start >= rows => start,
+            RevealMode::Center => ((start + end) / 2).saturating_sub(rows / 2),
+            RevealMode::Top => start,
+            RevealMode::Minimal if start < self.first_line => start,
+            RevealMode::Minimal => end - rows,
+        };
+        // Jumps elsewhere in the buffer slide there, while following the cursor does not.
+        if mode == RevealMode::Center {
+            self.glide_to(first, line_count);
+        } else {
+            self.scroll_to(first, line_count);
+        }
+    }
+
+    /// Sets how wide the lines of buffers that wrap them can be.
+    pub fn set_wrap_width(&mut self, width: f64) {
+        self.wrap_width = width;
+    }
+
+    /// Scrolls down until the given line is shown whole within `rows` rows, for buffers whose
+    /// lines take more than one row when wrapped.
+    pub fn reveal_wrapped<C: CharacterCache>(&mut self,
+                                             buffer: &TextBuffer,
+                                             line: usize,
+                                             rows: usize,
+                                             metrics: &mut TextMetrics,
+                                             cache: &mut C) {
+        self.reveal_range(line..line + 1, RevealMode::Minimal, rows, buffer.line_count());
+        if !buffer.get_options().wrap {
+            return;
+        }
+        let first = self.first_line;
+        while self.first_line < line {
+            let mut shown = 0;
+            for i in self.first_line..line + 1 {
+                shown += self.line(buffer, i, metrics, cache).layout.rows();
+            }
+            if shown <= rows {
+                break;
+            }
+            self.first_line += 1;
+        }
+        if self.first_line != first {
+            self.snap();
+        }
+    }
+
+    /// The line shown at the given row of the editor, along with which of its rows it is, if
+    /// the buffer reaches that far.
+    pub fn line_at_row<C: CharacterCache>(&mut self,
+                                          buffer: &TextBuffer,
+                                          row: usize,
+                                          metrics: &mut TextMetrics,
+                                          cache: &mut C)
+                                          -> Option<(usize, usize)> {
+        let mut top = 0;
+        for line in self.first_line..buffer.line_count() {
+            let rows = self.line(buffer, line, metrics, cache).layout.rows();
+            if row < top + rows {
+                return Some((line, row - top));
+            }
+            top += rows;
+        }
+        None
+    }
+
+    /// The given line of the buffer, which is only measured again if the buffer, its tab width,
+    /// the font size or the wrap width changed since it was last drawn.
+    pub fn line<C: CharacterCache>(&mut self,
+                                   buffer: &TextBuffer,
+                                   line: usize,
+                                   metrics: &mut TextMetrics,
+                                   cache: &mut C)
+                                   -> &Line {
+        let tab_width = buffer.get_options().tab_width;
+        let wrap = if buffer.get_options().wrap {
+            Some(self.wrap_width)
+        } else {
+            None
+        };
+        let measured = (buffer.version(), tab_width, metrics.size(), wrap);
+        if measured != self.measured {
+            self.lines.clear();
+            self.measured = measured;
+        }
+
+        self.lines.entry(line).or_insert_with(|| {
+            let text = buffer.lines().nth(line).unwrap().trim_right_matches('\n');
+            Line {
+                number: (line + 1).to_string(),
+                layout: metrics.layout(cache, text, tab_width, wrap),
+            }
+        })
+    }
+
+    /// The columns the text of each of the given lines starts and ends at, leaving out the
+    /// indentation and trailing whitespace, as drawn in the minimap. They are only measured
+    /// again once the buffer or its tab width changes.
+    pub fn minimap_extents(&mut self,
+                           buffer: &TextBuffer,
+                           lines: Range<usize>)
+                           -> Vec<(usize, usize)> {
+        let outlined = (buffer.version(), buffer.get_options().tab_width);
+        if outlined != self.outlined {
+            self.extents.clear();
+            self.outlined = outlined;
+        } else if self.extents.len() > 3 * (lines.end - lines.start) {
+            self.extents.retain(|line, _| lines.start <= *line && *line < lines.end);
+        }
+
+        let mut extents = Vec::new();
+        for line in lines {
+            let extent = *self.extents.entry(line).or_insert_with(|| {
+                let text = buffer.lines().nth(line).unwrap().trim_right();
+                let indent = text.len() - text.trim_left().len();
+                (buffer.display_column(line, indent), buffer.display_column(line, text.len()))
+            });
+            extents.push(extent);
+        }
+        extents
+    }
+
+    /// Drops the measurements of the lines more than a screen away from the given range, keeping
+    /// the ones measured ahead of scrolling.
+    pub fn forget_hidden(&mut self, visible: &Range<usize>) {
+        let rows = visible.end - visible.start;
+        if self.lines.len() > 3 * rows {
+            let start = visible.start.saturating_sub(rows);
+            let end = visible.end + rows;
+            self.lines.retain(|&line, _| start <= line && line < end);
+        }
+    }
+}
@@ -0,0 +1,313 @@
+use std::iter;
+
+use piston_window::{Button, Input, Key};
+
+use editor_core::backend::Move;
+
+use action::{Action, Modifiers};
+
+/// The largest count in front of a command. Each repetition is an action of its own, so larger
+/// counts would hold up the editor for nothing.
+const MAX_COUNT: usize = 10_000;
+
+/// What keys do in the Vim-style input mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Keys move the cursor and run commands.
+    Normal,
+    /// Keys type text, as they do without the Vim-style mode.
+    Insert,
+    /// Keys extend the selection and run commands on it.
+    Visual,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual => "VISUAL",
+        }
+    }
+}
+
+/// Translates keys into actions the way Vim would: motions (`hjkl`, `w`, `b`, `e`, `0`, `$`,
+/// `gg`, `G`), operators taking a motion (`d`, `y`, `c`) or doubled for whole lines, `p` and
+/// `P` to put what was copied, counts in front of them and the modes to type text and select
+/// it. Keys held with Ctrl keep their usual binding.
+#[derive(Clone, Debug)]
+pub struct Vim {
+    mode: Mode,
+    /// The digits typed in front of a command.
+    count: String,
+    /// The operator waiting for a motion.
+    operator: Option<char>,
+    /// Whether the first `g` of `gg` was typed.
+    g: bool,
+    /// Whether the text copied last was whole lines, which are put below or above the line of
+    /// the cursor instead of next to it.
+    linewise: bool,
+}
+
+impl Vim {
+    pub fn new() -> Vim {
+        Vim {
+            mode: Mode::Normal,
+            count: String::new(),
+            operator: None,
+            g: false,
+            linewise: false,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The actions the input stands for in the current mode, in the order they are run.
+    pub fn input(&mut self, input: &Input, modifiers: Modifiers, subwords: bool) -> Vec<Action> {
+        if self.mode == Mode::Insert {
+            return match *input {
+                Input::Press(Button::Keyboard(Key::Escape)) => {
+                    self.mode = Mode::Normal;
+                    vec![Action::Move(Move::Left)]
+                }
+                _ => Action::from_input(input, modifiers, subwords).into_iter().collect(),
+            };
+        }
+
+        match *input {
+            Input::Press(Button::Keyboard(Key::Escape)) => {
+                self.reset();
+                self.mode = Mode::Normal;
+                vec![Action::CollapseCursors]
+            }
+            Input::Press(Button::Keyboard(Key::R)) if modifiers.ctrl => vec![Action::Redo],
+            // Other keys run what they are bound to as long as it does not type anything, so
+            // that saving, opening files and the arrow keys keep working.
+            Input::Press(_) => {
+                match Action::from_input(input, modifiers, subwords) {
+                    Some(Action::Move(movement)) if self.mode == Mode::Visual => {
+                        vec![Action::Extend(movement)]
+                    }
+                    Some(action) => {
+                        if !action.is_edit() || action == Action::Undo ||
+                           action == Action::Redo {
+                            vec![action]
+                        } else {
+                            Vec::new()
+                        }
+                    }
+                    None => Vec::new(),
+                }
+            }
+            Input::Text(ref text) if !modifiers.ctrl => {
+                text.chars().filter(|c| !c.is_control()).flat_map(|c| self.key(c)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The actions for a character typed in normal or visual mode.
+    fn key(&mut self, c: char) -> Vec<Action> {
+        if c.is_digit(10) && (c != '0' || !self.count.is_empty()) {
+            // Digits taking the count past the largest one are dropped.
+            let count = format!("{}{}", self.count, c);
+            if count.parse::<usize>().map_or(false, |n| n <= MAX_COUNT) {
+                self.count = count;
+            }
+            return Vec::new();
+        }
+        let count = self.count.parse::<usize>().unwrap_or(1);
+        let g = self.g;
+        self.g = false;
+
+        if let Some(movement) = motion(c, g) {
+            let linewise = c == 'j' || c == 'k';
+            return self.motion(movement, linewise, count);
+        }
+        if c == 'g' && !g {
+            self.g = true;
+            return Vec::new();
+        }
+
+        if self.mode == Mode::Visual {
+            let actions = match c {
+                'd' | 'x' => {
+                    self.linewise = false;
+                    vec![Action::Copy, Action::Delete]
+                }
+                'y' => {
+                    self.linewise = false;
+                    vec![Action::Copy, Action::CollapseCursors]
+                }
+                'c' => {
+                    self.linewise = false;
+                    self.mode = Mode::Insert;
+                    vec![Action::Copy, Action::Delete]
+                }
+                'p' | 'P' => vec![Action::Paste],
+                'v' => vec![Action::CollapseCursors],
+                _ => return self.reset(),
+            };
+            if self.mode == Mode::Visual {
+                self.mode = Mode::Normal;
+            }
+            self.reset();
+            return actions;
+        }
+
+        // Doubling an operator runs it on the lines.
+        if let Some(operator) = self.operator {
+            if c != operator {
+                return self.reset();
+            }
+            let mut actions = vec![Action::SelectLines];
+            actions.extend(repeat(Action::Extend(Move::Down), count - 1));
+            self.linewise = true;
+            actions.extend(self.operate(operator));
+            self.reset();
+            return actions;
+        }
+
+        let mut actions = Vec::new();
+        match c {
+            'd' | 'y' | 'c' => {
+                self.operator = Some(c);
+                return actions;
+            }
+            'x' => {
+                self.linewise = false;
+                actions.extend(repeat(Action::Extend(Move::Right), count));
+                actions.push(Action::Copy);
+                actions.push(Action::Delete);
+            }
+            'p' if self.linewise => {
+                actions.push(Action::Move(Move::LineEnd));
+                actions.push(Action::Move(Move::Right));
+                actions.extend(repeat(Action::Paste, count));
+            }
+            'P' if self.linewise => {
+                actions.push(Action::Move(Move::FirstColumn));
+                actions.extend(repeat(Action::Paste, count));
+            }
+            'p' => {
+                actions.push(Action::Move(Move::Right));
+                actions.extend(repeat(Action::Paste, count));
+            }
+            'P' => actions.extend(repeat(Action::Paste, count)),
+            'u' => actions.extend(repeat(Action::Undo, count)),
+            'v' => self.mode = Mode::Visual,
+            'i' => self.mode = Mode::Insert,
+            'a' => {
+                actions.push(Action::Move(Move::Right));
+                self.mode = Mode::Insert;
+            }
+            'I' => {
+                actions.push(Action::Move(Move::LineStart));
+                self.mode = Mode::Insert;
+            }
+            'A' => {
+                actions.push(Action::Move(Move::LineEnd));
+                self.mode = Mode::Insert;
+            }
+            'o' => {
+                actions.push(Action::Move(Move::LineEnd));
+                actions.push(Action::NewLine);
+                self.mode = Mode::Insert;
+            }
+            'O' => {
+                actions.push(Action::Move(Move::FirstColumn));
+                actions.push(Action::NewLine);
+                actions.push(Action::Move(Move::Up));
+                self.mode = Mode::Insert;
+            }
+            _ => {}
+        }
+        self.reset();
+        actions
+    }
+
+    /// The actions for a motion, which moves the cursor, extends the selection in visual mode,
+    /// or selects the text the waiting operator runs on. Operators on `j` and `k` take whole
+    /// lines.
+    fn motion(&mut self, movement: Move, linewise: bool, count: usize) -> Vec<Action> {
+        // `gg` and `G` go to the first or the last line whatever the count.
+        let count = if movement == Move::BufferStart || movement == Move::BufferEnd {
+            1
+        } else {
+            count
+        };
+        let operator = match self.operator {
+            Some(operator) => operator,
+            None => {
+                let action = if self.mode == Mode::Visual {
+                    Action::Extend(movement)
+                } else {
+                    Action::Move(movement)
+                };
+                self.reset();
+                return repeat(action, count).collect();
+            }
+        };
+
+        let mut actions = Vec::new();
+        if linewise {
+            if movement == Move::Up {
+                actions.extend(repeat(Action::Move(Move::Up), count));
+            }
+            actions.push(Action::SelectLines);
+            actions.extend(repeat(Action::Extend(Move::Down), count));
+        } else {
+            actions.extend(repeat(Action::Extend(movement), count));
+        }
+        self.linewise = linewise;
+        actions.extend(self.operate(operator));
+        self.reset();
+        actions
+    }
+
+    /// The actions running the operator on the selection.
+    fn operate(&mut self, operator: char) -> Vec<Action> {
+        match operator {
+            'd' => vec![Action::Copy, Action::Delete],
+            'c' => {
+                self.mode = Mode::Insert;
+                vec![Action::Copy, Action::Delete]
+            }
+            _ => vec![Action::Copy, Action::CollapseCursors],
+        }
+    }
+
+    /// Forgets the count and operator typed so far.
+    fn reset(&mut self) -> Vec<Action> {
+        self.count.clear();
+        self.operator = None;
+        self.g = false;
+        Vec::new()
+    }
+}
+
+/// The movement of a motion key, given whether `g` was typed before it.
+fn motion(c: char, g: bool) -> Option<Move> {
+    let movement = match c {
+        'g' if g => Move::BufferStart,
+        'h' => Move::Left,
+        'j' => Move::Down,
+        'k' => Move::Up,
+        'l' => Move::Right,
+        'w' => Move::NextWordStart,
+        'b' => Move::WordLeft,
+        'e' => Move::WordRight,
+        '0' => Move::FirstColumn,
+        '^' => Move::LineStart,
+        '$' => Move::LineEnd,
+        'G' => Move::BufferEnd,
+        _ => return None,
+    };
+    Some(movement)
+}
+
+fn repeat(action: Action, count: usize) -> iter::Take<iter::Repeat<Action>> {
+    iter::repeat(action).take(count)
+}
@@ -0,0 +1,82 @@
+use std::iter;
+use std::path::PathBuf;
+
+use action::{self, Action};
+
+/// What pressing Enter on a line of the welcome page does.
+#[derive(Clone, Debug)]
+pub enum Item {
+    /// Runs the action, as the keys shown next to it would.
+    Run(Action),
+    Open(PathBuf),
+    /// Asks for a folder to show in the file tree and work in.
+    OpenFolder,
+    /// Opens the configuration file.
+    OpenSettings,
+}
+
+/// The page shown on the first start and from the command palette, with the quick actions, the
+/// files opened last and every key binding.
+#[derive(Clone, Debug)]
+pub struct Welcome {
+    lines: Vec<String>,
+    /// The item of each line, if it has one.
+    items: Vec<Option<Item>>,
+}
+
+impl Welcome {
+    pub fn new(recent: Vec<PathBuf>) -> Welcome {
+        let mut welcome = Welcome {
+            lines: Vec::new(),
+            items: Vec::new(),
+        };
+        welcome.text("Welcome");
+        welcome.text("");
+        welcome.text("Move to a line and press Enter to run it.");
+        welcome.text("");
+        welcome.entry("Open file", "Ctrl+O", Item::Run(Action::OpenFile));
+        welcome.entry("Open folder", "", Item::OpenFolder);
+        welcome.entry("Open settings", "", Item::OpenSettings);
+        welcome.entry("Command palette", "Ctrl+Shift+P", Item::Run(Action::CommandPalette));
+
+        if !recent.is_empty() {
+            welcome.text("");
+            welcome.text("Recent files");
+            for path in recent {
+                let name = path.to_string_lossy().into_owned();
+                welcome.entry(&name, "", Item::Open(path));
+            }
+        }
+
+        // Generated from the commands, so that it lists the keys as they are bound.
+        welcome.text("");
+        welcome.text("Keys");
+        for command in action::commands().into_iter().filter(|c| !c.keys.is_empty()) {
+            welcome.entry(command.name, command.keys, Item::Run(command.action));
+        }
+        welcome
+    }
+
+    /// The text of the page, one line per entry.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.clone()
+    }
+
+    /// The item on the given line of the page, if any.
+    pub fn item(&self, line: usize) -> Option<&Item> {
+        self.items.get(line).and_then(Option::as_ref)
+    }
+
+    fn text(&mut self, text: &str) {
+        self.lines.push(String::from(text));
+        self.items.push(None);
+    }
+
+    /// Adds an entry, with the keys it is bound to lined up on the right.
+    fn entry(&mut self, name: &str, keys: &str, item: Item) {
+        let padding = 40usize.saturating_sub(name.chars().count() + 2);
+        let spaces: String = iter::repeat(' ').take(padding).collect();
+        self.lines.push(format!("  {}{}  {}", name, spaces, keys).trim_right().to_owned());
+        self.items.push(Some(item));
+    }
+}